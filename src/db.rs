@@ -1,32 +1,196 @@
 use crate::{
-    expression::Expression,
-    query::{Literal, Operation, Statement},
+    expression::{Arithmetic, Expression},
+    query::{
+        Column, Identifier, Literal, Operand, Operation, OrderBy, SelectColumn, Statement,
+        UpdateValue,
+    },
     table::{
-        Table, TableError,
+        CursorIterator, Table, TableError, TableResult,
         data::Data,
-        metadata::{Field, Type},
+        index::SecondaryIndex,
+        metadata::{Field, FieldDefinition, Metadata, Type},
+        options::TableOptions,
+    },
+    utils::{
+        entry_vec::{EntryVector, EntryVectorIntoIter},
+        external_sort::ExternalSorter,
+        range::{Range, SimpleRange},
     },
-    utils::{entry_vec::EntryVector, range::Range},
 };
 use std::{
-    collections::HashMap,
-    fs::OpenOptions,
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{self, OpenOptions},
     io,
-    mem::MaybeUninit,
+    ops::Deref,
     path::{Path, PathBuf},
 };
 
 pub enum OperationResult<'a> {
     Ok,
-    Entries(EntryVector<Literal<'a>>),
+    Entries(ResultSet<'a>),
     Count(usize),
+    /// Key assigned to the row of a single row insert that omitted the primary key, inserts of
+    /// several rows return their Count instead
+    Inserted(usize),
+}
+
+/// Results are serialized as `{"status":"ok"}`, `{"count":n}`, `{"inserted":key}` or an array
+/// with an object for each row
+#[cfg(feature = "serde")]
+mod result_json {
+    use super::OperationResult;
+    use crate::query::Literal;
+    use serde::{Serialize, Serializer, ser::SerializeMap};
+
+    struct Row<'r, 'a> {
+        columns: &'r [&'r str],
+        values: &'r [Literal<'a>],
+    }
+
+    impl Serialize for Row<'_, '_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+            for (column, value) in self.columns.iter().zip(self.values) {
+                map.serialize_entry(column, value)?;
+            }
+            map.end()
+        }
+    }
+
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Json<'r, 'a> {
+        Status { status: &'static str },
+        Count { count: usize },
+        Inserted { inserted: usize },
+        Rows(Vec<Row<'r, 'a>>),
+    }
+
+    impl OperationResult<'_> {
+        /// Serializes the result as JSON, naming the values of each row with the column names,
+        /// which can be taken from ResultSet::column_names.
+        /// Panics if the rows don't have a value for each column name
+        pub fn to_json(&self, column_names: &[&str]) -> String {
+            let json = match self {
+                Self::Ok => Json::Status { status: "ok" },
+                Self::Count(count) => Json::Count { count: *count },
+                Self::Inserted(key) => Json::Inserted { inserted: *key },
+                Self::Entries(result) => {
+                    assert_eq!(
+                        result.entries.entry_size(),
+                        column_names.len(),
+                        "Rows don't have a value for each column name"
+                    );
+                    let rows = result.entries.iter().map(|values| Row {
+                        columns: column_names,
+                        values,
+                    });
+                    Json::Rows(rows.collect())
+                }
+            };
+            serde_json::to_string(&json).expect("Results can always be serialized")
+        }
+    }
+}
+
+/// The rows returned by a select, along with the token to fetch the ones after them
+pub struct ResultSet<'a> {
+    pub entries: EntryVector<Literal<'a>>,
+    /// Name of each column of the entries
+    columns: Vec<&'a str>,
+    next_token: Option<Literal<'static>>,
+}
+
+impl<'a> ResultSet<'a> {
+    /// Returns the names of the columns, which are the aliases given with AS or the names of
+    /// the selected fields
+    #[inline]
+    pub fn column_names(&self) -> &[&'a str] {
+        &self.columns
+    }
+
+    /// Returns the primary key of the last row, None if there are no rows or they were sorted
+    /// by ORDER BY.
+    /// To get the next page, repeat the select adding `WHERE <primary> > token` to its conditions,
+    /// the results are empty once every row has been returned
+    #[inline]
+    pub fn next_token(&self) -> Option<Literal<'static>> {
+        self.next_token.clone()
+    }
+}
+
+impl<'a> Deref for ResultSet<'a> {
+    type Target = EntryVector<Literal<'a>>;
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+/// Rows of a select returned by DB::execute_iter
+pub struct Rows<'a> {
+    columns: Vec<&'a str>,
+    source: RowSource<'a>,
+}
+
+enum RowSource<'a> {
+    /// Rows read from the table as they're requested
+    Stream {
+        rows: Box<FilteringIter<'a>>,
+        fields: Vec<Field>,
+    },
+    /// Rows of a select that had to compute every row before returning the first one
+    Buffered(EntryVectorIntoIter<Literal<'a>>),
+}
+
+impl<'a> Rows<'a> {
+    /// Returns the names of the columns, like ResultSet::column_names
+    #[inline]
+    pub fn column_names(&self) -> &[&'a str] {
+        &self.columns
+    }
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = DBResult<Vec<Literal<'a>>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            RowSource::Stream { rows, fields } => {
+                let row = rows
+                    .next()?
+                    .map(|(key, data)| fields.iter().map(|f| f.read_row(key, data)).collect());
+                Some(row.map_err(DBError::from))
+            }
+            RowSource::Buffered(rows) => rows.next().map(Ok),
+        }
+    }
+}
+
+/// Work done to execute a statement
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExecStats {
+    /// Rows inside of the primary key ranges that were checked against the WHERE expression
+    pub rows_examined: usize,
+    /// Rows that matched the WHERE expression
+    pub rows_matched: usize,
+    /// Pages requested from the pager, see PagerStats::page_reads
+    pub pages_read: usize,
+    /// True if the primary key or a secondary index bounded the scan instead of going over
+    /// every row
+    pub used_index: bool,
 }
 
 pub struct DB<'a> {
     dir: &'a Path,
     tables: HashMap<String, Table>,
+    /// Secondary indexes of each open table
+    indexes: HashMap<String, Vec<SecondaryIndex>>,
+    sort_memory_limit: usize,
 }
 
+/// Maximum number of keys kept in memory while sorting before spilling them to disk
+pub const DEFAULT_SORT_MEMORY_LIMIT: usize = 1 << 16;
+
 #[inline]
 fn table_data_path(name: &str) -> PathBuf {
     Path::new(name).with_extension("tbl")
@@ -42,30 +206,378 @@ fn table_paths(name: &str) -> (PathBuf, PathBuf) {
     (table_data_path(name), table_metadata_path(name))
 }
 
+#[inline]
+fn index_paths(table: &str, column: &str) -> (PathBuf, PathBuf) {
+    let path = Path::new(table);
+    (
+        path.with_extension(format!("{column}.idx")),
+        path.with_extension(format!("{column}.imt")),
+    )
+}
+
 #[derive(Debug)]
 pub enum DBError {
     FailedToOpenTable,
     TableNotExists,
     TableAlreadyExists,
+    InvalidValue(String),
+    MissingValue(String),
+    SelectNotLast,
+    /// The statement references a column that the table doesn't have
+    UnknownColumn(String),
+    /// Generated fields are computed on read, so they can't be written to
+    GeneratedField(String),
+    /// Updates can't change the key of a row
+    PrimaryField(String),
+    /// A select with aggregates can only return or sort by the column it's grouped by
+    NotGrouped(String),
+    /// A literal in the WHERE clause can't be compared with the column
+    MismatchedType(String),
+    /// DB::execute_iter only runs selects
+    NotSelect,
+    /// The value computed by an update doesn't fit in the column
+    Overflow(String),
+    /// The column is the primary key or already has an index
+    IndexExists(String),
     TableError(TableError),
+    Io(io::Error),
 }
 
 impl From<TableError> for DBError {
     fn from(value: TableError) -> Self {
-        Self::TableError(value)
+        match value {
+            TableError::Io(e) => Self::Io(e),
+            value => Self::TableError(value),
+        }
+    }
+}
+
+impl From<io::Error> for DBError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
     }
 }
 
 pub type DBResult<T> = Result<T, DBError>;
 
+/// Converts the literal into the type of the field, failing if it can't be represented
+fn coerce<'l>(field: &Field, literal: Literal<'l>) -> DBResult<Literal<'l>> {
+    match literal {
+        Literal::Null if field.nullable => Ok(literal),
+        _ => field
+            .typ
+            .coerce(literal)
+            .ok_or_else(|| DBError::InvalidValue(field.name.str().to_owned())),
+    }
+}
+
+/// Checks that every column referenced by the statement exists in the table, and that updates
+/// only assign to columns that can be written
+fn validate_columns(
+    metadata: &Metadata,
+    operation: &Operation,
+    wher: Option<&Expression>,
+    order_by: Option<&OrderBy>,
+    group_by: Option<&Identifier>,
+) -> DBResult<()> {
+    let mut columns: Vec<&str> = match operation {
+        Operation::Select { columns, .. } => columns
+            .iter()
+            .filter_map(|c| c.column.field())
+            .map(|c| &**c)
+            .collect(),
+        Operation::Insert { values, .. } => values.iter().flatten().map(|(c, _)| &***c).collect(),
+        Operation::Update {
+            values, returning, ..
+        } => values
+            .iter()
+            .flat_map(|(c, value)| {
+                let operands = match value {
+                    UpdateValue::Computed(left, _, Operand::Field(right)) => vec![*left, *right],
+                    UpdateValue::Computed(left, _, Operand::Literal(_)) => vec![*left],
+                    UpdateValue::Literal(_) => vec![],
+                };
+                std::iter::once(*c).chain(operands)
+            })
+            .map(|c| &**c)
+            .chain(returning.iter().map(|c| &***c))
+            .collect(),
+        _ => vec![],
+    };
+    if let Some(wher) = wher {
+        columns.extend(wher.fields());
+    }
+    if let Some(order_by) = order_by {
+        columns.push(order_by.column);
+    }
+    if let Some(group_by) = group_by {
+        columns.push(group_by);
+    }
+    if let Some(column) = columns.into_iter().find(|c| metadata.field(c).is_none()) {
+        return Err(DBError::UnknownColumn(column.to_owned()));
+    }
+    if let Operation::Update { values, .. } = operation {
+        for (column, _) in values {
+            let field = metadata.field(column).expect("Columns are validated");
+            if field.primary {
+                return Err(DBError::PrimaryField(field.name.str().to_owned()));
+            }
+            reject_generated(field)?;
+        }
+    }
+    Ok(())
+}
+
+/// Converts the literals of the comparisons into the type of the column they're compared with,
+/// so that the key range and the filter compare values of the same type
+fn coerce_expression(metadata: &Metadata, expression: &mut Expression) -> DBResult<()> {
+    expression.try_map_literals(&|name, literal| {
+        let field = metadata.field(name).expect("Columns are validated");
+        match literal {
+            Literal::Null => Ok(literal),
+            _ => field
+                .typ
+                .coerce(literal)
+                .ok_or_else(|| DBError::MismatchedType(field.name.str().to_owned())),
+        }
+    })
+}
+
+/// Value written to a field by an update, with the columns it reads resolved
+enum Assignment<'f, 'l> {
+    Literal(Literal<'l>),
+    Computed(&'f Field, Arithmetic, Result<&'f Field, Literal<'l>>),
+}
+
+impl<'f, 'l> Assignment<'f, 'l> {
+    /// Resolves the value assigned to the field. Arithmetic is only allowed between
+    /// operands of the same numeric type as the field
+    fn new(metadata: &'f Metadata, field: &Field, value: UpdateValue<'l>) -> DBResult<Self> {
+        let (left, op, right) = match value {
+            UpdateValue::Literal(literal) => return coerce(field, literal).map(Self::Literal),
+            UpdateValue::Computed(left, op, right) => (left, op, right),
+        };
+        let mismatched = || DBError::MismatchedType(field.name.str().to_owned());
+        let left = metadata.field(left).expect("Columns are validated");
+        if !matches!(field.typ, Type::Int | Type::Uint | Type::Float) || left.typ != field.typ {
+            return Err(mismatched());
+        }
+        let right = match right {
+            Operand::Field(right) => {
+                let right = metadata.field(right).expect("Columns are validated");
+                if right.typ != field.typ {
+                    return Err(mismatched());
+                }
+                Ok(right)
+            }
+            Operand::Literal(Literal::Null) => Err(Literal::Null),
+            Operand::Literal(literal) => Err(field.typ.coerce(literal).ok_or_else(mismatched)?),
+        };
+        Ok(Self::Computed(left, op, right))
+    }
+
+    /// Value to write to the field of the row, computed from its current data
+    fn value(&self, field: &Field, key: usize, data: &Data) -> DBResult<Literal<'l>> {
+        let (left, op, right) = match self {
+            Self::Literal(literal) => return Ok(literal.clone()),
+            Self::Computed(left, op, right) => (left, op, right),
+        };
+        let left = left.read_row(key, data);
+        let right = match right {
+            Ok(field) => field.read_row(key, data),
+            Err(literal) => literal.clone(),
+        };
+        if matches!(left, Literal::Null) || matches!(right, Literal::Null) {
+            return coerce(field, Literal::Null);
+        }
+        match op.apply(left, right) {
+            Literal::Null => Err(DBError::Overflow(field.name.str().to_owned())),
+            result => Ok(result),
+        }
+    }
+}
+
+#[inline]
+fn reject_generated(field: &Field) -> DBResult<()> {
+    match field.generated {
+        Some(_) => Err(DBError::GeneratedField(field.name.str().to_owned())),
+        None => Ok(()),
+    }
+}
+
+/// Returns the entries without the repeated ones, keeping the first of each.
+/// Skip and limit are applied to the remaining entries
+fn distinct_entries<'a>(
+    entries: &EntryVector<Literal<'a>>,
+    skip: Option<usize>,
+    limit: Option<usize>,
+) -> EntryVector<Literal<'a>> {
+    let mut seen = BTreeSet::new();
+    let mut distinct = EntryVector::new(entries.entry_size());
+    entries
+        .iter()
+        .filter(|&entry| seen.insert(entry))
+        .skip(skip.unwrap_or(0))
+        .take(limit.unwrap_or(usize::MAX))
+        .for_each(|entry| distinct.push(entry.iter().cloned()));
+    distinct
+}
+
+/// Counts the rows of the cursor in each group, returning an entry with the columns for each one.
+/// Without a grouping column every row is in the same group.
+/// Groups are returned in the order of their value unless they're sorted in descending order
+fn select_groups<'b>(
+    table: &'b Table,
+    cursor: &FilteringCursor<'b>,
+    columns: &[SelectColumn],
+    group_by: Option<&Identifier>,
+    order_by: Option<OrderBy>,
+    skip: Option<usize>,
+    limit: Option<usize>,
+) -> DBResult<EntryVector<Literal<'b>>> {
+    let metadata = &table.metadata.metadata;
+    let grouped = |column: &Identifier| group_by.is_some_and(|group| **group == **column);
+    let ungrouped = columns
+        .iter()
+        .filter_map(|c| c.column.field())
+        .chain(order_by.map(|o| o.column))
+        .find(|column| !grouped(column));
+    if let Some(column) = ungrouped {
+        return Err(DBError::NotGrouped(column.to_string()));
+    }
+    let group_field =
+        group_by.map(|column| *metadata.field(column).expect("Columns are validated"));
+
+    let mut groups = BTreeMap::new();
+    if group_field.is_none() {
+        // Counting without groups returns a row even if nothing matches
+        groups.insert(Literal::Null, 0usize);
+    }
+    for row in cursor.iter() {
+        let (id, data) = row?;
+        let key = group_field.map_or(Literal::Null, |f| f.read_row(id, data));
+        *groups.entry(key).or_default() += 1;
+    }
+
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    if order_by.is_some_and(|o| o.descending) {
+        groups.reverse();
+    }
+    let mut entries = EntryVector::new(columns.len());
+    groups
+        .into_iter()
+        .skip(skip.unwrap_or(0))
+        .take(limit.unwrap_or(usize::MAX))
+        .for_each(|(key, count)| {
+            entries.push(columns.iter().map(|c| match c.column {
+                Column::Field(_) => key.clone(),
+                Column::Count => Literal::Uint(count),
+            }))
+        });
+    Ok(entries)
+}
+
+/// Inserts a single row, filling the missing fields with their default value or NULL.
+/// If replace is set, a row with the same key is overwritten instead of failing.
+/// Returns the key if it was assigned because the row omitted it
+fn insert_row(
+    table: &mut Table,
+    indexes: &mut [SecondaryIndex],
+    values: &[(&Identifier, Literal)],
+    replace: bool,
+) -> DBResult<Option<usize>> {
+    let fields: Vec<_> = values
+        .iter()
+        .map(|(i, l)| {
+            let f = table
+                .metadata
+                .metadata
+                .field(i)
+                .expect("Columns are validated");
+            (f, l)
+        })
+        .collect();
+    let mut value = vec![0u8; table.entry_size.size];
+    let data = Data::new_mut(&mut value);
+    let metadata = &table.metadata.metadata;
+    for f in metadata.data_fields().filter(|f| f.generated.is_none()) {
+        if fields
+            .iter()
+            .any(|(field, _)| field.name.str() == f.name.str())
+        {
+            continue;
+        }
+        if let Some(default) = metadata.default_value(f) {
+            f.write(&default, data)?;
+        } else if f.nullable {
+            f.write(&Literal::Null, data)?;
+        } else {
+            return Err(DBError::MissingValue(f.name.str().to_owned()));
+        }
+    }
+
+    let mut id = None;
+    let mut key_parts = 0;
+    for &(f, l) in &fields {
+        reject_generated(f)?;
+        let l = coerce(f, l.clone())?;
+        if f.primary {
+            let part = f.encode_key(&l);
+            let part = part.ok_or_else(|| DBError::InvalidValue(f.name.str().to_owned()))?;
+            id = Some(id.unwrap_or(0) | part);
+            key_parts += 1;
+        } else {
+            f.write(&l, data)?;
+        }
+    }
+    // Every field of a composite key has to be given, only single keys can be autoincremented
+    if (id.is_none() && !table.autoincrement())
+        || (id.is_some() && key_parts < metadata.key_fields().count())
+    {
+        let missing = metadata
+            .key_fields()
+            .find(|k| !fields.iter().any(|(f, _)| f.name.str() == k.name.str()))
+            .unwrap_or(metadata.primary());
+        return Err(DBError::MissingValue(missing.name.str().to_owned()));
+    }
+
+    let (key, assigned) = match id {
+        Some(key) if replace => {
+            if let Some(old) = table.get(key)? {
+                for index in indexes.iter_mut() {
+                    index.remove(&index.field.read(old), key)?;
+                }
+            }
+            table.upsert(key, &value)?;
+            (key, None)
+        }
+        _ => {
+            let key = table.insert_returning_key(id, &value)?;
+            (key, id.is_none().then_some(key))
+        }
+    };
+    let data = Data::new_ref(&value);
+    for index in indexes.iter_mut() {
+        index.insert(&index.field.read(data), key)?;
+    }
+    Ok(assigned)
+}
+
 impl<'a> DB<'a> {
     pub fn new(dir: &'a Path) -> Self {
         Self {
             dir,
             tables: HashMap::new(),
+            indexes: HashMap::new(),
+            sort_memory_limit: DEFAULT_SORT_MEMORY_LIMIT,
         }
     }
 
+    /// Sets how many keys can be sorted in memory before ORDER BY spills to disk
+    pub fn set_sort_memory_limit(&mut self, keys: usize) {
+        self.sort_memory_limit = keys;
+    }
+
     pub fn table(&mut self, name: &str) -> DBResult<&mut Table> {
         if !self.tables.contains_key(name) {
             let (data, metadata) = table_paths(name);
@@ -79,132 +591,614 @@ impl<'a> DB<'a> {
             let mut open_options = OpenOptions::new();
             open_options.read(true).write(true).create(false);
 
-            let data_file = open_options
-                .clone()
-                .open(data_path)
-                .expect("Failed to open table's data");
-            let metadata_file = open_options
-                .open(metadata_path)
-                .expect("Failed to open table's metadata");
-            let new_table = Table::open(data_file, metadata_file).expect("Failed to create table");
+            // Corrupt or incompatible files are reported as DBError::Io
+            let data_file = open_options.clone().open(data_path)?;
+            let metadata_file = open_options.open(metadata_path)?;
+            let new_table = Table::open(data_file, metadata_file)?;
+            let indexes = new_table
+                .metadata
+                .metadata
+                .data_fields()
+                .filter(|f| f.indexed)
+                .map(|&field| {
+                    let (data, metadata) = index_paths(name, field.name.str());
+                    let data_file = open_options.clone().open(self.dir.join(data))?;
+                    let metadata_file = open_options.open(self.dir.join(metadata))?;
+                    SecondaryIndex::open(data_file, metadata_file, field)
+                })
+                .collect::<io::Result<Vec<_>>>()?;
             self.tables.insert(name.to_owned(), new_table);
+            self.indexes.insert(name.to_owned(), indexes);
         }
         Ok(self.tables.get_mut(name).unwrap())
     }
 
-    pub fn create_table(
+    /// Like DB::table, but also returns the secondary indexes of the table
+    fn table_with_indexes(
         &mut self,
         name: &str,
-        primary_field: (&str, Type),
-        fields: &[(&str, Type)],
-    ) -> io::Result<()> {
-        if self.tables.contains_key(name) {
-            return Err(io::Error::other("Table already exists"));
+    ) -> DBResult<(&mut Table, &mut Vec<SecondaryIndex>)> {
+        self.table(name)?;
+        let table = self.tables.get_mut(name).unwrap();
+        let indexes = self.indexes.entry(name.to_owned()).or_default();
+        Ok((table, indexes))
+    }
+
+    /// Creates a secondary index over the column, which adds every row already in the table to it.
+    /// Selects and updates whose WHERE requires a value for the column look it up in the index
+    pub fn create_index(&mut self, name: &str, column: &str) -> DBResult<()> {
+        let dir = self.dir;
+        let (table, indexes) = self.table_with_indexes(name)?;
+        let Some(field) = table.metadata.metadata.field(column).copied() else {
+            return Err(DBError::UnknownColumn(column.to_owned()));
+        };
+        reject_generated(&field)?;
+        // The primary key is already the key of the table
+        if field.primary || field.indexed {
+            return Err(DBError::IndexExists(column.to_owned()));
         }
 
+        let (data, metadata_path) = index_paths(name, column);
+        let mut open_options = OpenOptions::new();
+        open_options.read(true).write(true).create_new(true);
+        let data_file = open_options.clone().open(dir.join(data))?;
+        let metadata_file = open_options.open(dir.join(metadata_path))?;
+        let mut index = SecondaryIndex::create(data_file, metadata_file, field)?;
+        for row in table.rows() {
+            let (key, data) = row?;
+            index.insert(&field.read(data), key)?;
+        }
+
+        let metadata = &mut table.metadata.metadata;
+        let num_fields = metadata.num_fields;
+        metadata.fields[..num_fields]
+            .iter_mut()
+            .find(|f| f.name.str() == column)
+            .expect("The field was found before")
+            .indexed = true;
+        index.field.indexed = true;
+        indexes.push(index);
+        Ok(())
+    }
+
+    pub fn create_table<'f, F>(
+        &mut self,
+        name: &str,
+        primary_field: (&str, Type),
+        fields: &[F],
+    ) -> DBResult<()>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
+        self.create_table_with_key(name, &[primary_field], fields)
+    }
+
+    /// Like DB::create_table, but the key of the table is made of the primary fields, see
+    /// Table::create_with_options. Rows are inserted and found by the values of all of them, and
+    /// WHERE can also match on the leading ones
+    pub fn create_table_with_key<'f, F>(
+        &mut self,
+        name: &str,
+        primary_fields: &[(&str, Type)],
+        fields: &[F],
+    ) -> DBResult<()>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
         let (data, metadata) = table_paths(name);
         let data_path = self.dir.join(data);
         let metadata_path = self.dir.join(metadata);
+        if self.tables.contains_key(name) || data_path.exists() || metadata_path.exists() {
+            return Err(DBError::TableAlreadyExists);
+        }
+        // Checked before creating the files, so an invalid table doesn't leave them behind
+        Metadata::check_key_definitions(primary_fields, fields)?;
         let mut open_options = OpenOptions::new();
         open_options.read(true).write(true).create_new(true);
 
         let data_file = open_options.clone().open(data_path)?;
         let metadata_file = open_options.open(metadata_path)?;
-        let table = Table::create(data_file, metadata_file, primary_field, fields)?;
+        let table = Table::create_with_options(
+            data_file,
+            metadata_file,
+            primary_fields,
+            fields,
+            TableOptions::new(),
+        )?;
         self.tables.insert(name.to_owned(), table);
         Ok(())
     }
 
+    /// Closes the table and removes its files
+    pub fn drop_table(&mut self, name: &str) -> DBResult<()> {
+        let (data, metadata) = table_paths(name);
+        let data_path = self.dir.join(data);
+        let metadata_path = self.dir.join(metadata);
+        if !self.tables.contains_key(name) && (!data_path.exists() || !metadata_path.exists()) {
+            return Err(DBError::TableNotExists);
+        }
+
+        let indexed: Vec<_> = self
+            .table(name)?
+            .metadata
+            .metadata
+            .data_fields()
+            .filter(|f| f.indexed)
+            .map(|f| f.name.str().to_owned())
+            .collect();
+
+        // Dropping the table flushes it, so the files have to be removed afterwards
+        drop(self.tables.remove(name));
+        drop(self.indexes.remove(name));
+        fs::remove_file(data_path)?;
+        fs::remove_file(metadata_path)?;
+        for column in indexed {
+            let (data, metadata) = index_paths(name, &column);
+            fs::remove_file(self.dir.join(data))?;
+            fs::remove_file(self.dir.join(metadata))?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the data and metadata of every open table and its indexes to disk.
+    /// Tables are flushed in name order and the first error is returned
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        let mut tables: Vec<_> = self.tables.iter_mut().collect();
+        tables.sort_by_key(|(name, _)| *name);
+        for (name, table) in tables {
+            table.flush()?;
+            for index in self.indexes.get_mut(name).into_iter().flatten() {
+                index.tree.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Executes the statements in order, returning the result of each one.
+    /// Entries borrow from the tables, so only the last statement can be a select
+    pub fn execute_all<'b>(
+        &'b mut self,
+        mut statements: Vec<Statement<'b>>,
+    ) -> DBResult<Vec<OperationResult<'b>>> {
+        let Some(last) = statements.pop() else {
+            return Ok(vec![]);
+        };
+        if statements
+            .iter()
+            .any(|s| matches!(s.operation, Operation::Select { .. }))
+        {
+            return Err(DBError::SelectNotLast);
+        }
+
+        let mut results = Vec::with_capacity(statements.len() + 1);
+        for statement in statements {
+            let result = match self.execute(statement)? {
+                OperationResult::Ok => OperationResult::Ok,
+                OperationResult::Count(count) => OperationResult::Count(count),
+                OperationResult::Inserted(key) => OperationResult::Inserted(key),
+                OperationResult::Entries(_) => unreachable!("Selects are only allowed last"),
+            };
+            results.push(result);
+        }
+        results.push(self.execute(last)?);
+        Ok(results)
+    }
+
     pub fn execute<'b>(&'b mut self, statement: Statement<'b>) -> DBResult<OperationResult<'b>> {
-        let operation = statement.operation;
-        let table_id = operation.table();
-        let table = self.table(table_id)?;
-        match operation {
-            Operation::Select { columns, .. } => {
-                // TODO: Make sure that these fields exists when parsing
-                let fields: Vec<_> = columns
-                    .iter()
-                    .map(|i| table.metadata.metadata.field(i).unwrap())
-                    .collect();
+        self.execute_recording(statement, &mut ExecStats::default())
+    }
 
-                let mut entries = EntryVector::<Literal>::new(fields.len());
+    /// Like DB::execute, but also returns how much work the statement needed
+    pub fn execute_with_stats<'b>(
+        &'b mut self,
+        statement: Statement<'b>,
+    ) -> DBResult<(OperationResult<'b>, ExecStats)> {
+        let mut stats = ExecStats::default();
+        let result = self.execute_recording(statement, &mut stats)?;
+        Ok((result, stats))
+    }
 
-                let cursor = FilteringCursor::from_options(
+    /// Like DB::execute for a select, but the rows are read from the table as the iterator is
+    /// advanced instead of being collected first, so a big result that is only partly consumed
+    /// only reads the rows that were returned.
+    /// The iterator owns the cursor over the table and borrows the table, so the DB stays
+    /// borrowed until it's dropped. Selects that sort, group or remove repeated rows need every
+    /// row before returning the first one, so their rows are collected with DB::execute
+    pub fn execute_iter<'b>(&'b mut self, mut statement: Statement<'b>) -> DBResult<Rows<'b>> {
+        let Operation::Select {
+            table: table_id,
+            ref columns,
+            distinct,
+        } = statement.operation
+        else {
+            return Err(DBError::NotSelect);
+        };
+        let buffered = distinct
+            || statement.order_by.is_some()
+            || statement.group_by.is_some()
+            || columns.iter().any(|c| c.column == Column::Count);
+        if buffered {
+            let OperationResult::Entries(result) = self.execute(statement)? else {
+                unreachable!("Selects return entries")
+            };
+            return Ok(Rows {
+                columns: result.columns,
+                source: RowSource::Buffered(result.entries.into_iter()),
+            });
+        }
+
+        let (table, indexes) = self.table_with_indexes(table_id)?;
+        let metadata = &table.metadata.metadata;
+        validate_columns(
+            metadata,
+            &statement.operation,
+            statement.wher.as_deref(),
+            None,
+            None,
+        )?;
+        if let Some(wher) = statement.wher.as_deref_mut() {
+            coerce_expression(metadata, wher)?;
+        }
+        let Operation::Select { columns, .. } = statement.operation else {
+            unreachable!("The operation was matched before")
+        };
+        let fields = columns
+            .iter()
+            .map(|column| {
+                let name = column.column.field().expect("Aggregates are buffered");
+                *metadata.field(name).expect("Columns are validated")
+            })
+            .collect();
+        let table: &'b Table = table;
+        let cursor = FilteringCursor::from_options(
+            table,
+            statement.limit,
+            statement.skip,
+            statement.wher.map(|x| *x),
+        )
+        .with_indexes(indexes)?;
+        Ok(Rows {
+            columns: columns.iter().map(SelectColumn::name).collect(),
+            source: RowSource::Stream {
+                rows: Box::new(cursor.into_iter()),
+                fields,
+            },
+        })
+    }
+
+    fn execute_recording<'b>(
+        &'b mut self,
+        mut statement: Statement<'b>,
+        stats: &mut ExecStats,
+    ) -> DBResult<OperationResult<'b>> {
+        let operation = statement.operation;
+        if let Operation::CreateTable {
+            table,
+            primary: (primary, typ),
+            fields,
+            autoincrement,
+        } = operation
+        {
+            self.create_table(table, (primary, typ), &fields)?;
+            if autoincrement {
+                self.table(table)?.set_autoincrement(true);
+            }
+            return Ok(OperationResult::Ok);
+        }
+        if let Operation::DropTable { table } = operation {
+            self.drop_table(table)?;
+            return Ok(OperationResult::Ok);
+        }
+        if let Operation::CreateIndex { table, column } = operation {
+            self.create_index(table, column)?;
+            return Ok(OperationResult::Ok);
+        }
+        let sort_memory_limit = self.sort_memory_limit;
+        let table_id = operation.table();
+        let (table, indexes) = self.table_with_indexes(table_id)?;
+        validate_columns(
+            &table.metadata.metadata,
+            &operation,
+            statement.wher.as_deref(),
+            statement.order_by.as_ref(),
+            statement.group_by,
+        )?;
+        if let Some(wher) = statement.wher.as_deref_mut() {
+            coerce_expression(&table.metadata.metadata, wher)?;
+        }
+        let page_reads = |table: &Table, indexes: &[SecondaryIndex]| {
+            let index_reads: usize = indexes
+                .iter()
+                .map(|i| i.tree.pager.stats().page_reads)
+                .sum();
+            table.pager.stats().page_reads + index_reads
+        };
+        let reads_before = page_reads(table, indexes);
+        let result = match operation {
+            Operation::Select { columns, .. }
+                if statement.group_by.is_some()
+                    || columns.iter().any(|c| c.column == Column::Count) =>
+            {
+                let cursor =
+                    FilteringCursor::from_options(table, None, None, statement.wher.map(|x| *x))
+                        .with_indexes(indexes)?;
+                let entries = select_groups(
                     table,
-                    statement.limit,
+                    &cursor,
+                    &columns,
+                    statement.group_by,
+                    statement.order_by,
                     statement.skip,
-                    statement.wher.map(|x| *x),
-                );
-
-                cursor.iter().for_each(|(id, data)| {
-                    let literals = fields.iter().map(|f| {
-                        if f.primary {
-                            Literal::Uint(id)
-                        } else {
-                            f.read(data)
-                        }
-                    });
-                    entries.push(literals);
-                });
-                Ok(OperationResult::Entries(entries))
+                    statement.limit,
+                )?;
+                cursor.record(stats);
+                Ok(OperationResult::Entries(ResultSet {
+                    entries,
+                    columns: columns.iter().map(SelectColumn::name).collect(),
+                    next_token: None,
+                }))
             }
-            Operation::Insert { values, .. } => {
-                let fields: Vec<_> = values
+            Operation::Select {
+                columns, distinct, ..
+            } => {
+                // Skip and limit apply to the distinct rows, so they're applied after removing
+                // the repeated ones
+                let (skip, limit) = if distinct {
+                    (None, None)
+                } else {
+                    (statement.skip, statement.limit)
+                };
+                let fields: Vec<_> = columns
                     .iter()
-                    .map(|(i, l)| {
-                        let f = table.metadata.metadata.field(i).unwrap();
-                        (f, l)
+                    .map(|column| {
+                        let name = column
+                            .column
+                            .field()
+                            .expect("Aggregates are selected by groups");
+                        table
+                            .metadata
+                            .metadata
+                            .field(name)
+                            .expect("Columns are validated")
                     })
                     .collect();
-                let mut value = vec![0u8; table.entry_size.size];
-                let data = Data::new_mut(&mut value);
-
-                let mut id: MaybeUninit<usize> = MaybeUninit::uninit();
-                for (f, l) in fields {
-                    if f.primary {
-                        if let Literal::Uint(n) = l {
-                            id.write(*n);
+
+                let mut entries = EntryVector::<Literal>::new(fields.len());
+                let mut push_entry = |id: usize, data: &'b Data| {
+                    let literals = fields.iter().map(|f| f.read_row(id, data));
+                    entries.push(literals);
+                };
+
+                if let Some(order_by) = statement.order_by {
+                    // Skip and limit apply to the sorted rows, so every matching row is sorted
+                    let field = *table
+                        .metadata
+                        .metadata
+                        .field(order_by.column)
+                        .expect("Columns are validated");
+                    let cursor = FilteringCursor::from_options(
+                        table,
+                        None,
+                        None,
+                        statement.wher.map(|x| *x),
+                    )
+                    .with_indexes(indexes)?;
+                    let compare = |a: &Literal, b: &Literal| {
+                        if order_by.descending {
+                            b.cmp(a)
                         } else {
-                            unimplemented!("Only uint ids are supported")
+                            a.cmp(b)
                         }
+                    };
+                    // The value is read while scanning, only the keys spilled to disk have to
+                    // be looked up again
+                    let mut sorter = ExternalSorter::new(sort_memory_limit, compare);
+                    for row in cursor.iter() {
+                        let (id, data) = row?;
+                        sorter.push(field.read_row(id, data), id)?;
+                    }
+                    let load = |id| DBResult::Ok(field.read_row(id, table.find(id)?));
+                    let sorted = sorter
+                        .finish(load)?
+                        .skip(skip.unwrap_or(0))
+                        .take(limit.unwrap_or(usize::MAX));
+                    for id in sorted {
+                        let id = id?;
+                        push_entry(id, table.find(id)?);
+                    }
+                    cursor.record(stats);
+                    if distinct {
+                        entries = distinct_entries(&entries, statement.skip, statement.limit);
+                    }
+                    Ok(OperationResult::Entries(ResultSet {
+                        entries,
+                        columns: columns.iter().map(SelectColumn::name).collect(),
+                        next_token: None,
+                    }))
+                } else {
+                    let cursor = FilteringCursor::from_options(
+                        table,
+                        limit,
+                        skip,
+                        statement.wher.map(|x| *x),
+                    );
+                    let indexes: &'b [SecondaryIndex] = indexes;
+                    let covered = cursor.covered_rows(indexes, &fields)?;
+                    let cursor = if covered.is_some() {
+                        cursor
                     } else {
-                        f.write(l, data);
+                        cursor.with_indexes(indexes)?
+                    };
+                    let mut last_id = None;
+                    match covered {
+                        Some(rows) => {
+                            for (id, value) in rows {
+                                let literals = fields.iter().map(|f| {
+                                    if f.primary {
+                                        f.decode_key(id)
+                                    } else {
+                                        value.clone()
+                                    }
+                                });
+                                entries.push(literals);
+                                last_id = Some(id);
+                            }
+                            stats.used_index = true;
+                        }
+                        None => {
+                            for row in cursor.iter() {
+                                let (id, data) = row?;
+                                push_entry(id, data);
+                                last_id = Some(id);
+                            }
+                        }
+                    }
+                    cursor.record(stats);
+                    if distinct {
+                        entries = distinct_entries(&entries, statement.skip, statement.limit);
+                        // The rows of a distinct select don't correspond to a single key
+                        last_id = None;
                     }
+                    // Tokens are values of the primary field, which don't identify a row of a
+                    // composite key
+                    let metadata = &table.metadata.metadata;
+                    let primary = *metadata.primary();
+                    let last_id = last_id.filter(|_| !metadata.is_composite_key());
+                    Ok(OperationResult::Entries(ResultSet {
+                        entries,
+                        columns: columns.iter().map(SelectColumn::name).collect(),
+                        next_token: last_id.map(|id| primary.decode_key(id)),
+                    }))
                 }
-                let id = unsafe { id.assume_init() };
-
-                table.insert(id, &value)?;
-                Ok(OperationResult::Ok)
             }
-            Operation::Update { values, .. } => {
-                let fields: Vec<_> = values
+            Operation::Insert {
+                values, replace, ..
+            } => {
+                let mut assigned = None;
+                for row in values.iter() {
+                    assigned = insert_row(table, indexes, row, replace)?;
+                }
+                match assigned {
+                    Some(key) if values.len() == 1 => Ok(OperationResult::Inserted(key)),
+                    _ => Ok(OperationResult::Count(values.len())),
+                }
+            }
+            Operation::Update {
+                values, returning, ..
+            } => {
+                let metadata = &table.metadata.metadata;
+                let fields = values
                     .iter()
-                    .map(|(i, l)| {
-                        let f = table.metadata.metadata.field(i).unwrap();
-                        (f, l)
+                    .map(|(i, value)| {
+                        let f = metadata.field(i).expect("Columns are validated");
+                        Assignment::new(metadata, f, value.clone()).map(|a| (f, a))
                     })
-                    .collect();
+                    .collect::<DBResult<Vec<_>>>()?;
 
                 let cursor = FilteringCursor::from_options(
                     table,
                     statement.limit,
                     statement.skip,
                     statement.wher.map(|x| *x),
-                );
+                )
+                .with_indexes(indexes)?;
+                let mut updated_indexes: Vec<_> = indexes
+                    .iter_mut()
+                    .filter(|index| {
+                        fields
+                            .iter()
+                            .any(|(f, _)| f.name.str() == index.field.name.str())
+                    })
+                    .collect();
 
-                let mut count = 0usize;
-                cursor.iter().for_each(|(_, data)| {
-                    for (field, literal) in fields.iter() {
-                        field.write(literal, data);
+                // The rows are written after the scan, which reads the table
+                let mut updated_rows = vec![];
+                let mut update = |(key, data): (usize, &Data)| {
+                    let mut row = data.read_all().to_vec();
+                    let updated = Data::new_mut(&mut row);
+                    for (field, assignment) in fields.iter() {
+                        // Computed values read the row before any field is updated
+                        field.write(&assignment.value(field, key, data)?, updated)?;
                     }
-                    count += 1;
-                });
-                Ok(OperationResult::Count(count))
+                    for index in updated_indexes.iter_mut() {
+                        index.remove(&index.field.read(data), key)?;
+                        index.insert(&index.field.read(updated), key)?;
+                    }
+                    updated_rows.push((key, row));
+                    DBResult::Ok(())
+                };
+                match cursor.single_key() {
+                    // Updating a single key doesn't need to seek and scan the range
+                    Some(key) => {
+                        if let Some(row) = cursor.find(key)? {
+                            update(row)?;
+                        }
+                    }
+                    None => cursor.iter().try_for_each(|row| update(row?))?,
+                }
+                cursor.record(stats);
+                let mut updated_keys = Vec::with_capacity(updated_rows.len());
+                for (key, mut row) in updated_rows {
+                    table
+                        .find_cursor(key)?
+                        .set_value(table, Data::new_mut(&mut row))?;
+                    updated_keys.push(key);
+                }
+                if returning.is_empty() {
+                    Ok(OperationResult::Count(updated_keys.len()))
+                } else {
+                    // The rows are read back from the table, so they have the written values
+                    let metadata = &table.metadata.metadata;
+                    let fields: Vec<_> = returning
+                        .iter()
+                        .map(|c| metadata.field(c).expect("Columns are validated"))
+                        .collect();
+                    let mut entries = EntryVector::new(fields.len());
+                    for key in updated_keys {
+                        let data = table.find(key)?;
+                        entries.push(fields.iter().map(|f| f.read_row(key, data)));
+                    }
+                    Ok(OperationResult::Entries(ResultSet {
+                        entries,
+                        columns: returning.iter().map(|c| &***c).collect(),
+                        next_token: None,
+                    }))
+                }
             }
             Operation::Delete { .. } => {
-                unimplemented!("Don't know how to delete entries")
+                let cursor = FilteringCursor::from_options(
+                    table,
+                    statement.limit,
+                    statement.skip,
+                    statement.wher.map(|x| *x),
+                )
+                .with_indexes(indexes)?;
+                // The rows are deleted after the scan, which can't go on while the tree changes
+                let keys: Vec<_> = match cursor.single_key() {
+                    Some(key) => cursor.find(key)?.into_iter().map(|(key, _)| key).collect(),
+                    None => cursor
+                        .iter()
+                        .map(|row| row.map(|(key, _)| key))
+                        .collect::<TableResult<_>>()?,
+                };
+                cursor.record(stats);
+                for &key in &keys {
+                    let data = table.find(key)?;
+                    for index in indexes.iter_mut() {
+                        index.remove(&index.field.read(data), key)?;
+                    }
+                    table.delete(key)?;
+                }
+                Ok(OperationResult::Count(keys.len()))
             }
-        }
+            Operation::CreateTable { .. }
+            | Operation::DropTable { .. }
+            | Operation::CreateIndex { .. } => {
+                unreachable!("Table operations are executed before this")
+            }
+        };
+        stats.pages_read = page_reads(table, indexes) - reads_before;
+        result
     }
 }
 
@@ -212,9 +1206,16 @@ pub struct FilteringCursor<'a> {
     table: &'a Table,
     limit: usize,
     skip: usize,
+    primary: Field,
     fields: Vec<Field>,
     expression: Expression<'a>,
     range: Range<Literal<'a>>,
+    /// Rows inside of the key range that were checked against the expression
+    examined: Cell<usize>,
+    /// Rows that passed the expression
+    matched: Cell<usize>,
+    /// Keys found in a secondary index, which are looked up instead of scanning the range
+    candidates: Option<Vec<usize>>,
 }
 
 impl<'a> FilteringCursor<'a> {
@@ -224,26 +1225,35 @@ impl<'a> FilteringCursor<'a> {
         skip: usize,
         mut expression: Expression<'a>,
     ) -> Self {
-        let index = table
-            .metadata
-            .metadata
-            .fields()
-            .find(|f| f.primary)
-            .expect("Primary field not found");
-        let index_name = index.name.str();
-        let range = expression.extract_index(index_name);
+        let metadata = &table.metadata.metadata;
+        // Literals are compared with the values of the fields, so they need the same type
+        expression.map_literals(&|name, literal| {
+            metadata
+                .field(name)
+                .and_then(|f| f.typ.coerce(literal.clone()))
+                .unwrap_or(literal)
+        });
+        let primary = *metadata.primary();
+        let mut range = expression.extract_index(primary.name.str());
+        // The intervals are seeked one after the other, so they have to be in key order
+        range.normalize();
+        let expression = expression.simplify();
         let field_names = expression.fields();
         let fields: Vec<_> = field_names
             .iter()
-            .map(|f| *table.metadata.metadata.field(f).unwrap())
+            .map(|f| *metadata.field(f).expect("Columns are validated"))
             .collect();
         Self {
             table,
             limit,
             skip,
+            primary,
             fields,
             expression,
             range,
+            examined: Cell::new(0),
+            matched: Cell::new(0),
+            candidates: None,
         }
     }
 
@@ -261,33 +1271,225 @@ impl<'a> FilteringCursor<'a> {
         )
     }
 
-    fn evaluate_entry(&self, index: usize, data: &Data) -> bool {
-        let mut iter = self.fields.iter().map(|f| {
-            if f.primary {
-                Literal::Uint(index)
-            } else {
-                f.read(data)
-            }
+    /// Looks up the rows in a secondary index if the primary key doesn't bound the scan and the
+    /// expression requires a value for an indexed field
+    pub fn with_indexes(mut self, indexes: &[SecondaryIndex]) -> TableResult<Self> {
+        if self.uses_index() {
+            return Ok(self);
+        }
+        let candidates = indexes.iter().find_map(|index| {
+            let value = self.expression.required_value(index.field.name.str())?;
+            Some(index.find(&value))
         });
-        self.expression.eval(&mut iter)
+        self.candidates = candidates.transpose()?;
+        Ok(self)
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (usize, &'a mut Data)> {
-        self.range
-            .iter()
-            .flat_map(|r| {
-                let cursor = match r.start() {
-                    Some(Literal::Uint(id)) => self.table.find_cursor(id),
+    /// Returns the keys and indexed values of the rows that pass the expression, after the
+    /// skipped ones and until the limit, if the primary key doesn't bound the scan and a
+    /// secondary index holds every field that is selected or read by the expression. The rows are
+    /// then never looked up in the table
+    pub fn covered_rows(
+        &self,
+        indexes: &'a [SecondaryIndex],
+        selected: &[&Field],
+    ) -> TableResult<Option<Vec<(usize, Literal<'a>)>>> {
+        if !matches!(self.range.buf[..], [SimpleRange::Full]) {
+            return Ok(None);
+        }
+        let index = indexes.iter().find(|index| {
+            let name = index.field.name.str();
+            let covered = |f: &Field| f.primary || f.name.str() == name;
+            selected.iter().all(|f| covered(f)) && self.fields.iter().all(covered)
+        });
+        let Some((index, value)) = index.and_then(|index| {
+            let value = self.expression.required_value(index.field.name.str())?;
+            Some((index, value))
+        }) else {
+            return Ok(None);
+        };
+        let mut rows = Vec::new();
+        let mut passed = 0;
+        for (key, stored) in index.entries(&value)? {
+            if passed >= self.skip.saturating_add(self.limit) {
+                break;
+            }
+            self.examined.set(self.examined.get() + 1);
+            let resolve = |name: &str| {
+                let field = self.fields.iter().find(|f| f.name.str() == name)?;
+                Some(if field.primary {
+                    field.decode_key(key)
+                } else {
+                    stored.clone()
+                })
+            };
+            let passes = self
+                .expression
+                .eval(&resolve)
+                .expect("Fields are read from the same expression");
+            if !passes {
+                continue;
+            }
+            self.matched.set(self.matched.get() + 1);
+            passed += 1;
+            if passed > self.skip {
+                rows.push((key, stored));
+            }
+        }
+        Ok(Some(rows))
+    }
+
+    /// Returns true if the scan is bounded by the primary key or a secondary index instead of
+    /// going over every row
+    pub fn uses_index(&self) -> bool {
+        self.candidates.is_some() || !matches!(self.range.buf[..], [SimpleRange::Full])
+    }
+
+    /// Adds the rows that have been scanned so far to the stats
+    fn record(&self, stats: &mut ExecStats) {
+        stats.rows_examined += self.examined.get();
+        stats.rows_matched += self.matched.get();
+        stats.used_index |= self.uses_index();
+    }
+
+    /// Returns the key if the range is a single value of the primary key. The range only covers
+    /// the first field of a composite key, the expression has to require values for the rest
+    pub fn single_key(&self) -> Option<usize> {
+        let [SimpleRange::Value(value)] = &self.range.buf[..] else {
+            return None;
+        };
+        let mut key = self.primary.encode_key(value)?;
+        for field in self.table.metadata.metadata.key_fields().skip(1) {
+            let value = self.expression.required_value(field.name.str())?;
+            key |= field.encode_key(&value)?;
+        }
+        Some(key)
+    }
+
+    /// Looks up the key directly instead of seeking, returning the row if it passes the filter.
+    /// Only correct if the key is the single key of the range
+    fn find(&self, key: usize) -> TableResult<Option<(usize, &'a Data)>> {
+        let Some(data) = self.table.get(key)? else {
+            return Ok(None);
+        };
+        self.examined.set(self.examined.get() + 1);
+        if !self.evaluate_entry(key, data) {
+            return Ok(None);
+        }
+        self.matched.set(self.matched.get() + 1);
+        Ok((self.skip == 0 && self.limit > 0).then_some((key, data)))
+    }
+
+    /// Fields are only read when the expression reaches their comparison
+    fn evaluate_entry(&self, index: usize, data: &Data) -> bool {
+        let resolve = |name: &str| {
+            let field = self.fields.iter().find(|f| f.name.str() == name)?;
+            Some(field.read_row(index, data))
+        };
+        self.expression
+            .eval(&resolve)
+            .expect("Fields are read from the same expression")
+    }
+
+    /// Returns the next row inside of the ranges, or the next candidate key of a secondary index,
+    /// without checking it against the expression
+    fn next_in_range(&self, state: &mut ScanState<'a>) -> Option<TableResult<(usize, &'a Data)>> {
+        if let Some(candidates) = &self.candidates {
+            while let Some(&key) = candidates.get(state.index) {
+                state.index += 1;
+                if let Some(row) = self.table.get(key).transpose() {
+                    return Some(row.map(|data| (key, data)));
+                }
+            }
+            return None;
+        }
+        let primary = self.primary;
+        loop {
+            let r = self.range.buf.get(state.index)?;
+            let rows = state.rows.get_or_insert_with(|| {
+                // Bounds that aren't of the key type are checked while scanning from the start
+                let cursor = match r.start().and_then(|l| primary.encode_key(&l)) {
+                    Some(key) => self.table.find_cursor(key),
                     None => self.table.min_cursor(),
-                    _ => unimplemented!("Only uint can be used as id"),
                 };
-                cursor
-                    .into_iter(self.table)
-                    .skip_while(|&(index, _)| !r.value_past_start(&index.into()))
-                    .take_while(|&(index, _)| r.value_before_end(&index.into()))
-                    .filter(|&(index, ref data)| self.evaluate_entry(index, data))
-            })
-            .skip(self.skip)
-            .take(self.limit)
+                CursorIterator::new(self.table, cursor)
+            });
+            for row in rows.by_ref() {
+                let Ok((key, _)) = row else {
+                    return Some(row);
+                };
+                let value = primary.decode_key(key);
+                if !r.value_past_start(&value) {
+                    continue;
+                }
+                if r.value_before_end(&value) {
+                    return Some(row);
+                }
+                break;
+            }
+            state.rows = None;
+            state.index += 1;
+        }
+    }
+
+    /// Returns the next row that passes the expression, after the skipped ones and until the
+    /// limit is reached
+    fn next_row(&self, state: &mut ScanState<'a>) -> Option<TableResult<(usize, &'a Data)>> {
+        while state.passed < self.skip.saturating_add(self.limit) {
+            let (key, data) = match self.next_in_range(state)? {
+                Ok(row) => row,
+                Err(e) => return Some(Err(e)),
+            };
+            self.examined.set(self.examined.get() + 1);
+            if !self.evaluate_entry(key, data) {
+                continue;
+            }
+            self.matched.set(self.matched.get() + 1);
+            state.passed += 1;
+            if state.passed > self.skip {
+                return Some(Ok((key, data)));
+            }
+        }
+        None
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = TableResult<(usize, &'a Data)>> {
+        let mut state = ScanState::default();
+        std::iter::from_fn(move || self.next_row(&mut state))
+    }
+}
+
+/// Position of a scan over the rows of a FilteringCursor
+#[derive(Default)]
+struct ScanState<'a> {
+    /// Index of the range, or of the candidate key, that is being scanned
+    index: usize,
+    /// Rows of the range that is being scanned
+    rows: Option<CursorIterator<'a>>,
+    /// Rows that passed the expression so far, including the skipped ones
+    passed: usize,
+}
+
+/// Iterator that owns its FilteringCursor, so it can outlive the function that created it
+pub struct FilteringIter<'a> {
+    cursor: FilteringCursor<'a>,
+    state: ScanState<'a>,
+}
+
+impl<'a> Iterator for FilteringIter<'a> {
+    type Item = TableResult<(usize, &'a Data)>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_row(&mut self.state)
+    }
+}
+
+impl<'a> IntoIterator for FilteringCursor<'a> {
+    type Item = TableResult<(usize, &'a Data)>;
+    type IntoIter = FilteringIter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        FilteringIter {
+            cursor: self,
+            state: ScanState::default(),
+        }
     }
 }
@@ -2,12 +2,15 @@ use crate::utils::range::Range;
 use crate::utils::range::SimpleRange;
 use crate::{range, simple_range};
 use std::cmp::Ordering;
+use std::convert::Infallible;
 
 use crate::query::{Identifier, Literal};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Comparison {
     Equals,
+    /// Equality where NULL is equal to NULL
+    NullSafeEquals,
     NotEquals,
     LessThanEquals,
     LessThan,
@@ -18,7 +21,7 @@ pub enum Comparison {
 impl Comparison {
     fn pass_filter(&self, ord: Ordering) -> bool {
         match self {
-            Self::Equals => matches!(ord, Ordering::Equal),
+            Self::Equals | Self::NullSafeEquals => matches!(ord, Ordering::Equal),
             Self::NotEquals => !matches!(ord, Ordering::Equal),
             Self::LessThanEquals => matches!(ord, Ordering::Equal | Ordering::Less),
             Self::LessThan => matches!(ord, Ordering::Less),
@@ -26,14 +29,61 @@ impl Comparison {
             Self::MoreThan => matches!(ord, Ordering::Greater),
         }
     }
+    /// Comparisons against NULL are always false, except for NullSafeEquals
     pub fn eval(&self, left: &Literal, right: &Literal) -> bool {
-        let ordering = left
-            .partial_cmp(right)
-            .expect("The two expressions should have the same type");
-        self.pass_filter(ordering)
+        match (left, right) {
+            (Literal::Null, Literal::Null) => return matches!(self, Self::NullSafeEquals),
+            (Literal::Null, _) | (_, Literal::Null) => return false,
+            _ => {}
+        }
+        self.pass_filter(left.cmp(right))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Arithmetic {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Arithmetic {
+    /// Applies the operation to two numbers of the same type.
+    /// Returns NULL if they aren't, or if the result overflows or divides by zero
+    pub fn apply(&self, left: Literal, right: Literal) -> Literal<'static> {
+        match (left, right) {
+            (Literal::Int(l), Literal::Int(r)) => match self {
+                Self::Add => l.checked_add(r),
+                Self::Subtract => l.checked_sub(r),
+                Self::Multiply => l.checked_mul(r),
+                Self::Divide => l.checked_div(r),
+            }
+            .map_or(Literal::Null, Literal::Int),
+            (Literal::Uint(l), Literal::Uint(r)) => match self {
+                Self::Add => l.checked_add(r),
+                Self::Subtract => l.checked_sub(r),
+                Self::Multiply => l.checked_mul(r),
+                Self::Divide => l.checked_div(r),
+            }
+            .map_or(Literal::Null, Literal::Uint),
+            (Literal::Float(l), Literal::Float(r)) => Literal::Float(match self {
+                Self::Add => l + r,
+                Self::Subtract => l - r,
+                Self::Multiply => l * r,
+                Self::Divide => l / r,
+            }),
+            _ => Literal::Null,
+        }
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum EvalError {
+    /// The iterator ran out of values before every field was compared
+    MissingField,
+}
+
 pub type BoxedExpression<'a> = Box<Expression<'a>>;
 #[derive(Clone, PartialEq, Debug)]
 pub enum Expression<'a> {
@@ -44,9 +94,55 @@ pub enum Expression<'a> {
         right: Literal<'a>,
         sym: Comparison,
     },
+    /// Matches strings against a pattern where % matches any run of characters and _ matches
+    /// a single one
+    Like {
+        left: &'a Identifier,
+        pattern: &'a str,
+    },
+    /// field IS NULL, or IS NOT NULL when negated
+    IsNull {
+        left: &'a Identifier,
+        negated: bool,
+    },
     Empty,
 }
 
+/// Returns true if the whole text matches the LIKE pattern
+pub fn like_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last % in the pattern and of the text when it was reached
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some('_') => {
+                p += 1;
+                t += 1;
+            }
+            Some(&c) if c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            // Let the last % match one more character and try again
+            _ => match backtrack {
+                Some((last_p, last_t)) => {
+                    backtrack = Some((last_p, last_t + 1));
+                    p = last_p + 1;
+                    t = last_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '%')
+}
+
 impl<'a> Expression<'a> {
     pub fn binary(
         left: impl Into<&'a Identifier>,
@@ -70,7 +166,9 @@ impl<'a> Expression<'a> {
                 l.field_recursive(v);
                 r.field_recursive(v);
             }
-            &Self::Binary { left, .. } => v.push(left),
+            &Self::Binary { left, .. } | &Self::Like { left, .. } | &Self::IsNull { left, .. } => {
+                v.push(left)
+            }
             Self::Empty => {}
         }
     }
@@ -83,19 +181,86 @@ impl<'a> Expression<'a> {
         v
     }
 
-    // TODO: Optimize this to not have to read the same fields a lot of times
-    /// This function uses an iterator of Literals that should come from the fields in self.fields
-    /// to evaluate an expression
+    /// Replaces the literal of every comparison with the result of the function, which receives
+    /// the field that is compared and the literal
+    pub fn map_literals(&mut self, f: &impl Fn(&Identifier, Literal<'a>) -> Literal<'a>) {
+        let result: Result<(), Infallible> =
+            self.try_map_literals(&|field, literal| Ok(f(field, literal)));
+        let Ok(()) = result;
+    }
+
+    /// Like Expression::map_literals, but stops at the first error of the function.
+    /// The literals before the failing one are left replaced
+    pub fn try_map_literals<E>(
+        &mut self,
+        f: &impl Fn(&Identifier, Literal<'a>) -> Result<Literal<'a>, E>,
+    ) -> Result<(), E> {
+        match self {
+            Self::And(l, r) | Self::Or(l, r) => {
+                l.try_map_literals(f)?;
+                r.try_map_literals(f)
+            }
+            Self::Binary { left, right, .. } => {
+                *right = f(left, right.clone())?;
+                Ok(())
+            }
+            Self::Like { .. } | Self::IsNull { .. } | Self::Empty => Ok(()),
+        }
+    }
+
+    /// Removes the Empty nodes left by Self::extract_index, which are always true.
+    /// And drops the Empty side, while Or becomes Empty if either side is
+    pub fn simplify(self) -> Self {
+        match self {
+            Self::And(l, r) => match (l.simplify(), r.simplify()) {
+                (Self::Empty, x) | (x, Self::Empty) => x,
+                (l, r) => Self::And(Box::new(l), Box::new(r)),
+            },
+            Self::Or(l, r) => match (l.simplify(), r.simplify()) {
+                (Self::Empty, _) | (_, Self::Empty) => Self::Empty,
+                (l, r) => Self::Or(Box::new(l), Box::new(r)),
+            },
+            x => x,
+        }
+    }
+
+    /// Returns a value that the field has to be equal to for the expression to be true.
+    /// Only the comparisons joined by And at the top of the expression are checked
+    pub fn required_value(&self, field: &str) -> Option<Literal<'a>> {
+        match self {
+            Self::And(l, r) => l.required_value(field).or_else(|| r.required_value(field)),
+            Self::Binary {
+                left,
+                right,
+                sym: Comparison::Equals,
+            } if &(***left) == field => Some(right.clone()),
+            _ => None,
+        }
+    }
+
+    /// Evaluates the expression reading the value of each compared field with the resolver,
+    /// which returns None if it doesn't know the field.
+    /// And and Or stop after their left side if it decides the result, so the fields of the
+    /// right side aren't read.
     /// Self::extract_index should be used before to get index constraints instead of filtering
-    pub fn eval(&self, iter: &mut impl Iterator<Item = Literal<'a>>) -> bool {
+    pub fn eval(&self, resolve: &dyn Fn(&str) -> Option<Literal<'a>>) -> Result<bool, EvalError> {
         match self {
-            Self::And(l, r) => l.eval(iter) && r.eval(iter),
-            Self::Or(l, r) => l.eval(iter) || r.eval(iter),
-            Self::Binary { right, sym, .. } => {
-                let left = iter.next().expect("Ran out of fields in the iterator");
-                sym.eval(&left, right)
+            Self::And(l, r) => Ok(l.eval(resolve)? && r.eval(resolve)?),
+            Self::Or(l, r) => Ok(l.eval(resolve)? || r.eval(resolve)?),
+            Self::Binary { left, right, sym } => {
+                let left = resolve(left).ok_or(EvalError::MissingField)?;
+                Ok(sym.eval(&left, right))
+            }
+            Self::Like { left, pattern } => {
+                let left = resolve(left).ok_or(EvalError::MissingField)?;
+                Ok(matches!(left, Literal::String(s) if like_matches(pattern, &s)))
+            }
+            // The primary key is never NULL, so its check is constant
+            Self::IsNull { left, negated } => {
+                let left = resolve(left).ok_or(EvalError::MissingField)?;
+                Ok(matches!(left, Literal::Null) != *negated)
             }
-            Self::Empty => true,
+            Self::Empty => Ok(true),
         }
     }
 
@@ -120,14 +285,22 @@ impl<'a> Expression<'a> {
             }
             Expression::Binary { left, right, sym } => {
                 if &(***left) == index_name {
-                    let r = Range::from_comparison(*sym, *right);
+                    // The key is never NULL, so comparing it with NULL is always false
+                    let r = match right {
+                        Literal::Null => range!({}),
+                        _ => Range::from_comparison(*sym, right.clone()),
+                    };
                     *self = Expression::Empty;
                     r
                 } else {
                     range!({,})
                 }
             }
-            Expression::Empty => range!({}),
+            // Keys are integers, so a string prefix doesn't bound them and LIKE only filters
+            // NULL isn't ordered with the other values, so it can't be looked up in a range
+            Expression::Like { .. } | Expression::IsNull { .. } | Expression::Empty => {
+                range!({,})
+            }
         }
     }
 }
@@ -158,6 +331,9 @@ macro_rules! expression {
     ($x:tt > $y:tt) => {
         Expression::Binary { left: $x.into(), right: $y.into(), sym: Comparison::MoreThan}
     };
+    ($x:tt <=> $y:tt) => {
+        Expression::Binary { left: $x.into(), right: $y.into(), sym: Comparison::NullSafeEquals}
+    };
     ($x:tt <= $y:tt) => {
         Expression::Binary { left: $x.into(), right: $y.into(), sym: Comparison::LessThanEquals}
     };
@@ -171,12 +347,21 @@ macro_rules! expression {
 
 #[cfg(test)]
 mod tests {
-    use crate::expression;
     use crate::utils::range::IntervalEnd;
     use crate::utils::range::IntervalStart;
 
     use super::*;
 
+    /// Evaluates the expression giving the values to its fields in the order of
+    /// Expression::fields
+    fn eval_values<'a>(expr: &Expression<'a>, values: &[Literal<'a>]) -> Result<bool, EvalError> {
+        let fields = expr.fields();
+        expr.eval(&|name| {
+            let position = fields.iter().position(|&f| f == name)?;
+            values.get(position).cloned()
+        })
+    }
+
     #[test]
     fn test_fields() {
         let expr = expression!(("id" < 5usize) & ("test" > 10usize));
@@ -188,7 +373,7 @@ mod tests {
     fn test_true_expression() {
         let expr = expression!(("id" < 5usize) & ("test" > 10usize));
         let iter = [Literal::Uint(1), Literal::Uint(20)];
-        let res = expr.eval(&mut iter.iter().copied());
+        let res = eval_values(&expr, &iter).unwrap();
         assert!(res, "This expression should return true")
     }
 
@@ -196,10 +381,146 @@ mod tests {
     fn test_false_expression() {
         let expr = expression!(("id" < 5usize) & ("test" > 10usize));
         let iter = [Literal::Uint(9), Literal::Uint(10)];
-        let res = expr.eval(&mut iter.iter().copied());
+        let res = eval_values(&expr, &iter).unwrap();
         assert!(!res, "This expression should return false")
     }
 
+    #[test]
+    fn test_null_comparison() {
+        let expr = expression!(("test" != 10usize));
+        let iter = [Literal::Null];
+        let res = eval_values(&expr, &iter).unwrap();
+        assert!(!res, "Comparing with NULL should return false");
+
+        let null = Literal::Null;
+        let expr = expression!(("test" = null));
+        let iter = [Literal::Null];
+        let res = eval_values(&expr, &iter).unwrap();
+        assert!(!res, "NULL shouldn't be equal to NULL");
+    }
+
+    #[test]
+    fn test_null_safe_equals() {
+        let null = Literal::Null;
+        let expr = expression!(("test" <=> null));
+        assert!(
+            eval_values(&expr, &[Literal::Null]).unwrap(),
+            "NULL <=> NULL"
+        );
+        assert!(
+            !eval_values(&expr, &[Literal::Uint(1)]).unwrap(),
+            "1 <=> NULL"
+        );
+
+        let expr = expression!(("test" <=> 5usize));
+        assert!(!eval_values(&expr, &[Literal::Null]).unwrap(), "NULL <=> 5");
+        assert!(eval_values(&expr, &[Literal::Uint(5)]).unwrap(), "5 <=> 5");
+        assert!(!eval_values(&expr, &[Literal::Uint(4)]).unwrap(), "4 <=> 5");
+    }
+
+    #[test]
+    fn test_nan_comparison() {
+        let nan = Literal::Float(f64::NAN);
+        let one = Literal::Float(1.0);
+        assert!(Comparison::MoreThan.eval(&nan, &one));
+        assert!(Comparison::LessThan.eval(&one, &nan));
+        assert!(Comparison::Equals.eval(&nan, &nan));
+        assert!(!Comparison::Equals.eval(&nan, &Literal::Float(f64::INFINITY)));
+
+        let expr = expression!(("score" < 2.5f64));
+        assert!(!eval_values(&expr, &[nan]).unwrap());
+        assert!(eval_values(&expr, &[one]).unwrap());
+    }
+
+    #[test]
+    fn test_missing_field() {
+        let expr = expression!(("id" < 5usize) & ("test" > 10usize));
+        let iter = [Literal::Uint(1)];
+        let res = eval_values(&expr, &iter);
+        assert_eq!(res, Err(EvalError::MissingField));
+    }
+
+    #[test]
+    fn test_short_circuit_reads() {
+        let reads = std::cell::Cell::new(0);
+        let resolve = |name: &str| {
+            reads.set(reads.get() + 1);
+            match name {
+                "a" => Some(Literal::Uint(0)),
+                _ => Some(Literal::Uint(1)),
+            }
+        };
+
+        let expr = expression!(("a" = 1usize) & ("b" = 1usize));
+        assert!(!expr.eval(&resolve).unwrap());
+        assert_eq!(reads.get(), 1, "b shouldn't be read once a fails");
+
+        reads.set(0);
+        let expr = expression!(("b" = 1usize) | ("a" = 1usize));
+        assert!(expr.eval(&resolve).unwrap());
+        assert_eq!(reads.get(), 1, "a shouldn't be read once b passes");
+
+        reads.set(0);
+        let expr = expression!(("b" = 1usize) & ("a" = 1usize));
+        assert!(!expr.eval(&resolve).unwrap());
+        assert_eq!(reads.get(), 2);
+    }
+
+    #[test]
+    fn test_nested_expression_fields() {
+        let expr = expression!((("a" = 1usize) & ("b" = 2usize)) | ("c" = 3usize));
+        let iter = [Literal::Uint(0), Literal::Uint(3), Literal::Uint(9)];
+        let res = eval_values(&expr, &iter).unwrap();
+        assert!(!res, "c should be compared against its own value");
+    }
+
+    #[test]
+    fn test_like() {
+        let cases = [
+            ("foo%", "foobar", true),
+            ("foo%", "foo", true),
+            ("foo%", "afoo", false),
+            ("%bar", "foobar", true),
+            ("%bar", "foobars", false),
+            ("f%r", "foobar", true),
+            ("f%o%r", "foobar", true),
+            ("f%x%r", "foobar", false),
+            ("fo_bar", "foobar", true),
+            ("fo_bar", "fobar", false),
+            ("%o_a%", "foobar", true),
+            ("%", "", true),
+            ("_", "", false),
+            ("ñ_", "ñá", true),
+        ];
+        for (pattern, text, expected) in cases {
+            assert_eq!(
+                like_matches(pattern, text),
+                expected,
+                "{text} LIKE {pattern}"
+            );
+        }
+
+        let expr = Expression::Like {
+            left: "name".into(),
+            pattern: "foo%",
+        };
+        assert!(eval_values(&expr, &[Literal::from("food")]).unwrap());
+        assert!(!eval_values(&expr, &[Literal::Null]).unwrap());
+        assert!(!eval_values(&expr, &[Literal::Uint(1)]).unwrap());
+    }
+
+    #[test]
+    fn test_extracting_like() {
+        for pattern in ["foo%", "%", "f%o"] {
+            let mut expr = Expression::Like {
+                left: "name".into(),
+                pattern,
+            };
+            assert_eq!(expr.extract_index("name").buf, vec![simple_range!({,})]);
+            assert!(matches!(expr, Expression::Like { .. }));
+        }
+    }
+
     #[test]
     fn test_extracting_index() {
         let index = "id";
@@ -219,5 +540,42 @@ mod tests {
             expr,
             expression!(({} & (field = 5usize)) | ({} & (field = 10usize)))
         );
+        assert_eq!(
+            expr.simplify(),
+            expression!((field = 5usize) | (field = 10usize))
+        );
+
+        for mut expr in [
+            expression!(index > (Literal::Null)),
+            expression!(index != (Literal::Null)),
+            expression!(index < (Literal::Null)),
+            expression!(index <=> (Literal::Null)),
+        ] {
+            assert!(expr.extract_index(index).is_empty(), "{expr:?}");
+            assert_eq!(expr, Expression::Empty);
+        }
+    }
+
+    #[test]
+    fn test_simplify() {
+        let field = "test";
+        for (expr, expected) in [
+            (expression!({} & {}), expression!({})),
+            (
+                expression!((field = 5usize) & {}),
+                expression!(field = 5usize),
+            ),
+            (expression!({} | (field = 5usize)), expression!({})),
+            (
+                expression!(((field = 5usize) | ({} & {})) & (field < 10usize)),
+                expression!(field < 10usize),
+            ),
+            (
+                expression!((field = 5usize) & (field < 10usize)),
+                expression!((field = 5usize) & (field < 10usize)),
+            ),
+        ] {
+            assert_eq!(expr.clone().simplify(), expected, "{expr:?}");
+        }
     }
 }
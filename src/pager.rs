@@ -1,16 +1,26 @@
-use std::cell::{RefCell, UnsafeCell};
+use std::borrow::Cow;
+use std::cell::UnsafeCell;
 use std::fmt::Debug;
 use std::fs;
 use std::io::Seek;
 use std::marker::PhantomData;
-use std::os::unix::fs::FileExt;
+use std::sync::{Mutex, RwLock};
 use std::{io, iter, ptr};
 
+mod compression;
+mod encryption;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+
+use crate::pager::compression::PageMap;
+use crate::pager::encryption::PageCipher;
 use crate::table::internal::InternalNodeHeader;
 use crate::table::leaf::LeafNodeHeader;
 use crate::table::node::{Node, NodeMut, NodeType};
+use crate::table::wal::LoggedPage;
+use crate::utils::file_io;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct PageNum(pub usize);
 
@@ -22,12 +32,23 @@ impl PageNum {
 }
 
 pub const PAGE_SIZE: usize = 1024;
+/// Size of the keys given to Pager::new_encrypted
+pub const ENCRYPTION_KEY_SIZE: usize = 32;
+/// Space that a page of an encrypted data file takes on disk, it's followed by its nonce and tag
+pub const ENCRYPTED_PAGE_SIZE: usize = PAGE_SIZE + encryption::NONCE_SIZE + encryption::TAG_SIZE;
 
 #[derive(Clone, Debug)]
 #[repr(align(8))]
 pub struct Page([u8; PAGE_SIZE]);
 
 impl Page {
+    /// Copies the page from its bytes, which have to be PAGE_SIZE long
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut page = Self([0; PAGE_SIZE]);
+        page.0.copy_from_slice(bytes);
+        page
+    }
+
     pub fn page_header(&self) -> &PageHeader<'_> {
         #[allow(clippy::transmute_ptr_to_ref)]
         unsafe {
@@ -49,6 +70,48 @@ impl Page {
         }
     }
 
+    fn metadata_ref(&self) -> &MetadataPage {
+        #[allow(clippy::transmute_ptr_to_ref)]
+        unsafe {
+            std::mem::transmute(ptr::from_ref(self))
+        }
+    }
+
+    /// Bytes of the page after its header
+    pub fn body(&self) -> &[u8] {
+        &self.0[PAGE_HEADER_SIZE..]
+    }
+
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        &mut self.0[PAGE_HEADER_SIZE..]
+    }
+
+    /// Page that comes after this one in a list of pages, stored at the start of the body
+    pub fn next_page(&self) -> PageNum {
+        let next = &self.body()[..NEXT_PAGE_SIZE];
+        PageNum(u64::from_le_bytes(next.try_into().unwrap()) as usize)
+    }
+
+    pub fn set_next_page(&mut self, next: PageNum) {
+        self.body_mut()[..NEXT_PAGE_SIZE].copy_from_slice(&(next.0 as u64).to_le_bytes());
+    }
+
+    /// CRC32 of the page, leaving out the bytes that store it
+    fn compute_checksum(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&self.0[..CHECKSUM_OFFSET]);
+        hasher.update(&self.0[CHECKSUM_OFFSET + CHECKSUM_SIZE..]);
+        hasher.finalize()
+    }
+
+    fn update_checksum(&mut self) {
+        self.page_header_mut().checksum = self.compute_checksum();
+    }
+
+    fn checksum_matches(&self) -> bool {
+        self.page_header().checksum == self.compute_checksum()
+    }
+
     pub fn initialize_metadata_page(_page: &mut Self, _root: PageNum) {
         // NOOP
         // let metadata = page.metadata();
@@ -57,9 +120,15 @@ impl Page {
 }
 
 pub const PAGE_HEADER_SIZE: usize = std::mem::size_of::<PageHeader>();
-#[repr(align(8))]
+const CHECKSUM_OFFSET: usize = std::mem::offset_of!(PageHeader, checksum);
+const CHECKSUM_SIZE: usize = std::mem::size_of::<u32>();
+/// Size of the pointer written by Page::set_next_page
+pub const NEXT_PAGE_SIZE: usize = std::mem::size_of::<u64>();
+#[repr(C, align(8))]
 pub struct PageHeader<'page> {
     pub node_type: NodeType,
+    /// Written on flush and verified when the page is read back
+    checksum: u32,
     phantom: PhantomData<&'page mut Page>,
 }
 
@@ -99,101 +168,535 @@ impl<'page> PageHeader<'page> {
     }
 }
 
-// TODO: Use this page for collecting free pages and something else
-pub struct MetadataPage {}
+/// First page of the data file, it records the shape of the entries so that the file can't be
+/// read with the metadata of another table, and where the list of free pages starts
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct MetadataPage {
+    header: [u8; PAGE_HEADER_SIZE],
+    /// Size of the values stored in the leaves, 0 until the table sets it
+    pub entry_size: usize,
+    /// Metadata::schema_hash of the table that created the file
+    pub schema_hash: u32,
+    /// Last page freed with Pager::free_page, each free page points to the one freed before it
+    pub free_list: PageNum,
+    /// Number of pages in the page map of a compressed file, 0 if the pages aren't compressed
+    page_map_len: usize,
+    page_map_offset: u64,
+    page_map_checksum: u32,
+    /// Derived from the key of an encrypted file to check the key it's opened with, 0 if the
+    /// pages aren't encrypted
+    key_check: u64,
+}
+
+/// Counters of the work done by the pager since it was created or its stats were reset
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PagerStats {
+    /// Number of times a page was requested, whether it was loaded from disk or not
+    pub page_reads: usize,
+    /// Number of pages written to disk by flushes
+    pub page_writes: usize,
+    /// Page requests served from memory
+    pub cache_hits: usize,
+    /// Page requests that had to read the page from disk or create it
+    pub cache_misses: usize,
+}
 
 // TODO: Change pager from using a vec to something else
 const MAX_PAGES: usize = 1024 * 1024;
+/// Loads pages on demand and keeps them in memory until the table is dropped.
+/// It can be shared between threads: pages are loaded while holding the write lock of the
+/// vector, which never reallocates, so references to loaded pages stay valid. Every accessor
+/// that returns a page for writing takes the pager mutably, so pages can't change while a
+/// shared pager is being read, see Pager::load_page
 pub struct Pager {
     file: fs::File,
     num_pages: usize,
-    pub pages: RefCell<Vec<UnsafeCell<Option<Page>>>>,
+    pub pages: RwLock<Vec<UnsafeCell<Option<Page>>>>,
+    /// Pages that were accessed mutably since the last flush
+    dirty: Mutex<Vec<bool>>,
+    /// Pages that were accessed mutably since they were last taken by Pager::take_uncommitted
+    uncommitted: Mutex<Vec<bool>>,
+    stats: Mutex<PagerStats>,
+    /// Set if the pages are stored compressed, see Pager::new_compressed
+    page_map: Option<PageMap>,
+    /// Set if the pages are stored encrypted, see Pager::new_encrypted
+    cipher: Option<PageCipher>,
 }
 
+// Through a shared pager, pages are only added to empty slots under the write lock
+unsafe impl Sync for Pager {}
+
 impl Pager {
-    pub fn new(mut file: fs::File) -> io::Result<Self> {
+    pub fn new(file: fs::File) -> io::Result<Self> {
+        Self::open(file, false, None, &[])
+    }
+
+    /// Like Pager::new, but a new data file stores its pages compressed with LZ4. Pages are
+    /// compressed when they are flushed and decompressed when they are read, so they are the
+    /// same in memory. Existing files keep their format, Pager::new also opens compressed files
+    #[cfg(feature = "compression")]
+    pub fn new_compressed(file: fs::File) -> io::Result<Self> {
+        Self::open(file, true, None, &[])
+    }
+
+    /// Opens a data file whose pages are encrypted with the key, a new file is created
+    /// encrypted. The key isn't stored, only a value derived from it that is checked here, so
+    /// a wrong key fails instead of reading garbage. The metadata page isn't encrypted
+    #[cfg(feature = "encryption")]
+    pub fn new_encrypted(file: fs::File, key: &[u8; ENCRYPTION_KEY_SIZE]) -> io::Result<Self> {
+        Self::open(file, false, Some(key), &[])
+    }
+
+    /// Opens the data file, compressed if it's new and compressed is set, and encrypted with the
+    /// key if there is one. The recovered pages were committed to a write-ahead log, they replace
+    /// the ones in the file.
+    /// Pages are verified when they are read, Pager::verify checks all of them up front
+    pub(crate) fn open(
+        mut file: fs::File,
+        compressed: bool,
+        key: Option<&[u8; ENCRYPTION_KEY_SIZE]>,
+        recovered: &[LoggedPage],
+    ) -> io::Result<Self> {
         let length = file.seek(io::SeekFrom::End(0))? as usize;
-        let num_pages = length / PAGE_SIZE;
-        let pager = Self {
+        let num_pages = length / stored_page_size(key.is_some());
+        let mut pager = Self {
             file,
             num_pages,
             pages: Vec::with_capacity(MAX_PAGES).into(),
+            dirty: Vec::new().into(),
+            uncommitted: Vec::new().into(),
+            stats: Mutex::default(),
+            page_map: None,
+            cipher: key.map(PageCipher::new),
         };
+        // The metadata page in the file could have been written partially
+        let metadata = match recovered.iter().rfind(|(page_num, _)| page_num.is_null()) {
+            Some((_, page)) => *Page::from_bytes(page).metadata_ref(),
+            None => pager.read_metadata()?,
+        };
+        match (metadata.key_check, &pager.cipher) {
+            (0, None) => {}
+            (0, Some(_)) if num_pages == 0 => {}
+            (0, Some(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The data file isn't encrypted",
+                ));
+            }
+            (_, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "The data file is encrypted, it has to be opened with its key",
+                ));
+            }
+            (check, Some(cipher)) if check == cipher.key_check() => {}
+            (_, Some(_)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "The key doesn't match the one the data file was encrypted with",
+                ));
+            }
+        }
+        if metadata.page_map_len > 0 {
+            let page_map = PageMap::read(&pager.file, &metadata)?;
+            pager.num_pages = page_map.len();
+            pager.page_map = Some(page_map);
+        } else if compressed && num_pages == 0 {
+            pager.page_map = Some(PageMap::new());
+        }
         if num_pages == 0 {
             let root_page = PageNum(1);
-            let metadata_page = pager.get_page(PageNum(0));
+            let metadata_page = pager.get_page(PageNum(0))?;
             Page::initialize_metadata_page(metadata_page, root_page);
-            let root_page = pager.get_page(root_page);
+            let root_page = pager.get_page(root_page)?;
             LeafNodeHeader::initialize(root_page, PageNum::NULL);
+            if let Some(cipher) = &pager.cipher {
+                pager.get_page(PageNum(0))?.metadata().key_check = cipher.key_check();
+            }
+        }
+        if !recovered.is_empty() {
+            for (page_num, page) in recovered {
+                pager.restore_page(*page_num, page);
+            }
+            pager.flush()?;
         }
         Ok(pager)
     }
 
-    pub fn get_metadata(&mut self) -> &mut MetadataPage {
-        self.get_page(PageNum(0)).metadata()
+    /// Replaces the page in memory with the contents, it's written by the next flush
+    fn restore_page(&mut self, page_num: PageNum, contents: &[u8]) {
+        assert!(page_num.0 < MAX_PAGES, "Can't request more than MAX_PAGES");
+        let pages = self.pages.get_mut().unwrap();
+        if page_num.0 >= pages.len() {
+            pages.resize_with(page_num.0 + 1, || UnsafeCell::new(None));
+        }
+        *pages[page_num.0].get_mut() = Some(Page::from_bytes(contents));
+        self.mark_dirty(page_num);
+    }
+
+    /// Returns true if the pages are stored compressed
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        self.page_map.is_some()
+    }
+
+    /// Returns true if the pages are stored encrypted
+    #[inline]
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Returns true if the data file was created by Pager::new_compressed, without opening it
+    pub fn is_compressed_file(file: &fs::File) -> io::Result<bool> {
+        Ok(Self::read_file_metadata(file)?.page_map_len > 0)
+    }
+
+    /// Returns true if the data file was created by Pager::new_encrypted, without opening it
+    pub fn is_encrypted_file(file: &fs::File) -> io::Result<bool> {
+        Ok(Self::read_file_metadata(file)?.key_check != 0)
+    }
+
+    fn read_file_metadata(file: &fs::File) -> io::Result<MetadataPage> {
+        let mut page = Page([0; PAGE_SIZE]);
+        if file.metadata()?.len() >= PAGE_SIZE as u64 {
+            file_io::read_exact_at(file, &mut page.0, 0)?;
+        }
+        Ok(*page.metadata_ref())
+    }
+
+    pub fn get_metadata(&mut self) -> io::Result<&mut MetadataPage> {
+        Ok(self.get_page(PageNum(0))?.metadata())
+    }
+
+    /// Returns a copy of the metadata page, a page that wasn't loaded yet is read from disk
+    /// without caching it
+    pub fn read_metadata(&self) -> io::Result<MetadataPage> {
+        let pages = self.pages.read().unwrap();
+        let cached = pages
+            .first()
+            .and_then(|page| unsafe { &*page.get() }.as_ref());
+        if let Some(page) = cached {
+            return Ok(*page.metadata_ref());
+        }
+        let mut page = Page([0; PAGE_SIZE]);
+        if self.num_pages > 0 {
+            self.read_page(PageNum(0), &mut page)?;
+        }
+        Ok(*page.metadata_ref())
+    }
+
+    /// Returns the page for writing, it will be written back on the next flush.
+    /// Fails if the page can't be read or its checksum doesn't match
+    pub fn get_page(&mut self, page_num: PageNum) -> io::Result<&mut Page> {
+        self.load_page(page_num)?;
+        self.mark_dirty(page_num);
+        Ok(self.loaded_page(page_num))
     }
 
-    #[allow(clippy::mut_from_ref)]
-    pub fn get_page(&self, page_num: PageNum) -> &mut Page {
+    /// Returns the page for reading, it won't be written back unless it's modified elsewhere
+    pub fn get_page_ref(&self, page_num: PageNum) -> io::Result<&Page> {
+        self.load_page(page_num)
+    }
+
+    /// Returns a page that was already read for writing, without going to disk.
+    /// Panics if the page wasn't read before
+    pub fn loaded_page(&mut self, page_num: PageNum) -> &mut Page {
+        self.mark_dirty(page_num);
+        let slot = self.pages.get_mut().unwrap().get_mut(page_num.0);
+        slot.and_then(|slot| slot.get_mut().as_mut())
+            .expect("The page has to be read before")
+    }
+
+    /// Returns a page that was already read, without going to disk.
+    /// Panics if the page wasn't read before
+    pub fn loaded_page_ref(&self, page_num: PageNum) -> &Page {
+        let pages = self.pages.read().unwrap();
+        let page = pages
+            .get(page_num.0)
+            .and_then(|slot| unsafe { &*slot.get() }.as_ref());
+        let page = page.expect("The page has to be read before");
+        // Filled slots aren't emptied while the pager is borrowed, see Pager::load_page
+        unsafe { &*(page as *const Page) }
+    }
+
+    #[inline]
+    pub fn stats(&self) -> PagerStats {
+        *self.stats.lock().unwrap()
+    }
+
+    pub fn reset_stats(&self) {
+        *self.stats.lock().unwrap() = PagerStats::default();
+    }
+
+    fn update_stats(&self, update: impl FnOnce(&mut PagerStats)) {
+        update(&mut self.stats.lock().unwrap());
+    }
+
+    /// Makes the next flush write the page back to disk
+    pub fn mark_dirty(&self, page_num: PageNum) {
+        for flags in [&self.dirty, &self.uncommitted] {
+            let mut flags = flags.lock().unwrap();
+            if page_num.0 >= flags.len() {
+                flags.resize(page_num.0 + 1, false);
+            }
+            flags[page_num.0] = true;
+        }
+    }
+
+    pub fn is_dirty(&self, page_num: PageNum) -> bool {
+        self.dirty
+            .lock()
+            .unwrap()
+            .get(page_num.0)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Returns the page, reading it from disk if it isn't in memory yet. Slots are only filled
+    /// here, while holding the write lock, and pages are only modified through a mutable borrow
+    /// of the pager, so the references handed out from a shared pager never see a change
+    fn load_page(&self, page_num: PageNum) -> io::Result<&Page> {
         assert!(page_num.0 < MAX_PAGES, "Can't request more than MAX_PAGES");
-        let len = self.pages.borrow().len();
+        self.update_stats(|stats| stats.page_reads += 1);
+        {
+            let pages = self.pages.read().unwrap();
+            if let Some(slot) = pages.get(page_num.0)
+                && let Some(page) = unsafe { &*slot.get() }
+            {
+                self.update_stats(|stats| stats.cache_hits += 1);
+                return Ok(page);
+            }
+        }
+
+        // Another thread could have loaded the page before the write lock was taken
+        let mut pages = self.pages.write().unwrap();
+        let len = pages.len();
         if page_num.0 >= len {
-            self.pages
-                .borrow_mut()
-                .extend(iter::repeat_with(|| UnsafeCell::new(None)).take(page_num.0 - len + 1));
-        }
-
-        let page_slot = unsafe { &mut *self.pages.borrow()[page_num.0].get() };
-        match page_slot {
-            Some(page) => page,
-            None => {
-                let page = page_slot.insert(Page([0; 1024]));
-                if page_num.0 < self.num_pages {
-                    let page_offset = page_num.0 * PAGE_SIZE;
-                    self.file
-                        .read_exact_at(&mut page.0, page_offset as u64)
-                        .expect("Failed to read file");
-                }
-                page_slot.as_mut().unwrap()
+            pages.extend(iter::repeat_with(|| UnsafeCell::new(None)).take(page_num.0 - len + 1));
+        }
+        if let Some(page) = unsafe { &*pages[page_num.0].get() } {
+            self.update_stats(|stats| stats.cache_hits += 1);
+            return Ok(page);
+        }
+        self.update_stats(|stats| stats.cache_misses += 1);
+        let mut page = Page([0; PAGE_SIZE]);
+        if page_num.0 < self.num_pages {
+            self.read_page(page_num, &mut page)?;
+        }
+        // The slot is empty, so nothing else can be borrowing it
+        let page_slot = unsafe { &mut *pages[page_num.0].get() };
+        Ok(page_slot.insert(page))
+    }
+
+    /// Reads the page from disk, verifying its checksum unless the unchecked-checksums feature
+    /// is enabled
+    fn read_page(&self, page_num: PageNum, page: &mut Page) -> io::Result<()> {
+        match &self.cipher {
+            // The metadata page is never compressed nor encrypted
+            Some(cipher) if page_num.0 > 0 => {
+                let mut stored = vec![0; ENCRYPTED_PAGE_SIZE];
+                self.read_stored_page(page_num, &mut stored)?;
+                cipher.decrypt(page_num, &stored, page)?;
+            }
+            _ => self.read_stored_page(page_num, &mut page.0)?,
+        }
+        if cfg!(not(feature = "unchecked-checksums")) && !page.checksum_matches() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Checksum mismatch on page {}", page_num.0),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads the bytes of the page as they are stored, decompressing them if the file is
+    /// compressed
+    fn read_stored_page(&self, page_num: PageNum, buf: &mut [u8]) -> io::Result<()> {
+        match &self.page_map {
+            Some(page_map) if page_num.0 > 0 => page_map.read_page(&self.file, page_num, buf),
+            _ => {
+                let page_offset = page_num.0 * stored_page_size(self.is_encrypted());
+                file_io::read_exact_at(&self.file, buf, page_offset as u64)
             }
         }
     }
 
-    pub fn get_node(&self, page_num: PageNum) -> NodeMut<'_> {
-        self.get_page(page_num).page_header_mut().node_mut()
+    /// Reads every page in the file to check that none of them is corrupted
+    pub fn verify(&self) -> io::Result<()> {
+        let mut page = Page([0; PAGE_SIZE]);
+        (0..self.num_pages).try_for_each(|i| self.read_page(PageNum(i), &mut page))
     }
 
-    pub fn get_free_page(&self) -> PageNum {
-        let page_num = PageNum(self.pages.borrow().len().max(self.num_pages));
-        self.get_page(page_num);
+    pub fn get_node(&mut self, page_num: PageNum) -> io::Result<NodeMut<'_>> {
+        Ok(self.get_page(page_num)?.page_header_mut().node_mut())
+    }
+
+    /// Like Pager::get_page for several pages at once, which have to be different
+    pub fn get_pages<const N: usize>(
+        &mut self,
+        page_nums: [PageNum; N],
+    ) -> io::Result<[&mut Page; N]> {
+        for page_num in page_nums {
+            self.get_page(page_num)?;
+        }
+        let pages = self.pages.get_mut().unwrap();
+        Ok(pages
+            .get_disjoint_mut(page_nums.map(|page_num| page_num.0))
+            .expect("The pages have to be different")
+            .map(|slot| slot.get_mut().as_mut().expect("The page was just loaded")))
+    }
+
+    /// Like Pager::get_node for several nodes at once, which have to be in different pages
+    pub fn get_nodes<const N: usize>(
+        &mut self,
+        page_nums: [PageNum; N],
+    ) -> io::Result<[NodeMut<'_>; N]> {
+        Ok(self
+            .get_pages(page_nums)?
+            .map(|page| page.page_header_mut().node_mut()))
+    }
+
+    pub fn get_node_ref(&self, page_num: PageNum) -> io::Result<Node<'_>> {
+        Ok(self.get_page_ref(page_num)?.page_header().node())
+    }
+
+    /// Like Pager::loaded_page_ref for the node in the page
+    pub fn loaded_node_ref(&self, page_num: PageNum) -> Node<'_> {
+        self.loaded_page_ref(page_num).page_header().node()
+    }
+
+    /// Like Pager::loaded_page for the node in the page
+    pub fn loaded_node(&mut self, page_num: PageNum) -> NodeMut<'_> {
+        self.loaded_page(page_num).page_header_mut().node_mut()
+    }
+
+    /// Returns a new zeroed page past the end of the file, it's written by the next flush
+    pub fn get_free_page(&mut self) -> PageNum {
+        let page_num = PageNum(self.pages.read().unwrap().len().max(self.num_pages));
+        self.restore_page(page_num, &[0; PAGE_SIZE]);
         page_num
     }
 
-    pub fn flush(&mut self) {
-        let biggest_page_index = self
-            .pages
-            .borrow()
+    /// Returns a page freed with Pager::free_page if there is one, otherwise a new page like
+    /// Pager::get_free_page. Reused pages keep the contents they had when they were freed
+    pub fn reuse_free_page(&mut self) -> io::Result<PageNum> {
+        let page_num = self.get_metadata()?.free_list;
+        if page_num.is_null() {
+            return Ok(self.get_free_page());
+        }
+        let next = self.get_page_ref(page_num)?.next_page();
+        self.get_metadata()?.free_list = next;
+        Ok(page_num)
+    }
+
+    /// Adds the page to the free list in the metadata page, the page mustn't be used afterwards
+    pub fn free_page(&mut self, page_num: PageNum) -> io::Result<()> {
+        let metadata = self.get_metadata()?;
+        let previous = metadata.free_list;
+        metadata.free_list = page_num;
+        self.get_page(page_num)?.set_next_page(previous);
+        Ok(())
+    }
+
+    /// Returns the contents of the pages modified since the last call, with their checksums
+    /// updated, so they can be committed to a WAL. They are still written by the next flush
+    pub fn take_uncommitted(&mut self) -> Vec<(PageNum, &[u8])> {
+        let uncommitted = std::mem::take(self.uncommitted.get_mut().unwrap());
+        let pages = self.pages.get_mut().unwrap();
+        uncommitted
             .iter()
+            .zip(pages.iter_mut())
             .enumerate()
-            .rev()
-            .find(|(_, p)| unsafe { p.get().as_ref() }.is_some())
-            .map(|(i, _)| i)
-            .expect("At least one page shouldn't be empty");
+            .filter(|(_, (uncommitted, _))| **uncommitted)
+            .filter_map(|(i, (_, page))| {
+                let page = page.get_mut().as_mut()?;
+                page.update_checksum();
+                Some((PageNum(i), &page.0[..]))
+            })
+            .collect()
+    }
+
+    /// Writes the dirty pages back to disk, pages that were only read are skipped
+    pub fn flush(&mut self) -> io::Result<()> {
+        let dirty = self.dirty.lock().unwrap().clone();
+        let Some(biggest_page_index) = dirty.iter().rposition(|&d| d) else {
+            // No pages were modified, so nothing has to be written
+            return Ok(());
+        };
+        if self.page_map.is_some() {
+            self.num_pages = self.num_pages.max(biggest_page_index + 1);
+            return self.flush_compressed(&dirty);
+        }
+        let page_size = stored_page_size(self.is_encrypted());
         if biggest_page_index >= self.num_pages {
-            let file_size = (biggest_page_index - self.num_pages + 1) * PAGE_SIZE;
-            self.file
-                .set_len(file_size as u64)
-                .expect("Failed to set length");
+            let file_size = (biggest_page_index + 1) * page_size;
+            self.file.set_len(file_size as u64)?;
+            self.num_pages = biggest_page_index + 1;
         }
-        for i in 0..=biggest_page_index {
-            let page = unsafe { &*self.pages.borrow()[i].get() };
+        for (i, _) in dirty.iter().enumerate().filter(|(_, d)| **d) {
+            let page = self.pages.get_mut().unwrap()[i].get_mut();
             if let Some(page) = page {
-                let page_location = i * PAGE_SIZE;
-                self.file
-                    .write_all_at(&page.0, page_location as u64)
-                    .expect("Failed to write pager data");
+                page.update_checksum();
+                let page = stored_page(self.cipher.as_ref(), PageNum(i), page);
+                let page_location = i * page_size;
+                file_io::write_all_at(&self.file, &page, page_location as u64)?;
+                self.update_stats(|stats| stats.page_writes += 1);
             }
         }
-        self.file.sync_data().expect("Failed to sync pager");
+        self.file.sync_data()?;
+        self.dirty.lock().unwrap().clear();
+        Ok(())
+    }
+
+    /// Writes the modified pages compressed, then the page map after them, and finally the
+    /// metadata page pointing to the new map
+    fn flush_compressed(&mut self, dirty: &[bool]) -> io::Result<()> {
+        self.load_page(PageNum(0))?;
+        let pages = self.pages.get_mut().unwrap();
+        let page_map = self.page_map.as_mut().unwrap();
+        let mut page_writes = 0;
+        for (i, page) in pages.iter_mut().enumerate().skip(1) {
+            if let Some(page) = page.get_mut()
+                && dirty.get(i).copied().unwrap_or(false)
+            {
+                page.update_checksum();
+                let page = stored_page(self.cipher.as_ref(), PageNum(i), page);
+                page_map.write_page(&self.file, PageNum(i), &page)?;
+                page_writes += 1;
+            }
+        }
+        let metadata_page = pages[0].get_mut().as_mut().unwrap();
+        let file_size = page_map.write(&self.file, metadata_page.metadata())?;
+        metadata_page.update_checksum();
+        file_io::write_all_at(&self.file, &metadata_page.0, 0)?;
+        self.file.set_len(file_size)?;
+        self.update_stats(|stats| stats.page_writes += page_writes + 1);
+        self.file.sync_data()?;
+        self.dirty.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+/// Returns the page as it's written to the file, encrypted if the pager has a cipher
+fn stored_page<'p>(
+    cipher: Option<&PageCipher>,
+    page_num: PageNum,
+    page: &'p Page,
+) -> Cow<'p, [u8]> {
+    match cipher {
+        Some(cipher) if page_num.0 > 0 => Cow::Owned(cipher.encrypt(page_num, page)),
+        _ => Cow::Borrowed(&page.0),
+    }
+}
+
+/// Space that each page takes in a data file that isn't compressed. Encrypted pages are
+/// followed by their nonce and tag, the metadata page takes the same space to keep the
+/// offsets of the rest
+fn stored_page_size(encrypted: bool) -> usize {
+    if encrypted {
+        ENCRYPTED_PAGE_SIZE
+    } else {
+        PAGE_SIZE
     }
 }
@@ -0,0 +1,154 @@
+use std::{fs, io};
+
+use crate::{
+    pager::{MetadataPage, PAGE_SIZE, PageNum},
+    utils::file_io,
+};
+
+const LOCATION_SIZE: usize = std::mem::size_of::<u64>() + std::mem::size_of::<u32>();
+
+#[derive(Clone, Copy, Default)]
+struct PageLocation {
+    offset: u64,
+    len: u32,
+}
+
+/// Where each page of a compressed data file is stored. The metadata page is always stored
+/// uncompressed at the start of the file, and points to the map, which goes after the rest of
+/// the pages. A page that doesn't fit in its place anymore is moved to the end of the file, the
+/// space it used isn't reused. Neither is the space of a map once a new one is written, so the
+/// map that the metadata page points to is never overwritten before the metadata page is
+pub struct PageMap {
+    locations: Vec<PageLocation>,
+    /// End of the pages and the last map in the file, where the next map and the moved pages
+    /// are written
+    end: u64,
+}
+
+impl PageMap {
+    /// Map of a new data file
+    pub fn new() -> Self {
+        let metadata = PageLocation {
+            offset: 0,
+            len: PAGE_SIZE as u32,
+        };
+        Self {
+            locations: vec![metadata],
+            end: PAGE_SIZE as u64,
+        }
+    }
+
+    /// Reads the map that the metadata page points to
+    pub fn read(file: &fs::File, metadata: &MetadataPage) -> io::Result<Self> {
+        if cfg!(not(feature = "compression")) {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "The data file is compressed, which needs the compression feature",
+            ));
+        }
+        let mut bytes = vec![0; metadata.page_map_len * LOCATION_SIZE];
+        file_io::read_exact_at(file, &mut bytes, metadata.page_map_offset)?;
+        if crc32fast::hash(&bytes) != metadata.page_map_checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Checksum mismatch in the page map",
+            ));
+        }
+        let locations = bytes
+            .chunks_exact(LOCATION_SIZE)
+            .map(|location| {
+                let (offset, len) = location.split_at(std::mem::size_of::<u64>());
+                PageLocation {
+                    offset: u64::from_le_bytes(offset.try_into().unwrap()),
+                    len: u32::from_le_bytes(len.try_into().unwrap()),
+                }
+            })
+            .collect();
+        Ok(Self {
+            locations,
+            end: metadata.page_map_offset + bytes.len() as u64,
+        })
+    }
+
+    /// Number of pages in the file, including the metadata page
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// Reads the page from the file and decompresses it into the buffer, which has the size of
+    /// the stored page
+    pub fn read_page(&self, file: &fs::File, page_num: PageNum, buf: &mut [u8]) -> io::Result<()> {
+        let location = self.locations[page_num.0];
+        let mut compressed = vec![0; location.len as usize];
+        file_io::read_exact_at(file, &mut compressed, location.offset)?;
+        if decompress(&compressed, buf) != Some(buf.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Page {} can't be decompressed", page_num.0),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Compresses the page and writes it where it was if it fits, otherwise at the end
+    pub fn write_page(
+        &mut self,
+        file: &fs::File,
+        page_num: PageNum,
+        page: &[u8],
+    ) -> io::Result<()> {
+        let compressed = compress(page);
+        if page_num.0 >= self.locations.len() {
+            self.locations
+                .resize(page_num.0 + 1, PageLocation::default());
+        }
+        let location = &mut self.locations[page_num.0];
+        if compressed.len() > location.len as usize {
+            location.offset = self.end;
+            self.end += compressed.len() as u64;
+        }
+        location.len = compressed.len() as u32;
+        file_io::write_all_at(file, &compressed, location.offset)
+    }
+
+    /// Writes the map after the pages and points the metadata page to it.
+    /// Returns the size of the file, which ends with the map
+    pub fn write(&mut self, file: &fs::File, metadata: &mut MetadataPage) -> io::Result<u64> {
+        let bytes: Vec<u8> = self
+            .locations
+            .iter()
+            .flat_map(|location| {
+                let offset = location.offset.to_le_bytes();
+                offset.into_iter().chain(location.len.to_le_bytes())
+            })
+            .collect();
+        file_io::write_all_at(file, &bytes, self.end)?;
+        metadata.page_map_len = self.locations.len();
+        metadata.page_map_offset = self.end;
+        metadata.page_map_checksum = crc32fast::hash(&bytes);
+        self.end += bytes.len() as u64;
+        Ok(self.end)
+    }
+}
+
+#[cfg(feature = "compression")]
+fn compress(page: &[u8]) -> Vec<u8> {
+    lz4_flex::block::compress(page)
+}
+
+#[cfg(feature = "compression")]
+fn decompress(compressed: &[u8], buf: &mut [u8]) -> Option<usize> {
+    lz4_flex::block::decompress_into(compressed, buf).ok()
+}
+
+// Page maps can't be read without the feature, so pages are never compressed
+#[cfg(not(feature = "compression"))]
+fn compress(_page: &[u8]) -> Vec<u8> {
+    unreachable!("Pages are only compressed with the compression feature")
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_compressed: &[u8], _buf: &mut [u8]) -> Option<usize> {
+    unreachable!("Pages are only decompressed with the compression feature")
+}
@@ -0,0 +1,142 @@
+use std::io;
+
+use crate::pager::{
+    ENCRYPTED_PAGE_SIZE, ENCRYPTION_KEY_SIZE, PAGE_HEADER_SIZE, PAGE_SIZE, Page, PageNum,
+};
+
+/// Encrypted with the key and stored in the metadata page, so that opening a file with the wrong
+/// key fails before any page is read
+const KEY_CHECK_MAGIC: [u8; 8] = *b"RUSTDBKY";
+/// Nonce of the key check, it's never used for a page because page nonces are random
+const KEY_CHECK_NONCE: [u8; NONCE_SIZE] = [0xff; NONCE_SIZE];
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+
+/// Encrypts the bodies of the pages with ChaCha20-Poly1305, the headers stay readable but are
+/// authenticated along with the page number, so a page that was modified or moved fails to
+/// load. Every write uses a new random nonce, which is stored after the page with the tag
+pub struct PageCipher {
+    key: [u8; ENCRYPTION_KEY_SIZE],
+}
+
+impl PageCipher {
+    pub fn new(key: &[u8; ENCRYPTION_KEY_SIZE]) -> Self {
+        Self { key: *key }
+    }
+
+    /// Value stored in the metadata page of files encrypted with the key, it's never 0
+    pub fn key_check(&self) -> u64 {
+        let mut check = KEY_CHECK_MAGIC;
+        seal(&self.key, &KEY_CHECK_NONCE, &[], &mut check);
+        u64::from_le_bytes(check).max(1)
+    }
+
+    /// Returns the page as it's stored: the page with its body encrypted, followed by the nonce
+    /// and the tag. The checksum has to be up to date
+    pub fn encrypt(&self, page_num: PageNum, page: &Page) -> Vec<u8> {
+        let mut stored = Vec::with_capacity(ENCRYPTED_PAGE_SIZE);
+        stored.extend_from_slice(&page.0);
+        let nonce = random_nonce();
+        let (header, body) = stored.split_at_mut(PAGE_HEADER_SIZE);
+        let tag = seal(&self.key, &nonce, &associated_data(page_num, header), body);
+        stored.extend_from_slice(&nonce);
+        stored.extend_from_slice(&tag);
+        stored
+    }
+
+    /// Decrypts a page written by PageCipher::encrypt, failing if it doesn't authenticate
+    pub fn decrypt(&self, page_num: PageNum, stored: &[u8], page: &mut Page) -> io::Result<()> {
+        let (contents, trailer) = stored.split_at(PAGE_SIZE);
+        let (nonce, tag) = trailer.split_at(NONCE_SIZE);
+        page.0.copy_from_slice(contents);
+        let (header, body) = page.0.split_at_mut(PAGE_HEADER_SIZE);
+        let nonce = nonce.try_into().unwrap();
+        let tag = tag.try_into().unwrap();
+        if !open(
+            &self.key,
+            nonce,
+            &associated_data(page_num, header),
+            body,
+            tag,
+        ) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Page {} can't be decrypted, it was modified or the key is wrong",
+                    page_num.0
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The header and the number of the page are authenticated without being encrypted
+fn associated_data(page_num: PageNum, header: &[u8]) -> Vec<u8> {
+    let mut data = (page_num.0 as u64).to_le_bytes().to_vec();
+    data.extend_from_slice(header);
+    data
+}
+
+#[cfg(feature = "encryption")]
+fn random_nonce() -> [u8; NONCE_SIZE] {
+    use chacha20poly1305::{AeadCore, ChaCha20Poly1305, aead::OsRng};
+    ChaCha20Poly1305::generate_nonce(&mut OsRng).into()
+}
+
+/// Encrypts the bytes in place and returns the tag
+#[cfg(feature = "encryption")]
+fn seal(
+    key: &[u8; ENCRYPTION_KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    associated_data: &[u8],
+    bytes: &mut [u8],
+) -> [u8; TAG_SIZE] {
+    use chacha20poly1305::{AeadInPlace, ChaCha20Poly1305, KeyInit};
+    ChaCha20Poly1305::new(key.into())
+        .encrypt_in_place_detached(nonce.into(), associated_data, bytes)
+        .expect("Pages are much smaller than the maximum message size")
+        .into()
+}
+
+/// Decrypts the bytes in place, returns false if the tag doesn't match
+#[cfg(feature = "encryption")]
+fn open(
+    key: &[u8; ENCRYPTION_KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    associated_data: &[u8],
+    bytes: &mut [u8],
+    tag: &[u8; TAG_SIZE],
+) -> bool {
+    use chacha20poly1305::{AeadInPlace, ChaCha20Poly1305, KeyInit};
+    ChaCha20Poly1305::new(key.into())
+        .decrypt_in_place_detached(nonce.into(), associated_data, bytes, tag.into())
+        .is_ok()
+}
+
+// Ciphers are only created with a key, which can only be given with the feature
+#[cfg(not(feature = "encryption"))]
+fn random_nonce() -> [u8; NONCE_SIZE] {
+    unreachable!("Pages are only encrypted with the encryption feature")
+}
+
+#[cfg(not(feature = "encryption"))]
+fn seal(
+    _key: &[u8; ENCRYPTION_KEY_SIZE],
+    _nonce: &[u8; NONCE_SIZE],
+    _associated_data: &[u8],
+    _bytes: &mut [u8],
+) -> [u8; TAG_SIZE] {
+    unreachable!("Pages are only encrypted with the encryption feature")
+}
+
+#[cfg(not(feature = "encryption"))]
+fn open(
+    _key: &[u8; ENCRYPTION_KEY_SIZE],
+    _nonce: &[u8; NONCE_SIZE],
+    _associated_data: &[u8],
+    _bytes: &mut [u8],
+    _tag: &[u8; TAG_SIZE],
+) -> bool {
+    unreachable!("Pages are only decrypted with the encryption feature")
+}
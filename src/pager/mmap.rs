@@ -0,0 +1,150 @@
+use std::{fs, io, sync::Mutex};
+
+use memmap2::{MmapMut, MmapOptions};
+
+use crate::{
+    pager::{MAX_PAGES, PAGE_SIZE, Page, PageNum, Pager},
+    table::{
+        leaf::LeafNodeHeader,
+        node::{Node, NodeMut},
+    },
+};
+
+/// Pager that maps the data file into memory and returns views of the mapped pages instead of
+/// reading them into owned copies.
+/// The mapping reserves space for MAX_PAGES up front and the file is grown under it, so it's
+/// never remapped and references to pages stay valid while new ones are allocated.
+/// Changes reach the file through the mapping, flushing updates the checksums of the modified
+/// pages and waits for them to be written
+pub struct MmapPager {
+    file: fs::File,
+    map: MmapMut,
+    base: *mut u8,
+    num_pages: Mutex<usize>,
+    /// Pages that were accessed mutably since the last flush
+    dirty: Mutex<Vec<bool>>,
+}
+
+impl MmapPager {
+    pub fn new(file: fs::File) -> io::Result<Self> {
+        if Pager::is_compressed_file(&file)? || Pager::is_encrypted_file(&file)? {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Compressed and encrypted data files can't be memory mapped",
+            ));
+        }
+        let length = file.metadata()?.len() as usize;
+        let num_pages = length / PAGE_SIZE;
+        let mut map = unsafe {
+            MmapOptions::new()
+                .len(MAX_PAGES * PAGE_SIZE)
+                .map_mut(&file)?
+        };
+        let base = map.as_mut_ptr();
+        let pager = Self {
+            file,
+            map,
+            base,
+            num_pages: Mutex::new(num_pages),
+            dirty: Vec::new().into(),
+        };
+        #[cfg(not(feature = "unchecked-checksums"))]
+        pager.verify()?;
+        if num_pages == 0 {
+            let root_page = PageNum(1);
+            pager.grow(root_page)?;
+            let metadata_page = pager.get_page(PageNum(0));
+            Page::initialize_metadata_page(metadata_page, root_page);
+            let root_page = pager.get_page(root_page);
+            LeafNodeHeader::initialize(root_page, PageNum::NULL);
+        }
+        Ok(pager)
+    }
+
+    #[inline]
+    pub fn num_pages(&self) -> usize {
+        *self.num_pages.lock().unwrap()
+    }
+
+    /// Returns the page for writing, its checksum is updated on the next flush
+    #[allow(clippy::mut_from_ref)]
+    pub fn get_page(&self, page_num: PageNum) -> &mut Page {
+        let page = self.page(page_num);
+        let mut dirty = self.dirty.lock().unwrap();
+        if page_num.0 >= dirty.len() {
+            dirty.resize(page_num.0 + 1, false);
+        }
+        dirty[page_num.0] = true;
+        page
+    }
+
+    pub fn get_page_ref(&self, page_num: PageNum) -> &Page {
+        self.page(page_num)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn page(&self, page_num: PageNum) -> &mut Page {
+        assert!(
+            page_num.0 < self.num_pages(),
+            "Page {} is past the end of the file",
+            page_num.0
+        );
+        // The map is page aligned and pages are a multiple of the alignment of Page
+        unsafe { &mut *self.base.add(page_num.0 * PAGE_SIZE).cast::<Page>() }
+    }
+
+    pub fn get_node(&self, page_num: PageNum) -> NodeMut<'_> {
+        self.get_page(page_num).page_header_mut().node_mut()
+    }
+
+    pub fn get_node_ref(&self, page_num: PageNum) -> Node<'_> {
+        self.get_page_ref(page_num).page_header().node()
+    }
+
+    /// Grows the file by a page and returns it, the new page is zeroed
+    pub fn get_free_page(&self) -> PageNum {
+        let page_num = PageNum(self.num_pages());
+        self.grow(page_num).expect("Failed to grow the data file");
+        self.get_page(page_num);
+        page_num
+    }
+
+    /// Extends the file so it has the page, the mapping already covers it
+    fn grow(&self, page_num: PageNum) -> io::Result<()> {
+        assert!(page_num.0 < MAX_PAGES, "Can't request more than MAX_PAGES");
+        let mut num_pages = self.num_pages.lock().unwrap();
+        if page_num.0 >= *num_pages {
+            self.file.set_len(((page_num.0 + 1) * PAGE_SIZE) as u64)?;
+            *num_pages = page_num.0 + 1;
+        }
+        Ok(())
+    }
+
+    /// Checks the checksum of every page in the file
+    pub fn verify(&self) -> io::Result<()> {
+        match (0..self.num_pages()).find(|&i| !self.page(PageNum(i)).checksum_matches()) {
+            Some(i) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Checksum mismatch on page {i}"),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Updates the checksums of the modified pages and writes the mapping back to disk
+    pub fn flush(&mut self) -> io::Result<()> {
+        let dirty = std::mem::take(self.dirty.get_mut().unwrap());
+        for (i, _) in dirty.iter().enumerate().filter(|(_, d)| **d) {
+            self.page(PageNum(i)).update_checksum();
+        }
+        let length = self.num_pages() * PAGE_SIZE;
+        self.map.flush_range(0, length)
+    }
+}
+
+impl Drop for MmapPager {
+    fn drop(&mut self) {
+        // Errors can't be reported from drop, MmapPager::flush should be used to handle them
+        let _ = self.flush();
+    }
+}
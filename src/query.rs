@@ -1,9 +1,13 @@
 use crate::expression;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::io;
 use std::ops::Deref;
 
 use chumsky::{prelude::*, text::digits};
 
-use crate::expression::{BoxedExpression, Comparison, Expression};
+use crate::expression::{Arithmetic, BoxedExpression, Comparison, Expression};
+use crate::table::metadata::{FieldDefinition, Type};
 
 #[repr(transparent)]
 #[derive(Debug, PartialEq)]
@@ -35,55 +39,117 @@ impl<'a> From<&'a Identifier> for &'a str {
     }
 }
 
-type ParsingError<'a> = extra::Err<Simple<'a, char>>;
+type ParsingError<'a> = extra::Err<Rich<'a, char>>;
 
-#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+/// Strings are borrowed from the input when possible and owned when they had to be built,
+/// like the unescaped strings of the parser
+#[derive(Debug, Clone)]
 pub enum Literal<'a> {
-    String(&'a str),
+    Null,
+    String(Cow<'a, str>),
     Int(isize),
     Uint(usize),
     Float(f64),
 }
 
-// This is true under the case that our set contains literals of the same type, which should be
-// true
+impl Literal<'_> {
+    /// Position of the variant, literals with different types are ordered by it
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Null => 0,
+            Self::String(_) => 1,
+            Self::Int(_) => 2,
+            Self::Uint(_) => 3,
+            Self::Float(_) => 4,
+        }
+    }
+}
+
+/// Total ordering of floats where NaN is equal to itself and greater than every other value
+fn float_cmp(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => a.partial_cmp(&b).unwrap(),
+    }
+}
+
+impl PartialEq for Literal<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
 impl Eq for Literal<'_> {}
 
+impl PartialOrd for Literal<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Ord for Literal<'_> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap_or_else(|| {
-            panic!(
-                "Can't compare literals with different types: {:?} and {:?}",
-                self, other
-            )
-        })
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::String(a), Self::String(b)) => a.cmp(b),
+            (Self::Int(a), Self::Int(b)) => a.cmp(b),
+            (Self::Uint(a), Self::Uint(b)) => a.cmp(b),
+            (Self::Float(a), Self::Float(b)) => float_cmp(*a, *b),
+            _ => self.rank().cmp(&other.rank()),
+        }
     }
 }
 
+/// Size of the length prefix of strings
+pub const STRING_LENGTH_SIZE: usize = std::mem::size_of::<u32>();
+
 impl<'a> Literal<'a> {
-    pub fn write_to(&self, buf: &mut [u8]) {
-        match self {
+    /// Writes the literal using the on-disk encoding: fixed width little-endian integers and
+    /// strings prefixed by their length as a u32.
+    /// Fails if the buffer isn't the size of the literal or is too short for the string
+    pub fn write_to(&self, buf: &mut [u8]) -> io::Result<()> {
+        let data: &[u8] = match self {
+            Self::Null => {
+                buf.fill(0);
+                return Ok(());
+            }
             Self::String(str) => {
                 let data = str.as_bytes();
                 let len = data.len();
-                const USIZE_FIELD: usize = std::mem::size_of::<usize>();
-
-                buf[0..USIZE_FIELD].copy_from_slice(&len.to_ne_bytes());
-                buf[USIZE_FIELD..(USIZE_FIELD + len)].copy_from_slice(data);
-            }
-            Self::Int(i) => {
-                let data = &i.to_ne_bytes();
-                buf.copy_from_slice(data);
-            }
-            Self::Uint(i) => {
-                let data = &i.to_ne_bytes();
-                buf.copy_from_slice(data);
-            }
-            Self::Float(f) => {
-                let data = &f.to_ne_bytes();
-                buf.copy_from_slice(data);
+                let prefix = u32::try_from(len)
+                    .ok()
+                    .filter(|_| STRING_LENGTH_SIZE + len <= buf.len())
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "String of {len} bytes doesn't fit in {} bytes",
+                                buf.len().saturating_sub(STRING_LENGTH_SIZE)
+                            ),
+                        )
+                    })?;
+
+                buf[0..STRING_LENGTH_SIZE].copy_from_slice(&prefix.to_le_bytes());
+                buf[STRING_LENGTH_SIZE..(STRING_LENGTH_SIZE + len)].copy_from_slice(data);
+                return Ok(());
             }
+            Self::Int(i) => &(*i as i64).to_le_bytes(),
+            Self::Uint(i) => &(*i as u64).to_le_bytes(),
+            Self::Float(f) => &f.to_le_bytes(),
+        };
+        if buf.len() != data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Value has {} bytes but the buffer has {}",
+                    data.len(),
+                    buf.len()
+                ),
+            ));
         }
+        buf.copy_from_slice(data);
+        Ok(())
     }
 }
 
@@ -107,22 +173,128 @@ impl From<f64> for Literal<'_> {
 
 impl<'a> From<&'a str> for Literal<'a> {
     fn from(value: &'a str) -> Self {
-        Self::String(value)
+        Self::String(Cow::Borrowed(value))
     }
 }
 
+impl From<String> for Literal<'_> {
+    fn from(value: String) -> Self {
+        Self::String(Cow::Owned(value))
+    }
+}
+
+/// Literals are serialized as the plain value: NULL as a unit, strings as strings and numbers as
+/// numbers. Deserializing can't tell the integer types apart, so negative integers become Int
+/// and the rest become Uint.
+/// Strings are borrowed from the input when possible, the ones that have to be unescaped are
+/// owned
+#[cfg(feature = "serde")]
+mod literal_serde {
+    use super::Literal;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    impl Serialize for Literal<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match *self {
+                Literal::Null => serializer.serialize_unit(),
+                Literal::String(ref s) => serializer.serialize_str(s),
+                Literal::Int(i) => serializer.serialize_i64(i as i64),
+                Literal::Uint(u) => serializer.serialize_u64(u as u64),
+                Literal::Float(f) => serializer.serialize_f64(f),
+            }
+        }
+    }
+
+    struct LiteralVisitor;
+
+    impl<'de> de::Visitor<'de> for LiteralVisitor {
+        type Value = Literal<'de>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("null, a string or a number")
+        }
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(Literal::Null)
+        }
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(Literal::Null)
+        }
+        fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+            Ok(Literal::from(v))
+        }
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(Literal::from(v.to_owned()))
+        }
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            if v < 0 {
+                Ok(Literal::Int(v as isize))
+            } else {
+                Ok(Literal::Uint(v as usize))
+            }
+        }
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(Literal::Uint(v as usize))
+        }
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+            Ok(Literal::Float(v))
+        }
+    }
+
+    impl<'de: 'a, 'a> Deserialize<'de> for Literal<'a> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_any(LiteralVisitor)
+        }
+    }
+}
+
+/// Whitespace and `--` comments that run until the end of the line
+fn padding<'a>() -> impl Parser<'a, &'a str, (), ParsingError<'a>> + Clone {
+    let comment = just("--").then(none_of("\n").repeated()).ignored();
+    choice((comment, text::whitespace().at_least(1)))
+        .repeated()
+        .ignored()
+}
+
+fn null<'a>() -> impl Parser<'a, &'a str, Literal<'a>, ParsingError<'a>> + Clone {
+    just("NULL").to(Literal::Null)
+}
+
+/// A string between quotes, which can contain the escapes \", \\, \n, \t and \uXXXX.
+/// Strings without escapes borrow from the input, the ones with escapes own their unescaped
+/// version
 fn string<'a>() -> impl Parser<'a, &'a str, Literal<'a>, ParsingError<'a>> + Clone {
-    none_of("\"")
+    let unicode = digits(16).exactly(4).to_slice().try_map(|hex: &str, span| {
+        u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| Rich::custom(span, format!("\\u{hex} isn't a valid character")))
+    });
+    let escape = just('\\').ignore_then(choice((
+        just('"'),
+        just('\\'),
+        just('n').to('\n'),
+        just('t').to('\t'),
+        just('u').ignore_then(unicode),
+    )));
+    choice((none_of("\\\""), escape))
         .repeated()
-        .to_slice()
+        .collect::<String>()
+        .map_with(|unescaped, extra| {
+            let raw: &'a str = extra.slice();
+            // Escapes are longer than the character they stand for
+            if raw.len() == unescaped.len() {
+                Literal::from(raw)
+            } else {
+                Literal::from(unescaped)
+            }
+        })
         .delimited_by(just("\""), just("\""))
-        .map(Literal::String)
 }
 
 fn num<'a>() -> impl Parser<'a, &'a str, usize, ParsingError<'a>> + Clone {
     digits(10).to_slice().try_map(|v: &str, span| {
         let digit: Result<usize, _> = v.parse();
-        digit.map_err(|_e| Simple::new(Some('a'.into()), span))
+        digit.map_err(|e| Rich::custom(span, format!("Invalid number {v}: {e}")))
     })
 }
 
@@ -138,35 +310,50 @@ fn integer<'a>() -> impl Parser<'a, &'a str, Literal<'a>, ParsingError<'a>> + Cl
     .map(Literal::Int)
 }
 
+/// 1.5, 1., .5, 1e9 or 6.02e-23
 fn float<'a>() -> impl Parser<'a, &'a str, Literal<'a>, ParsingError<'a>> + Clone {
-    digits(10)
-        .to_slice()
-        .then_ignore(just("."))
-        .then(digits(10).to_slice())
-        .try_map(|(f, s), span| {
-            let mut string = String::from(f);
-            string.push('.');
-            string.push_str(s);
-            let digit: Result<f64, _> = string.parse();
-            digit
-                .map(Literal::Float)
-                .map_err(|_e| Simple::new(Some('a'.into()), span))
-        })
+    let exponent = one_of("eE").then(one_of("+-").or_not()).then(digits(10));
+    choice((
+        digits(10)
+            .then(just("."))
+            .then(digits(10).or_not())
+            .then(exponent.or_not())
+            .ignored(),
+        just(".").then(digits(10)).then(exponent.or_not()).ignored(),
+        digits(10).then(exponent).ignored(),
+    ))
+    .to_slice()
+    .try_map(|f: &str, span| {
+        let digit: Result<f64, _> = f.parse();
+        digit
+            .map(Literal::Float)
+            .map_err(|e| Rich::custom(span, format!("Invalid float {f}: {e}")))
+    })
 }
 
 fn value<'a>() -> impl Parser<'a, &'a str, Literal<'a>, ParsingError<'a>> + Clone {
-    chumsky::primitive::choice((string(), unsigned_integer(), integer(), float()))
+    // Floats go first so that the integer part of a float isn't parsed as an integer
+    chumsky::primitive::choice((null(), string(), float(), unsigned_integer(), integer()))
 }
 
+/// A bare identifier, or any text between double quotes or backticks
 fn ident<'a>() -> impl Parser<'a, &'a str, &'a Identifier, ParsingError<'a>> + Clone {
-    text::ident().map(Identifier::new)
+    // Quoted identifiers can be reserved words or contain spaces
+    let quoted = |quote| {
+        none_of([quote])
+            .repeated()
+            .at_least(1)
+            .to_slice()
+            .delimited_by(just(quote), just(quote))
+    };
+    choice((text::ident(), quoted('"'), quoted('`'))).map(Identifier::new)
 }
 
 fn parentheses<'a, T>(
     parser: impl Parser<'a, &'a str, T, ParsingError<'a>> + Clone,
 ) -> impl Parser<'a, &'a str, Vec<T>, ParsingError<'a>> + Clone {
     parser
-        .separated_by(just(",").padded())
+        .separated_by(just(",").padded_by(padding()))
         .collect::<Vec<_>>()
         .delimited_by(just("("), just(")"))
 }
@@ -176,28 +363,124 @@ fn binary_operation<'a, L, S, R>(
     right: impl Parser<'a, &'a str, R, ParsingError<'a>> + Clone,
     sym: impl Parser<'a, &'a str, S, ParsingError<'a>> + Clone,
 ) -> impl Parser<'a, &'a str, (L, R, S), ParsingError<'a>> + Clone {
-    left.then(sym.padded())
+    left.then(sym.padded_by(padding()))
         .then(right)
         .map(|((l, s), r)| (l, r, s))
 }
 
+/// Column returned by a select
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Column<'a> {
+    Field(&'a Identifier),
+    /// COUNT(*), the number of rows in the group
+    Count,
+}
+
+impl<'a> Column<'a> {
+    /// Returns the field if the column isn't an aggregate
+    pub fn field(&self) -> Option<&'a Identifier> {
+        match self {
+            Self::Field(field) => Some(field),
+            Self::Count => None,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Column<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Field(value.into())
+    }
+}
+
+/// Column of a select along with the name it's returned as
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SelectColumn<'a> {
+    pub column: Column<'a>,
+    /// Name given with `AS alias`, overrides the default name
+    pub alias: Option<&'a Identifier>,
+}
+
+impl<'a> SelectColumn<'a> {
+    /// Returns the name of the column in the results, the field name or COUNT(*) if it
+    /// doesn't have an alias
+    pub fn name(&self) -> &'a str {
+        match (self.alias, self.column) {
+            (Some(alias), _) => alias,
+            (None, Column::Field(field)) => field,
+            (None, Column::Count) => "COUNT(*)",
+        }
+    }
+}
+
+impl<'a, T: Into<Column<'a>>> From<T> for SelectColumn<'a> {
+    fn from(value: T) -> Self {
+        Self {
+            column: value.into(),
+            alias: None,
+        }
+    }
+}
+
+/// Right side of an arithmetic update
+#[derive(Debug, PartialEq, Clone)]
+pub enum Operand<'a> {
+    Field(&'a Identifier),
+    Literal(Literal<'a>),
+}
+
+/// Value assigned to a column by an update
+#[derive(Debug, PartialEq, Clone)]
+pub enum UpdateValue<'a> {
+    Literal(Literal<'a>),
+    /// field op operand, computed from the values of each row before it's updated
+    Computed(&'a Identifier, Arithmetic, Operand<'a>),
+}
+
+impl<'a, T: Into<Literal<'a>>> From<T> for UpdateValue<'a> {
+    fn from(value: T) -> Self {
+        Self::Literal(value.into())
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Operation<'a> {
     Select {
         table: &'a Identifier,
-        columns: Vec<&'a Identifier>,
+        columns: Vec<SelectColumn<'a>>,
+        /// Repeated rows are only returned once
+        distinct: bool,
     },
     Insert {
         table: &'a Identifier,
-        values: Vec<(&'a Identifier, Literal<'a>)>,
+        /// One entry per row that has to be inserted
+        values: Vec<Vec<(&'a Identifier, Literal<'a>)>>,
+        /// Rows whose key is already in the table replace the existing row instead of failing
+        replace: bool,
     },
     Update {
         table: &'a Identifier,
-        values: Vec<(&'a Identifier, Literal<'a>)>,
+        values: Vec<(&'a Identifier, UpdateValue<'a>)>,
+        /// Columns of the updated rows that are returned, the count is returned if it's empty
+        returning: Vec<&'a Identifier>,
     },
     Delete {
         table: &'a Identifier,
     },
+    CreateTable {
+        table: &'a Identifier,
+        primary: (&'a Identifier, Type),
+        fields: Vec<FieldDefinition<'a>>,
+        /// Inserts that omit the primary key are given the next key
+        autoincrement: bool,
+    },
+    DropTable {
+        table: &'a Identifier,
+    },
+    /// Creates a secondary index over a column that isn't the primary key
+    CreateIndex {
+        table: &'a Identifier,
+        column: &'a Identifier,
+    },
 }
 
 impl<'a> Operation<'a> {
@@ -207,74 +490,292 @@ impl<'a> Operation<'a> {
             Self::Insert { table, .. } => table,
             Self::Update { table, .. } => table,
             Self::Delete { table } => table,
+            Self::CreateTable { table, .. } => table,
+            Self::DropTable { table } => table,
+            Self::CreateIndex { table, .. } => table,
         }
     }
 }
 
-/// SELECT a, b, c FROM table
+/// SELECT [DISTINCT] a, b AS c, COUNT(*) FROM table
 fn select<'a>() -> impl Parser<'a, &'a str, Operation<'a>, ParsingError<'a>> + Clone {
-    let columns = ident()
-        .separated_by(just(",").padded())
+    let count = just("COUNT")
+        .then(
+            just("*")
+                .padded_by(padding())
+                .delimited_by(just("("), just(")"))
+                .padded_by(padding()),
+        )
+        .to(Column::Count);
+    let column = choice((count, ident().map(Column::Field)))
+        .then(
+            just("AS")
+                .padded_by(padding())
+                .ignore_then(ident())
+                .or_not(),
+        )
+        .map(|(column, alias)| SelectColumn { column, alias });
+    let columns = column
+        .separated_by(just(",").padded_by(padding()))
         .at_least(1)
         .collect::<Vec<_>>();
 
     just("SELECT")
-        .padded()
-        .ignore_then(columns)
-        .then_ignore(just("FROM").padded())
+        .padded_by(padding())
+        .ignore_then(just("DISTINCT").padded_by(padding()).or_not())
+        .then(columns)
+        .then_ignore(just("FROM").padded_by(padding()))
         .then(ident())
-        .map(|(columns, table)| Operation::Select { columns, table })
+        .map(|((distinct, columns), table)| Operation::Select {
+            columns,
+            table,
+            distinct: distinct.is_some(),
+        })
 }
 
-/// INSERT INTO table (col1, col2) VALUES (1, 2)
+/// INSERT INTO table (col1, col2) VALUES (1, 2), (3, 4) [ON CONFLICT REPLACE]
 fn insert<'a>() -> impl Parser<'a, &'a str, Operation<'a>, ParsingError<'a>> + Clone {
+    let rows = parentheses(value())
+        .padded_by(padding())
+        .separated_by(just(","))
+        .at_least(1)
+        .collect::<Vec<_>>();
     just("INSERT")
-        .padded()
-        .then(just("INTO").padded())
+        .padded_by(padding())
+        .then(just("INTO").padded_by(padding()))
         .ignore_then(ident())
-        .then(parentheses(ident()).padded())
-        .then_ignore(just("VALUES").padded())
-        .then(parentheses(value()).padded())
-        .try_map(|((table, columns), parentheses), span| {
-            if columns.len() != parentheses.len() {
-                Err(Simple::new(Some('a'.into()), span))
+        .then(parentheses(ident()).padded_by(padding()))
+        .then_ignore(just("VALUES").padded_by(padding()))
+        .then(rows)
+        .then(
+            just("ON")
+                .padded_by(padding())
+                .then(just("CONFLICT").padded_by(padding()))
+                .then(just("REPLACE").padded_by(padding()))
+                .or_not()
+                .map(|conflict| conflict.is_some()),
+        )
+        .try_map(|(((table, columns), rows), replace), span| {
+            if rows.iter().any(|row| columns.len() != row.len()) {
+                Err(Rich::custom(
+                    span,
+                    format!(
+                        "Every row needs a value for each of the {} columns",
+                        columns.len()
+                    ),
+                ))
             } else {
-                let values = columns.into_iter().zip(parentheses).collect();
-                Ok(Operation::Insert { table, values })
+                let values = rows
+                    .into_iter()
+                    .map(|row| columns.iter().copied().zip(row).collect())
+                    .collect();
+                Ok(Operation::Insert {
+                    table,
+                    values,
+                    replace,
+                })
             }
         })
 }
 
-/// UPDATE table SET col1 = 1
+/// UPDATE table SET col1 = 1, col2 = col2 + 1, col3 = col1 * col2
 fn update<'a>() -> impl Parser<'a, &'a str, Operation<'a>, ParsingError<'a>> + Clone {
-    let values = binary_operation(ident(), value(), just("=").ignored())
+    let operand = choice((value().map(Operand::Literal), ident().map(Operand::Field)));
+    let computed = binary_operation(ident(), operand, arithmetic())
+        .map(|(field, operand, op)| UpdateValue::Computed(field, op, operand));
+    let update_value = choice((computed, value().map(UpdateValue::Literal)));
+    let values = binary_operation(ident(), update_value, just("=").ignored())
         .map(|(l, r, _)| (l, r))
-        .padded()
+        .padded_by(padding())
         .separated_by(just(","))
         .collect::<Vec<_>>();
     just("UPDATE")
-        .padded()
+        .padded_by(padding())
         .ignore_then(ident())
-        .then_ignore(just("SET").padded())
+        .then_ignore(just("SET").padded_by(padding()))
         .then(values)
-        .map(|(table, values)| Operation::Update { table, values })
+        .map(|(table, values)| Operation::Update {
+            table,
+            values,
+            returning: vec![],
+        })
 }
 
 /// DELETE FROM table
 fn delete<'a>() -> impl Parser<'a, &'a str, Operation<'a>, ParsingError<'a>> + Clone {
     just("DELETE")
-        .padded()
-        .ignore_then(just("FROM").padded())
+        .padded_by(padding())
+        .ignore_then(just("FROM").padded_by(padding()))
         .ignore_then(ident())
         .map(|table| Operation::Delete { table })
 }
 
+fn typ<'a>() -> impl Parser<'a, &'a str, Type, ParsingError<'a>> + Clone {
+    choice((
+        just("UINT").to(Type::Uint),
+        just("INT").to(Type::Int),
+        just("FLOAT").to(Type::Float),
+        just("STRING")
+            .ignore_then(
+                num()
+                    .padded_by(padding())
+                    .delimited_by(just("("), just(")")),
+            )
+            .map(Type::String),
+        just("CSTRING")
+            .ignore_then(
+                num()
+                    .padded_by(padding())
+                    .delimited_by(just("("), just(")")),
+            )
+            .map(Type::CString),
+    ))
+}
+
+#[derive(Clone)]
+enum ColumnModifier<'a> {
+    Primary,
+    Autoincrement,
+    Nullable,
+    Default(Literal<'a>),
+    Generated(&'a Identifier, Arithmetic, Literal<'a>),
+}
+
+/// name STRING(255) [PRIMARY KEY | AUTOINCREMENT | NULL | DEFAULT "value" | AS (field * 2)]
+fn column_definition<'a>()
+-> impl Parser<'a, &'a str, (&'a Identifier, Type, Vec<ColumnModifier<'a>>), ParsingError<'a>> + Clone
+{
+    let modifier = choice((
+        just("PRIMARY")
+            .then(just("KEY").padded_by(padding()))
+            .to(ColumnModifier::Primary),
+        just("AUTOINCREMENT").to(ColumnModifier::Autoincrement),
+        just("NULL").to(ColumnModifier::Nullable),
+        just("DEFAULT")
+            .ignore_then(value().padded_by(padding()))
+            .map(ColumnModifier::Default),
+        just("AS")
+            .ignore_then(
+                ident()
+                    .then(arithmetic().padded_by(padding()))
+                    .then(value())
+                    .padded_by(padding())
+                    .delimited_by(just("("), just(")"))
+                    .padded_by(padding()),
+            )
+            .map(|((source, op), operand)| ColumnModifier::Generated(source, op, operand)),
+    ))
+    .padded_by(padding());
+    ident()
+        .then(typ().padded_by(padding()))
+        .then(modifier.repeated().collect::<Vec<_>>())
+        .map(|((name, typ), modifiers)| (name, typ, modifiers))
+}
+
+/// CREATE TABLE table (id UINT PRIMARY KEY, col1 INT, col2 STRING(20))
+fn create_table<'a>() -> impl Parser<'a, &'a str, Operation<'a>, ParsingError<'a>> + Clone {
+    just("CREATE")
+        .padded_by(padding())
+        .then(just("TABLE").padded_by(padding()))
+        .ignore_then(ident())
+        .then(parentheses(column_definition().padded_by(padding())).padded_by(padding()))
+        .try_map(|(table, columns), span| {
+            let mut primary = None;
+            let mut autoincrement = false;
+            let mut fields = vec![];
+            for (name, typ, modifiers) in columns {
+                let mut field = FieldDefinition::new(name, typ);
+                let mut is_primary = false;
+                let mut is_autoincrement = false;
+                for modifier in modifiers {
+                    match modifier {
+                        ColumnModifier::Primary => is_primary = true,
+                        ColumnModifier::Autoincrement => is_autoincrement = true,
+                        ColumnModifier::Nullable => field = field.nullable(),
+                        ColumnModifier::Default(l) => field = field.default(l),
+                        ColumnModifier::Generated(source, op, operand) => {
+                            field = field.generated(source, op, operand)
+                        }
+                    }
+                }
+                if is_autoincrement && !(is_primary && typ == Type::Uint) {
+                    // Only UINT primary keys can be assigned automatically
+                    return Err(Rich::custom(
+                        span,
+                        "Only UINT primary keys can be AUTOINCREMENT",
+                    ));
+                }
+                if !is_primary {
+                    fields.push(field);
+                } else if primary.is_none()
+                    && !field.nullable
+                    && field.default.is_none()
+                    && field.generated.is_none()
+                {
+                    primary = Some((name, typ));
+                    autoincrement = is_autoincrement;
+                } else if primary.is_some() {
+                    return Err(Rich::custom(span, "Tables can only have one PRIMARY KEY"));
+                } else {
+                    return Err(Rich::custom(
+                        span,
+                        "PRIMARY KEY columns can't be NULLABLE, DEFAULT or GENERATED",
+                    ));
+                }
+            }
+            match primary {
+                Some(primary) => Ok(Operation::CreateTable {
+                    table,
+                    primary,
+                    fields,
+                    autoincrement,
+                }),
+                None => Err(Rich::custom(span, "Tables need a PRIMARY KEY column")),
+            }
+        })
+}
+
+/// DROP TABLE table
+fn drop_table<'a>() -> impl Parser<'a, &'a str, Operation<'a>, ParsingError<'a>> + Clone {
+    just("DROP")
+        .padded_by(padding())
+        .ignore_then(just("TABLE").padded_by(padding()))
+        .ignore_then(ident())
+        .map(|table| Operation::DropTable { table })
+}
+
+/// CREATE INDEX ON table (column)
+fn create_index<'a>() -> impl Parser<'a, &'a str, Operation<'a>, ParsingError<'a>> + Clone {
+    just("CREATE")
+        .padded_by(padding())
+        .ignore_then(just("INDEX").padded_by(padding()))
+        .ignore_then(just("ON").padded_by(padding()))
+        .ignore_then(ident())
+        .then(
+            ident()
+                .padded_by(padding())
+                .delimited_by(just("("), just(")"))
+                .padded_by(padding()),
+        )
+        .map(|(table, column)| Operation::CreateIndex { table, column })
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Statement<'a> {
     pub operation: Operation<'a>,
     pub wher: Option<BoxedExpression<'a>>,
     pub limit: Option<usize>,
     pub skip: Option<usize>,
+    pub order_by: Option<OrderBy<'a>>,
+    /// Column the selected rows are grouped by, aggregates are computed for each group
+    pub group_by: Option<&'a Identifier>,
+}
+
+/// Column the selected rows are sorted by
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct OrderBy<'a> {
+    pub column: &'a Identifier,
+    pub descending: bool,
 }
 
 impl<'a> Statement<'a> {
@@ -284,15 +785,27 @@ impl<'a> Statement<'a> {
             wher: None,
             limit: None,
             skip: None,
+            group_by: None,
+            order_by: None,
         }
     }
 }
 
+fn arithmetic<'a>() -> impl Parser<'a, &'a str, Arithmetic, ParsingError<'a>> + Clone {
+    choice((
+        just("+").to(Arithmetic::Add),
+        just("-").to(Arithmetic::Subtract),
+        just("*").to(Arithmetic::Multiply),
+        just("/").to(Arithmetic::Divide),
+    ))
+}
+
 /// TODO: Make sure that literals correspond to their expected type
 fn comparison<'a>() -> impl Parser<'a, &'a str, Comparison, ParsingError<'a>> + Clone {
     choice((
         just("=").to(Comparison::Equals),
         just("!=").to(Comparison::NotEquals),
+        just("<=>").to(Comparison::NullSafeEquals),
         just("<=").to(Comparison::LessThanEquals),
         just("<").to(Comparison::LessThan),
         just(">=").to(Comparison::MoreThanEquals),
@@ -308,23 +821,74 @@ fn binary_expression<'a>() -> impl Parser<'a, &'a str, Expression<'a>, ParsingEr
     })
 }
 
+/// field IN (1, 2, 3), which compares the field with each of the values using OR
+fn in_expression<'a>() -> impl Parser<'a, &'a str, Expression<'a>, ParsingError<'a>> + Clone {
+    ident()
+        .then_ignore(just("IN").padded_by(padding()))
+        .then(parentheses(value().padded_by(padding())))
+        .try_map(|(left, values), span| {
+            values
+                .into_iter()
+                .map(|right| Expression::binary(left, right, Comparison::Equals))
+                .reduce(|l, r| expression!(l | r))
+                .ok_or_else(|| Rich::custom(span, "IN needs at least one value"))
+        })
+}
+
+/// field LIKE "pattern"
+fn like_expression<'a>() -> impl Parser<'a, &'a str, Expression<'a>, ParsingError<'a>> + Clone {
+    ident()
+        .then_ignore(just("LIKE").padded_by(padding()))
+        .then(
+            none_of("\"")
+                .repeated()
+                .to_slice()
+                .delimited_by(just("\""), just("\"")),
+        )
+        .map(|(left, pattern)| Expression::Like { left, pattern })
+}
+
+/// field IS NULL or field IS NOT NULL
+fn is_null_expression<'a>() -> impl Parser<'a, &'a str, Expression<'a>, ParsingError<'a>> + Clone {
+    ident()
+        .then_ignore(just("IS").padded_by(padding()))
+        .then(just("NOT").padded_by(padding()).or_not())
+        .then_ignore(just("NULL"))
+        .map(|(left, not)| Expression::IsNull {
+            left,
+            negated: not.is_some(),
+        })
+}
+
+/// Boolean combination of conditions. AND binds tighter than OR, both associate to the left
+/// and parentheses can group any part of the expression
 fn expression<'a>() -> impl Parser<'a, &'a str, BoxedExpression<'a>, ParsingError<'a>> + Clone {
     recursive::<_, BoxedExpression<'a>, _, _, _>(|expr| {
-        let and_expr = expr
-            .clone()
-            .then_ignore(just("AND").padded())
-            .then(expr.clone())
-            .delimited_by(just("(").padded(), just(")").padded())
-            .map(|(l, r)| Box::new(expression!(l & r)));
-        let or_expr = expr
-            .clone()
-            .then_ignore(just("OR").padded())
-            .then(expr)
-            .delimited_by(just("(").padded(), just(")").padded())
-            .map(|(l, r)| Box::new(expression!(l | r)));
+        let parenthesized = expr.delimited_by(
+            just("(").padded_by(padding()),
+            just(")").padded_by(padding()),
+        );
+        let in_expr = in_expression().map(Box::new);
+        let like_expr = like_expression().map(Box::new);
+        let is_null_expr = is_null_expression().map(Box::new);
         let binary = binary_expression().map(Box::new);
-
-        choice((and_expr, or_expr, binary)).padded()
+        let atom =
+            choice((parenthesized, in_expr, like_expr, is_null_expr, binary)).padded_by(padding());
+
+        let and_expr = atom.clone().foldl(
+            just("AND")
+                .padded_by(padding())
+                .ignore_then(atom)
+                .repeated(),
+            |l, r| Box::new(expression!(l & r)),
+        );
+        and_expr.clone().foldl(
+            just("OR")
+                .padded_by(padding())
+                .ignore_then(and_expr)
+                .repeated(),
+            |l, r| Box::new(expression!(l | r)),
+        )
     })
 }
 
@@ -332,46 +896,131 @@ enum Clause<'a> {
     Limit(usize),
     Skip(usize),
     Where(BoxedExpression<'a>),
+    OrderBy(OrderBy<'a>),
+    GroupBy(&'a Identifier),
+    Returning(Vec<&'a Identifier>),
 }
 
 fn parse_limit<'a>() -> impl Parser<'a, &'a str, Clause<'a>, ParsingError<'a>> + Clone {
     just("LIMIT")
-        .padded()
-        .ignore_then(num().padded())
+        .padded_by(padding())
+        .ignore_then(num().padded_by(padding()))
         .map(Clause::Limit)
 }
 
+/// SKIP n or OFFSET n
 fn parse_skip<'a>() -> impl Parser<'a, &'a str, Clause<'a>, ParsingError<'a>> + Clone {
-    just("SKIP")
-        .padded()
-        .ignore_then(num().padded())
+    choice((just("SKIP"), just("OFFSET")))
+        .padded_by(padding())
+        .ignore_then(num().padded_by(padding()))
         .map(Clause::Skip)
 }
 
 fn parse_where<'a>() -> impl Parser<'a, &'a str, Clause<'a>, ParsingError<'a>> + Clone {
     just("WHERE")
-        .padded()
+        .padded_by(padding())
         .ignore_then(expression())
         .map(Clause::Where)
 }
 
+fn parse_order_by<'a>() -> impl Parser<'a, &'a str, Clause<'a>, ParsingError<'a>> + Clone {
+    just("ORDER")
+        .padded_by(padding())
+        .ignore_then(just("BY").padded_by(padding()))
+        .ignore_then(ident())
+        .then(
+            choice((just("ASC").to(false), just("DESC").to(true)))
+                .padded_by(padding())
+                .or_not(),
+        )
+        .map(|(column, descending)| {
+            Clause::OrderBy(OrderBy {
+                column,
+                descending: descending.unwrap_or(false),
+            })
+        })
+}
+
+fn parse_group_by<'a>() -> impl Parser<'a, &'a str, Clause<'a>, ParsingError<'a>> + Clone {
+    just("GROUP")
+        .padded_by(padding())
+        .ignore_then(just("BY").padded_by(padding()))
+        .ignore_then(ident().padded_by(padding()))
+        .map(Clause::GroupBy)
+}
+
+/// RETURNING a, b, only allowed in updates
+fn parse_returning<'a>() -> impl Parser<'a, &'a str, Clause<'a>, ParsingError<'a>> + Clone {
+    just("RETURNING")
+        .padded_by(padding())
+        .ignore_then(
+            ident()
+                .padded_by(padding())
+                .separated_by(just(","))
+                .at_least(1)
+                .collect(),
+        )
+        .map(Clause::Returning)
+}
+
 fn parse_clause<'a>() -> impl Parser<'a, &'a str, Clause<'a>, ParsingError<'a>> + Clone {
-    chumsky::primitive::choice((parse_limit(), parse_skip(), parse_where()))
+    chumsky::primitive::choice((
+        parse_limit(),
+        parse_skip(),
+        parse_where(),
+        parse_order_by(),
+        parse_group_by(),
+        parse_returning(),
+    ))
 }
 
 pub fn parser<'a>() -> impl Parser<'a, &'a str, Statement<'a>, ParsingError<'a>> + Clone {
-    let operation_parser = chumsky::primitive::choice((select(), insert(), update(), delete()));
-    operation_parser.map(Statement::new).foldl(
-        parse_clause().repeated(),
-        |mut statement, clause| {
-            match clause {
-                Clause::Skip(s) => statement.skip = Some(s),
-                Clause::Limit(l) => statement.limit = Some(l),
-                Clause::Where(w) => statement.wher = Some(w),
+    let operation_parser = chumsky::primitive::choice((
+        select(),
+        insert(),
+        update(),
+        delete(),
+        create_table(),
+        drop_table(),
+        create_index(),
+    ));
+    operation_parser
+        .map(|operation| (Statement::new(operation), None))
+        .foldl(
+            parse_clause().repeated(),
+            |(mut statement, mut returning), clause| {
+                match clause {
+                    Clause::Skip(s) => statement.skip = Some(s),
+                    Clause::Limit(l) => statement.limit = Some(l),
+                    Clause::Where(w) => statement.wher = Some(w),
+                    Clause::OrderBy(o) => statement.order_by = Some(o),
+                    Clause::GroupBy(g) => statement.group_by = Some(g),
+                    Clause::Returning(columns) => returning = Some(columns),
+                }
+                (statement, returning)
+            },
+        )
+        .try_map(|(mut statement, returning), span| {
+            match (&mut statement.operation, returning) {
+                (_, None) => {}
+                (Operation::Update { returning, .. }, Some(columns)) => *returning = columns,
+                (_, Some(_)) => {
+                    return Err(Rich::custom(span, "Only UPDATE can have RETURNING"));
+                }
             }
-            statement
-        },
-    )
+            Ok(statement)
+        })
+        .then_ignore(padding())
+}
+
+/// Parses several statements separated by `;`, a trailing `;` is allowed
+pub fn parse_script<'a>() -> impl Parser<'a, &'a str, Vec<Statement<'a>>, ParsingError<'a>> + Clone
+{
+    parser()
+        .separated_by(just(";").padded_by(padding()))
+        .allow_trailing()
+        .at_least(1)
+        .collect()
 }
 
 #[cfg(test)]
@@ -423,10 +1072,57 @@ mod tests {
         assert_parse!(float(), str, Literal::Float(4.5))
     }
 
+    #[test]
+    fn test_parse_float_forms() {
+        for (str, expected) in [
+            ("1e9", 1e9),
+            (".5", 0.5),
+            ("1.", 1.0),
+            ("6.02e23", 6.02e23),
+            ("2.5E-3", 2.5e-3),
+            ("1e+2", 100.0),
+        ] {
+            assert_parse!(value(), str, Literal::Float(expected));
+        }
+        assert_parse!(value(), "1", Literal::Uint(1));
+        assert!(value().parse("1e").has_errors());
+        assert!(value().parse(".").has_errors());
+    }
+
     #[test]
     fn test_parse_string() {
         let str = "\"string\"";
-        assert_parse!(string(), str, Literal::String("string"))
+        assert_parse!(string(), str, Literal::from("string"))
+    }
+
+    #[test]
+    fn test_parse_string_escapes() {
+        for (str, expected) in [
+            (r#""say \"hi\"""#, "say \"hi\""),
+            (r#""a\nb""#, "a\nb"),
+            (r#""\ttab \\ slash""#, "\ttab \\ slash"),
+            (r#""\u00e9t\u00E9""#, "été"),
+        ] {
+            assert_parse!(string(), str, Literal::from(expected));
+        }
+        let parsed = |str| string().parse(str).into_output().unwrap();
+        assert!(matches!(
+            parsed(r#""a\nb""#),
+            Literal::String(Cow::Owned(_))
+        ));
+        assert!(matches!(
+            parsed(r#""ab""#),
+            Literal::String(Cow::Borrowed("ab"))
+        ));
+        assert!(string().parse(r#""\x""#).has_errors());
+        assert!(string().parse(r#""\ud800""#).has_errors());
+        assert!(string().parse(r#""unterminated\""#).has_errors());
+    }
+
+    #[test]
+    fn test_parse_null() {
+        let str = "NULL";
+        assert_parse!(value(), str, Literal::Null)
     }
 
     #[test]
@@ -435,6 +1131,16 @@ mod tests {
         assert_parse!(comparison(), str, Comparison::MoreThan)
     }
 
+    #[test]
+    fn test_parse_null_safe_equals() {
+        let str = "field <=> NULL";
+        assert_parse!(
+            binary_expression(),
+            str,
+            Expression::binary("field", Literal::Null, Comparison::NullSafeEquals)
+        );
+    }
+
     #[test]
     fn parse_binary_expression() {
         let str = "id < 5";
@@ -445,6 +1151,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_operator_spacing() {
+        let cases = [
+            ("id<5", "id < 5", "id  <  5", Comparison::LessThan),
+            ("id<=5", "id <= 5", "id\t<=\n5", Comparison::LessThanEquals),
+            ("id>=5", "id >= 5", "id  >=  5", Comparison::MoreThanEquals),
+            ("id=5", "id = 5", "id  =  5", Comparison::Equals),
+            ("id!=5", "id != 5", "id  !=  5", Comparison::NotEquals),
+            (
+                "id<=>5",
+                "id <=> 5",
+                "id  <=>  5",
+                Comparison::NullSafeEquals,
+            ),
+        ];
+        for (tight, normal, loose, sym) in cases {
+            let expected = Expression::binary("id", 5usize, sym);
+            for str in [tight, normal, loose] {
+                assert_parse!(binary_expression(), str, expected.clone());
+                assert_parse!(expression(), str, Box::new(expected.clone()));
+            }
+        }
+
+        let tight = "(id<5 AND (name=2 OR score>-1))";
+        let loose = "(  id  <  5  AND  (  name  =  2  OR  score  >  -1  )  )";
+        assert_eq!(
+            expression().parse(tight).unwrap(),
+            expression().parse(loose).unwrap()
+        );
+
+        let tight = "UPDATE table SET a=1, b=2 WHERE id>=3 LIMIT 2";
+        let loose = "UPDATE table SET a = 1 , b  =  2 WHERE  id  >=  3  LIMIT 2";
+        assert_eq!(
+            parser().parse(tight).unwrap(),
+            parser().parse(loose).unwrap()
+        );
+    }
+
     #[test]
     fn parse_and_expression() {
         let str = "(id < 5 AND (size > 10 AND field = 5))";
@@ -465,6 +1209,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_in_expression() {
+        let str = "id IN (5, 1, 9)";
+        assert_parse!(
+            expression(),
+            str,
+            expression!((("id" = 5usize) | ("id" = 1usize)) | ("id" = 9usize)).into()
+        );
+        assert!(expression().parse("id IN ()").has_errors());
+    }
+
+    #[test]
+    fn parse_like_expression() {
+        let str = "name LIKE \"fo_%\"";
+        assert_parse!(
+            expression(),
+            str,
+            Box::new(Expression::Like {
+                left: "name".into(),
+                pattern: "fo_%"
+            })
+        );
+    }
+
+    #[test]
+    fn parse_is_null_expression() {
+        let is_null = |left: &'static str, negated| {
+            Box::new(Expression::IsNull {
+                left: left.into(),
+                negated,
+            })
+        };
+        assert_parse!(expression(), "score IS NULL", is_null("score", false));
+        assert_parse!(expression(), "score  IS  NOT  NULL", is_null("score", true));
+        assert_parse!(
+            expression(),
+            "id > 1 AND score IS NOT NULL",
+            Box::new(Expression::And(
+                Box::new(Expression::binary("id", 1usize, Comparison::MoreThan)),
+                is_null("score", true)
+            ))
+        );
+        assert!(expression().parse("score IS 5").has_errors());
+    }
+
     #[test]
     fn parse_complex_expression() {
         let str = "(id < 5 OR (size > 10 AND field = 5))";
@@ -475,6 +1264,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_expression_precedence() {
+        let str = "id < 5 AND size > 1 OR field = 2";
+        assert_parse!(
+            expression(),
+            str,
+            expression!((("id" < 5usize) & ("size" > 1usize)) | ("field" = 2usize)).into()
+        );
+
+        let str = "id < 5 OR size > 1 AND field = 2";
+        assert_parse!(
+            expression(),
+            str,
+            expression!(("id" < 5usize) | (("size" > 1usize) & ("field" = 2usize))).into()
+        );
+
+        let str = "id < 5 AND (size > 1 OR field = 2)";
+        assert_parse!(
+            expression(),
+            str,
+            expression!(("id" < 5usize) & (("size" > 1usize) | ("field" = 2usize))).into()
+        );
+
+        // Chains associate to the left
+        let str = "id < 5 AND size > 1 AND field = 2 AND id IN (1, 2)";
+        assert_parse!(
+            expression(),
+            str,
+            expression!(
+                ((("id" < 5usize) & ("size" > 1usize)) & ("field" = 2usize))
+                    & (("id" = 1usize) | ("id" = 2usize))
+            )
+            .into()
+        );
+
+        let str = "((id < 5)) OR size > 1 OR field = 2";
+        assert_parse!(
+            expression(),
+            str,
+            expression!((("id" < 5usize) | ("size" > 1usize)) | ("field" = 2usize)).into()
+        );
+
+        assert!(expression().parse("id < 5 AND").has_errors());
+        assert!(expression().parse("(id < 5 OR size > 1").has_errors());
+    }
+
+    #[test]
+    fn parse_where_before_clauses() {
+        // ORDER starts like OR, the expression has to end before it
+        let str = "SELECT id FROM table WHERE id = 1 OR id = 2 ORDER BY id LIMIT 1";
+        let statement = parser().parse(str).unwrap();
+        assert_eq!(
+            statement.wher,
+            Some(expression!(("id" = 1usize) | ("id" = 2usize)).into())
+        );
+        assert!(statement.order_by.is_some());
+        assert_eq!(statement.limit, Some(1));
+    }
+
     #[test]
     fn test_parse_select() {
         let str = "SELECT col1, col2 FROM table";
@@ -483,6 +1331,7 @@ mod tests {
             str,
             Operation::Select {
                 table: "table".into(),
+                distinct: false,
                 columns: vec!["col1".into(), "col2".into()],
             }
         )
@@ -496,12 +1345,56 @@ mod tests {
             str,
             Operation::Insert {
                 table: "table".into(),
-                values: vec![
+                replace: false,
+                values: vec![vec![
                     ("col1".into(), Literal::Uint(3)),
                     ("col2".into(), Literal::Uint(5))
+                ]],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_insert_on_conflict() {
+        let str = "INSERT INTO table (col1) VALUES (3) ON CONFLICT REPLACE";
+        assert_parse_operation!(
+            parser(),
+            str,
+            Operation::Insert {
+                table: "table".into(),
+                replace: true,
+                values: vec![vec![("col1".into(), Literal::Uint(3))]],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_insert_multiple_rows() {
+        let str = "INSERT INTO table (col1, col2) VALUES (3, 5), (4, \"a\") ,(5,6)";
+        let row = |a: usize, b: Literal<'static>| {
+            vec![("col1".into(), Literal::Uint(a)), ("col2".into(), b)]
+        };
+        assert_parse_operation!(
+            parser(),
+            str,
+            Operation::Insert {
+                table: "table".into(),
+                replace: false,
+                values: vec![
+                    row(3, Literal::Uint(5)),
+                    row(4, Literal::from("a")),
+                    row(5, Literal::Uint(6))
                 ],
             }
         );
+
+        let str = "INSERT INTO table (col1, col2) VALUES (3, 5), (4)";
+        assert!(parser().parse(str).has_errors());
+        let errors = insert().parse(str).into_errors();
+        assert_eq!(
+            errors[0].to_string(),
+            "Every row needs a value for each of the 2 columns"
+        );
     }
 
     #[test]
@@ -513,13 +1406,68 @@ mod tests {
             Operation::Update {
                 table: "table".into(),
                 values: vec![
-                    ("col1".into(), Literal::Uint(0)),
-                    ("col2".into(), Literal::Uint(3))
-                ]
+                    ("col1".into(), Literal::Uint(0).into()),
+                    ("col2".into(), Literal::Uint(3).into())
+                ],
+                returning: vec![]
             }
         );
     }
 
+    #[test]
+    fn test_parse_computed_update() {
+        let str = "UPDATE table SET count = count + 1, total = price * count, name = NULL";
+        let statement = parser().parse(str).unwrap();
+        let Operation::Update { values, .. } = statement.operation else {
+            panic!("Should parse an update");
+        };
+        assert_eq!(
+            values,
+            [
+                (
+                    "count".into(),
+                    UpdateValue::Computed(
+                        "count".into(),
+                        Arithmetic::Add,
+                        Operand::Literal(Literal::Uint(1))
+                    )
+                ),
+                (
+                    "total".into(),
+                    UpdateValue::Computed(
+                        "price".into(),
+                        Arithmetic::Multiply,
+                        Operand::Field("count".into())
+                    )
+                ),
+                ("name".into(), Literal::Null.into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_returning() {
+        let str = "UPDATE table SET col1 = 0 WHERE id > 2 RETURNING id, col1";
+        let statement = parser().parse(str).unwrap();
+        let Operation::Update { returning, .. } = statement.operation else {
+            panic!("Should parse an update");
+        };
+        let names: Vec<&str> = returning.iter().map(|c| &***c).collect();
+        assert_eq!(names, ["id", "col1"]);
+        assert!(statement.wher.is_some());
+
+        assert!(
+            parser()
+                .parse("SELECT id FROM table RETURNING id")
+                .has_errors()
+        );
+        assert!(
+            parser()
+                .parse("UPDATE table SET col1 = 0 RETURNING")
+                .has_errors()
+        );
+    }
+
     #[test]
     fn test_parse_delete() {
         let str = "DELETE FROM table";
@@ -532,11 +1480,137 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_create_table() {
+        let str = "CREATE TABLE table (name STRING(255), id UINT PRIMARY KEY, score FLOAT NULL, \
+                   level INT DEFAULT -1, tag CSTRING(8))";
+        assert_parse_operation!(
+            parser(),
+            str,
+            Operation::CreateTable {
+                table: "table".into(),
+                primary: ("id".into(), Type::Uint),
+                fields: vec![
+                    FieldDefinition::new("name", Type::String(255)),
+                    FieldDefinition::new("score", Type::Float).nullable(),
+                    FieldDefinition::new("level", Type::Int).default(-1isize),
+                    FieldDefinition::new("tag", Type::CString(8)),
+                ],
+                autoincrement: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_generated_column() {
+        let str = "CREATE TABLE table (id UINT PRIMARY KEY, score INT, double INT AS (score * 2))";
+        assert_parse_operation!(
+            parser(),
+            str,
+            Operation::CreateTable {
+                table: "table".into(),
+                primary: ("id".into(), Type::Uint),
+                fields: vec![
+                    FieldDefinition::new("score", Type::Int),
+                    FieldDefinition::new("double", Type::Int).generated(
+                        "score",
+                        Arithmetic::Multiply,
+                        2usize
+                    ),
+                ],
+                autoincrement: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_autoincrement() {
+        let str = "CREATE TABLE table (id UINT PRIMARY KEY AUTOINCREMENT, name INT)";
+        assert_parse_operation!(
+            parser(),
+            str,
+            Operation::CreateTable {
+                table: "table".into(),
+                primary: ("id".into(), Type::Uint),
+                fields: vec![FieldDefinition::new("name", Type::Int)],
+                autoincrement: true,
+            }
+        );
+        let not_primary = "CREATE TABLE table (id UINT PRIMARY KEY, name UINT AUTOINCREMENT)";
+        assert!(parser().parse(not_primary).has_errors());
+        let not_uint = "CREATE TABLE table (id INT PRIMARY KEY AUTOINCREMENT, name INT)";
+        assert!(parser().parse(not_uint).has_errors());
+    }
+
+    #[test]
+    fn test_parse_create_table_primary_key() {
+        let no_primary = "CREATE TABLE table (id UINT, name INT)";
+        assert!(parser().parse(no_primary).has_errors());
+        let two_primaries = "CREATE TABLE table (id UINT PRIMARY KEY, name INT PRIMARY KEY)";
+        assert!(parser().parse(two_primaries).has_errors());
+
+        // Errors explain what's wrong instead of pointing at a character
+        let errors = |str| -> Vec<String> {
+            let errors = create_table().parse(str).into_errors();
+            errors.iter().map(ToString::to_string).collect()
+        };
+        assert_eq!(errors(no_primary), ["Tables need a PRIMARY KEY column"]);
+        assert_eq!(
+            errors(two_primaries),
+            ["Tables can only have one PRIMARY KEY"]
+        );
+    }
+
+    #[test]
+    fn test_parse_drop_table() {
+        let str = "DROP TABLE table";
+        assert_parse_operation!(
+            parser(),
+            str,
+            Operation::DropTable {
+                table: "table".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_create_index() {
+        let str = "CREATE INDEX ON table (name)";
+        assert_parse_operation!(
+            parser(),
+            str,
+            Operation::CreateIndex {
+                table: "table".into(),
+                column: "name".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let expected = parser()
+            .parse("SELECT id FROM table WHERE id < 5 LIMIT 10")
+            .unwrap();
+        let queries = [
+            "-- get ids\nSELECT id FROM table WHERE id < 5 LIMIT 10",
+            "SELECT id -- the ids\n FROM table WHERE id < 5 LIMIT 10",
+            "SELECT id FROM table -- where\nWHERE id < 5 -- limit\n LIMIT 10",
+            "SELECT id FROM table WHERE id < 5 LIMIT 10 -- get ids",
+            "SELECT id FROM table WHERE id < 5 LIMIT 10\n-- one\n-- two\n",
+        ];
+        for query in queries {
+            assert_parse!(parser(), query, expected);
+        }
+
+        assert!(parser().parse("-- only a comment").has_errors());
+    }
+
     #[test]
     fn test_clauses() {
         let str = "SELECT id FROM table LIMIT 10 SKIP 5";
         let operation = Operation::Select {
             table: "table".into(),
+            distinct: false,
             columns: vec!["id".into()],
         };
         assert_parse!(
@@ -546,8 +1620,177 @@ mod tests {
                 operation,
                 wher: None,
                 skip: Some(5),
-                limit: Some(10)
+                limit: Some(10),
+                group_by: None,
+                order_by: None,
             }
         )
     }
+
+    #[test]
+    fn test_parse_distinct() {
+        let str = "SELECT DISTINCT name, score FROM table";
+        assert_parse_operation!(
+            parser(),
+            str,
+            Operation::Select {
+                table: "table".into(),
+                distinct: true,
+                columns: vec!["name".into(), "score".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_group_by() {
+        let str = "SELECT name, COUNT( * ) FROM table GROUP BY name";
+        let statement = parser().parse(str).unwrap();
+        assert_eq!(
+            statement.operation,
+            Operation::Select {
+                table: "table".into(),
+                distinct: false,
+                columns: vec!["name".into(), Column::Count.into()],
+            }
+        );
+        assert_eq!(statement.group_by, Some("name".into()));
+
+        let statement = parser().parse("SELECT COUNT(*) FROM table").unwrap();
+        assert_eq!(statement.group_by, None);
+    }
+
+    #[test]
+    fn test_parse_alias() {
+        let str = "SELECT id AS key, name, COUNT(*) AS total FROM table";
+        assert_parse_operation!(
+            parser(),
+            str,
+            Operation::Select {
+                table: "table".into(),
+                distinct: false,
+                columns: vec![
+                    SelectColumn {
+                        column: "id".into(),
+                        alias: Some("key".into()),
+                    },
+                    "name".into(),
+                    SelectColumn {
+                        column: Column::Count,
+                        alias: Some("total".into()),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_quoted_identifiers() {
+        let str = r#"SELECT "select", `order id` FROM "table" WHERE "order" > 5"#;
+        let statement = parser().parse(str).unwrap();
+        assert_eq!(
+            statement.operation,
+            Operation::Select {
+                table: "table".into(),
+                distinct: false,
+                columns: vec!["select".into(), "order id".into()],
+            }
+        );
+        assert_eq!(
+            statement.wher.as_deref(),
+            Some(&Expression::Binary {
+                left: "order".into(),
+                right: Literal::Uint(5),
+                sym: Comparison::MoreThan,
+            })
+        );
+
+        let str = r#"UPDATE table SET `from` = "where" WHERE "order id" = 1"#;
+        let statement = parser().parse(str).unwrap();
+        let Operation::Update { values, .. } = statement.operation else {
+            panic!("Should parse an update");
+        };
+        assert_eq!(values, [("from".into(), Literal::from("where").into())]);
+        assert!(parser().parse(r#"SELECT "" FROM table"#).has_errors());
+    }
+
+    #[test]
+    fn test_offset() {
+        let skip = parser()
+            .parse("SELECT id FROM table LIMIT 10 SKIP 5")
+            .unwrap();
+        for str in [
+            "SELECT id FROM table LIMIT 10 OFFSET 5",
+            "SELECT id FROM table OFFSET 5 LIMIT 10",
+            "SELECT id FROM table SKIP 5 LIMIT 10",
+        ] {
+            assert_eq!(parser().parse(str).unwrap(), skip, "{str}");
+        }
+
+        let str = "SELECT id FROM table OFFSET 2 WHERE id < 5 LIMIT 0";
+        let statement = parser().parse(str).unwrap();
+        assert_eq!(statement.skip, Some(2));
+        assert_eq!(statement.limit, Some(0));
+        assert!(statement.wher.is_some());
+    }
+
+    #[test]
+    fn test_order_by() {
+        let operation = Operation::Select {
+            table: "table".into(),
+            distinct: false,
+            columns: vec!["id".into()],
+        };
+        let order_by = |descending| {
+            Some(OrderBy {
+                column: "value".into(),
+                descending,
+            })
+        };
+        let queries = [
+            ("SELECT id FROM table ORDER BY value", order_by(false)),
+            (
+                "SELECT id FROM table ORDER BY value ASC LIMIT 3",
+                order_by(false),
+            ),
+            (
+                "SELECT id FROM table ORDER BY value DESC LIMIT 3",
+                order_by(true),
+            ),
+        ];
+        for (query, order_by) in queries {
+            let statement = parser().parse(query).unwrap();
+            assert_eq!(statement.operation, operation);
+            assert_eq!(statement.order_by, order_by);
+        }
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let select = || {
+            Statement::new(Operation::Select {
+                table: "table".into(),
+                distinct: false,
+                columns: vec!["id".into()],
+            })
+        };
+        let delete = || {
+            Statement::new(Operation::Delete {
+                table: "table".into(),
+            })
+        };
+
+        assert_parse!(
+            parse_script(),
+            "SELECT id FROM table; DELETE FROM table",
+            vec![select(), delete()]
+        );
+        assert_parse!(
+            parse_script(),
+            "SELECT id FROM table;\n-- comment\nDELETE FROM table;\n",
+            vec![select(), delete()]
+        );
+        assert_parse!(parse_script(), "SELECT id FROM table", vec![select()]);
+        assert!(parse_script().parse("").has_errors());
+        assert!(parse_script().parse("SELECT id FROM table;;").has_errors());
+    }
 }
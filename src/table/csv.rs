@@ -0,0 +1,200 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    query::Literal,
+    table::{Table, data::Data, metadata::Type},
+};
+
+/// A cell of a CSV record, quoted cells are never NULL
+struct Cell {
+    text: String,
+    quoted: bool,
+}
+
+fn invalid_data(line: usize, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {line}: {message}"),
+    )
+}
+
+/// Splits the text into records along with the line each one starts at.
+/// Quoted cells can contain commas, newlines and quotes written twice
+fn records(text: &str) -> io::Result<Vec<(usize, Vec<Cell>)>> {
+    let mut records = vec![];
+    let mut line = 1;
+    let mut chars = text.chars().peekable();
+    while chars.peek().is_some() {
+        let start = line;
+        let mut cells = vec![];
+        let mut cell = Cell {
+            text: String::new(),
+            quoted: false,
+        };
+        let mut in_quotes = false;
+        loop {
+            match chars.next() {
+                Some('"') if in_quotes && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    cell.text.push('"');
+                }
+                Some('"') if in_quotes => in_quotes = false,
+                Some('"') if cell.text.is_empty() && !cell.quoted => {
+                    in_quotes = true;
+                    cell.quoted = true;
+                }
+                Some(c) if in_quotes => {
+                    line += usize::from(c == '\n');
+                    cell.text.push(c);
+                }
+                None if in_quotes => return Err(invalid_data(start, "unterminated quote")),
+                Some(',') => cells.push(std::mem::replace(
+                    &mut cell,
+                    Cell {
+                        text: String::new(),
+                        quoted: false,
+                    },
+                )),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    line += 1;
+                    break;
+                }
+                Some(c) if cell.quoted => {
+                    return Err(invalid_data(line, format!("{c:?} after a quoted cell")));
+                }
+                Some(c) => cell.text.push(c),
+            }
+        }
+        cells.push(cell);
+        records.push((start, cells));
+    }
+    Ok(records)
+}
+
+/// Parses the cell as a value of the type, an empty unquoted cell is NULL
+fn parse_cell(typ: Type, cell: &Cell) -> Option<Literal<'_>> {
+    if cell.text.is_empty() && !cell.quoted {
+        return Some(Literal::Null);
+    }
+    let literal = match typ {
+        Type::String(_) | Type::CString(_) => Literal::from(cell.text.as_str()),
+        Type::Int => Literal::Int(cell.text.parse().ok()?),
+        Type::Uint => Literal::Uint(cell.text.parse().ok()?),
+        Type::Float => Literal::Float(cell.text.parse().ok()?),
+    };
+    typ.coerce(literal)
+}
+
+/// Writes a cell, strings are always quoted so that an empty cell can be read back as NULL
+fn write_cell<W: Write>(w: &mut W, literal: &Literal) -> io::Result<()> {
+    match literal {
+        Literal::Null => Ok(()),
+        Literal::String(s) => write!(w, "\"{}\"", s.replace('"', "\"\"")),
+        Literal::Int(i) => write!(w, "{i}"),
+        Literal::Uint(u) => write!(w, "{u}"),
+        // Debug prints the shortest representation that parses back to the same float
+        Literal::Float(f) => write!(w, "{f:?}"),
+    }
+}
+
+impl Table {
+    /// Writes the table as CSV, with a header of the field names, primary key included, and a
+    /// row per entry in key order.
+    /// Strings are quoted with their quotes doubled and NULL is written as an empty cell
+    pub fn export_csv<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let fields: Vec<_> = self.metadata.metadata.fields().copied().collect();
+        let names: Vec<_> = fields.iter().map(|f| f.name.str()).collect();
+        writeln!(w, "{}", names.join(","))?;
+        for row in self.rows() {
+            let (key, data) = row?;
+            for (i, field) in fields.iter().enumerate() {
+                if i > 0 {
+                    w.write_all(b",")?;
+                }
+                write_cell(w, &field.read_row(key, data))?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts the rows of a CSV with a header naming the column of each cell, in the format
+    /// written by Table::export_csv. Returns the number of inserted rows.
+    /// Columns that aren't in the header get their default value or NULL, and generated
+    /// columns are ignored. Errors give the line of the row that couldn't be inserted, the rows
+    /// before it are kept
+    pub fn import_csv<R: Read>(&mut self, r: &mut R) -> io::Result<usize> {
+        let mut text = String::new();
+        r.read_to_string(&mut text)?;
+        let mut records = records(&text)?.into_iter();
+        let Some((_, header)) = records.next() else {
+            return Ok(0);
+        };
+        let metadata = &self.metadata.metadata;
+        let columns = header
+            .iter()
+            .map(|cell| {
+                metadata
+                    .field(&cell.text)
+                    .copied()
+                    .ok_or_else(|| invalid_data(1, format!("unknown column {}", cell.text)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let missing_key = metadata
+            .key_fields()
+            .any(|k| !columns.iter().any(|f| f.name.str() == k.name.str()));
+        if missing_key {
+            return Err(invalid_data(1, "missing the primary key column"));
+        }
+
+        // Every row starts with the defaults of the columns that aren't in the header
+        let mut template = vec![0u8; self.entry_size.size];
+        let data = Data::new_mut(&mut template);
+        for field in metadata.data_fields().filter(|f| f.generated.is_none()) {
+            if columns.iter().any(|f| f.name.str() == field.name.str()) {
+                continue;
+            }
+            match metadata.default_value(field) {
+                Some(default) => field.write(&default, data)?,
+                None if field.nullable => field.write(&Literal::Null, data)?,
+                None => {
+                    let message = format!("missing column {}", field.name.str());
+                    return Err(invalid_data(1, message));
+                }
+            }
+        }
+
+        let mut count = 0;
+        for (line, cells) in records {
+            if cells.len() != columns.len() {
+                let message = format!("expected {} cells, found {}", columns.len(), cells.len());
+                return Err(invalid_data(line, message));
+            }
+            let mut row = template.clone();
+            let data = Data::new_mut(&mut row);
+            // The parts of a composite key are combined as their columns are read
+            let mut key = Some(0);
+            for (field, cell) in columns.iter().zip(&cells) {
+                if field.generated.is_some() {
+                    continue;
+                }
+                let name = field.name.str();
+                let value = parse_cell(field.typ, cell)
+                    .filter(|l| field.nullable || *l != Literal::Null)
+                    .ok_or_else(|| invalid_data(line, format!("invalid value for {name}")))?;
+                if field.primary {
+                    key = key
+                        .zip(field.encode_key(&value))
+                        .map(|(key, part)| key | part);
+                } else {
+                    field.write(&value, data)?;
+                }
+            }
+            let key = key.ok_or_else(|| invalid_data(line, "invalid primary key"))?;
+            self.insert(key, &row).map_err(|e| invalid_data(line, e))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
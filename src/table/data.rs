@@ -48,6 +48,17 @@ impl Data {
         self.0[start..end].copy_from_slice(data);
     }
 
+    /// Reads a single byte flag at the given offset
+    #[inline]
+    pub fn flag(&self, offset: usize) -> bool {
+        self.0[offset] != 0
+    }
+
+    #[inline]
+    pub fn set_flag(&mut self, offset: usize, value: bool) {
+        self.0[offset] = value as u8;
+    }
+
     #[inline]
     pub fn get_mut(&mut self, layout: Layout) -> &mut [u8] {
         let start = layout.offset;
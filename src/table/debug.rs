@@ -1,3 +1,5 @@
+use std::io;
+
 use crate::{
     pager::PageNum,
     table::{Table, node::Node},
@@ -8,7 +10,10 @@ fn print_with_indent(str: &str, indentation: usize) {
 }
 
 fn debug_node(table: &Table, page_num: PageNum, indentation: usize) {
-    let node = table.pager.get_page(page_num).page_header().node();
+    let node = match table.pager.get_node_ref(page_num) {
+        Ok(node) => node,
+        Err(e) => return print_with_indent(&format!("{page_num:?}: {e}"), indentation),
+    };
     match node {
         Node::InternalNode(internal) => {
             print_with_indent(
@@ -36,10 +41,10 @@ fn debug_node(table: &Table, page_num: PageNum, indentation: usize) {
                 indentation,
             );
             for i in 0..leaf.num_cells {
-                let cell = leaf.cell_unchecked(i, table.entry_size);
+                let cell = leaf.cell_unchecked(i, table.cell_size);
                 let key = cell.key;
-                let data = cell.data(table.entry_size);
-                let value = usize::from_ne_bytes(data.read_all().try_into().unwrap());
+                let data = cell.data(table.cell_size);
+                let value = u64::from_le_bytes(data.read_all().try_into().unwrap());
                 print_with_indent(&format!("Key: {}, Value: {}", key, value), indentation + 1);
             }
         }
@@ -51,18 +56,35 @@ pub fn debug_table(table: &Table) {
     debug_node(table, root, 0);
 }
 
-pub fn debug_find(table: &Table, key: usize) {
+/// Counts the rows by adding up the cells of every leaf, which Table::len should match
+pub fn count_leaf_cells(table: &Table) -> io::Result<usize> {
+    fn count(table: &Table, page_num: PageNum) -> io::Result<usize> {
+        match table.pager.get_node_ref(page_num)? {
+            Node::InternalNode(internal) => {
+                let children = (0..internal.num_keys).map(|i| internal.cell_unchecked(i).ptr);
+                children
+                    .chain([internal.right_child])
+                    .map(|child| count(table, child))
+                    .sum()
+            }
+            Node::LeafNode(leaf) => Ok(leaf.num_cells),
+        }
+    }
+    count(table, table.get_root())
+}
+
+pub fn debug_find(table: &Table, key: usize) -> io::Result<()> {
     let mut page_num = table.get_root();
-    let mut node = table.pager.get_page(page_num).page_header().node();
+    let mut node = table.pager.get_node_ref(page_num)?;
     println!("Searching for key {}", key);
     while let Node::InternalNode(internal) = node {
         let index = internal.find_index(key);
         println!("Internal: {:?}, found next at index {}", page_num, index);
         page_num = internal.find(key);
-        let page = table.pager.get_page(page_num);
-        node = page.page_header().node();
+        node = table.pager.get_node_ref(page_num)?;
     }
     let leaf = node.leaf().unwrap();
-    let index = leaf.find(key, table.entry_size);
+    let index = leaf.find(key, table.cell_size);
     println!("Leaf: {:?}, found next at index {}", page_num, index);
+    Ok(())
 }
@@ -0,0 +1,130 @@
+use std::{fs, io};
+
+use crate::{
+    query::Literal,
+    table::{
+        Table, TableError, TableResult,
+        data::Data,
+        metadata::{Field, FieldDefinition, Type},
+    },
+};
+
+/// Bits at the bottom of the keys that tell apart the rows whose values have the same hash
+const SLOT_BITS: u32 = 32;
+
+/// Index over a column that isn't the primary key, used to find the rows with a given value.
+/// It's a tree whose keys are the hash of the value followed by a slot, so that rows with equal
+/// values get different keys, and whose values are the primary keys of the rows along with the
+/// value of the column. Values with the same hash share the keys, so the stored values are
+/// compared with the one that is looked up. Keeping the value lets selects that only read the
+/// primary key and the column be answered from the index, see SecondaryIndex::entries
+pub struct SecondaryIndex {
+    pub tree: Table,
+    pub field: Field,
+}
+
+/// Fields of the rows of the index tree, after its key
+const PRIMARY_FIELD: &str = "primary";
+const VALUE_FIELD: &str = "value";
+
+impl SecondaryIndex {
+    pub fn create(data_file: fs::File, metadata_file: fs::File, field: Field) -> io::Result<Self> {
+        let tree = Table::create(
+            data_file,
+            metadata_file,
+            ("key", Type::Uint),
+            &[
+                FieldDefinition::new(PRIMARY_FIELD, Type::Uint),
+                FieldDefinition::new(VALUE_FIELD, field.typ).nullable(),
+            ],
+        )?;
+        Ok(Self { tree, field })
+    }
+
+    pub fn open(data_file: fs::File, metadata_file: fs::File, field: Field) -> io::Result<Self> {
+        let tree = Table::open(data_file, metadata_file)?;
+        Ok(Self { tree, field })
+    }
+
+    /// Hashes the value, using the key encoding for numbers so that equal values hash the same
+    fn hash(&self, value: &Literal) -> usize {
+        let mut hasher = crc32fast::Hasher::new();
+        match (self.field.typ.encode_key(value), value) {
+            (Some(key), _) => hasher.update(&(key as u64).to_le_bytes()),
+            (None, Literal::String(s)) => hasher.update(s.as_bytes()),
+            (None, _) => {}
+        }
+        hasher.finalize() as usize
+    }
+
+    /// Iterates over the keys and rows of the index whose values have the same hash
+    fn bucket(&self, value: &Literal) -> impl Iterator<Item = TableResult<(usize, &Data)>> {
+        let start = self.hash(value) << SLOT_BITS;
+        let end = start + (1 << SLOT_BITS);
+        self.tree.scan(Some(start), Some(end))
+    }
+
+    fn tree_field(&self, name: &str) -> &Field {
+        self.tree
+            .metadata
+            .metadata
+            .field(name)
+            .expect("Index trees have a primary and a value field")
+    }
+
+    fn read_primary(&self, data: &Data) -> usize {
+        match self.tree_field(PRIMARY_FIELD).read(data) {
+            Literal::Uint(primary) => primary,
+            _ => unreachable!("Primary keys are stored as UINT"),
+        }
+    }
+
+    /// Adds the row with that primary key and value of the field
+    pub fn insert(&mut self, value: &Literal, primary: usize) -> TableResult<()> {
+        let start = self.hash(value) << SLOT_BITS;
+        let last = self.bucket(value).last().transpose()?;
+        let key = last.map_or(start, |(key, _)| key + 1);
+        if key >= start + (1 << SLOT_BITS) {
+            return Err(TableError::KeysExhausted);
+        }
+        let mut row = vec![0; self.tree.entry_size.size];
+        let data = Data::new_mut(&mut row);
+        // The value was already written to the row of the table, which has the same type
+        self.tree_field(PRIMARY_FIELD)
+            .write(&Literal::Uint(primary), data)
+            .and_then(|_| self.tree_field(VALUE_FIELD).write(value, data))
+            .expect("Indexed values fit the column");
+        self.tree.insert(key, &row)
+    }
+
+    /// Removes the row with that primary key, which has to have been inserted with the value
+    pub fn remove(&mut self, value: &Literal, primary: usize) -> TableResult<()> {
+        let (key, _) = self
+            .bucket(value)
+            .find(|row| !matches!(row, Ok((_, data)) if self.read_primary(data) != primary))
+            .ok_or(TableError::KeyNotFound)??;
+        self.tree.delete(key)
+    }
+
+    /// Returns the primary keys of the rows that have the value, sorted, along with the value
+    /// stored in the index
+    pub fn entries(&self, value: &Literal) -> TableResult<Vec<(usize, Literal<'_>)>> {
+        let value_field = self.tree_field(VALUE_FIELD);
+        let mut entries = vec![];
+        for row in self.bucket(value) {
+            let (_, data) = row?;
+            let stored = value_field.read(data);
+            if stored.cmp(value).is_eq() {
+                entries.push((self.read_primary(data), stored));
+            }
+        }
+        entries.sort_unstable_by_key(|&(primary, _)| primary);
+        Ok(entries)
+    }
+
+    /// Returns the sorted primary keys of the rows that have the value
+    pub fn find(&self, value: &Literal) -> TableResult<Vec<usize>> {
+        let entries = self.entries(value)?;
+        Ok(entries.into_iter().map(|(primary, _)| primary).collect())
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    fs,
+    io::{self, Read, Seek, Write},
+};
+
+const KEY_SIZE: usize = std::mem::size_of::<u64>();
+
+/// Append-only log of the keys in the order they were inserted.
+/// Keys are kept in memory and appended to the file on flush
+pub struct InsertionLog {
+    file: fs::File,
+    keys: Vec<usize>,
+    flushed: usize,
+}
+
+impl InsertionLog {
+    pub fn open(mut file: fs::File) -> io::Result<Self> {
+        let mut buf = vec![];
+        file.rewind()?;
+        file.read_to_end(&mut buf)?;
+        let keys: Vec<_> = buf
+            .chunks_exact(KEY_SIZE)
+            .map(|k| u64::from_le_bytes(k.try_into().unwrap()) as usize)
+            .collect();
+        let flushed = keys.len();
+        Ok(Self {
+            file,
+            keys,
+            flushed,
+        })
+    }
+
+    #[inline]
+    pub fn append(&mut self, key: usize) {
+        self.keys.push(key);
+    }
+
+    /// Returns the keys in insertion order
+    #[inline]
+    pub fn keys(&self) -> &[usize] {
+        &self.keys
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.flushed == self.keys.len() {
+            return Ok(());
+        }
+        let data: Vec<u8> = self.keys[self.flushed..]
+            .iter()
+            .flat_map(|k| (*k as u64).to_le_bytes())
+            .collect();
+        self.file
+            .seek(io::SeekFrom::Start((self.flushed * KEY_SIZE) as u64))?;
+        self.file.write_all(&data)?;
+        self.file.sync_data()?;
+        self.flushed = self.keys.len();
+        Ok(())
+    }
+}
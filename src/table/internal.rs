@@ -157,6 +157,30 @@ impl<'page> InternalNodeHeader<'page> {
         }
     }
 
+    /// Changes the ptr at this index, the right child if index == num_keys
+    pub fn set_ptr(&mut self, index: usize, ptr: PageNum) {
+        if index == self.num_keys {
+            self.right_child = ptr;
+        } else {
+            self.cell_mut_unchecked(index).ptr = ptr;
+        }
+    }
+
+    /// Returns the index of the child that points to the page
+    pub fn child_index(&self, page_num: PageNum) -> usize {
+        (0..=self.num_keys)
+            .find(|&i| self.ptr(i) == page_num)
+            .expect("Page isn't a child of this node")
+    }
+
+    /// Removes the cell at index, shifting the following cells to the left
+    pub fn remove_at_index(&mut self, index: usize) {
+        for i in index + 1..self.num_keys {
+            self.move_cell(i, i - 1);
+        }
+        self.num_keys -= 1;
+    }
+
     /// Find the page that contains the given key
     pub fn find(&self, key: usize) -> PageNum {
         let index = self.find_index(key);
@@ -167,7 +191,8 @@ impl<'page> InternalNodeHeader<'page> {
         }
     }
 
-    /// Inserts a key and value in the correct place
+    /// Inserts the key after a child was split, ptr is the new right half of the child that
+    /// contained the key
     pub fn insert(&mut self, key: usize, ptr: PageNum) {
         let index = self.find_index(key);
         if index < self.num_keys {
@@ -175,7 +200,10 @@ impl<'page> InternalNodeHeader<'page> {
             for i in (index..self.num_keys).rev() {
                 self.move_cell(i, i + 1);
             }
-            self.cell_mut_unchecked(index).initialize(key, ptr);
+            // The split child keeps the smaller keys, the new one goes after it
+            let left = self.cell_unchecked(index + 1).ptr;
+            self.cell_mut_unchecked(index).initialize(key, left);
+            self.cell_mut_unchecked(index + 1).ptr = ptr;
         } else {
             self.cell_mut_unchecked(index)
                 .initialize(key, self.right_child);
@@ -187,3 +215,5 @@ impl<'page> InternalNodeHeader<'page> {
 
 const FREE_INTERNAL_NODE_SIZE: usize = PAGE_SIZE - INTERNAL_NODE_HEADER_SIZE - PAGE_HEADER_SIZE;
 pub const INTERNAL_NODE_CELL_COUNT: usize = FREE_INTERNAL_NODE_SIZE / INTERNAL_NODE_CELL_SIZE;
+/// Internal nodes with less keys than this get merged or borrow from a sibling on delete
+pub const INTERNAL_NODE_MIN_KEYS: usize = (INTERNAL_NODE_CELL_COUNT - 1) / 2;
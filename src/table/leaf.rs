@@ -171,6 +171,14 @@ impl<'page> LeafNodeHeader<'page> {
         self.num_cells += 1;
     }
 
+    /// Removes the cell at index, shifting the following cells to the left
+    pub fn remove_at_index(&mut self, index: usize, entry_size: Size) {
+        for i in index + 1..self.num_cells {
+            self.move_cell(i, i - 1, entry_size);
+        }
+        self.num_cells -= 1;
+    }
+
     /// Inserts the cell into the leaf node and returns the cell num it was inserted at
     pub fn insert(&mut self, key: usize, value: &[u8], entry_size: Size) -> usize {
         let index = self.find_index(key, entry_size);
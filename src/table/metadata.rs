@@ -2,10 +2,17 @@ use std::{
     fmt::Debug,
     fs,
     io::{self, Read, Seek, Write},
-    ops::Add,
+    ops::{Add, RangeInclusive},
+    str::Utf8Error,
 };
 
-use crate::{pager::PageNum, query::Literal, table::data::Data};
+use crate::{
+    expression::Arithmetic,
+    pager::{PAGE_SIZE, PageNum},
+    query::{Literal, STRING_LENGTH_SIZE},
+    table::data::Data,
+    utils::bloom::{BLOOM_FILTER_WORDS, BloomFilter},
+};
 
 #[derive(Clone, Copy, Default, Debug)]
 pub struct Size {
@@ -25,7 +32,7 @@ impl Add for Size {
 
 impl Size {
     const ALIGN: usize = 8;
-    const fn new(size: usize) -> Size {
+    pub(crate) const fn new(size: usize) -> Size {
         let aligned = (size + Self::ALIGN - 1) & !(Self::ALIGN - 1);
         Self { size, aligned }
     }
@@ -38,8 +45,16 @@ pub struct Layout {
 }
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "length")
+)]
 pub enum Type {
+    /// Length-prefixed string
     String(usize),
+    /// Null-terminated string, can't contain NUL characters
+    CString(usize),
     #[default]
     Int,
     Uint,
@@ -49,57 +64,203 @@ pub enum Type {
 impl Type {
     pub fn size(&self) -> Size {
         match self {
-            Type::String(length) => Type::Uint.size() + Size::new(*length),
+            Type::String(length) => Size::new(STRING_LENGTH_SIZE + *length),
+            Type::CString(length) => Size::new(*length + 1),
             Type::Int => Size::new(std::mem::size_of::<i64>()),
             Type::Uint => Size::new(std::mem::size_of::<u64>()),
             Type::Float => Size::new(std::mem::size_of::<f64>()),
         }
     }
 
+    /// Reads a literal of this type, panicking if a string is corrupt
     pub fn read<'a>(&self, buf: &'a [u8]) -> Literal<'a> {
-        match self {
+        self.try_read(buf).expect("String field is corrupt")
+    }
+
+    /// Reads a literal of this type, failing with InvalidData if a string isn't valid UTF-8,
+    /// its length doesn't fit in the buffer or it's missing its terminator.
+    /// Strings are only validated without the unchecked-utf8 feature
+    pub fn try_read<'a>(&self, buf: &'a [u8]) -> io::Result<Literal<'a>> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        Ok(match self {
             Type::String(_) => {
-                const USIZE_FIELD: usize = std::mem::size_of::<usize>();
-                let length = usize::from_ne_bytes(buf[0..USIZE_FIELD].try_into().unwrap());
-                let str = &buf[USIZE_FIELD..(USIZE_FIELD + length)];
-                Literal::String(unsafe { str::from_utf8_unchecked(str) })
+                let (length, str) = buf
+                    .split_first_chunk::<STRING_LENGTH_SIZE>()
+                    .ok_or_else(|| invalid("String field is too short for its length".into()))?;
+                let length = u32::from_le_bytes(*length) as usize;
+                let str = str.get(..length).ok_or_else(|| {
+                    invalid(format!(
+                        "String of {length} bytes doesn't fit in {} bytes",
+                        str.len()
+                    ))
+                })?;
+                Literal::from(decode_str(str).map_err(|e| invalid(e.to_string()))?)
+            }
+            Type::CString(_) => {
+                let length = buf.iter().position(|&b| b == 0).ok_or_else(|| {
+                    invalid("Null-terminated string is missing its terminator".into())
+                })?;
+                Literal::from(decode_str(&buf[..length]).map_err(|e| invalid(e.to_string()))?)
             }
-            Type::Int => Literal::Int(isize::from_ne_bytes(
+            Type::Int => Literal::Int(i64::from_le_bytes(
                 buf.try_into().expect("Invalid size for parsing int"),
-            )),
-            Type::Uint => Literal::Uint(usize::from_ne_bytes(
+            ) as isize),
+            Type::Uint => Literal::Uint(u64::from_le_bytes(
                 buf.try_into().expect("Invalid size for parsing uint"),
-            )),
-            Type::Float => Literal::Float(f64::from_ne_bytes(
+            ) as usize),
+            Type::Float => Literal::Float(f64::from_le_bytes(
                 buf.try_into().expect("Invalid size for parsing float"),
             )),
+        })
+    }
+
+    /// Writes the literal into the buffer using the encoding of this type.
+    /// Fails if the value doesn't fit in the buffer, or is a null-terminated string with a NUL
+    pub fn write(&self, value: &Literal, buf: &mut [u8]) -> io::Result<()> {
+        match (self, value) {
+            (Type::CString(_), Literal::String(str)) => {
+                let data = str.as_bytes();
+                if data.contains(&0) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Null-terminated strings can't contain NUL",
+                    ));
+                }
+                let len = data.len();
+                if len >= buf.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "String of {len} bytes doesn't fit in {} bytes",
+                            buf.len().saturating_sub(1)
+                        ),
+                    ));
+                }
+                buf[..len].copy_from_slice(data);
+                buf[len] = 0;
+                Ok(())
+            }
+            _ => value.write_to(buf),
+        }
+    }
+
+    /// Converts a literal into the representation stored by this type.
+    /// Integers are converted between signed and unsigned as long as the value fits.
+    /// Returns None if the literal can't be stored in a field of this type
+    pub fn coerce<'a>(&self, literal: Literal<'a>) -> Option<Literal<'a>> {
+        match (self, &literal) {
+            (Type::CString(_), Literal::String(s)) if s.contains('\0') => None,
+            (Type::String(length) | Type::CString(length), Literal::String(s))
+                if s.len() > *length =>
+            {
+                None
+            }
+            (Type::String(_) | Type::CString(_), Literal::String(_))
+            | (Type::Int, Literal::Int(_))
+            | (Type::Uint, Literal::Uint(_))
+            | (Type::Float, Literal::Float(_)) => Some(literal),
+            (Type::Int, &Literal::Uint(u)) => isize::try_from(u).ok().map(Literal::Int),
+            (Type::Uint, &Literal::Int(i)) => usize::try_from(i).ok().map(Literal::Uint),
+            _ => None,
+        }
+    }
+
+    /// Maps a primary key of this type into the usize key space of the tree.
+    /// The encoding preserves the ordering of the values:
+    /// - Uint keys are stored as they are
+    /// - Int keys have their sign bit flipped, so negative values come first
+    /// - Float keys have their sign bit flipped when positive and every bit flipped when negative,
+    ///   NaN is stored above every other value and -0.0 is stored as 0.0
+    ///
+    /// Returns None if the literal isn't of this type or the type can't be used as a key
+    pub fn encode_key(&self, literal: &Literal) -> Option<usize> {
+        const SIGN: u64 = 1 << 63;
+        match (self, literal) {
+            (Type::Uint, &Literal::Uint(u)) => Some(u),
+            (Type::Int, &Literal::Int(i)) => Some((i as i64 as u64 ^ SIGN) as usize),
+            (Type::Float, &Literal::Float(f)) => {
+                let f = match f {
+                    f if f.is_nan() => f64::NAN,
+                    0.0 => 0.0,
+                    f => f,
+                };
+                let bits = f.to_bits();
+                let key = if bits & SIGN == 0 { bits | SIGN } else { !bits };
+                Some(key as usize)
+            }
+            _ => None,
+        }
+    }
+
+    /// Inverse of Type::encode_key
+    pub fn decode_key(&self, key: usize) -> Literal<'static> {
+        const SIGN: u64 = 1 << 63;
+        let key = key as u64;
+        match self {
+            Type::Int => Literal::Int((key ^ SIGN) as i64 as isize),
+            Type::Float => {
+                let bits = if key & SIGN != 0 { key & !SIGN } else { !key };
+                Literal::Float(f64::from_bits(bits))
+            }
+            _ => Literal::Uint(key as usize),
         }
     }
 }
 
-const MAX_NAME_LENGTH: usize = 32;
-#[derive(Clone, Copy, Default)]
+/// Converts stored bytes into a str, validating them unless the unchecked-utf8 feature is enabled
+#[inline]
+fn decode_str(bytes: &[u8]) -> Result<&str, Utf8Error> {
+    #[cfg(feature = "unchecked-utf8")]
+    {
+        Ok(unsafe { str::from_utf8_unchecked(bytes) })
+    }
+    #[cfg(not(feature = "unchecked-utf8"))]
+    {
+        str::from_utf8(bytes)
+    }
+}
+
+pub const MAX_NAME_LENGTH: usize = 64;
+#[derive(Clone, Copy)]
 pub struct Name {
     name_len: u8,
     name: [u8; MAX_NAME_LENGTH],
 }
 
+impl Default for Name {
+    fn default() -> Self {
+        Self {
+            name_len: 0,
+            name: [0; MAX_NAME_LENGTH],
+        }
+    }
+}
+
 impl Name {
-    pub fn new(str: &str) -> Self {
+    /// Fails with InvalidInput if the name is longer than MAX_NAME_LENGTH
+    pub fn new(str: &str) -> io::Result<Self> {
         let mut name = Name::default();
-        name.write(str);
-        name
+        name.write(str)?;
+        Ok(name)
     }
 
     pub fn str(&self) -> &str {
         let bytes = &self.name[..self.name_len as usize];
-        unsafe { str::from_utf8_unchecked(bytes) }
+        decode_str(bytes).expect("Name contains invalid UTF-8")
     }
 
-    pub fn write(&mut self, name: &str) {
+    /// Fails with InvalidInput if the name is longer than MAX_NAME_LENGTH, leaving the old one
+    pub fn write(&mut self, name: &str) -> io::Result<()> {
         let len = name.len();
+        if len > MAX_NAME_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Field name {name} is longer than {MAX_NAME_LENGTH} bytes"),
+            ));
+        }
         self.name[..len].copy_from_slice(name.as_bytes());
         self.name_len = len as u8;
+        Ok(())
     }
 }
 
@@ -109,63 +270,589 @@ impl Debug for Name {
     }
 }
 
+pub const MAX_DESCRIPTION_LENGTH: usize = 256;
+/// Free text describing a table, stored inline like Name
+#[derive(Clone, Copy)]
+pub struct Description {
+    len: u16,
+    text: [u8; MAX_DESCRIPTION_LENGTH],
+}
+
+impl Default for Description {
+    fn default() -> Self {
+        Self {
+            len: 0,
+            text: [0; MAX_DESCRIPTION_LENGTH],
+        }
+    }
+}
+
+impl Description {
+    /// Returns None if the text doesn't fit
+    pub fn new(text: &str) -> Option<Self> {
+        let len = text.len();
+        if len > MAX_DESCRIPTION_LENGTH {
+            return None;
+        }
+        let mut description = Self::default();
+        description.text[..len].copy_from_slice(text.as_bytes());
+        description.len = len as u16;
+        Some(description)
+    }
+
+    pub fn str(&self) -> &str {
+        let bytes = &self.text[..self.len as usize];
+        decode_str(bytes).expect("Description contains invalid UTF-8")
+    }
+}
+
+impl Debug for Description {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.str())
+    }
+}
+
+/// Describes a field when creating a table
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldDefinition<'a> {
+    pub name: &'a str,
+    pub typ: Type,
+    pub nullable: bool,
+    pub default: Option<Literal<'a>>,
+    /// Field, operation and number that the value is computed from, if it isn't stored
+    pub generated: Option<(&'a str, Arithmetic, Literal<'a>)>,
+}
+
+impl<'a> FieldDefinition<'a> {
+    pub fn new(name: &'a str, typ: Type) -> Self {
+        Self {
+            name,
+            typ,
+            nullable: false,
+            default: None,
+            generated: None,
+        }
+    }
+
+    /// Allows the field to hold NULL values
+    pub fn nullable(mut self) -> Self {
+        self.nullable = true;
+        self
+    }
+
+    /// Sets the value used when an insert doesn't specify this field
+    pub fn default(mut self, value: impl Into<Literal<'a>>) -> Self {
+        self.default = Some(value.into());
+        self
+    }
+
+    /// Computes the field on read by applying the operation to another field and the operand,
+    /// so it isn't stored and can't be written to
+    pub fn generated(
+        mut self,
+        source: &'a str,
+        op: Arithmetic,
+        operand: impl Into<Literal<'a>>,
+    ) -> Self {
+        self.generated = Some((source, op, operand.into()));
+        self
+    }
+}
+
+impl<'a> From<(&'a str, Type)> for FieldDefinition<'a> {
+    fn from((name, typ): (&'a str, Type)) -> Self {
+        Self::new(name, typ)
+    }
+}
+
+/// Change of a column between two schemas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SchemaChange<'a> {
+    Added(&'a str, Type),
+    Removed(&'a str, Type),
+    Retyped { name: &'a str, from: Type, to: Type },
+}
+
+/// How a generated field computes its value from a stored field of the same row
+#[derive(Clone, Copy, Debug)]
+pub struct Generation {
+    pub source_layout: Layout,
+    pub source_type: Type,
+    /// Offset of the NULL flag of the source field, None if it isn't nullable
+    pub source_null_offset: Option<usize>,
+    pub op: Arithmetic,
+    /// Always a number of the generated field's type
+    pub operand: Number,
+}
+
+/// Number stored as a tag and its bits. Unlike a Literal it has no pointers and every bit
+/// pattern is valid, so it can be read back from the metadata file and checked there
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Number {
+    tag: u8,
+    bits: u64,
+}
+
+impl Number {
+    const INT: u8 = 1;
+    const UINT: u8 = 2;
+    const FLOAT: u8 = 3;
+
+    /// Returns None if the literal isn't a number
+    pub fn new(value: Literal) -> Option<Self> {
+        let (tag, bits) = match value {
+            Literal::Int(i) => (Self::INT, i as i64 as u64),
+            Literal::Uint(u) => (Self::UINT, u as u64),
+            Literal::Float(f) => (Self::FLOAT, f.to_bits()),
+            _ => return None,
+        };
+        Some(Self { tag, bits })
+    }
+
+    /// Decodes the number, None if the tag isn't valid or it doesn't fit in a usize
+    pub fn literal(&self) -> Option<Literal<'static>> {
+        match self.tag {
+            Self::INT => isize::try_from(self.bits as i64).ok().map(Literal::Int),
+            Self::UINT => usize::try_from(self.bits).ok().map(Literal::Uint),
+            Self::FLOAT => Some(Literal::Float(f64::from_bits(self.bits))),
+            _ => None,
+        }
+    }
+
+    /// True if the number decodes to a value of the type
+    fn is_valid(&self, typ: Type) -> bool {
+        let tag = match typ {
+            Type::Int => Self::INT,
+            Type::Uint => Self::UINT,
+            Type::Float => Self::FLOAT,
+            _ => return false,
+        };
+        self.tag == tag && self.literal().is_some()
+    }
+}
+
+impl Generation {
+    fn eval(&self, typ: Type, buf: &Data) -> Literal<'static> {
+        if self
+            .source_null_offset
+            .is_some_and(|offset| buf.flag(offset))
+        {
+            return Literal::Null;
+        }
+        let source = self.source_type.read(buf.read(self.source_layout));
+        match (typ.coerce(source), self.operand.literal()) {
+            (Some(value), Some(operand)) => self.op.apply(value, operand),
+            _ => Literal::Null,
+        }
+    }
+}
+
+/// Bits of the key that store the value of a primary field. A single primary field takes the
+/// whole key, the fields of a composite key split it evenly with the first one in the most
+/// significant bits, so keys are ordered by the fields in the order they were declared
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyPart {
+    pub shift: u32,
+    pub bits: u32,
+}
+
+impl Default for KeyPart {
+    fn default() -> Self {
+        Self::WHOLE
+    }
+}
+
+impl KeyPart {
+    pub const WHOLE: Self = Self {
+        shift: 0,
+        bits: usize::BITS,
+    };
+
+    /// Bits of the key that belong to this part
+    #[inline]
+    pub fn mask(&self) -> usize {
+        (usize::MAX >> (usize::BITS - self.bits)) << self.shift
+    }
+
+    /// Smallest INT of a part, it's stored as 0 so that the order is kept
+    #[inline]
+    fn int_offset(&self) -> i64 {
+        1 << (self.bits - 1)
+    }
+
+    fn encode(&self, typ: Type, value: &Literal) -> Option<usize> {
+        if *self == Self::WHOLE {
+            return typ.encode_key(value);
+        }
+        let part = match (typ, value) {
+            (Type::Uint, &Literal::Uint(u)) => u,
+            (Type::Int, &Literal::Int(i)) => {
+                (i as i64).checked_add(self.int_offset())?.try_into().ok()?
+            }
+            _ => return None,
+        };
+        // Only WHOLE has all of the bits, so the shift can't overflow
+        (part >> self.bits == 0).then_some(part << self.shift)
+    }
+
+    fn decode(&self, typ: Type, key: usize) -> Literal<'static> {
+        if *self == Self::WHOLE {
+            return typ.decode_key(key);
+        }
+        let part = (key & self.mask()) >> self.shift;
+        match typ {
+            Type::Int => Literal::Int((part as i64 - self.int_offset()) as isize),
+            _ => Literal::Uint(part),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default, Debug)]
 pub struct Field {
     pub primary: bool,
+    /// Where the value of a primary field is stored in the key
+    pub key_part: KeyPart,
+    pub nullable: bool,
+    pub has_default: bool,
     pub layout: Layout,
+    /// Offset of the byte that flags the field as NULL, only used if the field is nullable
+    pub null_offset: usize,
     pub name: Name,
     pub typ: Type,
+    /// Set if the value is computed from another field instead of being stored
+    pub generated: Option<Generation>,
+    /// Set if the table keeps a secondary index over this field
+    pub indexed: bool,
 }
 
 impl Field {
     pub fn read<'a>(&self, buf: &'a Data) -> Literal<'a> {
+        self.try_read(buf).expect("String field is corrupt")
+    }
+
+    /// Like Field::read, but fails if a string is corrupt, see Type::try_read
+    pub fn try_read<'a>(&self, buf: &'a Data) -> io::Result<Literal<'a>> {
         assert!(!self.primary, "Can't read primary fields");
-        let field_buf = buf.read(self.layout);
-        self.typ.read(field_buf)
+        if let Some(generation) = &self.generated {
+            return Ok(generation.eval(self.typ, buf));
+        }
+        if self.is_null(buf) {
+            return Ok(Literal::Null);
+        }
+        self.typ.try_read(buf.read(self.layout))
+    }
+
+    /// Reads the value of this field for the row, decoding the key if this is the primary field
+    pub fn read_row<'a>(&self, key: usize, buf: &'a Data) -> Literal<'a> {
+        if self.primary {
+            self.decode_key(key)
+        } else {
+            self.read(buf)
+        }
+    }
+
+    /// Encodes the value of a primary field into its part of the key, the other bits are 0.
+    /// Returns None if the value isn't of the field's type or doesn't fit in its part
+    pub fn encode_key(&self, value: &Literal) -> Option<usize> {
+        assert!(self.primary, "Only primary fields are stored in the key");
+        self.key_part.encode(self.typ, value)
+    }
+
+    /// Reads the value of a primary field from its part of the key
+    pub fn decode_key(&self, key: usize) -> Literal<'static> {
+        assert!(self.primary, "Only primary fields are stored in the key");
+        self.key_part.decode(self.typ, key)
     }
 
-    pub fn write(&self, value: &Literal, buf: &mut Data) {
+    pub fn write(&self, value: &Literal, buf: &mut Data) -> io::Result<()> {
         assert!(!self.primary, "Can't write primary fields");
+        assert!(self.generated.is_none(), "Can't write generated fields");
+        if self.nullable {
+            buf.set_flag(self.null_offset, matches!(value, Literal::Null));
+        } else {
+            assert!(
+                !matches!(value, Literal::Null),
+                "Can't write NULL to a field that isn't nullable"
+            );
+        }
         let field_buf = buf.get_mut(self.layout);
-        value.write_to(field_buf);
+        self.typ.write(value, field_buf)
+    }
+
+    /// Returns true if the value of this field is NULL
+    #[inline]
+    pub fn is_null(&self, buf: &Data) -> bool {
+        self.nullable && buf.flag(self.null_offset)
     }
 }
 
-const MAX_FIELDS: usize = 64;
+/// Maximum number of fields of a table, the primary key included
+pub const MAX_FIELDS: usize = 64;
+/// Maximum number of primary fields of a composite key
+pub const MAX_KEY_FIELDS: usize = 4;
+const MAX_DEFAULTS_SIZE: usize = PAGE_SIZE;
 pub struct Metadata {
     pub root: PageNum,
     pub num_fields: usize,
     pub fields: [Field; MAX_FIELDS],
+    /// An entry containing the default values of the fields that have one
+    defaults: [u8; MAX_DEFAULTS_SIZE],
+    /// Filter over the primary keys, used to skip lookups of keys that don't exist
+    pub bloom_filter: Option<BloomFilter>,
+    /// True if the table keeps a log of the keys in insertion order
+    pub insertion_log: bool,
+    /// Optional description of the table, empty if it doesn't have one
+    pub description: Description,
+    /// Inserts that would make the tree taller than this fail
+    pub max_height: Option<usize>,
+    /// Key after the biggest one ever inserted, so autoincrement keys aren't reused after deletes
+    pub next_autoincrement: usize,
+    /// True if inserts that omit the primary key are given the next autoincrement key
+    pub autoincrement: bool,
+    /// Number of rows in the table, kept up to date by inserts and deletes
+    pub row_count: usize,
 }
 
 impl Metadata {
-    /// Create a new metadata struct with the corresponding fields.
-    pub fn new(root: PageNum, primary_field: (&str, Type), fields: &[(&str, Type)]) -> Self {
+    /// Checks that the fields fit in the metadata: there are at most MAX_FIELDS of them, primary
+    /// key included, and no name is longer than MAX_NAME_LENGTH
+    pub fn check_definitions<'f, F>(primary_field: (&str, Type), fields: &[F]) -> io::Result<()>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
+        Self::check_key_definitions(&[primary_field], fields)
+    }
+
+    /// Like Metadata::check_definitions for a key made of the primary fields. Composite keys
+    /// can have at most MAX_KEY_FIELDS fields, which have to be UINT or INT
+    pub fn check_key_definitions<'f, F>(
+        primary_fields: &[(&str, Type)],
+        fields: &[F],
+    ) -> io::Result<()>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
+        let invalid_input = |message| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        if primary_fields.is_empty() || primary_fields.len() > MAX_KEY_FIELDS {
+            return invalid_input(format!(
+                "Keys have to be made of 1 to {MAX_KEY_FIELDS} fields, found {}",
+                primary_fields.len()
+            ));
+        }
+        if primary_fields.len() > 1
+            && let Some((name, typ)) = primary_fields
+                .iter()
+                .find(|(_, typ)| !matches!(typ, Type::Uint | Type::Int))
+        {
+            return invalid_input(format!(
+                "Composite keys can only have UINT and INT fields, {name} is {typ:?}"
+            ));
+        }
+        let num_fields = fields.len() + primary_fields.len();
+        if num_fields > MAX_FIELDS {
+            return invalid_input(format!(
+                "Tables can't have more than {MAX_FIELDS} fields, found {num_fields}"
+            ));
+        }
+        let names = fields.iter().map(|f| f.clone().into().name);
+        match primary_fields
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(names)
+            .find(|name| name.len() > MAX_NAME_LENGTH)
+        {
+            Some(name) => invalid_input(format!(
+                "Field name {name} is longer than {MAX_NAME_LENGTH} bytes"
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Create a new metadata struct with the corresponding fields, the primary field goes first.
+    /// Fails with InvalidInput if they don't pass Metadata::check_definitions.
+    /// The values of the other fields are laid out in the order they are given, without gaps,
+    /// and the NULL flags of nullable fields are stored after all of the values
+    pub fn new<'f, F>(root: PageNum, primary_field: (&str, Type), fields: &[F]) -> io::Result<Self>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
+        Self::with_primary_at(root, 0, primary_field, fields)
+    }
+
+    /// Like Metadata::new, but the key is made of the primary fields, which go first in the
+    /// order they are given. Fails if they don't pass Metadata::check_key_definitions.
+    /// Each field of a composite key takes an equal part of the key's bits, see KeyPart
+    pub fn with_key<'f, F>(
+        root: PageNum,
+        primary_fields: &[(&str, Type)],
+        fields: &[F],
+    ) -> io::Result<Self>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
+        Self::layout(root, 0, primary_fields, fields)
+    }
+
+    /// Like Metadata::new, but the primary field is placed before fields[position] instead of
+    /// first. The primary field is stored as the key, so it's skipped when laying out the values
+    pub fn with_primary_at<'f, F>(
+        root: PageNum,
+        position: usize,
+        primary_field: (&str, Type),
+        fields: &[F],
+    ) -> io::Result<Self>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
+        Self::layout(root, position, &[primary_field], fields)
+    }
+
+    /// Places the primary fields before fields[position] and lays out the rest
+    fn layout<'f, F>(
+        root: PageNum,
+        position: usize,
+        primary_fields: &[(&str, Type)],
+        fields: &[F],
+    ) -> io::Result<Self>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
+        Self::check_key_definitions(primary_fields, fields)?;
+        let num_keys = primary_fields.len();
+        assert!(
+            position <= fields.len(),
+            "Primary field position {position} is after the {} fields",
+            fields.len()
+        );
+        // Index in Metadata::fields of each of the definitions
+        let keys = position..position + num_keys;
+        let slots = (0..fields.len() + num_keys).filter(|slot| !keys.contains(slot));
+        let definitions = || {
+            fields
+                .iter()
+                .cloned()
+                .map(Into::<FieldDefinition>::into)
+                .zip(slots.clone())
+        };
         let mut metadata = Self {
             root,
-            num_fields: fields.len() + 1,
+            num_fields: fields.len() + num_keys,
             fields: [Field::default(); MAX_FIELDS],
+            defaults: [0; MAX_DEFAULTS_SIZE],
+            bloom_filter: None,
+            insertion_log: false,
+            description: Description::default(),
+            max_height: None,
+            next_autoincrement: 0,
+            autoincrement: false,
+            row_count: 0,
         };
-        let (name, typ) = primary_field;
-        let primary = &mut metadata.fields[0];
-        primary.primary = true;
-        primary.name.write(name);
-        primary.typ = typ;
+        let bits = usize::BITS / num_keys as u32;
+        for (i, &(name, typ)) in primary_fields.iter().enumerate() {
+            let primary = &mut metadata.fields[position + i];
+            primary.primary = true;
+            primary.key_part = match num_keys {
+                1 => KeyPart::WHOLE,
+                _ => KeyPart {
+                    shift: (num_keys - 1 - i) as u32 * bits,
+                    bits,
+                },
+            };
+            primary.name.write(name)?;
+            primary.typ = typ;
+        }
 
         let mut offset = 0;
-        fields
-            .iter()
-            .copied()
-            .zip(metadata.fields[1..].iter_mut())
-            .for_each(|((name, typ), f)| {
-                f.name.write(name);
-                f.typ = typ;
-                let size = typ.size();
-                f.layout = Layout { offset, size };
-                offset += size.aligned;
+        for (definition, slot) in definitions() {
+            let f = &mut metadata.fields[slot];
+            f.name.write(definition.name)?;
+            f.typ = definition.typ;
+            if definition.generated.is_some() {
+                // Generated fields aren't stored, so they don't take space in the entry
+                continue;
+            }
+            f.nullable = definition.nullable;
+            let size = definition.typ.size();
+            f.layout = Layout { offset, size };
+            offset += size.aligned;
+        }
+        let values_size = offset;
+        metadata.fields[..metadata.num_fields]
+            .iter_mut()
+            .filter(|f| f.nullable)
+            .for_each(|f| {
+                f.null_offset = offset;
+                offset += 1;
+            });
+
+        definitions()
+            .filter_map(|(definition, i)| definition.generated.map(|g| (i, g)))
+            .for_each(|(i, (source, op, operand))| {
+                let typ = metadata.fields[i].typ;
+                assert!(
+                    matches!(typ, Type::Int | Type::Uint | Type::Float),
+                    "Generated fields have to be numbers"
+                );
+                let source = metadata
+                    .field(source)
+                    .expect("Generated field source doesn't exist");
+                assert!(
+                    !source.primary && source.generated.is_none(),
+                    "Generated fields have to be computed from a stored field"
+                );
+                let operand = typ
+                    .coerce(operand)
+                    .and_then(Number::new)
+                    .expect("Generated field operand doesn't match the field type");
+                let generation = Generation {
+                    source_layout: source.layout,
+                    source_type: source.typ,
+                    source_null_offset: source.nullable.then_some(source.null_offset),
+                    op,
+                    operand,
+                };
+                metadata.fields[i].generated = Some(generation);
+            });
+
+        let values_end = metadata
+            .data_fields()
+            .filter(|f| f.generated.is_none())
+            .fold(0, |end, f| {
+                assert!(
+                    f.layout.offset == end,
+                    "Field {} is stored at {}, expected {end}",
+                    f.name.str(),
+                    f.layout.offset
+                );
+                end + f.layout.size.aligned
+            });
+        assert_eq!(values_end, values_size, "Values don't fill the entry");
+
+        let entry_size = metadata.entry_size().size;
+        definitions()
+            .filter_map(|(definition, slot)| definition.default.map(|d| (d, slot)))
+            .for_each(|(default, slot)| {
+                let f = &mut metadata.fields[slot];
+                assert!(
+                    entry_size <= MAX_DEFAULTS_SIZE,
+                    "Entry is too big to store default values"
+                );
+                let default = match default {
+                    Literal::Null => {
+                        assert!(f.nullable, "Only nullable fields can default to NULL");
+                        default
+                    }
+                    _ => f
+                        .typ
+                        .coerce(default)
+                        .expect("Default value doesn't match the field type"),
+                };
+                f.has_default = true;
+                let data = Data::new_mut(&mut metadata.defaults[..entry_size]);
+                f.write(&default, data)
+                    .expect("Default value doesn't fit in the field");
             });
-        metadata.fields[0].primary = true;
-        metadata
+        Ok(metadata)
     }
     #[inline]
     pub fn field(&self, name: &str) -> Option<&Field> {
@@ -175,20 +862,482 @@ impl Metadata {
     pub fn fields(&self) -> impl Iterator<Item = &Field> + Clone {
         self.fields.iter().take(self.num_fields)
     }
+    /// Returns the primary field, the first one of a composite key
+    #[inline]
+    pub fn primary(&self) -> &Field {
+        self.fields()
+            .find(|f| f.primary)
+            .expect("Primary field not found")
+    }
+    /// Iterates over the fields that make up the key, in the order they were declared
+    #[inline]
+    pub fn key_fields(&self) -> impl Iterator<Item = &Field> + Clone {
+        self.fields().filter(|f| f.primary)
+    }
+    /// Returns true if the key is made of more than one field
+    #[inline]
+    pub fn is_composite_key(&self) -> bool {
+        self.primary().key_part != KeyPart::WHOLE
+    }
+    /// Encodes the values of the primary fields, in the order of Metadata::key_fields, into
+    /// the key of the row. Returns None if a value is missing or can't be encoded
+    pub fn encode_key(&self, values: &[Literal]) -> Option<usize> {
+        if values.len() != self.key_fields().count() {
+            return None;
+        }
+        self.key_prefix_range(values).map(|range| *range.start())
+    }
+    /// Returns the keys of the rows whose leading primary fields have the values, the prefix
+    /// can be shorter than the key. Returns None if a value can't be encoded
+    pub fn key_prefix_range(&self, prefix: &[Literal]) -> Option<RangeInclusive<usize>> {
+        let mut key_fields = self.key_fields();
+        if prefix.len() > key_fields.clone().count() {
+            return None;
+        }
+        // The prefix goes first so zip doesn't take the field after it
+        let start = prefix
+            .iter()
+            .zip(key_fields.by_ref())
+            .try_fold(0, |key, (value, field)| {
+                Some(key | field.encode_key(value)?)
+            })?;
+        let end = key_fields.fold(start, |key, field| key | field.key_part.mask());
+        Some(start..=end)
+    }
+    /// Iterates over the fields that aren't the primary key, in the order they were declared.
+    /// Metadata::new lays their values out in this order, so the stored ones come in ascending
+    /// layout.offset. Generated fields aren't stored and have an empty layout at offset 0
     #[inline]
     pub fn data_fields(&self) -> impl Iterator<Item = &Field> + Clone {
         self.fields().filter(|f| !f.primary)
     }
+    /// Lists the columns that were added, removed or retyped to get from this schema to the other
+    pub fn diff<'a>(&'a self, other: &'a Metadata) -> Vec<SchemaChange<'a>> {
+        let mut changes: Vec<_> = self
+            .fields()
+            .filter_map(|f| {
+                let name = f.name.str();
+                match other.field(name) {
+                    None => Some(SchemaChange::Removed(name, f.typ)),
+                    Some(o) if o.typ != f.typ => Some(SchemaChange::Retyped {
+                        name,
+                        from: f.typ,
+                        to: o.typ,
+                    }),
+                    Some(_) => None,
+                }
+            })
+            .collect();
+        changes.extend(
+            other
+                .fields()
+                .filter(|o| self.field(o.name.str()).is_none())
+                .map(|o| SchemaChange::Added(o.name.str(), o.typ)),
+        );
+        changes
+    }
+
+    /// Returns the default value of the field, None if it doesn't have one
+    pub fn default_value(&self, field: &Field) -> Option<Literal<'_>> {
+        if field.has_default {
+            let defaults = Data::new_ref(&self.defaults[..self.entry_size().size]);
+            Some(field.read(defaults))
+        } else {
+            None
+        }
+    }
+    /// CRC32 of the names, types and flags of the fields, tables with a different hash can't
+    /// read each other's entries
+    pub fn schema_hash(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        for field in self.fields() {
+            hasher.update(field.name.str().as_bytes());
+            hasher.update(format!("{:?}", field.typ).as_bytes());
+            hasher.update(&[
+                field.primary as u8,
+                field.nullable as u8,
+                field.generated.is_some() as u8,
+            ]);
+        }
+        hasher.finalize()
+    }
+
     #[inline]
     pub fn entry_size(&self) -> Size {
-        self.fields().fold(Size::default(), |acc, field| {
+        let values = self.fields().fold(Size::default(), |acc, field| {
             if field.primary {
                 acc
             } else {
                 acc + field.layout.size
             }
+        });
+        let null_flags = self.data_fields().filter(|f| f.nullable).count();
+        if null_flags > 0 {
+            values + Size::new(null_flags)
+        } else {
+            values
+        }
+    }
+}
+
+/// Writes the metadata field by field, integers in little endian and options and enums as a tag
+/// byte followed by their contents. MetadataReader decodes it back
+#[derive(Default)]
+struct MetadataWriter(Vec<u8>);
+
+impl MetadataWriter {
+    fn u8(&mut self, n: u8) {
+        self.0.push(n);
+    }
+
+    fn u32(&mut self, n: u32) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn u64(&mut self, n: u64) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn usize(&mut self, n: usize) {
+        self.u64(n as u64);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+
+    /// Length followed by the bytes
+    fn str(&mut self, str: &str) {
+        self.usize(str.len());
+        self.0.extend_from_slice(str.as_bytes());
+    }
+
+    fn typ(&mut self, typ: Type) {
+        let (tag, length) = match typ {
+            Type::String(length) => (0, length),
+            Type::CString(length) => (1, length),
+            Type::Int => (2, 0),
+            Type::Uint => (3, 0),
+            Type::Float => (4, 0),
+        };
+        self.u8(tag);
+        self.usize(length);
+    }
+
+    /// The aligned size isn't stored, it's always computed with Size::new
+    fn layout(&mut self, layout: Layout) {
+        self.usize(layout.offset);
+        self.usize(layout.size.size);
+    }
+
+    fn generation(&mut self, generation: Generation) {
+        self.typ(generation.source_type);
+        self.layout(generation.source_layout);
+        self.option(generation.source_null_offset, Self::usize);
+        self.u8(match generation.op {
+            Arithmetic::Add => 0,
+            Arithmetic::Subtract => 1,
+            Arithmetic::Multiply => 2,
+            Arithmetic::Divide => 3,
+        });
+        self.u8(generation.operand.tag);
+        self.u64(generation.operand.bits);
+    }
+
+    fn field(&mut self, field: &Field) {
+        self.str(field.name.str());
+        self.typ(field.typ);
+        self.bool(field.primary);
+        self.u32(field.key_part.shift);
+        self.u32(field.key_part.bits);
+        self.bool(field.nullable);
+        self.bool(field.has_default);
+        self.option(field.generated, Self::generation);
+        self.layout(field.layout);
+        self.usize(field.null_offset);
+        self.bool(field.indexed);
+    }
+
+    fn metadata(&mut self, metadata: &Metadata) {
+        self.usize(metadata.root.0);
+        self.usize(metadata.num_fields);
+        metadata.fields().for_each(|field| self.field(field));
+        self.0.extend_from_slice(&metadata.defaults);
+        self.option(metadata.bloom_filter.as_ref(), |w, filter| {
+            filter.words().iter().for_each(|&word| w.u64(word))
+        });
+        self.bool(metadata.insertion_log);
+        self.str(metadata.description.str());
+        self.option(metadata.max_height, Self::usize);
+        self.usize(metadata.next_autoincrement);
+        self.bool(metadata.autoincrement);
+        self.usize(metadata.row_count);
+    }
+}
+
+/// Decodes what MetadataWriter writes, failing with InvalidData on anything it wouldn't have
+/// written: unknown tags, lengths that don't fit and strings that aren't UTF-8
+struct MetadataReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> MetadataReader<'a> {
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if len > self.data.len() {
+            return Err(invalid_metadata(format!(
+                "Metadata file is truncated, {len} more bytes were expected but it has {}",
+                self.data.len()
+            )));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        let n = self.u64()?;
+        usize::try_from(n).map_err(|_| invalid_metadata(format!("{n} doesn't fit in a usize")))
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(invalid_metadata(format!("Invalid boolean {tag}"))),
+        }
+    }
+
+    fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> io::Result<T>,
+    ) -> io::Result<Option<T>> {
+        self.bool()?.then(|| read(self)).transpose()
+    }
+
+    /// Reads a str of at most max_len bytes, its UTF-8 is always validated
+    fn str(&mut self, max_len: usize) -> io::Result<&'a str> {
+        let len = self.usize()?;
+        if len > max_len {
+            return Err(invalid_metadata(format!(
+                "String of {len} bytes is longer than {max_len}"
+            )));
+        }
+        str::from_utf8(self.bytes(len)?).map_err(|e| invalid_metadata(e.to_string()))
+    }
+
+    fn typ(&mut self) -> io::Result<Type> {
+        let tag = self.u8()?;
+        let length = self.usize()?;
+        // Strings are stored with a u32 length, which also keeps Type::size from overflowing
+        if length > u32::MAX as usize {
+            return Err(invalid_metadata(format!(
+                "String length {length} is too big"
+            )));
+        }
+        Ok(match tag {
+            0 => Type::String(length),
+            1 => Type::CString(length),
+            2 => Type::Int,
+            3 => Type::Uint,
+            4 => Type::Float,
+            _ => return Err(invalid_metadata(format!("Invalid type {tag}"))),
+        })
+    }
+
+    /// Reads a layout, which has to be of the expected size
+    fn layout(&mut self, size: Size) -> io::Result<Layout> {
+        let offset = self.usize()?;
+        let stored = self.usize()?;
+        if stored != size.size {
+            return Err(invalid_metadata(format!(
+                "Value of {stored} bytes, expected {}",
+                size.size
+            )));
+        }
+        Ok(Layout { offset, size })
+    }
+
+    fn generation(&mut self, typ: Type) -> io::Result<Generation> {
+        let source_type = self.typ()?;
+        let generation = Generation {
+            source_layout: self.layout(source_type.size())?,
+            source_type,
+            source_null_offset: self.option(Self::usize)?,
+            op: match self.u8()? {
+                0 => Arithmetic::Add,
+                1 => Arithmetic::Subtract,
+                2 => Arithmetic::Multiply,
+                3 => Arithmetic::Divide,
+                tag => return Err(invalid_metadata(format!("Invalid operation {tag}"))),
+            },
+            operand: Number {
+                tag: self.u8()?,
+                bits: self.u64()?,
+            },
+        };
+        if !generation.operand.is_valid(typ) {
+            return Err(invalid_metadata(
+                "Generated field has an invalid operand".to_string(),
+            ));
+        }
+        Ok(generation)
+    }
+
+    fn field(&mut self) -> io::Result<Field> {
+        let name =
+            Name::new(self.str(MAX_NAME_LENGTH)?).expect("The length of the name was checked");
+        let typ = self.typ()?;
+        let primary = self.bool()?;
+        let key_part = KeyPart {
+            shift: self.u32()?,
+            bits: self.u32()?,
+        };
+        let end = key_part.shift.checked_add(key_part.bits);
+        if key_part.bits == 0 || end.is_none_or(|end| end > usize::BITS) {
+            return Err(invalid_metadata(format!(
+                "Field {} has an invalid key part {key_part:?}",
+                name.str()
+            )));
+        }
+        let nullable = self.bool()?;
+        let has_default = self.bool()?;
+        let generated = self.option(|r| r.generation(typ))?;
+        // Generated and primary fields aren't stored in the entry
+        let size = match generated.is_some() || primary {
+            true => Size::default(),
+            false => typ.size(),
+        };
+        Ok(Field {
+            primary,
+            key_part,
+            nullable,
+            has_default,
+            generated,
+            layout: self.layout(size)?,
+            null_offset: self.usize()?,
+            indexed: self.bool()?,
+            name,
+            typ,
         })
     }
+
+    fn metadata(&mut self) -> io::Result<Metadata> {
+        let root = PageNum(self.usize()?);
+        let num_fields = self.usize()?;
+        if !(1..=MAX_FIELDS).contains(&num_fields) {
+            return Err(invalid_metadata(format!(
+                "Tables have 1 to {MAX_FIELDS} fields, found {num_fields}"
+            )));
+        }
+        let mut fields = [Field::default(); MAX_FIELDS];
+        for field in &mut fields[..num_fields] {
+            *field = self.field()?;
+        }
+        let defaults = self.array()?;
+        let bloom_filter = self.option(|r| {
+            let mut words = [0; BLOOM_FILTER_WORDS];
+            for word in &mut words {
+                *word = r.u64()?;
+            }
+            Ok(BloomFilter::from_words(words))
+        })?;
+        let insertion_log = self.bool()?;
+        let description = Description::new(self.str(MAX_DESCRIPTION_LENGTH)?)
+            .expect("The length of the description was checked");
+        let metadata = Metadata {
+            root,
+            num_fields,
+            fields,
+            defaults,
+            bloom_filter,
+            insertion_log,
+            description,
+            max_height: self.option(Self::usize)?,
+            next_autoincrement: self.usize()?,
+            autoincrement: self.bool()?,
+            row_count: self.usize()?,
+        };
+        check_metadata(&metadata)?;
+        Ok(metadata)
+    }
+}
+
+/// Checks what the fields can't check on their own: the key is made of valid primary fields
+/// and every value, NULL flag and default is inside the entry
+fn check_metadata(metadata: &Metadata) -> io::Result<()> {
+    let num_keys = metadata.key_fields().count();
+    if !(1..=MAX_KEY_FIELDS).contains(&num_keys) {
+        return Err(invalid_metadata(format!(
+            "Keys are made of 1 to {MAX_KEY_FIELDS} fields, found {num_keys}"
+        )));
+    }
+    let invalid_key = metadata.key_fields().find(|f| {
+        f.nullable
+            || f.generated.is_some()
+            || match num_keys {
+                1 => f.key_part != KeyPart::WHOLE,
+                _ => !matches!(f.typ, Type::Uint | Type::Int),
+            }
+    });
+    if let Some(field) = invalid_key {
+        return Err(invalid_metadata(format!(
+            "Primary field {} can't be part of the key",
+            field.name.str()
+        )));
+    }
+
+    let entry_size = metadata.entry_size().size;
+    let fits = |layout: Layout| {
+        layout
+            .offset
+            .checked_add(layout.size.size)
+            .is_some_and(|end| end <= entry_size)
+    };
+    let outside = metadata.data_fields().find(|f| {
+        let (layout, null_offset) = match f.generated {
+            Some(generation) => (generation.source_layout, generation.source_null_offset),
+            None => (f.layout, f.nullable.then_some(f.null_offset)),
+        };
+        !fits(layout) || null_offset.is_some_and(|offset| offset >= entry_size)
+    });
+    if let Some(field) = outside {
+        return Err(invalid_metadata(format!(
+            "Field {} is stored outside of the entry of {entry_size} bytes",
+            field.name.str()
+        )));
+    }
+
+    let mut defaults = metadata.data_fields().filter(|f| f.has_default).peekable();
+    if defaults.peek().is_some() && entry_size > MAX_DEFAULTS_SIZE {
+        return Err(invalid_metadata(format!(
+            "Entry of {entry_size} bytes is too big for default values"
+        )));
+    }
+    let entry = Data::new_ref(&metadata.defaults[..entry_size.min(MAX_DEFAULTS_SIZE)]);
+    defaults.try_for_each(|f| f.try_read(entry).map(|_| ()))
 }
 
 pub struct MetadataHandler {
@@ -196,28 +1345,86 @@ pub struct MetadataHandler {
     pub metadata: Metadata,
 }
 
+/// Identifies metadata files, it's written at the start of the header
+const METADATA_MAGIC: [u8; 8] = *b"RUSTDBMT";
+/// Has to be increased whenever MetadataWriter changes how the metadata is encoded
+const METADATA_VERSION: u32 = 3;
+/// Magic, version and the length of the metadata that follows, all stored before it
+const METADATA_HEADER_SIZE: usize = METADATA_MAGIC.len() + 2 * std::mem::size_of::<u32>();
+
+fn invalid_metadata(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
 impl MetadataHandler {
-    const LENGTH: usize = std::mem::size_of::<Metadata>();
     pub fn new(file: fs::File, metadata: Metadata) -> Self {
         Self { file, metadata }
     }
 
+    /// Reads the metadata, failing with InvalidData if the file is truncated, was written by an
+    /// incompatible version or has values that the metadata can't hold, see MetadataReader
     pub fn open(mut file: fs::File) -> io::Result<Self> {
-        let mut buf = [0; Self::LENGTH];
+        let mut contents = vec![];
         file.rewind()?;
-        file.read_exact(&mut buf)?;
-        let metadata = unsafe { std::mem::transmute::<[u8; Self::LENGTH], Metadata>(buf) };
+        file.read_to_end(&mut contents)?;
+        if contents.len() < METADATA_HEADER_SIZE {
+            return Err(invalid_metadata(format!(
+                "Metadata file is truncated, it has {} bytes but the header needs {}",
+                contents.len(),
+                METADATA_HEADER_SIZE
+            )));
+        }
+        let (header, data) = contents.split_at(METADATA_HEADER_SIZE);
+        let (magic, header) = header.split_at(METADATA_MAGIC.len());
+        if magic != METADATA_MAGIC {
+            return Err(invalid_metadata(
+                "File isn't a table metadata file, its magic number doesn't match".to_string(),
+            ));
+        }
+        let (version, length) = header.split_at(std::mem::size_of::<u32>());
+        let version = u32::from_le_bytes(version.try_into().unwrap());
+        if version != METADATA_VERSION {
+            return Err(invalid_metadata(format!(
+                "Metadata file has version {version}, only version {METADATA_VERSION} is supported"
+            )));
+        }
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        if data.len() < length {
+            return Err(invalid_metadata(format!(
+                "Metadata file is truncated, it has {} bytes of metadata but expected {length}",
+                data.len()
+            )));
+        }
+        let mut reader = MetadataReader { data };
+        let metadata = reader.metadata()?;
+        if data.len() - reader.data.len() != length {
+            return Err(invalid_metadata(format!(
+                "Metadata file has {} bytes of metadata but its header says {length}",
+                data.len() - reader.data.len()
+            )));
+        }
         Ok(Self { file, metadata })
     }
 
-    pub fn flush(&mut self) {
-        let data = unsafe { std::mem::transmute::<&Metadata, &[u8; Self::LENGTH]>(&self.metadata) };
-        self.file
-            .set_len(data.len() as u64)
-            .expect("Failed to set metadata length");
-        self.file.rewind().expect("Failed to reset metadata fd");
-        self.file.write_all(data).expect("Failed to write metadata");
-        self.file.sync_data().expect("Failed to sync metadata");
+    /// Returns what Self::flush writes to the file: the header followed by the metadata
+    pub fn contents(&self) -> Vec<u8> {
+        let mut writer = MetadataWriter::default();
+        writer.metadata(&self.metadata);
+        let data = writer.0;
+        let mut contents = Vec::with_capacity(METADATA_HEADER_SIZE + data.len());
+        contents.extend_from_slice(&METADATA_MAGIC);
+        contents.extend_from_slice(&METADATA_VERSION.to_le_bytes());
+        contents.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        contents.extend_from_slice(&data);
+        contents
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        let contents = self.contents();
+        self.file.set_len(contents.len() as u64)?;
+        self.file.rewind()?;
+        self.file.write_all(&contents)?;
+        self.file.sync_data()
     }
 }
 
@@ -228,18 +1435,336 @@ mod tests {
     #[test]
     fn test_field() {
         let mut field = Field::default();
-        field.name.write("test");
+        field.name.write("test").unwrap();
         field.typ = Type::String(10);
 
         assert_eq!(field.name.str(), "test");
         assert_eq!(field.typ, Type::String(10));
+
+        let long = "a".repeat(MAX_NAME_LENGTH + 1);
+        let error = field.name.write(&long).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(field.name.str(), "test");
+        assert!(Name::new(&long[1..]).is_ok());
+        let fields = [(long.as_str(), Type::Uint)];
+        assert!(Metadata::new(PageNum(0), ("id", Type::Uint), &fields).is_err());
     }
 
     #[test]
     fn test_id_field() {
         let data_name = "test";
-        let metadata = Metadata::new(PageNum(0), ("id", Type::Uint), &[(data_name, Type::Uint)]);
+        let metadata =
+            Metadata::new(PageNum(0), ("id", Type::Uint), &[(data_name, Type::Uint)]).unwrap();
         let data_field = metadata.field(data_name).unwrap();
         assert_eq!(data_field.layout.offset, 0);
     }
+
+    #[test]
+    fn test_composite_key() {
+        let key_fields = [("a", Type::Uint), ("b", Type::Int)];
+        let metadata = Metadata::with_key(PageNum(0), &key_fields, &[("c", Type::Uint)]).unwrap();
+        assert!(metadata.is_composite_key());
+        assert_eq!(metadata.field("c").unwrap().layout.offset, 0);
+
+        let key = |a: usize, b: isize| {
+            metadata
+                .encode_key(&[Literal::Uint(a), Literal::Int(b)])
+                .unwrap()
+        };
+        // Keys are ordered by the first field, then by the second one
+        assert!(key(0, i32::MAX as isize) < key(1, i32::MIN as isize));
+        assert!(key(1, -1) < key(1, 0));
+        assert!(key(1, 0) < key(1, 1));
+
+        let a = metadata.field("a").unwrap();
+        let b = metadata.field("b").unwrap();
+        assert_eq!(a.decode_key(key(3, -7)), Literal::Uint(3));
+        assert_eq!(b.decode_key(key(3, -7)), Literal::Int(-7));
+
+        let range = metadata.key_prefix_range(&[Literal::Uint(3)]).unwrap();
+        assert_eq!(*range.start(), key(3, i32::MIN as isize));
+        assert_eq!(*range.end(), key(3, i32::MAX as isize));
+
+        // Each field only has half of the key
+        assert_eq!(
+            metadata.encode_key(&[Literal::Uint(1 << 32), Literal::Int(0)]),
+            None
+        );
+        assert_eq!(metadata.encode_key(&[Literal::Uint(1)]), None);
+        assert!(Metadata::check_key_definitions(&key_fields, &[("c", Type::Uint)]).is_ok());
+        let string_key = [("a", Type::Uint), ("b", Type::String(8))];
+        assert!(Metadata::check_key_definitions(&string_key, &[("c", Type::Uint)]).is_err());
+    }
+
+    #[test]
+    fn test_coerce() {
+        assert_eq!(Type::Uint.coerce(Literal::Int(5)), Some(Literal::Uint(5)));
+        assert_eq!(Type::Uint.coerce(Literal::Int(-5)), None);
+        assert_eq!(Type::Int.coerce(Literal::Uint(5)), Some(Literal::Int(5)));
+        assert_eq!(Type::Int.coerce(Literal::Uint(usize::MAX)), None);
+        assert_eq!(Type::Float.coerce(Literal::from("test")), None);
+        assert_eq!(
+            Type::CString(10).coerce(Literal::from("test")),
+            Some(Literal::from("test"))
+        );
+        assert_eq!(Type::CString(10).coerce(Literal::from("te\0st")), None);
+    }
+
+    #[test]
+    fn test_nullable_field() {
+        let fields = [
+            FieldDefinition::new("a", Type::Uint).nullable(),
+            FieldDefinition::new("b", Type::Int),
+        ];
+        let metadata = Metadata::new(PageNum(0), ("id", Type::Uint), &fields).unwrap();
+        let entry_size = metadata.entry_size();
+        assert_eq!(entry_size.size, 17);
+
+        let mut buf = vec![0u8; entry_size.size];
+        let data = Data::new_mut(&mut buf);
+        let a = metadata.field("a").unwrap();
+        let b = metadata.field("b").unwrap();
+        a.write(&Literal::Null, data).unwrap();
+        b.write(&Literal::Int(-3), data).unwrap();
+        assert_eq!(a.read(data), Literal::Null);
+        assert_eq!(b.read(data), Literal::Int(-3));
+
+        a.write(&Literal::Uint(4), data).unwrap();
+        assert_eq!(a.read(data), Literal::Uint(4));
+    }
+
+    #[test]
+    fn test_data_fields_offsets() {
+        let fields = [
+            FieldDefinition::new("a", Type::String(5)).nullable(),
+            FieldDefinition::new("double", Type::Int).generated("b", Arithmetic::Multiply, 2isize),
+            FieldDefinition::new("b", Type::Int),
+            FieldDefinition::new("c", Type::Float).nullable(),
+        ];
+        let metadata = Metadata::new(PageNum(0), ("id", Type::Uint), &fields).unwrap();
+        let names: Vec<_> = metadata.data_fields().map(|f| f.name.str()).collect();
+        assert_eq!(names, ["a", "double", "b", "c"]);
+
+        let offsets: Vec<_> = metadata
+            .data_fields()
+            .filter(|f| f.generated.is_none())
+            .map(|f| f.layout.offset)
+            .collect();
+        assert!(offsets.is_sorted());
+        assert_eq!(offsets, [0, 16, 24]);
+        let a = metadata.field("a").unwrap();
+        let c = metadata.field("c").unwrap();
+        assert_eq!((a.null_offset, c.null_offset), (32, 33));
+        assert_eq!(metadata.entry_size().size, 34);
+    }
+
+    #[test]
+    fn test_primary_not_first() {
+        let fields = [
+            FieldDefinition::new("a", Type::Int).nullable(),
+            FieldDefinition::new("b", Type::Uint).default(7usize),
+            FieldDefinition::new("double", Type::Uint).generated("b", Arithmetic::Multiply, 2usize),
+        ];
+        let metadata =
+            Metadata::with_primary_at(PageNum(0), 1, ("id", Type::Uint), &fields).unwrap();
+        let names: Vec<_> = metadata.fields().map(|f| f.name.str()).collect();
+        assert_eq!(names, ["a", "id", "b", "double"]);
+        assert_eq!(metadata.primary().name.str(), "id");
+        assert_eq!(metadata.fields().filter(|f| f.primary).count(), 1);
+        let offsets: Vec<_> = metadata.data_fields().map(|f| f.layout.offset).collect();
+        assert_eq!(offsets, [0, 8, 0]);
+        assert_eq!(metadata.entry_size().size, 17);
+
+        let a = metadata.field("a").unwrap();
+        let b = metadata.field("b").unwrap();
+        let double = metadata.field("double").unwrap();
+        assert_eq!(metadata.default_value(b), Some(Literal::Uint(7)));
+        let mut buf = vec![0u8; metadata.entry_size().size];
+        let data = Data::new_mut(&mut buf);
+        a.write(&Literal::Int(-4), data).unwrap();
+        b.write(&Literal::Uint(21), data).unwrap();
+        assert_eq!(a.read(data), Literal::Int(-4));
+        assert_eq!(b.read(data), Literal::Uint(21));
+        assert_eq!(double.read(data), Literal::Uint(42));
+        a.write(&Literal::Null, data).unwrap();
+        assert_eq!(a.read(data), Literal::Null);
+        assert_eq!(b.read(data), Literal::Uint(21));
+
+        let last = Metadata::with_primary_at(PageNum(0), 3, ("id", Type::Uint), &fields).unwrap();
+        let names: Vec<_> = last.fields().map(|f| f.name.str()).collect();
+        assert_eq!(names, ["a", "b", "double", "id"]);
+        let offsets: Vec<_> = last.data_fields().map(|f| f.layout.offset).collect();
+        assert_eq!(offsets, [0, 8, 0]);
+    }
+
+    #[test]
+    fn test_default_value() {
+        let fields = [
+            FieldDefinition::new("a", Type::Uint).default(5isize),
+            FieldDefinition::new("b", Type::String(10)).default("test"),
+            FieldDefinition::new("c", Type::Int)
+                .nullable()
+                .default(Literal::Null),
+            FieldDefinition::new("d", Type::Int),
+        ];
+        let metadata = Metadata::new(PageNum(0), ("id", Type::Uint), &fields).unwrap();
+        let default = |name| metadata.default_value(metadata.field(name).unwrap());
+        assert_eq!(default("a"), Some(Literal::Uint(5)));
+        assert_eq!(default("b"), Some(Literal::from("test")));
+        assert_eq!(default("c"), Some(Literal::Null));
+        assert_eq!(default("d"), None);
+    }
+
+    #[test]
+    fn test_diff() {
+        let primary = ("id", Type::Uint);
+        let metadata = Metadata::new(PageNum(0), primary, &[("name", Type::String(10))]).unwrap();
+        assert!(metadata.diff(&metadata).is_empty());
+
+        let added = Metadata::new(
+            PageNum(0),
+            primary,
+            &[("name", Type::String(10)), ("age", Type::Uint)],
+        )
+        .unwrap();
+        assert_eq!(
+            metadata.diff(&added),
+            vec![SchemaChange::Added("age", Type::Uint)]
+        );
+        assert_eq!(
+            added.diff(&metadata),
+            vec![SchemaChange::Removed("age", Type::Uint)]
+        );
+
+        let retyped = Metadata::new(PageNum(0), primary, &[("name", Type::String(20))]).unwrap();
+        assert_eq!(
+            metadata.diff(&retyped),
+            vec![SchemaChange::Retyped {
+                name: "name",
+                from: Type::String(10),
+                to: Type::String(20)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_key_encoding() {
+        let ints = [isize::MIN, -100, -1, 0, 1, 100, isize::MAX].map(Literal::Int);
+        let floats = [
+            f64::NEG_INFINITY,
+            -2.5,
+            -0.0,
+            1.0,
+            2.5,
+            f64::INFINITY,
+            f64::NAN,
+        ]
+        .map(Literal::Float);
+        for (typ, values) in [(Type::Int, ints), (Type::Float, floats)] {
+            let keys: Vec<_> = values.iter().map(|v| typ.encode_key(v).unwrap()).collect();
+            assert!(keys.is_sorted(), "{typ:?} keys should keep their order");
+            for (value, key) in values.iter().zip(keys) {
+                assert_eq!(typ.decode_key(key), *value);
+            }
+        }
+
+        assert_eq!(
+            Type::Float.encode_key(&Literal::Float(-0.0)),
+            Type::Float.encode_key(&Literal::Float(0.0))
+        );
+        assert_eq!(Type::Int.encode_key(&Literal::Uint(1)), None);
+        assert_eq!(Type::String(10).encode_key(&Literal::from("a")), None);
+    }
+
+    #[test]
+    fn test_description() {
+        let description = Description::new("Users of the app").unwrap();
+        assert_eq!(description.str(), "Users of the app");
+        assert_eq!(Description::default().str(), "");
+
+        let long = "a".repeat(MAX_DESCRIPTION_LENGTH + 1);
+        assert!(Description::new(&long).is_none());
+        assert!(Description::new(&long[1..]).is_some());
+    }
+
+    #[test]
+    fn test_generated_operand() {
+        let fields = [
+            FieldDefinition::new("a", Type::Int),
+            FieldDefinition::new("b", Type::Int).generated("a", Arithmetic::Multiply, 2isize),
+        ];
+        let metadata = Metadata::new(PageNum(0), ("id", Type::Uint), &fields).unwrap();
+        let generation = metadata.field("b").unwrap().generated.unwrap();
+        assert_eq!(generation.operand.literal(), Some(Literal::Int(2)));
+        assert!(Number::new(Literal::from("2")).is_none());
+
+        let mut handler = MetadataHandler::new(tempfile::tempfile().unwrap(), metadata);
+        handler.flush().unwrap();
+        let reopened = MetadataHandler::open(handler.file.try_clone().unwrap()).unwrap();
+        let generation = reopened.metadata.field("b").unwrap().generated.unwrap();
+        assert_eq!(generation.operand, Number::new(Literal::Int(2)).unwrap());
+
+        // An operand with an unknown tag or of another type than the field is rejected
+        for operand in [
+            Number { tag: 9, bits: 2 },
+            Number::new(Literal::Uint(2)).unwrap(),
+        ] {
+            let b = handler
+                .metadata
+                .fields
+                .iter_mut()
+                .find(|f| f.name.str() == "b");
+            b.unwrap().generated.as_mut().unwrap().operand = operand;
+            handler.flush().unwrap();
+            let error = MetadataHandler::open(handler.file.try_clone().unwrap())
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_metadata_encoding() {
+        use std::io::{Seek, SeekFrom, Write};
+
+        let fields = [
+            FieldDefinition::new("a", Type::Int)
+                .nullable()
+                .default(-3isize),
+            FieldDefinition::new("b", Type::Int).generated("a", Arithmetic::Add, 1isize),
+            FieldDefinition::new("c", Type::CString(12)).default("text"),
+        ];
+        let key_fields = [("x", Type::Uint), ("y", Type::Int)];
+        let mut metadata = Metadata::with_key(PageNum(3), &key_fields, &fields).unwrap();
+        metadata.description = Description::new("Encoded").unwrap();
+        metadata.bloom_filter = Some(BloomFilter::new());
+        metadata.max_height = Some(4);
+        metadata.row_count = 10;
+        let mut handler = MetadataHandler::new(tempfile::tempfile().unwrap(), metadata);
+        handler.flush().unwrap();
+        let contents = handler.contents();
+
+        let reopened = MetadataHandler::open(handler.file.try_clone().unwrap()).unwrap();
+        assert_eq!(reopened.contents(), contents);
+        let metadata = &reopened.metadata;
+        assert_eq!(metadata.description.str(), "Encoded");
+        assert_eq!(metadata.max_height, Some(4));
+        assert!(metadata.is_composite_key());
+        let default = |name| metadata.default_value(metadata.field(name).unwrap());
+        assert_eq!(default("a"), Some(Literal::Int(-3)));
+        assert_eq!(default("c"), Some(Literal::from("text")));
+
+        // Corrupting any byte can make the metadata invalid, which opening it reports instead
+        // of panicking
+        for i in METADATA_HEADER_SIZE..contents.len() {
+            let mut file = handler.file.try_clone().unwrap();
+            file.seek(SeekFrom::Start(i as u64)).unwrap();
+            file.write_all(&[contents[i] ^ 0xFF]).unwrap();
+            if let Err(error) = MetadataHandler::open(file.try_clone().unwrap()) {
+                assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{error}");
+            }
+            file.seek(SeekFrom::Start(i as u64)).unwrap();
+            file.write_all(&contents[i..=i]).unwrap();
+        }
+    }
 }
@@ -1,25 +1,46 @@
+pub mod csv;
 pub mod data;
+pub mod index;
+pub mod insertion_log;
 pub mod metadata;
+pub mod options;
 
 pub mod internal;
 pub mod leaf;
 pub mod node;
+pub mod overflow;
+pub mod validate;
+pub mod wal;
 
 pub mod debug;
 
-use std::{fs, io};
+use std::{
+    collections::{BTreeSet, HashMap, hash_map::Entry},
+    fs, io,
+    sync::Mutex,
+};
 
 use crate::{
     pager::{PageNum, Pager},
+    query::Literal,
     table::{
         data::Data,
-        internal::{INTERNAL_NODE_CELL_COUNT, InternalNodeHeader},
+        insertion_log::InsertionLog,
+        internal::{INTERNAL_NODE_CELL_COUNT, INTERNAL_NODE_MIN_KEYS, InternalNodeHeader},
         leaf::{LeafNodeCell, LeafNodeHeader},
-        metadata::{Metadata, MetadataHandler, Size, Type},
-        node::NodeMut,
+        metadata::{
+            Description, FieldDefinition, MAX_DESCRIPTION_LENGTH, Metadata, MetadataHandler, Size,
+            Type,
+        },
+        node::{Node, NodeMut},
+        options::TableOptions,
+        wal::Wal,
     },
+    utils::bloom::BloomFilter,
 };
 
+/// Position of a cell in a leaf. Cursors are created by walking the tree down to the leaf, so
+/// the page they point to was already read and accessing it can't fail
 #[derive(Debug, Clone, Copy)]
 pub struct Cursor {
     pub page_num: PageNum,
@@ -27,23 +48,65 @@ pub struct Cursor {
 }
 
 impl Cursor {
-    /// Returns the value that this cursor points to
-    pub fn value<'table>(&self, table: &'table Table) -> &'table mut Data {
-        let cell = self.cell(table);
-        cell.data_mut(table.entry_size)
+    /// Returns the value that this cursor points to, which can fail if it's stored in overflow
+    /// pages
+    pub fn value<'table>(&self, table: &'table Table) -> TableResult<&'table Data> {
+        table.cell_value(self.cell(table))
+    }
+    /// Returns the value that this cursor points to for writing.
+    /// Panics if the table stores its entries in overflow pages, use Cursor::set_value instead
+    pub fn value_mut<'table>(&self, table: &'table mut Table) -> &'table mut Data {
+        assert!(
+            !table.is_overflow(),
+            "Values in overflow pages have to be written with Cursor::set_value"
+        );
+        let entry_size = table.entry_size;
+        let cell = self.cell_mut(table);
+        cell.data_mut(entry_size)
+    }
+    /// Overwrites the whole value that this cursor points to, which must have the table's entry size
+    pub fn set_value(&self, table: &mut Table, data: &Data) -> io::Result<()> {
+        let value = data.read_all();
+        if value.len() != table.entry_size.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Value has {} bytes but entries have {}",
+                    value.len(),
+                    table.entry_size.size
+                ),
+            ));
+        }
+        table.write_value(*self, value)?;
+        Ok(())
     }
     /// Returns the entry that this cursor points to
-    pub fn cell<'table>(&self, table: &'table Table) -> &'table mut LeafNodeCell<'table> {
+    pub fn cell<'table>(&self, table: &'table Table) -> &'table LeafNodeCell<'table> {
         let cell_num = self.cell_num;
         let leaf = self.leaf(table);
-        leaf.cell_mut_unchecked(cell_num, table.entry_size)
+        leaf.cell_unchecked(cell_num, table.cell_size)
+    }
+    /// Returns the entry that this cursor points to for writing
+    pub fn cell_mut<'table>(&self, table: &'table mut Table) -> &'table mut LeafNodeCell<'table> {
+        let cell_num = self.cell_num;
+        let cell_size = table.cell_size;
+        let leaf = self.leaf_mut(table);
+        leaf.cell_mut_unchecked(cell_num, cell_size)
     }
     /// Returns the leaf node that this cursor points to
-    #[allow(clippy::mut_from_ref)]
-    pub fn leaf<'table>(&self, table: &'table Table) -> &'table mut LeafNodeHeader<'table> {
+    pub fn leaf<'table>(&self, table: &'table Table) -> &'table LeafNodeHeader<'table> {
+        let leaf = table
+            .pager
+            .loaded_node_ref(self.page_num)
+            .leaf()
+            .expect("A cursor has to point to a leaf");
+        unsafe { std::mem::transmute::<&LeafNodeHeader<'_>, &'table LeafNodeHeader<'table>>(leaf) }
+    }
+    /// Returns the leaf node that this cursor points to for writing
+    pub fn leaf_mut<'table>(&self, table: &'table mut Table) -> &'table mut LeafNodeHeader<'table> {
         let leaf = table
             .pager
-            .get_node(self.page_num)
+            .loaded_node(self.page_num)
             .leaf()
             .expect("A cursor has to point to a leaf");
         unsafe {
@@ -51,36 +114,49 @@ impl Cursor {
         }
     }
 
-    /// Advances the cursor, returns true while the cursor is valid
-    pub fn advance(&mut self, table: &Table) -> bool {
+    /// Moves the cursor to the key, or to the position where it would be inserted
+    pub fn seek(&mut self, table: &Table, key: usize) -> TableResult<()> {
+        *self = table.find_cursor(key)?;
+        Ok(())
+    }
+
+    /// Moves the cursor to the first cell of the leftmost leaf
+    pub fn reset(&mut self, table: &Table) -> TableResult<()> {
+        *self = table.min_cursor()?;
+        Ok(())
+    }
+
+    /// Advances the cursor, returns true while the cursor is valid.
+    /// Moving to the next leaf reads it, which can fail
+    pub fn advance(&mut self, table: &Table) -> TableResult<bool> {
         let leaf = self.leaf(table);
         self.cell_num += 1;
         if self.cell_num < leaf.num_cells {
-            return true;
+            return Ok(true);
         } else if leaf.is_root() {
-            return false;
+            return Ok(false);
         }
 
         // TODO: Add a next field in the leaf nodes to improve traversing
-        let first_cell = leaf.cell_unchecked(0, table.entry_size);
+        let first_cell = leaf.cell_unchecked(0, table.cell_size);
         let mut last_key = first_cell.key;
         let mut parent_ptr = leaf.parent_ptr;
 
         loop {
             let parent = table
                 .pager
-                .get_node(parent_ptr)
+                .get_node_ref(parent_ptr)?
                 .internal()
                 .expect("Parent can't be leaf node");
             let index = parent.find_index(last_key);
             if index < parent.num_keys {
                 let next_internal_page_num = parent.ptr(index + 1);
-                let page_num = table.leftmost_node(next_internal_page_num);
+                let page_num = table.leftmost_node(next_internal_page_num)?;
                 self.page_num = page_num;
                 self.cell_num = 0;
-                return true;
+                return Ok(true);
             } else if parent.is_root() {
-                return false;
+                return Ok(false);
             } else {
                 last_key = parent.cell_unchecked(0).key;
                 parent_ptr = parent.parent_ptr;
@@ -88,39 +164,63 @@ impl Cursor {
         }
     }
 
+    /// Iterates over the rows in key order starting at this cursor, using Cursor::advance
     pub fn into_iter<'a>(self, table: &'a Table) -> CursorIterator<'a> {
-        CursorIterator {
-            table,
-            cursor: self,
-            started: false,
-        }
+        CursorIterator::new(table, Ok(self))
     }
 }
 
+/// Iterator over the rows from a cursor. A page that can't be read is returned as an error,
+/// which ends the iteration
 pub struct CursorIterator<'a> {
     table: &'a Table,
-    cursor: Cursor,
+    /// None once the rows ran out or reading them failed
+    cursor: Option<TableResult<Cursor>>,
     started: bool,
 }
 
+impl<'a> CursorIterator<'a> {
+    /// Iterates from the cursor, or returns the error of finding it as the only item
+    pub(crate) fn new(table: &'a Table, cursor: TableResult<Cursor>) -> Self {
+        Self {
+            table,
+            cursor: Some(cursor),
+            started: false,
+        }
+    }
+
+    /// Moves the cursor to the next row, the first call keeps it on its cell if it's valid
+    fn step(&mut self, cursor: &mut Cursor) -> TableResult<bool> {
+        if !self.started {
+            self.started = true;
+            // Cursors from find_cursor can point past the last cell of their leaf
+            if cursor.cell_num < cursor.leaf(self.table).num_cells {
+                return Ok(true);
+            }
+        }
+        cursor.advance(self.table)
+    }
+}
+
 impl<'a> Iterator for CursorIterator<'a> {
-    type Item = (usize, &'a mut Data);
+    type Item = TableResult<(usize, &'a Data)>;
     fn next(&mut self) -> Option<Self::Item> {
-        if !self.started {
-            let leaf = self.cursor.leaf(self.table);
-            if leaf.num_cells == 0 {
-                None
-            } else {
-                self.started = true;
-                let cell = self.cursor.cell(self.table);
-                Some((cell.key, cell.data_mut(self.table.entry_size)))
+        let mut cursor = match self.cursor.take()? {
+            Ok(cursor) => cursor,
+            Err(e) => return Some(Err(e)),
+        };
+        let row = match self.step(&mut cursor) {
+            Ok(true) => {
+                let cell = cursor.cell(self.table);
+                self.table.cell_value(cell).map(|data| (cell.key, data))
             }
-        } else if self.cursor.advance(self.table) {
-            let cell = self.cursor.cell(self.table);
-            Some((cell.key, cell.data_mut(self.table.entry_size)))
-        } else {
-            None
+            Ok(false) => return None,
+            Err(e) => Err(e),
+        };
+        if row.is_ok() {
+            self.cursor = Some(Ok(cursor));
         }
+        Some(row)
     }
 }
 
@@ -128,6 +228,52 @@ impl<'a> Iterator for CursorIterator<'a> {
 pub enum TableError {
     DuplicateKey,
     KeyNotFound,
+    /// There is no key after the biggest one to assign
+    KeysExhausted,
+    /// The insert would make the tree taller than its maximum height
+    MaxHeightExceeded,
+    /// A page couldn't be read, or its checksum doesn't match
+    Io(io::Error),
+}
+
+impl std::fmt::Display for TableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Self::DuplicateKey => "Duplicate key",
+            Self::KeyNotFound => "Key not found",
+            Self::KeysExhausted => "No keys left to assign",
+            Self::MaxHeightExceeded => "Max tree height exceeded",
+            Self::Io(e) => return e.fmt(f),
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for TableError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TableError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<TableError> for io::Error {
+    fn from(value: TableError) -> Self {
+        let kind = match value {
+            TableError::DuplicateKey => io::ErrorKind::AlreadyExists,
+            TableError::KeyNotFound => io::ErrorKind::NotFound,
+            TableError::KeysExhausted | TableError::MaxHeightExceeded => io::ErrorKind::Other,
+            TableError::Io(e) => return e,
+        };
+        io::Error::new(kind, value)
+    }
 }
 
 pub type TableResult<T> = Result<T, TableError>;
@@ -135,39 +281,198 @@ pub type TableResult<T> = Result<T, TableError>;
 pub struct Table {
     pub pager: Pager,
     pub metadata: MetadataHandler,
+    pub insertion_log: Option<InsertionLog>,
+    /// Set if changes are committed to a write-ahead log before they're flushed
+    pub wal: Option<Wal>,
     pub entry_size: Size,
+    /// Size of the values in the leaf cells, which is the entry size unless the entries are
+    /// stored in overflow pages
+    pub cell_size: Size,
     pub max_leaf_cells: usize,
+    /// Values read from overflow pages, boxed so that they don't move while the table is
+    /// borrowed. Entries are removed when their pages are freed
+    overflow_values: Mutex<HashMap<PageNum, Box<[u8]>>>,
 }
 
 impl Table {
-    fn from_parts(pager: Pager, metadata_handler: MetadataHandler) -> io::Result<Self> {
+    fn from_parts(
+        mut pager: Pager,
+        metadata_handler: MetadataHandler,
+        insertion_log: Option<InsertionLog>,
+    ) -> io::Result<Self> {
+        if metadata_handler.metadata.insertion_log != insertion_log.is_some() {
+            return Err(io::Error::other(
+                "The insertion log has to be opened together with the table that created it",
+            ));
+        }
         let entry_size = metadata_handler.metadata.entry_size();
-        let max_leaf_cells = LeafNodeCell::max_cells(entry_size.aligned);
+        let schema_hash = metadata_handler.metadata.schema_hash();
+        let page = pager.read_metadata()?;
+        match (page.entry_size, page.schema_hash) {
+            // The data file is new, so it's given the shape of this table
+            (0, 0) => {
+                let page = pager.get_metadata()?;
+                page.entry_size = entry_size.size;
+                page.schema_hash = schema_hash;
+            }
+            (size, hash) if size == entry_size.size && hash == schema_hash => {}
+            (size, hash) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Data file has entries of {size} bytes with schema {hash:08x}, but the \
+                         metadata describes entries of {} bytes with schema {schema_hash:08x}",
+                        entry_size.size
+                    ),
+                ));
+            }
+        }
+        let cell_size = overflow::cell_size(entry_size);
+        let max_leaf_cells = LeafNodeCell::max_cells(cell_size.aligned);
         Ok(Self {
             pager,
             metadata: metadata_handler,
+            insertion_log,
+            wal: None,
             entry_size,
+            cell_size,
             max_leaf_cells,
+            overflow_values: Mutex::default(),
         })
     }
 
     /// Create a table with the specified fields.
-    pub fn create(
+    pub fn create<'f, F>(
         data_file: fs::File,
         metadata_file: fs::File,
         primary_field: (&str, Type),
-        fields: &[(&str, Type)],
-    ) -> io::Result<Self> {
-        let pager = Pager::new(data_file)?;
-        let metadata = Metadata::new(PageNum(1), primary_field, fields);
-        let metadata_handler = MetadataHandler::new(metadata_file, metadata);
-        Self::from_parts(pager, metadata_handler)
+        fields: &[F],
+    ) -> io::Result<Self>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
+        Self::create_with_options(
+            data_file,
+            metadata_file,
+            &[primary_field],
+            fields,
+            TableOptions::new(),
+        )
+    }
+
+    /// Create a table with the features of the options, see TableOptions.
+    /// The key is made of the primary fields, several of them make a composite key whose keys
+    /// are built with Metadata::encode_key, see Metadata::with_key
+    pub fn create_with_options<'f, F>(
+        data_file: fs::File,
+        metadata_file: fs::File,
+        primary_fields: &[(&str, Type)],
+        fields: &[F],
+        options: TableOptions,
+    ) -> io::Result<Self>
+    where
+        F: Into<FieldDefinition<'f>> + Clone,
+    {
+        let mut metadata = Metadata::with_key(PageNum(1), primary_fields, fields)?;
+        if let Some(description) = options.description {
+            metadata.description = Description::new(description).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Descriptions can't be longer than {MAX_DESCRIPTION_LENGTH} bytes"),
+                )
+            })?;
+        }
+        metadata.insertion_log = options.insertion_log.is_some();
+        Self::with_options(data_file, metadata_file, options, |file| {
+            Ok(MetadataHandler::new(file, metadata))
+        })
     }
 
     pub fn open(data_file: fs::File, metadata_file: fs::File) -> io::Result<Self> {
-        let pager = Pager::new(data_file)?;
-        let metadata_handler = MetadataHandler::open(metadata_file)?;
-        Self::from_parts(pager, metadata_handler)
+        Self::open_with_options(data_file, metadata_file, TableOptions::new())
+    }
+
+    /// Opens a table created with Table::create_with_options, with the key it was encrypted
+    /// with and the files of its logs. The transactions committed to the write-ahead log are
+    /// written to the data and metadata files first, changes that weren't committed are lost
+    pub fn open_with_options(
+        data_file: fs::File,
+        metadata_file: fs::File,
+        options: TableOptions,
+    ) -> io::Result<Self> {
+        Self::with_options(data_file, metadata_file, options, MetadataHandler::open)
+    }
+
+    /// Opens the pager and the logs of the options. The pages committed to the write-ahead log
+    /// are replayed through the pager, so they are compressed and encrypted like the rest, and
+    /// the log is only emptied once the data file has them
+    fn with_options(
+        data_file: fs::File,
+        metadata_file: fs::File,
+        options: TableOptions,
+        metadata: impl FnOnce(fs::File) -> io::Result<MetadataHandler>,
+    ) -> io::Result<Self> {
+        let (wal, recovered) = match options.wal {
+            Some(file) => {
+                let (wal, pages) = Wal::open(file, &metadata_file)?;
+                (Some(wal), pages)
+            }
+            None => (None, Vec::new()),
+        };
+        let pager = Pager::open(
+            data_file,
+            options.compressed,
+            options.key.as_ref(),
+            &recovered,
+        )?;
+        let insertion_log = options.insertion_log.map(InsertionLog::open).transpose()?;
+        let mut table = Self::from_parts(pager, metadata(metadata_file)?, insertion_log)?;
+        if let Some(mut wal) = wal {
+            wal.clear()?;
+            table.wal = Some(wal);
+        }
+        Ok(table)
+    }
+
+    /// Makes the changes since the last commit durable by appending them to the write-ahead
+    /// log, without writing the data file. A table without a log is flushed instead
+    pub fn commit(&mut self) -> io::Result<()> {
+        let Some(wal) = &mut self.wal else {
+            return self.flush();
+        };
+        let metadata = self.metadata.contents();
+        wal.commit(&self.pager.take_uncommitted(), &metadata)
+    }
+
+    /// Writes the modified pages, the metadata and the insertion log to disk, the table can
+    /// still be used afterwards.
+    /// With a write-ahead log this is a checkpoint: the changes are committed to the log, and
+    /// it's emptied once the files have all of them
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.wal.is_some() {
+            self.commit()?;
+        }
+        self.pager.flush()?;
+        self.metadata.flush()?;
+        if let Some(log) = &mut self.insertion_log {
+            log.flush()?;
+        }
+        if let Some(wal) = &mut self.wal {
+            wal.clear()?;
+        }
+        Ok(())
+    }
+
+    /// Reads every page of the data file to check that none of them is corrupted. Pages are
+    /// otherwise only checked when they are read
+    pub fn verify(&self) -> io::Result<()> {
+        self.pager.verify()
+    }
+
+    /// Returns the description of the table, None if it was created without one
+    pub fn description(&self) -> Option<&str> {
+        let description = self.metadata.metadata.description.str();
+        (!description.is_empty()).then_some(description)
     }
 
     #[inline]
@@ -183,117 +488,733 @@ impl Table {
         Cursor { page_num, cell_num }
     }
 
-    fn leftmost_node(&self, mut child_page_num: PageNum) -> PageNum {
-        let mut node = self.pager.get_node(child_page_num);
-        while let NodeMut::InternalNode(internal) = node {
+    fn leftmost_node(&self, mut child_page_num: PageNum) -> TableResult<PageNum> {
+        let mut node = self.pager.get_node_ref(child_page_num)?;
+        while let Node::InternalNode(internal) = node {
             child_page_num = internal.cell_unchecked(0).ptr;
-            node = self.pager.get_node(child_page_num);
+            node = self.pager.get_node_ref(child_page_num)?;
         }
-        child_page_num
+        Ok(child_page_num)
     }
 
-    fn rightmost_node(&self, mut child_page_num: PageNum) -> PageNum {
-        let mut node = self.pager.get_node(child_page_num);
-        while let NodeMut::InternalNode(internal) = node {
+    fn rightmost_node(&self, mut child_page_num: PageNum) -> TableResult<PageNum> {
+        let mut node = self.pager.get_node_ref(child_page_num)?;
+        while let Node::InternalNode(internal) = node {
             child_page_num = internal.right_child;
-            node = self.pager.get_node(child_page_num);
+            node = self.pager.get_node_ref(child_page_num)?;
+        }
+        Ok(child_page_num)
+    }
+
+    /// Returns the rightmost leaf, where the biggest key is
+    fn rightmost_leaf(&self) -> TableResult<&LeafNodeHeader<'_>> {
+        let page_num = self.rightmost_node(self.get_root())?;
+        Ok(self.pager.loaded_node_ref(page_num).leaf().unwrap())
+    }
+
+    fn collect_leaves(&self, page_num: PageNum, leaves: &mut Vec<PageNum>) -> TableResult<()> {
+        match self.pager.get_node_ref(page_num)? {
+            Node::InternalNode(internal) => {
+                for i in 0..=internal.num_keys {
+                    self.collect_leaves(internal.ptr(i), leaves)?;
+                }
+            }
+            Node::LeafNode(leaf) if leaf.num_cells > 0 => leaves.push(page_num),
+            Node::LeafNode(_) => {}
         }
-        child_page_num
+        Ok(())
     }
 
-    /// Returns a cursor pointing to the smallest node
-    pub fn min_cursor(&self) -> Cursor {
-        let page_num = self.leftmost_node(self.get_root());
-        self.cursor(page_num, 0)
+    /// Partitions the leaves into at most n contiguous segments of similar size.
+    /// Each segment is given by its first and last leaf, and covers a disjoint range of keys
+    /// that can be iterated with Table::scan_leaves
+    pub fn leaf_page_ranges(&self, n: usize) -> TableResult<Vec<(PageNum, PageNum)>> {
+        let mut leaves = vec![];
+        self.collect_leaves(self.get_root(), &mut leaves)?;
+
+        let segments = n.clamp(1, leaves.len().max(1));
+        Ok((0..segments)
+            .filter_map(|i| {
+                let start = i * leaves.len() / segments;
+                let end = (i + 1) * leaves.len() / segments;
+                (start < end).then(|| (leaves[start], leaves[end - 1]))
+            })
+            .collect())
+    }
+
+    /// Iterates over the rows stored from the first leaf to the last one, both included
+    pub fn scan_leaves(
+        &self,
+        (first, last): (PageNum, PageNum),
+    ) -> TableResult<impl Iterator<Item = TableResult<(usize, &Data)>>> {
+        let last_leaf = self.pager.get_node_ref(last)?.leaf().unwrap();
+        let last_key = last_leaf
+            .cell_unchecked(last_leaf.num_cells - 1, self.cell_size)
+            .key;
+        // The cursor can only point to a leaf that was read
+        self.pager.get_node_ref(first)?;
+        Ok(self
+            .cursor(first, 0)
+            .into_iter(self)
+            .take_while(move |row| !matches!(row, Ok((key, _)) if *key > last_key)))
+    }
+
+    /// Returns a cursor pointing to the smallest node, the first cell of the leftmost leaf
+    pub fn min_cursor(&self) -> TableResult<Cursor> {
+        let page_num = self.leftmost_node(self.get_root())?;
+        Ok(self.cursor(page_num, 0))
     }
 
     /// Returns a cursor pointing to the biggest node
-    pub fn max_cursor(&self) -> Cursor {
-        let page_num = self.rightmost_node(self.get_root());
+    pub fn max_cursor(&self) -> TableResult<Cursor> {
+        let page_num = self.rightmost_node(self.get_root())?;
         let mut cursor = self.cursor(page_num, 0);
         let leaf = cursor.leaf(self);
         let num_cells = leaf.num_cells;
         cursor.cell_num = num_cells - 1;
-        cursor
+        Ok(cursor)
+    }
+
+    /// Returns the smallest key, None if the table is empty
+    pub fn min_key(&self) -> TableResult<Option<usize>> {
+        let leaf = self.min_cursor()?.leaf(self);
+        Ok((leaf.num_cells > 0).then(|| leaf.cell_unchecked(0, self.cell_size).key))
+    }
+
+    /// Returns the biggest key, None if the table is empty
+    pub fn max_key(&self) -> TableResult<Option<usize>> {
+        let leaf = self.rightmost_leaf()?;
+        let last = leaf.num_cells.checked_sub(1);
+        Ok(last.map(|last| leaf.cell_unchecked(last, self.cell_size).key))
+    }
+
+    /// Starts maintaining a bloom filter over the keys of the table, which lets lookups of
+    /// missing keys return without reading any pages.
+    /// Existing keys are added to the filter
+    pub fn enable_bloom_filter(&mut self) -> TableResult<()> {
+        let mut filter = BloomFilter::new();
+        for row in self.rows() {
+            filter.insert(row?.0);
+        }
+        self.metadata.metadata.bloom_filter = Some(filter);
+        Ok(())
+    }
+
+    /// Returns false if the key is surely not in the table
+    #[inline]
+    fn might_contain(&self, key: usize) -> bool {
+        self.metadata
+            .metadata
+            .bloom_filter
+            .as_ref()
+            .is_none_or(|filter| filter.might_contain(key))
+    }
+
+    /// Returns the number of rows without scanning the table
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.metadata.metadata.row_count
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over all of the rows in key order
+    pub fn rows(&self) -> impl Iterator<Item = TableResult<(usize, &Data)>> {
+        CursorIterator::new(self, self.min_cursor())
+    }
+
+    /// Scans the table and returns the sorted distinct values of the column.
+    /// Only the distinct values are kept in memory
+    pub fn distinct_values(&self, column: &str) -> io::Result<Vec<Literal<'_>>> {
+        let field = *self.metadata.metadata.field(column).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Column {column} doesn't exist"),
+            )
+        })?;
+        if field.primary {
+            let keys = self
+                .rows()
+                .map(|row| row.map(|(key, _)| field.decode_key(key)));
+            // The later fields of a composite key aren't sorted across the rows
+            let keys: BTreeSet<_> = keys.collect::<TableResult<_>>()?;
+            return Ok(keys.into_iter().collect());
+        }
+        let values = self.rows().map(|row| row.map(|(_, data)| field.read(data)));
+        let values: BTreeSet<_> = values.collect::<TableResult<_>>()?;
+        Ok(values.into_iter().collect())
+    }
+
+    /// Iterates over the rows with start <= key < end in key order.
+    /// A None bound leaves that side of the range open
+    pub fn scan(
+        &self,
+        start: Option<usize>,
+        end: Option<usize>,
+    ) -> impl Iterator<Item = TableResult<(usize, &Data)>> {
+        let cursor = match start {
+            Some(key) => self.find_cursor(key),
+            None => self.min_cursor(),
+        };
+        CursorIterator::new(self, cursor).take_while(
+            move |row| !matches!(row, Ok((key, _)) if end.is_some_and(|end| *key >= end)),
+        )
+    }
+
+    /// Iterates over the rows whose leading primary fields have the values of the prefix, in
+    /// key order. With a composite key, the prefix can leave out the last fields of the key.
+    /// Returns None if the prefix has too many values or one of them can't be encoded
+    pub fn scan_key_prefix(
+        &self,
+        prefix: &[Literal],
+    ) -> Option<impl Iterator<Item = TableResult<(usize, &Data)>>> {
+        let range = self.metadata.metadata.key_prefix_range(prefix)?;
+        Some(self.scan(Some(*range.start()), range.end().checked_add(1)))
+    }
+
+    /// Iterates over all of the rows in the order they were inserted.
+    /// Returns None if the table doesn't keep an insertion log
+    pub fn rows_by_insertion(&self) -> Option<impl Iterator<Item = TableResult<(usize, &Data)>>> {
+        let log = self.insertion_log.as_ref()?;
+        // Deleted keys stay in the log
+        Some(log.keys().iter().filter_map(|&key| {
+            let row = self.get(key).transpose()?;
+            Some(row.map(|data| (key, data)))
+        }))
+    }
+
+    /// Returns true if the key is in the table, without reading its value
+    pub fn contains(&self, key: usize) -> TableResult<bool> {
+        if !self.might_contain(key) {
+            return Ok(false);
+        }
+        let cursor = self.find_cursor(key)?;
+        let leaf = cursor.leaf(self);
+        Ok(cursor.cell_num < leaf.num_cells
+            && leaf.cell_unchecked(cursor.cell_num, self.cell_size).key == key)
     }
 
     /// Returns the value for the specified key
     pub fn find(&self, key: usize) -> TableResult<&Data> {
-        let cursor = self.find_cursor(key);
+        self.get(key)?.ok_or(TableError::KeyNotFound)
+    }
+
+    /// Like Table::find, but a missing key is None, so only reading the pages can fail
+    pub fn get(&self, key: usize) -> TableResult<Option<&Data>> {
+        if !self.might_contain(key) {
+            return Ok(None);
+        }
+        let cursor = self.find_cursor(key)?;
         let leaf = cursor.leaf(self);
         if cursor.cell_num < leaf.num_cells && cursor.cell(self).key == key {
-            Ok(cursor.value(self))
+            cursor.value(self).map(Some)
         } else {
-            Err(TableError::KeyNotFound)
+            Ok(None)
         }
     }
 
     /// Returns a cursor pointing to the specified value.
     /// Can be used for inserting, so it doesn't always point to a cell with cell.key == key
-    pub fn find_cursor(&self, key: usize) -> Cursor {
+    pub fn find_cursor(&self, key: usize) -> TableResult<Cursor> {
         let mut page_num = self.get_root();
-        let mut node = self.pager.get_node(page_num);
-        while let NodeMut::InternalNode(ref mut internal) = node {
+        let mut node = self.pager.get_node_ref(page_num)?;
+        while let Node::InternalNode(internal) = node {
             page_num = internal.find(key);
-            let page = self.pager.get_page(page_num);
-            node = page.page_header_mut().node_mut();
+            node = self.pager.get_node_ref(page_num)?;
         }
         let leaf = node.leaf().unwrap();
-        let cell_num = leaf.find(key, self.entry_size);
-        self.cursor(page_num, cell_num)
+        let cell_num = leaf.find(key, self.cell_size);
+        Ok(self.cursor(page_num, cell_num))
+    }
+
+    /// Returns the number of levels of the tree, a table with only a root leaf has height 1
+    pub fn height(&self) -> TableResult<usize> {
+        let mut height = 1;
+        let mut node = self.pager.get_node_ref(self.get_root())?;
+        while let Node::InternalNode(internal) = node {
+            node = self.pager.get_node_ref(internal.ptr(0))?;
+            height += 1;
+        }
+        Ok(height)
+    }
+
+    /// Walks the whole tree and returns the number of (internal, leaf) nodes in it
+    pub fn node_count(&self) -> TableResult<(usize, usize)> {
+        let mut counts = (0, 0);
+        let mut pending = vec![self.get_root()];
+        while let Some(page_num) = pending.pop() {
+            match self.pager.get_node_ref(page_num)? {
+                Node::InternalNode(internal) => {
+                    counts.0 += 1;
+                    pending.extend((0..=internal.num_keys).map(|i| internal.ptr(i)));
+                }
+                Node::LeafNode(_) => counts.1 += 1,
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Returns true if the entries are too big for the leaves, so they are stored in overflow
+    /// pages and the leaf cells only have the first page
+    #[inline]
+    pub fn is_overflow(&self) -> bool {
+        overflow::is_overflow(self.entry_size)
+    }
+
+    /// Returns the value of a leaf cell of the table, reading it from its overflow pages if
+    /// they are used
+    pub fn cell_value<'table>(
+        &'table self,
+        cell: &LeafNodeCell<'table>,
+    ) -> TableResult<&'table Data> {
+        if !self.is_overflow() {
+            return Ok(cell.data(self.entry_size));
+        }
+        let first = overflow::first_page(cell.data(self.cell_size).read_all());
+        let mut values = self.overflow_values.lock().unwrap();
+        let value = match values.entry(first) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(overflow::read(&self.pager, first, self.entry_size.size)?)
+            }
+        };
+        // Boxes are only dropped through &mut self, so they live as long as the borrow
+        let value = unsafe { &*(value.as_ref() as *const [u8]) };
+        Ok(Data::new_ref(value))
+    }
+
+    /// Writes over the value that the cursor points to, which has to be in the table
+    fn write_value(&mut self, cursor: Cursor, value: &[u8]) -> TableResult<()> {
+        if !self.is_overflow() {
+            cursor.value_mut(self).write_all(value);
+            return Ok(());
+        }
+        let first = overflow::first_page(cursor.cell(self).data(self.cell_size).read_all());
+        overflow::overwrite(&mut self.pager, first, value)?;
+        if let Some(cached) = self.overflow_values.lock().unwrap().get_mut(&first) {
+            cached.copy_from_slice(value);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn max_height(&self) -> Option<usize> {
+        self.metadata.metadata.max_height
+    }
+
+    /// Limits the height of the tree, inserts that would exceed it fail with MaxHeightExceeded
+    pub fn set_max_height(&mut self, max_height: Option<usize>) {
+        self.metadata.metadata.max_height = max_height;
+    }
+
+    /// Returns true if inserting into the leaf would split every node up to the root
+    fn insert_grows_tree(&self, leaf: &LeafNodeHeader) -> TableResult<bool> {
+        if leaf.num_cells < self.max_leaf_cells {
+            return Ok(false);
+        }
+        let mut parent_ptr = leaf.parent_ptr;
+        while !parent_ptr.is_null() {
+            let parent = self.pager.get_node_ref(parent_ptr)?.internal().unwrap();
+            if parent.num_keys < INTERNAL_NODE_CELL_COUNT {
+                return Ok(false);
+            }
+            parent_ptr = parent.parent_ptr;
+        }
+        Ok(true)
+    }
+
+    /// Returns the key after the biggest one in the table, 0 if the table is empty
+    pub fn next_key(&self) -> TableResult<usize> {
+        let leaf = self.rightmost_leaf()?;
+        if leaf.num_cells == 0 {
+            return Ok(0);
+        }
+        let last = leaf.cell_unchecked(leaf.num_cells - 1, self.cell_size);
+        last.key.checked_add(1).ok_or(TableError::KeysExhausted)
+    }
+
+    /// Returns the key that the next autoincrement insert will use, unless a bigger key is
+    /// inserted before
+    #[inline]
+    pub fn next_autoincrement(&self) -> usize {
+        self.metadata.metadata.next_autoincrement
+    }
+
+    /// Makes autoincrement inserts start at the key, they still skip the keys in the table
+    pub fn set_next_autoincrement(&mut self, next: usize) {
+        self.metadata.metadata.next_autoincrement = next;
+    }
+
+    /// Returns true if inserts through the DB that omit the primary key get an autoincrement key
+    #[inline]
+    pub fn autoincrement(&self) -> bool {
+        self.metadata.metadata.autoincrement
+    }
+
+    /// Lets inserts through the DB omit the primary key, which has to be a UINT
+    pub fn set_autoincrement(&mut self, autoincrement: bool) {
+        let metadata = &self.metadata.metadata;
+        assert!(
+            metadata.primary().typ == Type::Uint && !metadata.is_composite_key(),
+            "Only single UINT primary keys can be autoincremented"
+        );
+        self.metadata.metadata.autoincrement = autoincrement;
+    }
+
+    /// Inserts the value and returns its key.
+    /// If no key is given, the next autoincrement key is used. It's stored with the metadata,
+    /// so keys of deleted rows aren't reused even after reopening the table
+    pub fn insert_returning_key(&mut self, key: Option<usize>, value: &[u8]) -> TableResult<usize> {
+        let key = match key {
+            Some(key) => key,
+            None => self.next_key()?.max(self.next_autoincrement()),
+        };
+        self.insert(key, value)?;
+        Ok(key)
     }
 
-    // TODO: Add a method for making entries without values
     pub fn insert(&mut self, key: usize, value: &[u8]) -> TableResult<()> {
-        let entry_size = self.entry_size;
+        let mut cursor = self.find_cursor(key)?;
+        self.insert_at_cursor(&mut cursor, key, value)
+    }
+
+    /// Inserts an entry without a value, nullable fields are NULL and the rest are zeroed.
+    /// Tables without fields other than the primary key only store keys
+    pub fn insert_key(&mut self, key: usize) -> TableResult<()> {
+        let mut value = vec![0; self.entry_size.size];
+        let data = Data::new_mut(&mut value);
+        self.metadata
+            .metadata
+            .data_fields()
+            .filter(|f| f.nullable)
+            .for_each(|f| data.set_flag(f.null_offset, true));
+        self.insert(key, &value)
+    }
+
+    /// Inserts entries whose keys are sorted by appending them to the rightmost leaf, which
+    /// avoids descending the tree for every key. The leaf is split when it's full like with
+    /// Table::insert.
+    /// Entries that don't come after every key in the table are inserted with Table::insert.
+    /// Returns the number of inserted entries, stopping at the first error
+    pub fn bulk_insert<'v>(
+        &mut self,
+        entries: impl Iterator<Item = (usize, &'v [u8])>,
+    ) -> TableResult<usize> {
+        let cell_size = self.cell_size;
+        let mut rightmost = self.rightmost_node(self.get_root())?;
+        let mut count = 0;
+        for (key, value) in entries {
+            let leaf = self.pager.loaded_node_ref(rightmost).leaf().unwrap();
+            let num_cells = leaf.num_cells;
+            let is_last = num_cells == 0 || leaf.cell_unchecked(num_cells - 1, cell_size).key < key;
+            if is_last {
+                let mut cursor = self.cursor(rightmost, num_cells);
+                self.insert_at_cursor(&mut cursor, key, value)?;
+                // The key is the biggest, so it always ends up in the rightmost leaf
+                rightmost = cursor.page_num;
+            } else {
+                self.insert(key, value)?;
+                rightmost = self.rightmost_node(self.get_root())?;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Inserts the entry at the cell that the cursor points to, which has to be where the key
+    /// goes in key order. The cursor is moved to the leaf the entry ends up in
+    fn insert_at_cursor(
+        &mut self,
+        cursor: &mut Cursor,
+        key: usize,
+        value: &[u8],
+    ) -> TableResult<()> {
+        let cell_size = self.cell_size;
         let max_leaf_cells = self.max_leaf_cells;
-        let mut cursor = self.find_cursor(key);
         let leaf = cursor.leaf(self);
         if cursor.cell_num < leaf.num_cells
-            && leaf.cell_unchecked(cursor.cell_num, entry_size).key == key
+            && leaf.cell_unchecked(cursor.cell_num, cell_size).key == key
         {
             return Err(TableError::DuplicateKey);
         }
+        if let Some(max) = self.max_height()
+            && self.height()? >= max
+            && self.insert_grows_tree(leaf)?
+        {
+            return Err(TableError::MaxHeightExceeded);
+        }
+        let is_full = leaf.num_cells == max_leaf_cells;
+        let is_root = leaf.is_root();
+        // Entries in overflow pages are written before inserting the cell that points to them
+        let first_page;
+        let value = if self.is_overflow() {
+            first_page = (overflow::write(&mut self.pager, value)?.0 as u64).to_le_bytes();
+            &first_page[..]
+        } else {
+            value
+        };
 
-        if leaf.num_cells == max_leaf_cells {
-            if leaf.is_root() {
-                self.split_root_leaf_and_insert(&mut cursor, key, value);
+        if is_full {
+            if is_root {
+                self.split_root_leaf_and_insert(cursor, key, value)?;
             } else {
-                self.split_nonroot_leaf_and_insert(&mut cursor, key, value);
+                self.split_nonroot_leaf_and_insert(cursor, key, value)?;
             }
         } else {
-            leaf.insert_at_index(cursor.cell_num, key, value, entry_size);
+            let leaf = cursor.leaf_mut(self);
+            leaf.insert_at_index(cursor.cell_num, key, value, cell_size);
         }
+        if let Some(filter) = &mut self.metadata.metadata.bloom_filter {
+            filter.insert(key);
+        }
+        let next_autoincrement = &mut self.metadata.metadata.next_autoincrement;
+        *next_autoincrement = (*next_autoincrement).max(key.saturating_add(1));
+        self.metadata.metadata.row_count += 1;
+        if let Some(log) = &mut self.insertion_log {
+            log.append(key);
+        }
+        Ok(())
+    }
+
+    /// Replaces the value of the key if it's in the table and inserts it otherwise.
+    /// Replacing writes over the existing cell, so the tree isn't changed.
+    /// Returns true if an existing value was replaced
+    pub fn upsert(&mut self, key: usize, value: &[u8]) -> TableResult<bool> {
+        let cursor = self.find_cursor(key)?;
+        let leaf = cursor.leaf(self);
+        if cursor.cell_num < leaf.num_cells && cursor.cell(self).key == key {
+            self.write_value(cursor, value)?;
+            return Ok(true);
+        }
+        self.insert(key, value)?;
+        Ok(false)
+    }
+
+    /// Removes the key from the table.
+    /// Leaves that end up with less than split_count cells borrow a cell from a sibling or get
+    /// merged with it, which can propagate up to the root. The pages of merged nodes aren't reused,
+    /// but the overflow pages of the entry are freed for later inserts
+    pub fn delete(&mut self, key: usize) -> TableResult<()> {
+        let cell_size = self.cell_size;
+        let max_leaf_cells = self.max_leaf_cells;
+        let cursor = self.find_cursor(key)?;
+        let leaf = cursor.leaf(self);
+        if cursor.cell_num >= leaf.num_cells
+            || leaf.cell_unchecked(cursor.cell_num, cell_size).key != key
+        {
+            return Err(TableError::KeyNotFound);
+        }
+
+        if self.is_overflow() {
+            let first = overflow::first_page(cursor.cell(self).data(cell_size).read_all());
+            overflow::free(&mut self.pager, first)?;
+            self.overflow_values.lock().unwrap().remove(&first);
+        }
+        let leaf = cursor.leaf_mut(self);
+        leaf.remove_at_index(cursor.cell_num, cell_size);
+        if !leaf.is_root() && leaf.num_cells < LeafNodeHeader::split_count(max_leaf_cells) {
+            self.rebalance_leaf(cursor.page_num)?;
+        }
+        self.metadata.metadata.row_count -= 1;
         Ok(())
     }
 
+    fn set_parent(&mut self, page_num: PageNum, parent: PageNum) -> TableResult<()> {
+        match self.pager.get_node(page_num)? {
+            NodeMut::InternalNode(internal) => internal.parent_ptr = parent,
+            NodeMut::LeafNode(leaf) => leaf.parent_ptr = parent,
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the left node of the pair formed by the child and a sibling
+    fn sibling_pair(parent: &InternalNodeHeader, page_num: PageNum) -> usize {
+        let index = parent.child_index(page_num);
+        index.saturating_sub(1)
+    }
+
+    /// Merges the leaf with a sibling if they fit in one leaf, otherwise moves a cell from it
+    fn rebalance_leaf(&mut self, page_num: PageNum) -> TableResult<()> {
+        let cell_size = self.cell_size;
+        let max_leaf_cells = self.max_leaf_cells;
+        let parent_page_num = self
+            .pager
+            .get_node_ref(page_num)?
+            .leaf()
+            .unwrap()
+            .parent_ptr;
+        let parent = self
+            .pager
+            .get_node_ref(parent_page_num)?
+            .internal()
+            .unwrap();
+        let left_index = Self::sibling_pair(parent, page_num);
+        let left_page_num = parent.ptr(left_index);
+        let right_page_num = parent.ptr(left_index + 1);
+        let [parent, left, right] =
+            self.pager
+                .get_nodes([parent_page_num, left_page_num, right_page_num])?;
+        let parent = parent.internal().unwrap();
+        let left = left.leaf().unwrap();
+        let right = right.leaf().unwrap();
+
+        if left.num_cells + right.num_cells <= max_leaf_cells {
+            for i in 0..right.num_cells {
+                let cell = left.cell_mut_unchecked(left.num_cells + i, cell_size);
+                cell.clone_from(right.cell_unchecked(i, cell_size), cell_size);
+            }
+            left.num_cells += right.num_cells;
+            return self.remove_child(parent_page_num, left_index);
+        }
+
+        if page_num == left_page_num {
+            let first = right.cell_unchecked(0, cell_size);
+            left.insert_at_index(
+                left.num_cells,
+                first.key,
+                first.data(cell_size).read_all(),
+                cell_size,
+            );
+            right.remove_at_index(0, cell_size);
+        } else {
+            let last = left.cell_unchecked(left.num_cells - 1, cell_size);
+            right.insert_at_index(0, last.key, last.data(cell_size).read_all(), cell_size);
+            left.num_cells -= 1;
+        }
+        parent.cell_mut_unchecked(left_index).key = right.cell_unchecked(0, cell_size).key;
+        Ok(())
+    }
+
+    /// Removes the separator at index and the child to its right, which was merged into the left
+    /// one. Rebalances the node if it underflows, and collapses the root if it has one child
+    fn remove_child(&mut self, page_num: PageNum, index: usize) -> TableResult<()> {
+        let internal = self.pager.get_node(page_num)?.internal().unwrap();
+        let left = internal.ptr(index);
+        internal.set_ptr(index + 1, left);
+        internal.remove_at_index(index);
+        let (is_root, num_keys, right_child) =
+            (internal.is_root(), internal.num_keys, internal.right_child);
+
+        if is_root {
+            if num_keys == 0 {
+                self.set_parent(right_child, PageNum::NULL)?;
+                self.set_root(right_child);
+            }
+        } else if num_keys < INTERNAL_NODE_MIN_KEYS {
+            self.rebalance_internal(page_num)?;
+        }
+        Ok(())
+    }
+
+    /// Same as rebalance_leaf for internal nodes, the separator in the parent moves down into
+    /// the merged node or gets rotated with the borrowed key
+    fn rebalance_internal(&mut self, page_num: PageNum) -> TableResult<()> {
+        let parent_page_num = self
+            .pager
+            .get_node_ref(page_num)?
+            .internal()
+            .unwrap()
+            .parent_ptr;
+        let parent = self
+            .pager
+            .get_node_ref(parent_page_num)?
+            .internal()
+            .unwrap();
+        let left_index = Self::sibling_pair(parent, page_num);
+        let separator = parent.cell_unchecked(left_index).key;
+        let left_page_num = parent.ptr(left_index);
+        let right_page_num = parent.ptr(left_index + 1);
+        let [parent, left, right] =
+            self.pager
+                .get_nodes([parent_page_num, left_page_num, right_page_num])?;
+        let parent = parent.internal().unwrap();
+        let left = left.internal().unwrap();
+        let right = right.internal().unwrap();
+
+        if left.num_keys + 1 + right.num_keys <= INTERNAL_NODE_CELL_COUNT {
+            let num_keys = left.num_keys;
+            left.cell_mut_unchecked(num_keys)
+                .initialize(separator, left.right_child);
+            for i in 0..right.num_keys {
+                let cell = left.cell_mut_unchecked(num_keys + 1 + i);
+                cell.clone_from(right.cell_unchecked(i));
+            }
+            left.num_keys = num_keys + 1 + right.num_keys;
+            left.right_child = right.right_child;
+            let children: Vec<_> = (0..=right.num_keys).map(|i| right.ptr(i)).collect();
+            for child in children {
+                self.set_parent(child, left_page_num)?;
+            }
+            return self.remove_child(parent_page_num, left_index);
+        }
+
+        // The child that changes node is updated once the nodes aren't borrowed anymore
+        let (child, new_parent) = if page_num == left_page_num {
+            let (first_key, first_ptr) = {
+                let first = right.cell_unchecked(0);
+                (first.key, first.ptr)
+            };
+            let num_keys = left.num_keys;
+            left.cell_mut_unchecked(num_keys)
+                .initialize(separator, left.right_child);
+            left.right_child = first_ptr;
+            left.num_keys += 1;
+            parent.cell_mut_unchecked(left_index).key = first_key;
+            right.remove_at_index(0);
+            (first_ptr, left_page_num)
+        } else {
+            let (last_key, last_ptr) = {
+                let last = left.cell_unchecked(left.num_keys - 1);
+                (last.key, last.ptr)
+            };
+            let moved = left.right_child;
+            for i in (0..right.num_keys).rev() {
+                right.move_cell(i, i + 1);
+            }
+            right.cell_mut_unchecked(0).initialize(separator, moved);
+            right.num_keys += 1;
+            parent.cell_mut_unchecked(left_index).key = last_key;
+            left.right_child = last_ptr;
+            left.num_keys -= 1;
+            (moved, right_page_num)
+        };
+        self.set_parent(child, new_parent)
+    }
+
     /// Creates a new leaf node, copies cells from self to other until self has split_count cells
     /// Also it creates a new entry in the correct leaf and mutates the cursor to point at it
     /// Returns the newly created page, as well as the first key in the right node
     fn split_leaf_and_insert(
-        &self,
+        &mut self,
         cursor: &mut Cursor,
         // leaf: &mut LeafNodeHeader,
         key: usize,
         value: &[u8],
         parent: PageNum,
         max_leaf_cells: usize,
-    ) -> (PageNum, usize) {
-        let leaf = cursor.leaf(self);
-
+    ) -> TableResult<(PageNum, usize)> {
+        let cell_size = self.cell_size;
         let new_leaf_page_num = self.pager.get_free_page();
-        let new_leaf_page = self.pager.get_page(new_leaf_page_num);
+        let [leaf_page, new_leaf_page] =
+            self.pager.get_pages([cursor.page_num, new_leaf_page_num])?;
+        let leaf = leaf_page
+            .page_header_mut()
+            .node_mut()
+            .leaf()
+            .expect("A cursor has to point to a leaf");
         let new_leaf = LeafNodeHeader::initialize(new_leaf_page, parent);
 
-        let entry_size = self.entry_size;
         // Copy half of the cells from old leaf, if new cell has to go into new leaf
         // copy it there and point the cursor
         let split_count = LeafNodeHeader::split_count(max_leaf_cells);
         for i in split_count..max_leaf_cells {
             let new_node_index = i - split_count;
-            let cell = new_leaf.cell_mut_unchecked(new_node_index, entry_size);
-            let old_cell = leaf.cell_unchecked(i, entry_size);
-            cell.clone_from(old_cell, entry_size);
+            let cell = new_leaf.cell_mut_unchecked(new_node_index, cell_size);
+            let old_cell = leaf.cell_unchecked(i, cell_size);
+            cell.clone_from(old_cell, cell_size);
         }
         // Set correct metadata before possible inserting into the old leaf
         leaf.parent_ptr = parent;
@@ -302,17 +1223,22 @@ impl Table {
         new_leaf.num_cells = max_leaf_cells - split_count;
         if cursor.cell_num < split_count {
             // No need to change the cursor, it's already correct
-            leaf.insert_at_index(cursor.cell_num, key, value, entry_size);
+            leaf.insert_at_index(cursor.cell_num, key, value, cell_size);
         } else {
-            let cell_num = new_leaf.insert(key, value, entry_size);
+            let cell_num = new_leaf.insert(key, value, cell_size);
             cursor.page_num = new_leaf_page_num;
             cursor.cell_num = cell_num;
         }
-        let split_key = new_leaf.cell_unchecked(0, entry_size).key;
-        (new_leaf_page_num, split_key)
+        let split_key = new_leaf.cell_unchecked(0, cell_size).key;
+        Ok((new_leaf_page_num, split_key))
     }
 
-    fn split_root_leaf_and_insert(&mut self, cursor: &mut Cursor, key: usize, value: &[u8]) {
+    fn split_root_leaf_and_insert(
+        &mut self,
+        cursor: &mut Cursor,
+        key: usize,
+        value: &[u8],
+    ) -> TableResult<()> {
         let old_leaf_page_num = cursor.page_num;
         let new_internal_page_num = self.pager.get_free_page();
         self.set_root(new_internal_page_num);
@@ -320,9 +1246,9 @@ impl Table {
         let max_leaf_cells = self.max_leaf_cells;
         // Split children into two new leaf nodes
         let (new_leaf_page_num, split_key) =
-            self.split_leaf_and_insert(cursor, key, value, new_internal_page_num, max_leaf_cells);
+            self.split_leaf_and_insert(cursor, key, value, new_internal_page_num, max_leaf_cells)?;
 
-        let new_internal_page = self.pager.get_page(new_internal_page_num);
+        let new_internal_page = self.pager.get_page(new_internal_page_num)?;
         InternalNodeHeader::initialize(
             new_internal_page,
             PageNum::NULL,
@@ -331,17 +1257,23 @@ impl Table {
             new_leaf_page_num,
         );
         // println!("Internal {:?}: \n{:?}", new_internal_page_num, new_internal);
-        // println!("Leaf {:?}: \n{:?}", old_leaf_page_num, leaf.debug(entry_size));
-        // println!("Leaf {:?}: \n{:?}", new_leaf_page_num, new_leaf.debug(entry_size));
+        // println!("Leaf {:?}: \n{:?}", old_leaf_page_num, leaf.debug(cell_size));
+        // println!("Leaf {:?}: \n{:?}", new_leaf_page_num, new_leaf.debug(cell_size));
+        Ok(())
     }
 
-    fn split_nonroot_leaf_and_insert(&mut self, cursor: &mut Cursor, key: usize, value: &[u8]) {
+    fn split_nonroot_leaf_and_insert(
+        &mut self,
+        cursor: &mut Cursor,
+        key: usize,
+        value: &[u8],
+    ) -> TableResult<()> {
         let leaf = cursor.leaf(self);
         let max_leaf_cells = self.max_leaf_cells;
         let parent_page_num = leaf.parent_ptr;
         let (new_leaf_page_num, leaf_split_key) =
-            self.split_leaf_and_insert(cursor, key, value, parent_page_num, max_leaf_cells);
-        self.insert_internal_recursive(parent_page_num, leaf_split_key, new_leaf_page_num);
+            self.split_leaf_and_insert(cursor, key, value, parent_page_num, max_leaf_cells)?;
+        self.insert_internal_recursive(parent_page_num, leaf_split_key, new_leaf_page_num)
     }
 
     /// Creates a new internal node, copies cells from self to other until self has split_count cells
@@ -349,16 +1281,25 @@ impl Table {
     /// field on the child
     /// Returns the newly created page, as well as the first key in the right node
     fn split_internal_and_insert(
-        &self,
-        internal: &mut InternalNodeHeader,
+        &mut self,
+        internal_page_num: PageNum,
         key: usize,
         ptr: PageNum,
         parent: PageNum,
-    ) -> (PageNum, usize) {
-        // println!("Old internal\n{:?}", internal);
+    ) -> TableResult<(PageNum, usize)> {
         let new_internal_page_num = self.pager.get_free_page();
-        let new_internal_page = self.pager.get_page(new_internal_page_num);
+        let [internal_page, new_internal_page] = self
+            .pager
+            .get_pages([internal_page_num, new_internal_page_num])?;
+        let internal = internal_page
+            .page_header_mut()
+            .node_mut()
+            .internal()
+            .expect("Parent should be internal");
+        // println!("Old internal\n{:?}", internal);
         let new_internal = InternalNodeHeader::initialize_empty(new_internal_page, parent);
+        // Children that move to the new node, their parent is changed after the split
+        let mut moved = Vec::with_capacity(INTERNAL_NODE_CELL_COUNT);
 
         let index = internal.find_index(key);
         // Copy half of the cells from old internal
@@ -369,21 +1310,12 @@ impl Table {
             let cell = new_internal.cell_mut_unchecked(new_node_index);
             let old_cell = internal.cell_unchecked(i);
             cell.clone_from(old_cell);
-            let ptr = old_cell.ptr;
-            let node = self.pager.get_node(ptr);
-            match node {
-                NodeMut::InternalNode(internal) => internal.parent_ptr = new_internal_page_num,
-                NodeMut::LeafNode(leaf) => leaf.parent_ptr = new_internal_page_num,
-            }
+            moved.push(old_cell.ptr);
         }
         new_internal.num_keys = REST;
         new_internal.parent_ptr = parent;
         new_internal.right_child = internal.right_child;
-        let node = self.pager.get_node(internal.right_child);
-        match node {
-            NodeMut::InternalNode(internal) => internal.parent_ptr = new_internal_page_num,
-            NodeMut::LeafNode(leaf) => leaf.parent_ptr = new_internal_page_num,
-        }
+        moved.push(internal.right_child);
 
         internal.num_keys = SPLIT_COUNT;
         internal.parent_ptr = parent;
@@ -397,14 +1329,13 @@ impl Table {
         } else {
             new_internal.insert(key, ptr);
             // Change the parent if we insert into the new one
-            let node = self.pager.get_node(ptr);
-            match node {
-                NodeMut::LeafNode(leaf) => leaf.parent_ptr = new_internal_page_num,
-                NodeMut::InternalNode(internal) => internal.parent_ptr = new_internal_page_num,
-            }
+            moved.push(ptr);
         }
 
-        (new_internal_page_num, split_key)
+        for child in moved {
+            self.set_parent(child, new_internal_page_num)?;
+        }
+        Ok((new_internal_page_num, split_key))
     }
 
     /// Recursively inserts an entry into internal nodes. If the node is full, it splits and
@@ -414,18 +1345,22 @@ impl Table {
         internal_page_num: PageNum,
         split_key: usize,
         ptr: PageNum,
-    ) {
+    ) -> TableResult<()> {
         let internal = self
             .pager
-            .get_node(internal_page_num)
+            .get_node_ref(internal_page_num)?
             .internal()
             .expect("Parent should be internal");
         if internal.num_keys == INTERNAL_NODE_CELL_COUNT {
             if internal.is_root() {
                 let new_root_page_num = self.pager.get_free_page();
-                let (new_internal_page_num, internal_split_key) =
-                    self.split_internal_and_insert(internal, split_key, ptr, new_root_page_num);
-                let new_root_page = self.pager.get_page(new_root_page_num);
+                let (new_internal_page_num, internal_split_key) = self.split_internal_and_insert(
+                    internal_page_num,
+                    split_key,
+                    ptr,
+                    new_root_page_num,
+                )?;
+                let new_root_page = self.pager.get_page(new_root_page_num)?;
                 let _new_root = InternalNodeHeader::initialize(
                     new_root_page,
                     PageNum::NULL,
@@ -447,23 +1382,32 @@ impl Table {
                 self.set_root(new_root_page_num);
             } else {
                 let parent_page_num = internal.parent_ptr;
-                let (new_internal_page_num, internal_split_key) =
-                    self.split_internal_and_insert(internal, split_key, ptr, parent_page_num);
+                let (new_internal_page_num, internal_split_key) = self.split_internal_and_insert(
+                    internal_page_num,
+                    split_key,
+                    ptr,
+                    parent_page_num,
+                )?;
                 self.insert_internal_recursive(
                     parent_page_num,
                     internal_split_key,
                     new_internal_page_num,
-                );
+                )?;
             }
         } else {
-            internal.insert(split_key, ptr);
+            self.pager
+                .get_node(internal_page_num)?
+                .internal()
+                .expect("Parent should be internal")
+                .insert(split_key, ptr);
         }
+        Ok(())
     }
 }
 
 impl Drop for Table {
     fn drop(&mut self) {
-        self.pager.flush();
-        self.metadata.flush();
+        // Errors can't be reported from drop, Table::flush should be used to handle them
+        let _ = self.flush();
     }
 }
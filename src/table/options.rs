@@ -0,0 +1,56 @@
+use std::fs;
+
+use crate::pager::ENCRYPTION_KEY_SIZE;
+
+/// Optional features of a table, given to Table::create_with_options and
+/// Table::open_with_options. They can be combined, a table can be compressed, encrypted and
+/// have a write-ahead log at the same time.
+/// Only the files and the key are needed to open a table, whether it's compressed and its
+/// description are read from the files
+#[derive(Default)]
+pub struct TableOptions<'a> {
+    pub(crate) insertion_log: Option<fs::File>,
+    pub(crate) wal: Option<fs::File>,
+    pub(crate) description: Option<&'a str>,
+    pub(crate) compressed: bool,
+    pub(crate) key: Option<[u8; ENCRYPTION_KEY_SIZE]>,
+}
+
+impl<'a> TableOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps a log of the keys in insertion order in the file, see InsertionLog
+    pub fn insertion_log(mut self, file: fs::File) -> Self {
+        self.insertion_log = Some(file);
+        self
+    }
+
+    /// Commits the changes to a write-ahead log in the file, see Table::commit
+    pub fn wal(mut self, file: fs::File) -> Self {
+        self.wal = Some(file);
+        self
+    }
+
+    /// Describes the table, it can't be longer than MAX_DESCRIPTION_LENGTH
+    pub fn description(mut self, description: &'a str) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    /// Stores the pages compressed, see Pager::new_compressed
+    #[cfg(feature = "compression")]
+    pub fn compressed(mut self) -> Self {
+        self.compressed = true;
+        self
+    }
+
+    /// Encrypts the pages with the key, see Pager::new_encrypted. The table has to be opened
+    /// with the same key
+    #[cfg(feature = "encryption")]
+    pub fn encrypted(mut self, key: &[u8; ENCRYPTION_KEY_SIZE]) -> Self {
+        self.key = Some(*key);
+        self
+    }
+}
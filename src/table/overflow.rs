@@ -0,0 +1,84 @@
+use std::io;
+
+use crate::{
+    pager::{NEXT_PAGE_SIZE, PAGE_HEADER_SIZE, PAGE_SIZE, PageNum, Pager},
+    table::{leaf::LeafNodeCell, metadata::Size},
+};
+
+/// Bytes of a value stored in each overflow page, after the pointer to the next page
+pub const OVERFLOW_PAGE_CAPACITY: usize = PAGE_SIZE - PAGE_HEADER_SIZE - NEXT_PAGE_SIZE;
+/// Leaves have to fit at least this many entries, bigger entries are stored in overflow pages
+pub const MIN_INLINE_CELLS: usize = 4;
+
+/// Returns true if the entries don't fit MIN_INLINE_CELLS times in a leaf. The leaf cells of
+/// those entries store the first page of a chain of overflow pages with the value
+pub const fn is_overflow(entry_size: Size) -> bool {
+    LeafNodeCell::max_cells(entry_size.aligned) < MIN_INLINE_CELLS
+}
+
+/// Size of the values stored in the leaf cells for entries of the size
+pub const fn cell_size(entry_size: Size) -> Size {
+    if is_overflow(entry_size) {
+        Size::new(std::mem::size_of::<u64>())
+    } else {
+        entry_size
+    }
+}
+
+/// Reads the first overflow page from the value of a leaf cell
+pub fn first_page(cell_value: &[u8]) -> PageNum {
+    PageNum(u64::from_le_bytes(cell_value.try_into().unwrap()) as usize)
+}
+
+/// Stores the value in a chain of overflow pages, reusing free pages first.
+/// Returns the first page, which is what the leaf cell stores
+pub fn write(pager: &mut Pager, value: &[u8]) -> io::Result<PageNum> {
+    // Pages are written from the end so each one knows the page after it
+    let mut next = PageNum::NULL;
+    for chunk in value.chunks(OVERFLOW_PAGE_CAPACITY).rev() {
+        let page_num = pager.reuse_free_page()?;
+        let page = pager.get_page(page_num)?;
+        page.set_next_page(next);
+        let payload = &mut page.body_mut()[NEXT_PAGE_SIZE..];
+        payload[..chunk.len()].copy_from_slice(chunk);
+        payload[chunk.len()..].fill(0);
+        next = page_num;
+    }
+    Ok(next)
+}
+
+/// Overwrites the value of the chain starting at the page, which has the same size
+pub fn overwrite(pager: &mut Pager, first: PageNum, value: &[u8]) -> io::Result<()> {
+    let mut page_num = first;
+    for chunk in value.chunks(OVERFLOW_PAGE_CAPACITY) {
+        let page = pager.get_page(page_num)?;
+        page.body_mut()[NEXT_PAGE_SIZE..NEXT_PAGE_SIZE + chunk.len()].copy_from_slice(chunk);
+        page_num = page.next_page();
+    }
+    Ok(())
+}
+
+/// Reassembles the size bytes of the value stored in the chain starting at the page
+pub fn read(pager: &Pager, first: PageNum, size: usize) -> io::Result<Box<[u8]>> {
+    let mut value = Vec::with_capacity(size);
+    let mut page_num = first;
+    while value.len() < size && !page_num.is_null() {
+        let page = pager.get_page_ref(page_num)?;
+        let remaining = (size - value.len()).min(OVERFLOW_PAGE_CAPACITY);
+        value.extend_from_slice(&page.body()[NEXT_PAGE_SIZE..NEXT_PAGE_SIZE + remaining]);
+        page_num = page.next_page();
+    }
+    value.resize(size, 0);
+    Ok(value.into_boxed_slice())
+}
+
+/// Adds every page of the chain starting at the page to the pager's free list
+pub fn free(pager: &mut Pager, first: PageNum) -> io::Result<()> {
+    let mut page_num = first;
+    while !page_num.is_null() {
+        let next = pager.get_page_ref(page_num)?.next_page();
+        pager.free_page(page_num)?;
+        page_num = next;
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use crate::{
+    pager::PageNum,
+    table::{Table, internal::INTERNAL_NODE_CELL_COUNT, node::Node},
+};
+
+impl Table {
+    /// Walks the whole tree checking its invariants: keys are sorted and unique within each
+    /// node and fall between the separators of the parent, children point back to their
+    /// parent, node sizes are within bounds and every leaf is at the same depth.
+    /// Returns a description of the first violation found
+    pub fn validate(&self) -> Result<(), String> {
+        let root = self.get_root();
+        let mut leaf_depth = None;
+        self.validate_node(root, PageNum::NULL, None, None, 0, &mut leaf_depth)
+    }
+
+    /// Checks the node and its children, every key has to be in lower..upper
+    fn validate_node(
+        &self,
+        page_num: PageNum,
+        parent: PageNum,
+        lower: Option<usize>,
+        upper: Option<usize>,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+    ) -> Result<(), String> {
+        let in_bounds =
+            |key: usize| lower.is_none_or(|l| key >= l) && upper.is_none_or(|u| key < u);
+        let check_keys = |keys: &mut dyn Iterator<Item = usize>| {
+            let mut previous = None;
+            for key in keys {
+                if previous.is_some_and(|p| p >= key) {
+                    return Err(format!(
+                        "Page {}: key {key} comes after {}",
+                        page_num.0,
+                        previous.unwrap()
+                    ));
+                }
+                if !in_bounds(key) {
+                    return Err(format!(
+                        "Page {}: key {key} is outside of its parent's range {lower:?}..{upper:?}",
+                        page_num.0
+                    ));
+                }
+                previous = Some(key);
+            }
+            Ok(())
+        };
+
+        let node = self
+            .pager
+            .get_node_ref(page_num)
+            .map_err(|e| format!("Page {}: {e}", page_num.0))?;
+        match node {
+            Node::InternalNode(internal) => {
+                if internal.parent_ptr != parent {
+                    return Err(format!(
+                        "Internal page {}: parent is {}, expected {}",
+                        page_num.0, internal.parent_ptr.0, parent.0
+                    ));
+                }
+                if internal.num_keys == 0 || internal.num_keys > INTERNAL_NODE_CELL_COUNT {
+                    return Err(format!(
+                        "Internal page {}: {} keys, expected 1..={INTERNAL_NODE_CELL_COUNT}",
+                        page_num.0, internal.num_keys
+                    ));
+                }
+                let keys = (0..internal.num_keys).map(|i| internal.cell_unchecked(i).key);
+                check_keys(&mut keys.clone())?;
+                for i in 0..=internal.num_keys {
+                    let child_lower = if i == 0 {
+                        lower
+                    } else {
+                        keys.clone().nth(i - 1)
+                    };
+                    let child_upper = if i == internal.num_keys {
+                        upper
+                    } else {
+                        keys.clone().nth(i)
+                    };
+                    self.validate_node(
+                        internal.ptr(i),
+                        page_num,
+                        child_lower,
+                        child_upper,
+                        depth + 1,
+                        leaf_depth,
+                    )?;
+                }
+                Ok(())
+            }
+            Node::LeafNode(leaf) => {
+                if leaf.parent_ptr != parent {
+                    return Err(format!(
+                        "Leaf page {}: parent is {}, expected {}",
+                        page_num.0, leaf.parent_ptr.0, parent.0
+                    ));
+                }
+                if leaf.num_cells > self.max_leaf_cells {
+                    return Err(format!(
+                        "Leaf page {}: {} cells, expected at most {}",
+                        page_num.0, leaf.num_cells, self.max_leaf_cells
+                    ));
+                }
+                if *leaf_depth.get_or_insert(depth) != depth {
+                    return Err(format!(
+                        "Leaf page {}: at depth {depth}, other leaves are at depth {}",
+                        page_num.0,
+                        leaf_depth.unwrap()
+                    ));
+                }
+                let cell_size = self.cell_size;
+                check_keys(&mut (0..leaf.num_cells).map(|i| leaf.cell_unchecked(i, cell_size).key))
+            }
+        }
+    }
+}
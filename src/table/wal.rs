@@ -0,0 +1,119 @@
+use std::{
+    fs,
+    io::{self, Read, Seek},
+};
+
+use crate::{
+    pager::{PAGE_SIZE, PageNum},
+    utils::file_io,
+};
+
+/// Starts every transaction, so a log that was overwritten by something else isn't replayed
+const TRANSACTION_MAGIC: [u8; 8] = *b"RUSTDBTX";
+/// Magic, number of pages and length of the metadata
+const HEADER_SIZE: usize = TRANSACTION_MAGIC.len() + 2 * std::mem::size_of::<u32>();
+const PAGE_RECORD_SIZE: usize = std::mem::size_of::<u64>() + PAGE_SIZE;
+const CHECKSUM_SIZE: usize = std::mem::size_of::<u32>();
+
+/// Number and contents of a page committed to the log
+pub type LoggedPage = (PageNum, Vec<u8>);
+
+/// Write-ahead log of the changes committed to a table since it was last flushed.
+/// Each transaction stores the pages modified since the previous one, as they are in memory,
+/// and the metadata, followed by a checksum of all of it. Pages are only written to the data
+/// file when the table is flushed, so the log only has to redo committed transactions: a
+/// transaction that wasn't completely written is discarded, which leaves the files as they
+/// were at the last commit
+pub struct Wal {
+    file: fs::File,
+    len: u64,
+}
+
+impl Wal {
+    /// Opens the log, writing the metadata of the last transaction it has to the metadata file.
+    /// Returns the pages of the transactions in the order they were committed, they have to be
+    /// written to the data file by the pager before the log is cleared
+    pub fn open(
+        mut file: fs::File,
+        metadata_file: &fs::File,
+    ) -> io::Result<(Self, Vec<LoggedPage>)> {
+        let mut contents = vec![];
+        file.rewind()?;
+        file.read_to_end(&mut contents)?;
+        let mut transactions = contents.as_slice();
+        let mut pages = vec![];
+        let mut metadata = None;
+        while let Some((records, transaction_metadata, rest)) = parse_transaction(transactions) {
+            for record in records.chunks_exact(PAGE_RECORD_SIZE) {
+                let (page_num, page) = record.split_at(std::mem::size_of::<u64>());
+                let page_num = u64::from_le_bytes(page_num.try_into().unwrap()) as usize;
+                pages.push((PageNum(page_num), page.to_vec()));
+            }
+            metadata = Some(transaction_metadata);
+            transactions = rest;
+        }
+        // Each transaction has the whole metadata file, so only the last one is written
+        if let Some(metadata) = metadata {
+            metadata_file.set_len(metadata.len() as u64)?;
+            file_io::write_all_at(metadata_file, metadata, 0)?;
+            metadata_file.sync_data()?;
+        }
+        let len = contents.len() as u64;
+        Ok((Self { file, len }, pages))
+    }
+
+    /// Appends a transaction with the contents of the pages and the metadata file, it's durable
+    /// once this returns
+    pub fn commit(&mut self, pages: &[(PageNum, &[u8])], metadata: &[u8]) -> io::Result<()> {
+        let size = HEADER_SIZE + pages.len() * PAGE_RECORD_SIZE + metadata.len() + CHECKSUM_SIZE;
+        let mut transaction = Vec::with_capacity(size);
+        transaction.extend_from_slice(&TRANSACTION_MAGIC);
+        transaction.extend_from_slice(&(pages.len() as u32).to_le_bytes());
+        transaction.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        for (page_num, page) in pages {
+            transaction.extend_from_slice(&(page_num.0 as u64).to_le_bytes());
+            transaction.extend_from_slice(page);
+        }
+        transaction.extend_from_slice(metadata);
+        let checksum = crc32fast::hash(&transaction);
+        transaction.extend_from_slice(&checksum.to_le_bytes());
+
+        file_io::write_all_at(&self.file, &transaction, self.len)?;
+        self.file.sync_data()?;
+        self.len += transaction.len() as u64;
+        Ok(())
+    }
+
+    /// Empties the log, once the data and metadata files have every committed change
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+        self.len = 0;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Splits the first transaction into its page records and metadata, returning the bytes after
+/// it. Returns None if there isn't a complete transaction with a matching checksum
+fn parse_transaction(bytes: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let header = bytes.get(..HEADER_SIZE)?;
+    let (magic, header) = header.split_at(TRANSACTION_MAGIC.len());
+    if magic != TRANSACTION_MAGIC {
+        return None;
+    }
+    let (num_pages, metadata_len) = header.split_at(std::mem::size_of::<u32>());
+    let num_pages = u32::from_le_bytes(num_pages.try_into().unwrap()) as usize;
+    let metadata_len = u32::from_le_bytes(metadata_len.try_into().unwrap()) as usize;
+    let body_end = HEADER_SIZE + num_pages * PAGE_RECORD_SIZE + metadata_len;
+    let checksum = bytes.get(body_end..body_end + CHECKSUM_SIZE)?;
+    if crc32fast::hash(&bytes[..body_end]).to_le_bytes() != checksum {
+        return None;
+    }
+    let (pages, metadata) = bytes[HEADER_SIZE..body_end].split_at(num_pages * PAGE_RECORD_SIZE);
+    Some((pages, metadata, &bytes[body_end + CHECKSUM_SIZE..]))
+}
@@ -0,0 +1,79 @@
+pub(crate) const BLOOM_FILTER_WORDS: usize = 512;
+const BLOOM_FILTER_BITS: usize = BLOOM_FILTER_WORDS * u64::BITS as usize;
+const NUM_HASHES: usize = 3;
+
+/// Fixed size bloom filter over keys.
+/// It can return false positives but never false negatives
+#[derive(Clone, Copy)]
+pub struct BloomFilter {
+    bits: [u64; BLOOM_FILTER_WORDS],
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// splitmix64 finalizer, spreads the bits of the key
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+impl BloomFilter {
+    pub const fn new() -> Self {
+        Self {
+            bits: [0; BLOOM_FILTER_WORDS],
+        }
+    }
+
+    /// Rebuilds a filter from the words returned by BloomFilter::words
+    pub(crate) const fn from_words(bits: [u64; BLOOM_FILTER_WORDS]) -> Self {
+        Self { bits }
+    }
+
+    pub(crate) fn words(&self) -> &[u64; BLOOM_FILTER_WORDS] {
+        &self.bits
+    }
+
+    /// Returns the bit positions of the key, using double hashing
+    fn positions(key: usize) -> impl Iterator<Item = usize> {
+        let h1 = mix(key as u64);
+        let h2 = mix(h1) | 1;
+        (0..NUM_HASHES).map(move |i| {
+            let hash = h1.wrapping_add((i as u64).wrapping_mul(h2));
+            (hash % BLOOM_FILTER_BITS as u64) as usize
+        })
+    }
+
+    pub fn insert(&mut self, key: usize) {
+        for bit in Self::positions(key) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns false if the key was never inserted
+    pub fn might_contain(&self, key: usize) -> bool {
+        Self::positions(key).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut filter = BloomFilter::new();
+        (0..1000).step_by(2).for_each(|k| filter.insert(k));
+        assert!((0..1000).step_by(2).all(|k| filter.might_contain(k)));
+
+        let false_positives = (1..1000)
+            .step_by(2)
+            .filter(|&k| filter.might_contain(k))
+            .count();
+        assert!(false_positives < 10, "Too many false positives");
+    }
+}
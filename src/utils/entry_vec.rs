@@ -1,5 +1,7 @@
 use std::ops::Index;
 
+/// Entries are equal if they have the same entry_size and values
+#[derive(Debug, Clone, PartialEq)]
 pub struct EntryVector<T> {
     entry_size: usize,
     vector: Vec<T>,
@@ -31,6 +33,12 @@ impl<T> EntryVector<T> {
         assert!(iter.next().is_none());
     }
 
+    /// Returns the number of values in each entry
+    #[inline]
+    pub fn entry_size(&self) -> usize {
+        self.entry_size
+    }
+
     /// Returns the number of entries that are contained
     #[inline]
     pub fn len(&self) -> usize {
@@ -43,6 +51,20 @@ impl<T> EntryVector<T> {
         self.vector.is_empty()
     }
 
+    /// Returns the entry at the index, None if it's out of bounds
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&[T]> {
+        let start = index.checked_mul(self.entry_size)?;
+        self.vector.get(start..start.checked_add(self.entry_size)?)
+    }
+
+    /// Iterates over the values of a single column, one per entry.
+    /// Panics if the column isn't smaller than entry_size
+    pub fn column(&self, column: usize) -> impl Iterator<Item = &T> {
+        assert!(column < self.entry_size, "Column out of bounds");
+        self.vector.iter().skip(column).step_by(self.entry_size)
+    }
+
     #[inline]
     pub fn iter(&self) -> EntryVectorIter<'_, T> {
         EntryVectorIter {
@@ -55,12 +77,42 @@ impl<T> EntryVector<T> {
 impl<T> Index<usize> for EntryVector<T> {
     type Output = [T];
     fn index(&self, index: usize) -> &Self::Output {
-        let internal_index = index * self.entry_size;
-        assert!(
-            internal_index + self.entry_size - 1 < self.vector.len(),
-            "Out of bounds access"
-        );
-        &self.vector[internal_index..(internal_index + self.entry_size)]
+        self.get(index).expect("Out of bounds access")
+    }
+}
+
+impl<T> IntoIterator for EntryVector<T> {
+    type Item = Vec<T>;
+    type IntoIter = EntryVectorIntoIter<T>;
+    fn into_iter(self) -> Self::IntoIter {
+        EntryVectorIntoIter {
+            entry_size: self.entry_size,
+            values: self.vector.into_iter(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a EntryVector<T> {
+    type Item = &'a [T];
+    type IntoIter = EntryVectorIter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator that takes the entries out of an EntryVector
+pub struct EntryVectorIntoIter<T> {
+    entry_size: usize,
+    values: std::vec::IntoIter<T>,
+}
+
+impl<T> Iterator for EntryVectorIntoIter<T> {
+    type Item = Vec<T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.values.len() == 0 {
+            return None;
+        }
+        Some(self.values.by_ref().take(self.entry_size).collect())
     }
 }
 
@@ -125,4 +177,60 @@ mod tests {
 
         assert_eq!(v.iter().count(), entries.len());
     }
+
+    #[test]
+    fn test_into_iter() {
+        let entries = [[1usize, 2], [3, 4], [5, 6]];
+        let mut v = EntryVector::<usize>::new(2);
+        entries.iter().for_each(|e| v.push(e.iter().copied()));
+
+        let borrowed: Vec<_> = (&v).into_iter().collect();
+        assert_eq!(borrowed, entries);
+        let owned: Vec<Vec<usize>> = v.into_iter().collect();
+        assert_eq!(owned, entries);
+    }
+
+    #[test]
+    fn test_equality() {
+        let mut a = EntryVector::<usize>::new(2);
+        let mut b = EntryVector::<usize>::new(2);
+        a.push([1, 2]);
+        b.push([1, 2]);
+        assert_eq!(a, b);
+        b.push([3, 4]);
+        assert_ne!(a, b);
+
+        // The same values split into different entries aren't equal
+        let mut c = EntryVector::<usize>::new(4);
+        c.push([1, 2, 3, 4]);
+        a.push([3, 4]);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_column() {
+        let mut v = EntryVector::<usize>::new(3);
+        v.push([1, 2, 3]);
+        v.push([4, 5, 6]);
+        v.push([7, 8, 9]);
+        assert_eq!(v.column(1).copied().collect::<Vec<_>>(), [2, 5, 8]);
+        assert_eq!(EntryVector::<usize>::new(3).column(2).count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_column_out_of_bounds() {
+        let v = EntryVector::<usize>::new(3);
+        let _ = v.column(3);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut v = EntryVector::<usize>::new(2);
+        v.push([1, 2]);
+        assert_eq!(v.get(0), Some(&[1, 2][..]));
+        assert_eq!(v.get(1), None);
+        assert_eq!(v.get(usize::MAX), None);
+    }
 }
@@ -0,0 +1,190 @@
+use std::{
+    cmp::Ordering,
+    fs,
+    io::{self, BufReader, BufWriter, Read, Seek, Write},
+};
+
+const KEY_SIZE: usize = std::mem::size_of::<usize>();
+
+/// Sorts keys by a value of their row using a bounded amount of memory.
+/// Keys are pushed with their value, once more than memory_limit of them are buffered, they are
+/// sorted and spilled into a temporary file as a sorted run. Only the keys are spilled, so the
+/// value of a spilled key is loaded again when the runs are merged
+pub struct ExternalSorter<V, F: Fn(&V, &V) -> Ordering> {
+    memory_limit: usize,
+    compare: F,
+    buffer: Vec<(V, usize)>,
+    runs: Vec<fs::File>,
+}
+
+impl<V, F: Fn(&V, &V) -> Ordering> ExternalSorter<V, F> {
+    pub fn new(memory_limit: usize, compare: F) -> Self {
+        Self {
+            memory_limit: memory_limit.max(1),
+            compare,
+            buffer: vec![],
+            runs: vec![],
+        }
+    }
+
+    pub fn push(&mut self, value: V, key: usize) -> io::Result<()> {
+        self.buffer.push((value, key));
+        if self.buffer.len() >= self.memory_limit {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of runs that were written to disk
+    #[inline]
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    fn sort_buffer(&mut self) {
+        let compare = &self.compare;
+        self.buffer.sort_by(|(a, _), (b, _)| compare(a, b));
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.sort_buffer();
+        let mut file = tempfile::tempfile()?;
+        let mut writer = BufWriter::new(&mut file);
+        for (_, key) in self.buffer.drain(..) {
+            writer.write_all(&key.to_ne_bytes())?;
+        }
+        writer.flush()?;
+        drop(writer);
+        file.rewind()?;
+        self.runs.push(file);
+        Ok(())
+    }
+
+    /// Returns an iterator over the keys in sorted order, load reads the value of a key that
+    /// was spilled. It's called once for each of them
+    pub fn finish<E, L>(mut self, load: L) -> Result<SortedKeys<V, F, L>, E>
+    where
+        E: From<io::Error>,
+        L: Fn(usize) -> Result<V, E>,
+    {
+        self.sort_buffer();
+        let runs = self
+            .runs
+            .into_iter()
+            .map(|file| Run::new(file, &load))
+            .collect::<Result<Vec<_>, E>>()?;
+        let mut memory = self.buffer.into_iter();
+        let memory_head = memory.next();
+        Ok(SortedKeys {
+            compare: self.compare,
+            load,
+            runs,
+            memory,
+            memory_head,
+        })
+    }
+}
+
+struct Run<V> {
+    reader: BufReader<fs::File>,
+    head: Option<(V, usize)>,
+}
+
+impl<V> Run<V> {
+    fn new<E: From<io::Error>>(
+        file: fs::File,
+        load: impl Fn(usize) -> Result<V, E>,
+    ) -> Result<Self, E> {
+        let mut run = Self {
+            reader: BufReader::new(file),
+            head: None,
+        };
+        run.advance(load)?;
+        Ok(run)
+    }
+
+    fn advance<E: From<io::Error>>(
+        &mut self,
+        load: impl Fn(usize) -> Result<V, E>,
+    ) -> Result<(), E> {
+        let mut buf = [0u8; KEY_SIZE];
+        self.head = match self.reader.read_exact(&mut buf) {
+            Ok(()) => {
+                let key = usize::from_ne_bytes(buf);
+                Some((load(key)?, key))
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(())
+    }
+}
+
+/// K-way merge of the sorted runs and the keys that stayed in memory
+pub struct SortedKeys<V, F: Fn(&V, &V) -> Ordering, L> {
+    compare: F,
+    load: L,
+    runs: Vec<Run<V>>,
+    memory: std::vec::IntoIter<(V, usize)>,
+    memory_head: Option<(V, usize)>,
+}
+
+impl<V, F, L, E> Iterator for SortedKeys<V, F, L>
+where
+    F: Fn(&V, &V) -> Ordering,
+    L: Fn(usize) -> Result<V, E>,
+    E: From<io::Error>,
+{
+    type Item = Result<usize, E>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let min_run = self
+            .runs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, r)| r.head.as_ref().map(|h| (i, h)))
+            .min_by(|(_, (a, _)), (_, (b, _))| (self.compare)(a, b));
+
+        match (min_run, &self.memory_head) {
+            (Some((i, (value, key))), memory)
+                if memory
+                    .as_ref()
+                    .is_none_or(|(m, _)| (self.compare)(value, m) != Ordering::Greater) =>
+            {
+                let key = *key;
+                Some(self.runs[i].advance(&self.load).map(|_| key))
+            }
+            (_, Some(_)) => {
+                let (_, key) = std::mem::replace(&mut self.memory_head, self.memory.next())?;
+                Some(Ok(key))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_external_sort() {
+        let value = |key: usize| (key * 37) % 100;
+        let mut sorter = ExternalSorter::new(8, |a: &usize, b: &usize| b.cmp(a));
+        for key in 0..100 {
+            sorter.push(value(key), key).unwrap();
+        }
+        assert!(sorter.runs() > 1, "Keys should have been spilled");
+
+        let loads = std::cell::Cell::new(0);
+        let load = |key| {
+            loads.set(loads.get() + 1);
+            io::Result::Ok(value(key))
+        };
+        let sorted: Vec<_> = sorter.finish(load).unwrap().map(Result::unwrap).collect();
+        let mut expected: Vec<_> = (0..100).collect();
+        expected.sort_by_key(|&key| std::cmp::Reverse(value(key)));
+        assert_eq!(sorted, expected);
+        // Only the keys that were spilled are loaded, once each
+        assert_eq!(loads.get(), 96);
+    }
+}
@@ -1,2 +1,5 @@
+pub mod bloom;
 pub mod entry_vec;
+pub mod external_sort;
+pub(crate) mod file_io;
 pub mod range;
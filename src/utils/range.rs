@@ -3,8 +3,8 @@ use std::cmp::Ordering;
 
 use crate::expression::Comparison;
 
-pub trait IntervalElement: Ord + Clone + Copy {}
-impl<T: Ord + Clone + Copy> IntervalElement for T {}
+pub trait IntervalElement: Ord + Clone {}
+impl<T: Ord + Clone> IntervalElement for T {}
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum IntervalStart<T: IntervalElement> {
@@ -39,6 +39,14 @@ impl<T: IntervalElement> IntervalStart<T> {
             Self::Closed(_) => false,
         }
     }
+
+    /// Returns the end of the values that come before this start
+    pub fn complement(&self) -> IntervalEnd<T> {
+        match self {
+            Self::Open(o) => IntervalEnd::Closed(o.clone()),
+            Self::Closed(c) => IntervalEnd::Open(c.clone()),
+        }
+    }
 }
 
 impl<T: IntervalElement> Eq for IntervalStart<T> {}
@@ -107,6 +115,14 @@ impl<T: IntervalElement> IntervalEnd<T> {
             Self::Closed(_) => false,
         }
     }
+
+    /// Returns the start of the values that come after this end
+    pub fn complement(&self) -> IntervalStart<T> {
+        match self {
+            Self::Open(o) => IntervalStart::Closed(o.clone()),
+            Self::Closed(c) => IntervalStart::Open(c.clone()),
+        }
+    }
 }
 
 impl<T: IntervalElement> Eq for IntervalEnd<T> {}
@@ -156,7 +172,7 @@ impl<T: IntervalElement> SimpleRange<T> {
     pub fn value_past_start(&self, v: &T) -> bool {
         match self {
             Self::Values(s, _) => s.past(v),
-            Self::Value(v) => matches!(v.cmp(v), Ordering::Equal | Ordering::Greater),
+            Self::Value(value) => matches!(v.cmp(value), Ordering::Equal | Ordering::Greater),
             Self::Start(s) => s.past(v),
             Self::End(_) => true,
             Self::Empty => true,
@@ -167,7 +183,7 @@ impl<T: IntervalElement> SimpleRange<T> {
     pub fn value_before_end(&self, v: &T) -> bool {
         match self {
             Self::Values(_, e) => e.before(v),
-            Self::Value(v) => matches!(v.cmp(v), Ordering::Equal | Ordering::Less),
+            Self::Value(value) => matches!(v.cmp(value), Ordering::Equal | Ordering::Less),
             Self::Start(_) => true,
             Self::End(e) => e.before(v),
             Self::Empty => true,
@@ -175,20 +191,35 @@ impl<T: IntervalElement> SimpleRange<T> {
         }
     }
 
-    fn contains(&self, v: &T) -> bool {
-        self.value_past_start(v) && self.value_before_end(v)
+    /// Returns true if the value is inside of the range
+    pub fn contains(&self, v: &T) -> bool {
+        !self.is_empty() && self.value_past_start(v) && self.value_before_end(v)
     }
 
-    /// Returns true if this range overlaps with the other one
-    pub fn overlaps(&self, other: &Self) -> bool {
+    /// Returns true if the range doesn't contain any value
+    pub fn is_empty(&self) -> bool {
         match self {
-            Self::Values(s, e) => other.contains(s.value()) || other.contains(e.value()),
-            Self::Value(v) => other.contains(v),
-            Self::Start(s) => other.value_before_end(s.value()),
-            Self::End(e) => other.value_past_start(e.value()),
             Self::Empty => true,
-            Self::Full => true,
+            Self::Values(s, e) => Self::from_bounds(Some(s.clone()), Some(e.clone())).is_none(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if this range and the other one have a value in common.
+    /// Ranges that only touch at a bound overlap if both of them include it
+    pub fn overlaps(&self, other: &Self) -> bool {
+        if self.is_empty() || other.is_empty() {
+            return false;
         }
+        let start = match (self.lower_bound(), other.lower_bound()) {
+            (Some(s1), Some(s2)) => Some(std::cmp::max(s1, s2)),
+            (s1, s2) => s1.or(s2),
+        };
+        let end = match (self.upper_bound(), other.upper_bound()) {
+            (Some(e1), Some(e2)) => Some(std::cmp::min(e1, e2)),
+            (e1, e2) => e1.or(e2),
+        };
+        Self::from_bounds(start, end).is_some()
     }
 
     /// Returns the union range
@@ -199,45 +230,45 @@ impl<T: IntervalElement> SimpleRange<T> {
             (Self::Values(s1, e1), Self::Values(s2, e2)) => {
                 let min_start = std::cmp::min(s1, s2);
                 let max_end = std::cmp::max(e1, e2);
-                Self::Values(*min_start, *max_end)
+                Self::Values(min_start.clone(), max_end.clone())
             }
             (Self::Values(s1, _), Self::Start(s2)) | (Self::Start(s2), Self::Values(s1, _)) => {
                 let min_start = std::cmp::min(s1, s2);
-                Self::Start(*min_start)
+                Self::Start(min_start.clone())
             }
             (Self::Values(_, e1), Self::End(e2)) | (Self::End(e2), Self::Values(_, e1)) => {
                 let max_end = std::cmp::max(e1, e2);
-                Self::End(*max_end)
+                Self::End(max_end.clone())
             }
             (Self::Values(s1, e1), Self::Value(v)) | (Self::Value(v), Self::Values(s1, e1)) => {
-                let s2 = &IntervalStart::Closed(*v);
+                let s2 = &IntervalStart::Closed(v.clone());
                 let min_start = std::cmp::min(s1, s2);
-                let e2 = &IntervalEnd::Closed(*v);
+                let e2 = &IntervalEnd::Closed(v.clone());
                 let max_end = std::cmp::max(e1, e2);
-                Self::Values(*min_start, *max_end)
+                Self::Values(min_start.clone(), max_end.clone())
             }
             (Self::Value(v), Self::Start(s)) | (Self::Start(s), Self::Value(v)) => {
-                let s2 = &IntervalStart::Closed(*v);
+                let s2 = &IntervalStart::Closed(v.clone());
                 let min_start = std::cmp::min(s, s2);
-                Self::Start(*min_start)
+                Self::Start(min_start.clone())
             }
             (Self::Value(v), Self::End(e)) | (Self::End(e), Self::Value(v)) => {
-                let e2 = &IntervalEnd::Closed(*v);
+                let e2 = &IntervalEnd::Closed(v.clone());
                 let max_end = std::cmp::max(e, e2);
-                Self::End(*max_end)
+                Self::End(max_end.clone())
             }
-            (v @ Self::Value(_), Self::Value(_)) => *v,
+            (v @ Self::Value(_), Self::Value(_)) => v.clone(),
             (Self::Start(s1), Self::Start(s2)) => {
                 let min_start = std::cmp::min(s1, s2);
-                Self::Start(*min_start)
+                Self::Start(min_start.clone())
             }
             (Self::End(e1), Self::End(e2)) => {
                 let max_end = std::cmp::max(e1, e2);
-                Self::End(*max_end)
+                Self::End(max_end.clone())
             }
             (Self::Start(_), Self::End(_)) | (Self::End(_), Self::Start(_)) => Self::Full,
             (Self::Full, _) | (_, Self::Full) => Self::Full,
-            (Self::Empty, o) | (o, Self::Empty) => *o,
+            (Self::Empty, o) | (o, Self::Empty) => o.clone(),
         }
     }
 
@@ -249,50 +280,132 @@ impl<T: IntervalElement> SimpleRange<T> {
             (Self::Values(s1, e1), Self::Values(s2, e2)) => {
                 let max_start = std::cmp::max(s1, s2);
                 let min_end = std::cmp::min(e1, e2);
-                Self::Values(*max_start, *min_end)
+                Self::Values(max_start.clone(), min_end.clone())
             }
             (Self::Values(s1, e1), Self::Start(s2)) | (Self::Start(s2), Self::Values(s1, e1)) => {
                 let max_start = std::cmp::max(s1, s2);
-                Self::Values(*max_start, *e1)
+                Self::Values(max_start.clone(), e1.clone())
             }
             (Self::Values(s1, e1), Self::End(e2)) | (Self::End(e2), Self::Values(s1, e1)) => {
                 let min_end = std::cmp::min(e1, e2);
-                Self::Values(*s1, *min_end)
+                Self::Values(s1.clone(), min_end.clone())
             }
             (Self::Start(s1), Self::Start(s2)) => {
                 let max_start = std::cmp::max(s1, s2);
-                Self::Start(*max_start)
+                Self::Start(max_start.clone())
             }
             (Self::End(e1), Self::End(e2)) => {
                 let min_end = std::cmp::min(e1, e2);
-                Self::End(*min_end)
+                Self::End(min_end.clone())
             }
-            (Self::Start(s), Self::End(e)) | (Self::End(e), Self::Start(s)) => Self::Values(*s, *e),
-            (Self::Full, o) | (o, Self::Full) => *o,
+            (Self::Start(s), Self::End(e)) | (Self::End(e), Self::Start(s)) => {
+                Self::Values(s.clone(), e.clone())
+            }
+            (Self::Full, o) | (o, Self::Full) => o.clone(),
             (Self::Empty, _) | (_, Self::Empty) => Self::Empty,
-            (v @ Self::Value(_), _) | (_, v @ Self::Value(_)) => *v,
+            (Self::Value(v), o) | (o, Self::Value(v)) if o.contains(v) => Self::Value(v.clone()),
+            (Self::Value(_), _) | (_, Self::Value(_)) => Self::Empty,
+        }
+    }
+
+    /// Returns the parts of this range that aren't in the other one, there can be up to two
+    /// when the other range is inside of this one
+    pub fn difference(&self, other: &Self) -> Vec<Self> {
+        if let Self::Empty = self {
+            return vec![];
+        }
+        if let Self::Empty = other {
+            return vec![self.clone()];
+        }
+        let (start, end) = (self.lower_bound(), self.upper_bound());
+        let mut pieces = vec![];
+        // Values before the other range
+        if let Some(other_start) = other.lower_bound() {
+            let before = other_start.complement();
+            let before_end = end
+                .clone()
+                .map_or(before.clone(), |e| std::cmp::min(e, before));
+            pieces.extend(Self::from_bounds(start.clone(), Some(before_end)));
+        }
+        // Values after the other range
+        if let Some(other_end) = other.upper_bound() {
+            let after = other_end.complement();
+            let start = start.map_or(after.clone(), |s| std::cmp::max(s, after));
+            pieces.extend(Self::from_bounds(Some(start), end));
+        }
+        pieces
+    }
+
+    /// Returns true if there's no gap between this range and the next one.
+    /// The next range can't start before this one
+    fn joins(&self, next: &Self) -> bool {
+        match (self.upper_bound(), next.lower_bound()) {
+            (Some(e), Some(s)) => match e.value().cmp(s.value()) {
+                Ordering::Greater => true,
+                Ordering::Equal => !(e.open() && s.open()),
+                Ordering::Less => false,
+            },
+            _ => true,
+        }
+    }
+
+    /// Builds the range between the bounds, None if it doesn't contain any value
+    fn from_bounds(start: Option<IntervalStart<T>>, end: Option<IntervalEnd<T>>) -> Option<Self> {
+        match (start, end) {
+            (None, None) => Some(Self::Full),
+            (Some(s), None) => Some(Self::Start(s)),
+            (None, Some(e)) => Some(Self::End(e)),
+            (Some(s), Some(e)) => match s.value().cmp(e.value()) {
+                Ordering::Less => Some(Self::Values(s, e)),
+                Ordering::Equal if !s.open() && !e.open() => Some(Self::Value(s.value().clone())),
+                _ => None,
+            },
         }
     }
 
     /// Returns the value at the start None if there isn't a start
     pub fn start(&self) -> Option<T> {
         match self {
-            Self::Value(v) => Some(*v),
-            Self::Values(s, _) => Some(*s.value()),
-            Self::Start(s) => Some(*s.value()),
+            Self::Value(v) => Some(v.clone()),
+            Self::Values(s, _) => Some(s.value().clone()),
+            Self::Start(s) => Some(s.value().clone()),
+            Self::End(_) => None,
+            Self::Empty => None,
+            Self::Full => None,
+        }
+    }
+
+    /// Returns the lower bound of the range, None if the range is unbounded below
+    pub fn lower_bound(&self) -> Option<IntervalStart<T>> {
+        match self {
+            Self::Value(v) => Some(IntervalStart::Closed(v.clone())),
+            Self::Values(s, _) => Some(s.clone()),
+            Self::Start(s) => Some(s.clone()),
             Self::End(_) => None,
             Self::Empty => None,
             Self::Full => None,
         }
     }
 
+    /// Returns the upper bound of the range, None if the range is unbounded above
+    pub fn upper_bound(&self) -> Option<IntervalEnd<T>> {
+        match self {
+            Self::Value(v) => Some(IntervalEnd::Closed(v.clone())),
+            Self::Values(_, e) => Some(e.clone()),
+            Self::Start(_) => None,
+            Self::End(e) => Some(e.clone()),
+            Self::Empty => None,
+            Self::Full => None,
+        }
+    }
+
     /// Returns the value at the end None if there isn't an end
     pub fn end(&self) -> Option<T> {
         match self {
-            Self::Value(v) => Some(*v),
-            Self::Values(_, e) => Some(*e.value()),
+            Self::Value(v) => Some(v.clone()),
+            Self::Values(_, e) => Some(e.value().clone()),
             Self::Start(_) => None,
-            Self::End(e) => Some(*e.value()),
+            Self::End(e) => Some(e.value().clone()),
             Self::Empty => None,
             Self::Full => None,
         }
@@ -361,13 +474,35 @@ pub struct Range<T: IntervalElement> {
 
 impl<T: IntervalElement> Range<T> {
     pub fn new(range: SimpleRange<T>) -> Self {
-        Self { buf: vec![range] }
+        let mut range = Self { buf: vec![range] };
+        range.normalize();
+        range
+    }
+
+    /// Range containing the values between lo and hi, both included
+    pub fn closed(lo: T, hi: T) -> Self {
+        range!({[lo], [hi]})
+    }
+
+    /// Range containing the values starting at lo, included
+    pub fn from(lo: T) -> Self {
+        range!({[lo],})
+    }
+
+    /// Range containing the values up to hi, included
+    pub fn to(hi: T) -> Self {
+        range!({,[hi]})
+    }
+
+    /// Range containing only v
+    pub fn single(v: T) -> Self {
+        range!({ v })
     }
 
     pub fn from_comparison(comp: Comparison, v: T) -> Self {
         match comp {
-            Comparison::Equals => range!({ v }),
-            Comparison::NotEquals => range!({,(v)} | {(v),}),
+            Comparison::Equals | Comparison::NullSafeEquals => range!({ v }),
+            Comparison::NotEquals => range!({,(v.clone())} | {(v),}),
             Comparison::MoreThanEquals => range!({[v],}),
             Comparison::MoreThan => range!({(v),}),
             Comparison::LessThanEquals => range!({,[v]}),
@@ -383,7 +518,7 @@ impl<T: IntervalElement> Range<T> {
             if union.overlaps(r) {
                 union = union.union(r);
             } else {
-                new_buf.push(*r);
+                new_buf.push(r.clone());
             }
         }
         new_buf.push(union);
@@ -392,27 +527,74 @@ impl<T: IntervalElement> Range<T> {
 
     pub fn union(&mut self, other: Self) {
         for r in &other.buf {
-            self.push_union(*r);
+            self.push_union(r.clone());
         }
+        self.normalize();
     }
 
-    fn push_intersection(&mut self, range: SimpleRange<T>) {
-        for r in &mut self.buf {
-            if range.overlaps(r) {
-                *r = range.intersection(r);
+    /// Keeps the values that are in both ranges, intersecting every pair of intervals
+    pub fn intersection(&mut self, other: Self) {
+        self.buf = self
+            .buf
+            .iter()
+            .flat_map(|r| {
+                other.buf.iter().map(|o| {
+                    if r.overlaps(o) {
+                        r.intersection(o)
+                    } else {
+                        SimpleRange::Empty
+                    }
+                })
+            })
+            .collect();
+        self.normalize();
+    }
+
+    /// Sorts the intervals by their start, merging the ones that overlap or are contiguous
+    /// and dropping the empty ones, so that equal ranges have the same intervals
+    pub fn normalize(&mut self) {
+        self.buf.retain(|r| !r.is_empty());
+        self.buf.sort_by_key(|r| r.lower_bound());
+        let mut merged: Vec<SimpleRange<T>> = Vec::with_capacity(self.buf.len());
+        for r in &self.buf {
+            match merged.last_mut() {
+                Some(last) if last.joins(r) => *last = last.union(r),
+                _ => merged.push(r.clone()),
             }
         }
+        self.buf = merged;
     }
 
-    pub fn intersection(&mut self, other: Self) {
+    /// Returns true if any of the intervals contains the value
+    pub fn contains(&self, v: &T) -> bool {
+        self.buf.iter().any(|r| r.contains(v))
+    }
+
+    /// Returns true if none of the intervals contain any value
+    pub fn is_empty(&self) -> bool {
+        self.buf.iter().all(SimpleRange::is_empty)
+    }
+
+    /// Removes the values of the other range, the result is empty if none are left
+    pub fn difference(&mut self, other: Self) {
         for r in other.buf {
-            self.push_intersection(r);
+            self.buf = self.buf.iter().flat_map(|s| s.difference(&r)).collect();
         }
+        self.normalize();
     }
 
+    /// Iterates over the intervals from the lowest to the highest, they never overlap because
+    /// every operation normalizes the range
     pub fn iter(&self) -> impl DoubleEndedIterator<Item = &SimpleRange<T>> {
         self.buf.iter()
     }
+
+    /// Iterates over the intervals from the highest start to the lowest
+    pub fn iter_rev(&self) -> impl Iterator<Item = &SimpleRange<T>> {
+        let mut intervals: Vec<_> = self.buf.iter().collect();
+        intervals.sort_by_key(|r| std::cmp::Reverse(r.lower_bound()));
+        intervals.into_iter()
+    }
 }
 
 #[macro_export]
@@ -595,4 +777,295 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_simple_range_difference() {
+        let r: SimpleRange<Literal> = simple_range!({[3usize], [10usize]});
+        assert_eq!(
+            r.difference(&simple_range!({ 5usize })),
+            vec![
+                simple_range!({[3usize], (5usize)}),
+                simple_range!({(5usize), [10usize]})
+            ]
+        );
+
+        assert_eq!(
+            r.difference(&simple_range!({[5usize],})),
+            vec![simple_range!({[3usize], (5usize)})]
+        );
+
+        assert_eq!(
+            r.difference(&simple_range!({,(5usize)})),
+            vec![simple_range!({[5usize], [10usize]})]
+        );
+
+        assert_eq!(
+            r.difference(&simple_range!({(3usize), [10usize]})),
+            vec![simple_range!({ 3usize })]
+        );
+
+        assert_eq!(r.difference(&simple_range!({[2usize], (11usize)})), vec![]);
+        assert_eq!(r.difference(&simple_range!({})), vec![r.clone()]);
+        assert_eq!(r.difference(&simple_range!({,})), vec![]);
+
+        let r: SimpleRange<Literal> = simple_range!({,});
+        assert_eq!(
+            r.difference(&simple_range!({ 5usize })),
+            vec![simple_range!({,(5usize)}), simple_range!({(5usize),})]
+        );
+
+        let r: SimpleRange<Literal> = simple_range!({ 5usize });
+        assert_eq!(r.difference(&simple_range!({(5usize),})), vec![r]);
+    }
+
+    #[test]
+    fn test_range_difference() {
+        let mut r: Range<Literal> = range!(
+            {[4usize], (10usize)}
+            |
+            {(14usize), (20usize)}
+        );
+        r.difference(range!({[6usize], [15usize]} | {18usize}));
+
+        assert_eq!(
+            r.buf,
+            vec![
+                simple_range!({[4usize], (6usize)}),
+                simple_range!({(15usize), (18usize)}),
+                simple_range!({(18usize), (20usize)})
+            ]
+        );
+
+        let mut r: Range<Literal> = range!({[4usize], (10usize)});
+        r.difference(range!({,}));
+        assert!(r.buf.is_empty());
+    }
+
+    #[test]
+    fn test_range_intersection_prunes() {
+        let r: Range<Literal> = range!(
+            ({[1usize], [3usize]} | {[5usize], [7usize]} | {[20usize], [30usize]})
+            & ({[6usize], [8usize]} | {[10usize], [25usize]})
+        );
+        assert_eq!(
+            r.buf,
+            vec![
+                simple_range!({[6usize], [7usize]}),
+                simple_range!({[20usize], [25usize]})
+            ]
+        );
+
+        let r: Range<Literal> = range!({[1usize], [10usize]} & {4usize});
+        assert_eq!(r.buf, vec![simple_range!({ 4usize })]);
+        let r: Range<Literal> = range!({[1usize], [10usize]} & {11usize});
+        assert!(r.buf.is_empty());
+    }
+
+    #[test]
+    fn test_value_intersection() {
+        let five: SimpleRange<usize> = simple_range!({ 5usize });
+        assert_eq!(
+            five.intersection(&simple_range!({(5usize),})),
+            simple_range!({})
+        );
+        assert_eq!(
+            five.intersection(&simple_range!({,(5usize)})),
+            simple_range!({})
+        );
+        assert_eq!(five.intersection(&simple_range!({[5usize],})), five);
+        assert_eq!(
+            five.intersection(&simple_range!({ 6usize })),
+            simple_range!({})
+        );
+
+        assert!(!five.overlaps(&simple_range!({(5usize), [10usize]})));
+        assert!(five.overlaps(&simple_range!({[5usize], [10usize]})));
+        let below: SimpleRange<usize> = simple_range!({[1usize], (5usize)});
+        assert!(!below.overlaps(&simple_range!({(5usize),})));
+        let wide: SimpleRange<usize> = simple_range!({[1usize], [10usize]});
+        assert!(wide.overlaps(&simple_range!({[3usize], [4usize]})));
+
+        let r: Range<usize> = range!({ 5usize } & ({,(5usize)} | {(5usize),}));
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_range_disjoint_intersection() {
+        let r: Range<Literal> = range!({[0usize], [5usize]} & {[10usize], [15usize]});
+        assert!(r.is_empty());
+        assert!(r.iter().next().is_none());
+
+        let r: Range<Literal> = range!({[10usize], [15usize]} & {[0usize], [5usize]});
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_range_normalize() {
+        let r: Range<Literal> = range!({[1usize], [5usize]} | {(5usize), [10usize]});
+        assert_eq!(r.buf, vec![simple_range!({[1usize], [10usize]})]);
+
+        let r: Range<Literal> = range!({[1usize], (5usize)} | {5usize});
+        assert_eq!(r.buf, vec![simple_range!({[1usize], [5usize]})]);
+
+        let r: Range<Literal> = range!({[1usize], (5usize)} | {(5usize), [10usize]});
+        assert_eq!(
+            r.buf,
+            vec![
+                simple_range!({[1usize], (5usize)}),
+                simple_range!({(5usize), [10usize]})
+            ]
+        );
+
+        let mut r: Range<Literal> = Range {
+            buf: vec![
+                simple_range!({(14usize), (20usize)}),
+                simple_range!({}),
+                simple_range!({[4usize], (10usize)}),
+                simple_range!({,(2usize)}),
+                simple_range!({[10usize], [12usize]}),
+            ],
+        };
+        r.normalize();
+        assert_eq!(
+            r.buf,
+            vec![
+                simple_range!({,(2usize)}),
+                simple_range!({[4usize], [12usize]}),
+                simple_range!({(14usize), (20usize)})
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_iter_rev() {
+        let r: Range<Literal> = range!(
+            ({(14usize), (20usize)}
+            |
+            {,(2usize)})
+            | {[4usize], (10usize)}
+        );
+
+        assert_eq!(
+            r.iter_rev().cloned().collect::<Vec<_>>(),
+            vec![
+                simple_range!({(14usize), (20usize)}),
+                simple_range!({[4usize], (10usize)}),
+                simple_range!({,(2usize)})
+            ]
+        );
+    }
+
+    #[test]
+    fn test_simple_range_value_contains() {
+        let r: SimpleRange<Literal> = simple_range!({ 5usize });
+        assert!(r.contains(&5usize.into()));
+        assert!(!r.contains(&4usize.into()));
+        assert!(!r.contains(&6usize.into()));
+    }
+
+    #[test]
+    fn test_contains() {
+        let r: SimpleRange<usize> = simple_range!({(3usize), [10usize]});
+        assert!(!r.contains(&3));
+        assert!(r.contains(&4));
+        assert!(r.contains(&10));
+        assert!(!r.contains(&11));
+
+        let r: SimpleRange<usize> = simple_range!({[3usize], (10usize)});
+        assert!(r.contains(&3));
+        assert!(!r.contains(&10));
+
+        let r: SimpleRange<usize> = simple_range!({(3usize),});
+        assert!(!r.contains(&3));
+        assert!(r.contains(&usize::MAX));
+
+        let r: SimpleRange<usize> = simple_range!({,[3usize]});
+        assert!(r.contains(&0));
+        assert!(!r.contains(&4));
+
+        assert!(simple_range!({,}).contains(&7usize));
+        assert!(!simple_range!({}).contains(&7usize));
+
+        let r: Range<usize> = range!({,(2usize)} | {[4usize], (10usize)});
+        assert!(r.contains(&1));
+        assert!(!r.contains(&2));
+        assert!(!r.contains(&3));
+        assert!(r.contains(&4));
+        assert!(!r.contains(&10));
+    }
+
+    #[test]
+    fn test_is_empty() {
+        let empty: [SimpleRange<usize>; 4] = [
+            simple_range!({}),
+            simple_range!({(5usize), (5usize)}),
+            simple_range!({[5usize], (5usize)}),
+            simple_range!({[6usize], [5usize]}),
+        ];
+        assert!(empty.iter().all(SimpleRange::is_empty));
+
+        let not_empty: [SimpleRange<usize>; 4] = [
+            simple_range!({[5usize], [5usize]}),
+            simple_range!({ 5usize }),
+            simple_range!({(5usize),}),
+            simple_range!({,}),
+        ];
+        assert!(!not_empty.iter().any(SimpleRange::is_empty));
+
+        let mut r: Range<usize> = range!({[4usize], (10usize)});
+        assert!(!r.is_empty());
+        r.difference(range!({,}));
+        assert!(r.is_empty());
+        assert!(Range::<usize>::new(SimpleRange::Empty).is_empty());
+    }
+
+    #[test]
+    fn test_runtime_constructors() {
+        let (lo, hi) = (4usize, 10usize);
+        let r: Range<Literal> = Range::closed(lo.into(), hi.into());
+        assert_eq!(r.buf, range!({[4usize], [10usize]}).buf);
+
+        let r: Range<Literal> = Range::from(lo.into());
+        assert_eq!(r.buf, range!({[4usize],}).buf);
+
+        let r: Range<Literal> = Range::to(hi.into());
+        assert_eq!(r.buf, range!({,[10usize]}).buf);
+
+        let r: Range<Literal> = Range::single(lo.into());
+        assert_eq!(r.buf, range!({ 4usize }).buf);
+    }
+
+    #[test]
+    fn test_from_comparison() {
+        let five = Literal::Uint(5);
+        let cases: [(Comparison, Vec<SimpleRange<Literal>>); 7] = [
+            (Comparison::Equals, vec![simple_range!({ 5usize })]),
+            (Comparison::NullSafeEquals, vec![simple_range!({ 5usize })]),
+            (
+                Comparison::NotEquals,
+                vec![simple_range!({,(5usize)}), simple_range!({(5usize),})],
+            ),
+            (Comparison::MoreThanEquals, vec![simple_range!({[5usize],})]),
+            (Comparison::MoreThan, vec![simple_range!({(5usize),})]),
+            (Comparison::LessThanEquals, vec![simple_range!({,[5usize]})]),
+            (Comparison::LessThan, vec![simple_range!({,(5usize)})]),
+        ];
+        for (comp, buf) in cases {
+            let r = Range::from_comparison(comp, five.clone());
+            assert_eq!(r.buf, buf, "{comp:?}");
+            for v in 4usize..=6 {
+                let v = Literal::Uint(v);
+                assert_eq!(r.contains(&v), comp.eval(&v, &five), "{v:?} {comp:?} 5");
+            }
+        }
+    }
+
+    #[test]
+    fn test_float_range() {
+        let r: Range<Literal> = Range::from(1.5f64.into());
+        let start = &r.buf[0];
+        assert!(start.contains(&f64::NAN.into()));
+        assert!(start.contains(&1.5f64.into()));
+        assert!(!start.contains(&f64::NEG_INFINITY.into()));
+    }
 }
@@ -1,10 +1,16 @@
+use chumsky::Parser;
 use rustdb::expression;
 use rustdb::{
-    db::{DB, OperationResult},
+    db::{DB, DBError, DBResult, OperationResult},
     expression::{Comparison, Expression},
-    query::{Identifier, Literal, Statement},
-    table::{data::Data, metadata::Type},
+    query::{Identifier, Literal, Statement, parse_script, parser},
+    table::{
+        TableError,
+        data::Data,
+        metadata::{FieldDefinition, Type},
+    },
 };
+use std::fs;
 use tempfile::tempdir;
 
 // macro_rules! array_into {
@@ -92,25 +98,28 @@ fn test_insert() {
         .map(|(name, _)| name.into())
         .collect();
 
-    for (id, data) in test_data.iter().copied().enumerate() {
+    for (id, data) in test_data.iter().cloned().enumerate() {
         let mut values: Vec<_> = field_names.iter().copied().zip(data).collect();
         values.push((id_field.into(), id.into()));
 
         let insert_statement = Statement {
             operation: rustdb::query::Operation::Insert {
                 table: table_name.into(),
-                values,
+                replace: false,
+                values: vec![values],
             },
             wher: None,
             limit: None,
             skip: None,
+            group_by: None,
+            order_by: None,
         };
         let result = db.execute(insert_statement).unwrap();
-        assert!(matches!(result, OperationResult::Ok));
+        assert!(matches!(result, OperationResult::Count(1)));
     }
 
     let table = db.table(table_name).unwrap();
-    for (id, literals) in test_data.iter().copied().enumerate() {
+    for (id, literals) in test_data.iter().cloned().enumerate() {
         let data = table.find(id).unwrap();
         table
             .metadata
@@ -155,13 +164,13 @@ fn test_select() {
     let entry_size = table.metadata.metadata.entry_size();
     let mut buffer = vec![0u8; entry_size.size];
     let data_buffer = Data::new_mut(&mut buffer);
-    for (id, data) in test_data.iter().copied().enumerate() {
+    for (id, data) in test_data.iter().cloned().enumerate() {
         table
             .metadata
             .metadata
             .data_fields()
             .zip(data)
-            .for_each(|(f, l)| f.write(&l, data_buffer));
+            .for_each(|(f, l)| f.write(&l, data_buffer).unwrap());
         table.insert(id, data_buffer.read_all()).unwrap();
     }
 
@@ -173,11 +182,14 @@ fn test_select() {
     let select_statement = Statement {
         operation: rustdb::query::Operation::Select {
             table: table_name.into(),
+            distinct: false,
             columns,
         },
         wher: None,
         limit: None,
         skip: None,
+        group_by: None,
+        order_by: None,
     };
 
     let entries = match db.execute(select_statement).unwrap() {
@@ -222,13 +234,13 @@ fn test_update() {
     let entry_size = table.metadata.metadata.entry_size();
     let mut buffer = vec![0u8; entry_size.size];
     let data_buffer = Data::new_mut(&mut buffer);
-    for (id, data) in test_data.iter().copied().enumerate() {
+    for (id, data) in test_data.iter().cloned().enumerate() {
         table
             .metadata
             .metadata
             .data_fields()
             .zip(data)
-            .for_each(|(f, l)| f.write(&l, data_buffer));
+            .for_each(|(f, l)| f.write(&l, data_buffer).unwrap());
         table.insert(id, data_buffer.read_all()).unwrap();
     }
 
@@ -243,10 +255,13 @@ fn test_update() {
         operation: rustdb::query::Operation::Update {
             table: table_name.into(),
             values,
+            returning: vec![],
         },
         wher: None,
         limit: None,
         skip: None,
+        group_by: None,
+        order_by: None,
     };
     match db.execute(update_statement).unwrap() {
         OperationResult::Count(c) => {
@@ -257,7 +272,7 @@ fn test_update() {
 
     let modified_values = test_data
         .iter()
-        .copied()
+        .cloned()
         .map(|[_, _, s]| [modified_uint.into(), modified_int.into(), s]);
 
     let table = db.table(table_name).unwrap();
@@ -313,13 +328,13 @@ fn test_select_clause() {
     let entry_size = table.metadata.metadata.entry_size();
     let mut buffer = vec![0u8; entry_size.size];
     let data_buffer = Data::new_mut(&mut buffer);
-    for (id, data) in test_data.iter().copied().enumerate() {
+    for (id, data) in test_data.iter().cloned().enumerate() {
         table
             .metadata
             .metadata
             .data_fields()
             .zip(data)
-            .for_each(|(f, l)| f.write(&l, data_buffer));
+            .for_each(|(f, l)| f.write(&l, data_buffer).unwrap());
         table.insert(id, data_buffer.read_all()).unwrap();
     }
 
@@ -331,6 +346,7 @@ fn test_select_clause() {
     let select_statement = Statement {
         operation: rustdb::query::Operation::Select {
             table: table_name.into(),
+            distinct: false,
             columns,
         },
         wher: Some(Box::new(expression!(
@@ -338,6 +354,8 @@ fn test_select_clause() {
         ))),
         limit: Some(2),
         skip: Some(2),
+        group_by: None,
+        order_by: None,
     };
 
     let entries = match db.execute(select_statement).unwrap() {
@@ -406,13 +424,13 @@ fn test_update_clause() {
     let entry_size = table.metadata.metadata.entry_size();
     let mut buffer = vec![0u8; entry_size.size];
     let data_buffer = Data::new_mut(&mut buffer);
-    for (id, data) in test_data.iter().copied().enumerate() {
+    for (id, data) in test_data.iter().cloned().enumerate() {
         table
             .metadata
             .metadata
             .data_fields()
             .zip(data)
-            .for_each(|(f, l)| f.write(&l, data_buffer));
+            .for_each(|(f, l)| f.write(&l, data_buffer).unwrap());
         table.insert(id, data_buffer.read_all()).unwrap();
     }
 
@@ -429,12 +447,15 @@ fn test_update_clause() {
         operation: rustdb::query::Operation::Update {
             table: table_name.into(),
             values,
+            returning: vec![],
         },
         wher: Some(Box::new(expression!(
             ((id_field > 2usize) & (id_field <= 9usize) & ("string" = "testing"))
         ))),
         limit: Some(limit),
         skip: Some(skip),
+        group_by: None,
+        order_by: None,
     };
 
     let mut count = 0usize;
@@ -479,3 +500,1675 @@ fn test_update_clause() {
             });
     }
 }
+
+#[test]
+fn test_insert_coercion() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let table_name = "test";
+    let id_field = "id";
+    let fields = [("uint", Type::Uint), ("int", Type::Int)];
+    db.create_table(table_name, (id_field, Type::Uint), &fields)
+        .unwrap();
+
+    let values = vec![
+        (id_field.into(), 0usize.into()),
+        (fields[0].0.into(), 5isize.into()),
+        (fields[1].0.into(), 7usize.into()),
+    ];
+    let insert_statement = Statement {
+        operation: rustdb::query::Operation::Insert {
+            table: table_name.into(),
+            replace: false,
+            values: vec![values],
+        },
+        wher: None,
+        limit: None,
+        skip: None,
+        group_by: None,
+        order_by: None,
+    };
+    db.execute(insert_statement).unwrap();
+
+    let table = db.table(table_name).unwrap();
+    let data = table.find(0).unwrap();
+    let expected = [Literal::Uint(5), Literal::Int(7)];
+    table
+        .metadata
+        .metadata
+        .data_fields()
+        .zip(expected)
+        .for_each(|(f, l)| assert_eq!(l, f.read(data)));
+}
+
+#[test]
+fn test_insert_negative_into_uint() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let table_name = "test";
+    let id_field = "id";
+    let fields = [("uint", Type::Uint), ("int", Type::Int)];
+    db.create_table(table_name, (id_field, Type::Uint), &fields)
+        .unwrap();
+
+    let values = vec![
+        (id_field.into(), 0usize.into()),
+        (fields[0].0.into(), (-5isize).into()),
+        (fields[1].0.into(), 7isize.into()),
+    ];
+    let insert_statement = Statement {
+        operation: rustdb::query::Operation::Insert {
+            table: table_name.into(),
+            replace: false,
+            values: vec![values],
+        },
+        wher: None,
+        limit: None,
+        skip: None,
+        group_by: None,
+        order_by: None,
+    };
+    let result = db.execute(insert_statement);
+    assert!(matches!(result, Err(DBError::InvalidValue(f)) if f == "uint"));
+
+    let table = db.table(table_name).unwrap();
+    table
+        .find(0)
+        .expect_err("Nothing should have been inserted");
+}
+
+#[test]
+fn test_insert_string_too_long() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("name", Type::String(8))])
+        .unwrap();
+
+    let statement = parser()
+        .parse(r#"INSERT INTO test (id, name) VALUES (0, "123456789")"#)
+        .unwrap();
+    let result = db.execute(statement);
+    assert!(matches!(result, Err(DBError::InvalidValue(f)) if f == "name"));
+
+    let statement = parser()
+        .parse(r#"INSERT INTO test (id, name) VALUES (0, "12345678")"#)
+        .unwrap();
+    db.execute(statement).unwrap();
+    let table = db.table("test").unwrap();
+    let field = table.metadata.metadata.field("name").unwrap();
+    assert_eq!(
+        field.read(table.find(0).unwrap()),
+        Literal::from("12345678")
+    );
+}
+
+#[test]
+fn test_nullable_fields() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let table_name = "test";
+    let id_field = "id";
+    let fields = [
+        FieldDefinition::new("uint", Type::Uint),
+        FieldDefinition::new("int", Type::Int).nullable(),
+    ];
+    db.create_table(table_name, (id_field, Type::Uint), &fields)
+        .unwrap();
+
+    let rows = [
+        vec![
+            (id_field.into(), 0usize.into()),
+            ("uint".into(), 5usize.into()),
+        ],
+        vec![
+            (id_field.into(), 1usize.into()),
+            ("uint".into(), 6usize.into()),
+            ("int".into(), 10isize.into()),
+        ],
+    ];
+    for values in rows {
+        let insert_statement = Statement {
+            operation: rustdb::query::Operation::Insert {
+                table: table_name.into(),
+                replace: false,
+                values: vec![values],
+            },
+            wher: None,
+            limit: None,
+            skip: None,
+            group_by: None,
+            order_by: None,
+        };
+        db.execute(insert_statement).unwrap();
+    }
+
+    let table = db.table(table_name).unwrap();
+    let data = table.find(0).unwrap();
+    let int_field = table.metadata.metadata.field("int").unwrap();
+    assert_eq!(int_field.read(data), Literal::Null);
+
+    let select_statement = Statement {
+        operation: rustdb::query::Operation::Select {
+            table: table_name.into(),
+            distinct: false,
+            columns: vec![id_field.into(), "int".into()],
+        },
+        wher: Some(Box::new(expression!(("int" >= 0isize)))),
+        limit: None,
+        skip: None,
+        group_by: None,
+        order_by: None,
+    };
+    let entries = match db.execute(select_statement).unwrap() {
+        OperationResult::Entries(entries) => entries,
+        _ => panic!("Should return entries"),
+    };
+    assert_eq!(entries.len(), 1, "The NULL entry should be filtered out");
+    assert_eq!(entries[0], [Literal::Uint(1), Literal::Int(10)]);
+}
+
+#[test]
+fn test_is_null() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY, score INT NULL, name STRING(8) NULL)";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+    for id in 0..6usize {
+        let score = if id % 2 == 0 {
+            "NULL".to_owned()
+        } else {
+            id.to_string()
+        };
+        let insert = format!(r#"INSERT INTO test (id, score, name) VALUES ({id}, {score}, "a")"#);
+        db.execute(parser().parse(&insert).unwrap()).unwrap();
+    }
+
+    let select_ids = |db: &mut DB, wher: &str| {
+        let select = format!("SELECT id FROM test WHERE {wher}");
+        let OperationResult::Entries(result) =
+            db.execute(parser().parse(&select).unwrap()).unwrap()
+        else {
+            panic!("Should return entries");
+        };
+        result
+            .entries
+            .iter()
+            .map(|row| match row[0] {
+                Literal::Uint(id) => id,
+                _ => panic!("Id should be a Uint"),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(select_ids(&mut db, "score IS NULL"), [0, 2, 4]);
+    assert_eq!(select_ids(&mut db, "score IS NOT NULL"), [1, 3, 5]);
+    assert_eq!(select_ids(&mut db, "score IS NULL AND id > 1"), [2, 4]);
+    assert_eq!(select_ids(&mut db, "name IS NULL"), Vec::<usize>::new());
+    // The primary key is never NULL
+    assert_eq!(select_ids(&mut db, "id IS NULL"), Vec::<usize>::new());
+    assert_eq!(select_ids(&mut db, "id IS NOT NULL"), [0, 1, 2, 3, 4, 5]);
+    // Comparisons with NULL are false, even though NULL sorts before every key
+    assert_eq!(select_ids(&mut db, "id > NULL"), Vec::<usize>::new());
+    assert_eq!(select_ids(&mut db, "id != NULL"), Vec::<usize>::new());
+    assert_eq!(select_ids(&mut db, "id < NULL"), Vec::<usize>::new());
+    assert_eq!(select_ids(&mut db, "id > NULL OR id = 3"), [3]);
+
+    let index = "CREATE INDEX ON test (score)";
+    db.execute(parser().parse(index).unwrap()).unwrap();
+    assert_eq!(select_ids(&mut db, "score IS NULL"), [0, 2, 4]);
+    assert_eq!(
+        select_ids(&mut db, "score IS NOT NULL AND score > 1"),
+        [3, 5]
+    );
+}
+
+#[test]
+fn test_update_string_too_long() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let fields = [("name", Type::String(8)), ("code", Type::CString(4))];
+    db.create_table("test", ("id", Type::Uint), &fields)
+        .unwrap();
+    let mut execute = |sql: &str| db.execute(parser().parse(sql).unwrap()).map(|_| ());
+    execute(r#"INSERT INTO test (id, name, code) VALUES (0, "abc", "abcd")"#).unwrap();
+
+    let result = execute(r#"INSERT INTO test (id, name, code) VALUES (1, "abc", "abcde")"#);
+    assert!(matches!(result, Err(DBError::InvalidValue(f)) if f == "code"));
+    let result = execute(r#"UPDATE test SET name = "123456789" WHERE id = 0"#);
+    assert!(matches!(result, Err(DBError::InvalidValue(f)) if f == "name"));
+    let result = execute(r#"UPDATE test SET code = "abcde" WHERE id = 0"#);
+    assert!(matches!(result, Err(DBError::InvalidValue(f)) if f == "code"));
+
+    let table = db.table("test").unwrap();
+    let metadata = &table.metadata.metadata;
+    let data = table.find(0).unwrap();
+    assert_eq!(
+        metadata.field("name").unwrap().read(data),
+        Literal::from("abc")
+    );
+    assert_eq!(
+        metadata.field("code").unwrap().read(data),
+        Literal::from("abcd")
+    );
+    assert!(table.find(1).is_err());
+}
+
+#[test]
+fn test_insert_defaults() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let table_name = "test";
+    let id_field = "id";
+    let fields = [
+        FieldDefinition::new("uint", Type::Uint).default(7usize),
+        FieldDefinition::new("string", Type::String(20)).default("unknown"),
+        FieldDefinition::new("int", Type::Int),
+    ];
+    db.create_table(table_name, (id_field, Type::Uint), &fields)
+        .unwrap();
+
+    let insert_statement = Statement {
+        operation: rustdb::query::Operation::Insert {
+            table: table_name.into(),
+            replace: false,
+            values: vec![vec![
+                (id_field.into(), 0usize.into()),
+                ("int".into(), 3isize.into()),
+            ]],
+        },
+        wher: None,
+        limit: None,
+        skip: None,
+        group_by: None,
+        order_by: None,
+    };
+    db.execute(insert_statement).unwrap();
+
+    let table = db.table(table_name).unwrap();
+    let data = table.find(0).unwrap();
+    let expected = array_into!(Literal; [7usize, "unknown", 3isize]);
+    table
+        .metadata
+        .metadata
+        .data_fields()
+        .zip(expected)
+        .for_each(|(f, l)| assert_eq!(l, f.read(data)));
+
+    let insert_statement = Statement {
+        operation: rustdb::query::Operation::Insert {
+            table: table_name.into(),
+            replace: false,
+            values: vec![vec![(id_field.into(), 1usize.into())]],
+        },
+        wher: None,
+        limit: None,
+        skip: None,
+        group_by: None,
+        order_by: None,
+    };
+    let result = db.execute(insert_statement);
+    assert!(matches!(result, Err(DBError::MissingValue(f)) if f == "int"));
+}
+
+#[test]
+fn test_create_table_statement() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY, name STRING(20), score INT)";
+    let statement = parser().parse(create).unwrap();
+    let result = db.execute(statement).unwrap();
+    assert!(matches!(result, OperationResult::Ok));
+
+    let insert = "INSERT INTO test (id, name, score) VALUES (1, \"hello\", -4)";
+    db.execute(parser().parse(insert).unwrap()).unwrap();
+
+    let select = "SELECT id, name, score FROM test";
+    let entries = match db.execute(parser().parse(select).unwrap()).unwrap() {
+        OperationResult::Entries(entries) => entries,
+        _ => panic!("Should return entries"),
+    };
+    assert_eq!(entries.len(), 1);
+    assert_eq!(
+        entries[0],
+        array_into!(Literal; [1usize, "hello", (-4isize)])
+    );
+
+    let statement = parser().parse(create).unwrap();
+    let result = db.execute(statement);
+    assert!(matches!(result, Err(DBError::TableAlreadyExists)));
+}
+
+#[test]
+fn test_drop_table() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let table_name = "test";
+    db.create_table(table_name, ("id", Type::Uint), &[("name", Type::Int)])
+        .unwrap();
+    db.table(table_name)
+        .unwrap()
+        .insert(0, &5usize.to_ne_bytes())
+        .unwrap();
+
+    let statement = parser().parse("DROP TABLE test").unwrap();
+    let result = db.execute(statement).unwrap();
+    assert!(matches!(result, OperationResult::Ok));
+    assert!(matches!(db.table(table_name), Err(DBError::TableNotExists)));
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    let statement = parser().parse("DROP TABLE test").unwrap();
+    let result = db.execute(statement);
+    assert!(matches!(result, Err(DBError::TableNotExists)));
+}
+
+#[test]
+fn test_open_corrupt_table() {
+    use std::io::ErrorKind;
+
+    let dir = tempdir().unwrap();
+    {
+        let mut db = DB::new(dir.path());
+        db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+            .unwrap();
+        db.create_table("other", ("id", Type::Uint), &[("value", Type::Int)])
+            .unwrap();
+        let table = db.table("other").unwrap();
+        table.insert(1, &1i64.to_le_bytes()).unwrap();
+    }
+    fs::write(dir.path().join("test.mt"), b"short").unwrap();
+    // Flipping a byte of the root page fails its checksum
+    let data_path = dir.path().join("other.tbl");
+    let mut data = fs::read(&data_path).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0xFF;
+    fs::write(&data_path, data).unwrap();
+
+    let mut db = DB::new(dir.path());
+    let result = db.table("test").map(|_| ());
+    let Err(DBError::Io(error)) = result else {
+        panic!("Opening test should fail, found {result:?}");
+    };
+    assert_eq!(error.kind(), ErrorKind::InvalidData, "{error}");
+
+    // Pages are verified when they are read, so the table opens and reading it fails
+    if cfg!(feature = "unchecked-checksums") {
+        return;
+    }
+    let error = db.table("other").unwrap().verify().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData, "{error}");
+    let select = parser().parse("SELECT id FROM other").unwrap();
+    let result = db.execute(select).map(|_| ());
+    let Err(DBError::Io(error)) = result else {
+        panic!("Reading other should fail, found {result:?}");
+    };
+    assert_eq!(error.kind(), ErrorKind::InvalidData, "{error}");
+}
+
+#[test]
+fn test_create_table_limits() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let long_name = "a_column_name_that_is_much_longer_than_the_limit_of_sixty_four_bytes";
+    let result = db.create_table("test", ("id", Type::Uint), &[(long_name, Type::Int)]);
+    let Err(DBError::Io(error)) = result else {
+        panic!("Expected an IO error, found {result:?}");
+    };
+    assert!(error.to_string().contains(long_name), "{error}");
+
+    let names: Vec<_> = (0..64).map(|i| format!("column{i}")).collect();
+    let fields: Vec<_> = names.iter().map(|n| (n.as_str(), Type::Uint)).collect();
+    let result = db.create_table("test", ("id", Type::Uint), &fields);
+    assert!(matches!(result, Err(DBError::Io(_))), "{result:?}");
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    // A name of exactly 40 characters used to panic
+    let name = "x".repeat(40);
+    db.create_table("test", ("id", Type::Uint), &[(name.as_str(), Type::Int)])
+        .unwrap();
+    let fields = &fields[..63];
+    db.create_table("wide", ("id", Type::Uint), fields).unwrap();
+    let metadata = &db.table("wide").unwrap().metadata.metadata;
+    assert_eq!(metadata.fields().count(), 64);
+}
+
+#[test]
+fn test_update_returning() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY, score INT, name STRING(16))";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+    for id in 0..6 {
+        let insert = format!(r#"INSERT INTO test (id, score, name) VALUES ({id}, {id}, "old")"#);
+        db.execute(parser().parse(&insert).unwrap()).unwrap();
+    }
+
+    let update =
+        r#"UPDATE test SET score = 50, name = "new" WHERE id > 3 RETURNING id, name, score"#;
+    let result = db.execute(parser().parse(update).unwrap()).unwrap();
+    let OperationResult::Entries(result) = result else {
+        panic!("RETURNING should return entries");
+    };
+    assert_eq!(result.column_names(), ["id", "name", "score"]);
+    let rows: Vec<_> = result.entries.iter().map(|row| row.to_vec()).collect();
+    assert_eq!(
+        rows,
+        [
+            array_into!(Literal; [4usize, "new", 50isize]),
+            array_into!(Literal; [5usize, "new", 50isize]),
+        ]
+    );
+
+    let update = "UPDATE test SET score = 7 WHERE id = 1";
+    let result = db.execute(parser().parse(update).unwrap()).unwrap();
+    assert!(matches!(result, OperationResult::Count(1)));
+
+    let update = "UPDATE test SET score = 7 WHERE id = 1 RETURNING missing";
+    let result = db.execute(parser().parse(update).unwrap());
+    assert!(matches!(result, Err(DBError::UnknownColumn(c)) if c == "missing"));
+}
+
+#[test]
+fn test_update_computed() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY, count UINT, step UINT, name STRING(16))";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+    for id in 0..6 {
+        let insert =
+            format!(r#"INSERT INTO test (id, count, step, name) VALUES ({id}, {id}, 2, "a")"#);
+        db.execute(parser().parse(&insert).unwrap()).unwrap();
+    }
+
+    let update = "UPDATE test SET count = count + 1 WHERE id > 2 RETURNING id, count";
+    let result = db.execute(parser().parse(update).unwrap()).unwrap();
+    let OperationResult::Entries(result) = result else {
+        panic!("RETURNING should return entries");
+    };
+    let rows: Vec<_> = result.entries.iter().map(|row| row.to_vec()).collect();
+    assert_eq!(
+        rows,
+        [
+            array_into!(Literal; [3usize, 4usize]),
+            array_into!(Literal; [4usize, 5usize]),
+            array_into!(Literal; [5usize, 6usize]),
+        ]
+    );
+
+    // Every assignment reads the values the row had before the update
+    let update = "UPDATE test SET count = count * step, step = step + count WHERE id = 1 RETURNING count, step";
+    let result = db.execute(parser().parse(update).unwrap()).unwrap();
+    let OperationResult::Entries(result) = result else {
+        panic!("RETURNING should return entries");
+    };
+    let rows: Vec<_> = result.entries.iter().map(|row| row.to_vec()).collect();
+    assert_eq!(rows, [array_into!(Literal; [2usize, 3usize])]);
+
+    let update = "UPDATE test SET count = count - 5 WHERE id = 0";
+    let result = db.execute(parser().parse(update).unwrap());
+    assert!(matches!(result, Err(DBError::Overflow(c)) if c == "count"));
+
+    let update = r#"UPDATE test SET name = name + "b" WHERE id = 0"#;
+    let result = db.execute(parser().parse(update).unwrap());
+    assert!(matches!(result, Err(DBError::MismatchedType(c)) if c == "name"));
+
+    let update = "UPDATE test SET count = count + 1.5 WHERE id = 0";
+    let result = db.execute(parser().parse(update).unwrap());
+    assert!(matches!(result, Err(DBError::MismatchedType(c)) if c == "count"));
+
+    let update = "UPDATE test SET count = missing + 1 WHERE id = 0";
+    let result = db.execute(parser().parse(update).unwrap());
+    assert!(matches!(result, Err(DBError::UnknownColumn(c)) if c == "missing"));
+}
+
+#[test]
+fn test_checkpoint() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let tables = [
+        ("first", 0x0123456789abcdefusize),
+        ("second", 0x0fedcba987654321),
+    ];
+    for (table_name, value) in tables {
+        db.create_table(table_name, ("id", Type::Uint), &[("value", Type::Uint)])
+            .unwrap();
+        let table = db.table(table_name).unwrap();
+        table.insert(0, &value.to_ne_bytes()).unwrap();
+    }
+
+    db.checkpoint().unwrap();
+
+    for (table_name, value) in tables {
+        let path = dir.path().join(table_name).with_extension("tbl");
+        let contents = std::fs::read(path).unwrap();
+        let bytes = value.to_ne_bytes();
+        assert!(
+            contents.windows(bytes.len()).any(|w| w == bytes),
+            "Table {} wasn't flushed",
+            table_name
+        );
+    }
+
+    let table = db.table(tables[0].0).unwrap();
+    table.insert(1, &5usize.to_ne_bytes()).unwrap();
+    db.checkpoint().unwrap();
+}
+
+#[test]
+fn test_insert_multiple_rows() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+
+    let insert = "INSERT INTO test (id, value) VALUES (2, -2), (0, 0), (1, -1)";
+    let result = db.execute(parser().parse(insert).unwrap()).unwrap();
+    assert!(matches!(result, OperationResult::Count(3)));
+
+    let select = "SELECT id, value FROM test";
+    let entries = match db.execute(parser().parse(select).unwrap()).unwrap() {
+        OperationResult::Entries(entries) => entries,
+        _ => panic!("Should return entries"),
+    };
+    let expected =
+        array_into!(Literal; [[0usize, 0isize], [1usize, (-1isize)], [2usize, (-2isize)]]);
+    assert_eq!(entries.len(), expected.len());
+    entries
+        .iter()
+        .zip(expected)
+        .for_each(|(value, expected)| assert_eq!(expected, value));
+}
+
+#[test]
+fn test_order_by_spills_to_disk() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.set_sort_memory_limit(4);
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+
+    let rows = 50usize;
+    let value = |id: usize| ((id * 37) % rows) as isize - 25;
+    let table = db.table("test").unwrap();
+    for id in 0..rows {
+        let mut data = vec![0u8; table.entry_size.size];
+        let field = *table.metadata.metadata.field("value").unwrap();
+        field
+            .write(&Literal::Int(value(id)), Data::new_mut(&mut data))
+            .unwrap();
+        table.insert(id, &data).unwrap();
+    }
+
+    let select = "SELECT id, value FROM test ORDER BY value DESC SKIP 5 LIMIT 20";
+    let entries = match db.execute(parser().parse(select).unwrap()).unwrap() {
+        OperationResult::Entries(entries) => entries,
+        _ => panic!("Should return entries"),
+    };
+    let mut expected: Vec<_> = (0..rows).map(|id| (value(id), id)).collect();
+    expected.sort_by(|a, b| b.cmp(a));
+    assert_eq!(entries.len(), 20);
+    entries
+        .iter()
+        .zip(expected.into_iter().skip(5))
+        .for_each(|(entry, (value, id))| {
+            assert_eq!(entry, [Literal::Uint(id), Literal::Int(value)]);
+        });
+}
+
+#[test]
+fn test_execute_all() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+
+    let script = "
+        INSERT INTO test (id, value) VALUES (1, 10);
+        INSERT INTO test (id, value) VALUES (0, 5), (2, 20);
+        UPDATE test SET value = 15 WHERE id = 1;
+        SELECT id, value FROM test;
+    ";
+    let results = db
+        .execute_all(parse_script().parse(script).unwrap())
+        .unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(matches!(results[0], OperationResult::Count(1)));
+    assert!(matches!(results[1], OperationResult::Count(2)));
+    assert!(matches!(results[2], OperationResult::Count(1)));
+    let OperationResult::Entries(entries) = &results[3] else {
+        panic!("Should return entries");
+    };
+    let expected = array_into!(Literal; [[0usize, 5isize], [1usize, 15isize], [2usize, 20isize]]);
+    assert_eq!(entries.len(), expected.len());
+    entries
+        .iter()
+        .zip(expected)
+        .for_each(|(value, expected)| assert_eq!(expected, value));
+}
+
+#[test]
+fn test_execute_all_select_not_last() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+
+    let script = "SELECT id FROM test; INSERT INTO test (id, value) VALUES (1, 10)";
+    let result = db.execute_all(parse_script().parse(script).unwrap());
+    assert!(matches!(result, Err(DBError::SelectNotLast)));
+    assert!(db.table("test").unwrap().find(1).is_err());
+}
+
+#[test]
+fn test_int_primary_key() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Int), &[("value", Type::Uint)])
+        .unwrap();
+
+    let insert = "INSERT INTO test (id, value) VALUES (3, 0), (-2, 1), (0, 2), (-7, 3), (5, 4)";
+    db.execute(parser().parse(insert).unwrap()).unwrap();
+
+    let queries = [
+        ("SELECT id FROM test", vec![-7isize, -2, 0, 3, 5]),
+        ("SELECT id FROM test WHERE id < 0", vec![-7, -2]),
+        ("SELECT id FROM test WHERE id >= -2 LIMIT 3", vec![-2, 0, 3]),
+        ("SELECT id FROM test WHERE id = 3", vec![3]),
+        ("SELECT id FROM test WHERE id = -7", vec![-7]),
+        (
+            "SELECT id FROM test ORDER BY value DESC LIMIT 2",
+            vec![5, -7],
+        ),
+    ];
+    for (query, expected) in queries {
+        let entries = match db.execute(parser().parse(query).unwrap()).unwrap() {
+            OperationResult::Entries(entries) => entries,
+            _ => panic!("Should return entries"),
+        };
+        let ids: Vec<_> = entries.iter().map(|e| e[0].clone()).collect();
+        let expected: Vec<_> = expected.into_iter().map(Literal::Int).collect();
+        assert_eq!(ids, expected, "{query}");
+    }
+}
+
+#[test]
+fn test_continuation_token() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+    let table = db.table("test").unwrap();
+    let rows = 25;
+    // Keys with gaps, so resuming can't rely on them being contiguous
+    for id in 0..rows {
+        table.insert(id * 3, &(id as i64).to_le_bytes()).unwrap();
+    }
+
+    let mut seen = vec![];
+    let mut token = None;
+    loop {
+        let query = match token {
+            Some(Literal::Uint(last)) => format!("SELECT id FROM test WHERE id > {last} LIMIT 10"),
+            None => "SELECT id FROM test LIMIT 10".to_string(),
+            Some(other) => panic!("Unexpected token {other:?}"),
+        };
+        let statement = parser().parse(&query).unwrap();
+        let OperationResult::Entries(entries) = db.execute(statement).unwrap() else {
+            panic!("Should return entries");
+        };
+        if entries.is_empty() {
+            assert!(entries.next_token().is_none());
+            break;
+        }
+        assert!(entries.len() <= 10);
+        let last = entries[entries.len() - 1][0].clone();
+        seen.extend(entries.iter().map(|row| match &row[0] {
+            Literal::Uint(id) => *id,
+            other => panic!("Unexpected id {other:?}"),
+        }));
+        token = entries.next_token();
+        assert_eq!(token, Some(last));
+    }
+
+    let expected: Vec<_> = (0..rows).map(|id| id * 3).collect();
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn test_generated_column() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let mut execute = |query: &str| {
+        let statement = parser().parse(query).unwrap();
+        match db.execute(statement) {
+            Ok(OperationResult::Entries(entries)) => {
+                Ok(entries.iter().map(|row| row.to_vec()).collect::<Vec<_>>())
+            }
+            Ok(_) => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| match row[..] {
+                    [Literal::Int(score), Literal::Int(doubled)] => (score, doubled),
+                    _ => panic!("Unexpected row {row:?}"),
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+
+    execute("CREATE TABLE test (id UINT PRIMARY KEY, score INT, score_doubled INT AS (score * 2))")
+        .unwrap();
+    execute("INSERT INTO test (id, score) VALUES (0, 5), (1, -3)").unwrap();
+    let select = "SELECT score, score_doubled FROM test";
+    assert_eq!(execute(select).unwrap(), vec![(5, 10), (-3, -6)]);
+
+    execute("UPDATE test SET score = 21 WHERE id = 0").unwrap();
+    assert_eq!(execute(select).unwrap(), vec![(21, 42), (-3, -6)]);
+
+    let rows = execute("SELECT score, score_doubled FROM test WHERE score_doubled < 0").unwrap();
+    assert_eq!(rows, vec![(-3, -6)]);
+
+    let insert = execute("INSERT INTO test (id, score, score_doubled) VALUES (2, 1, 2)");
+    assert!(matches!(insert, Err(DBError::GeneratedField(_))));
+    let update = execute("UPDATE test SET score_doubled = 0 WHERE id = 1");
+    assert!(matches!(update, Err(DBError::GeneratedField(_))));
+    assert_eq!(execute(select).unwrap(), vec![(21, 42), (-3, -6)]);
+}
+
+#[test]
+fn test_primary_key_declared_in_the_middle() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (name STRING(8), level INT NULL, id UINT PRIMARY KEY, \
+        score FLOAT, code STRING(4))";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+
+    let metadata = &db.table("test").unwrap().metadata.metadata;
+    assert_eq!(metadata.primary().name.str(), "id");
+    let fields: Vec<_> = metadata
+        .data_fields()
+        .map(|f| (f.name.str(), f.layout.offset))
+        .collect();
+    assert_eq!(
+        fields,
+        [("name", 0), ("level", 16), ("score", 24), ("code", 32)]
+    );
+
+    let insert = r#"INSERT INTO test (id, name, level, score, code) VALUES
+        (2, "second", -2, 2.5, "b"), (1, "first", NULL, 1.5, "a")"#;
+    db.execute(parser().parse(insert).unwrap()).unwrap();
+    let update = r#"UPDATE test SET code = "c", level = 7 WHERE id = 1"#;
+    db.execute(parser().parse(update).unwrap()).unwrap();
+
+    let select = "SELECT code, id, score, name, level FROM test";
+    let OperationResult::Entries(result) = db.execute(parser().parse(select).unwrap()).unwrap()
+    else {
+        panic!("Should return entries");
+    };
+    let rows: Vec<_> = result.entries.iter().map(|row| row.to_vec()).collect();
+    assert_eq!(
+        rows,
+        [
+            array_into!(Literal; ["c", 1usize, 1.5, "first", 7isize]),
+            array_into!(Literal; ["b", 2usize, 2.5, "second", (-2isize)]),
+        ]
+    );
+}
+
+#[test]
+fn test_in_seeks_in_key_order() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+    let table = db.table("test").unwrap();
+    for id in 0..20usize {
+        table.insert(id, &(id as i64).to_le_bytes()).unwrap();
+    }
+
+    for query in [
+        "SELECT id FROM test WHERE id IN (5, 1, 9) ORDER BY id LIMIT 2",
+        "SELECT id FROM test WHERE id IN (5, 1, 9) LIMIT 2",
+    ] {
+        let statement = parser().parse(query).unwrap();
+        let OperationResult::Entries(entries) = db.execute(statement).unwrap() else {
+            panic!("Should return entries");
+        };
+        let ids: Vec<_> = entries.iter().map(|row| row[0].clone()).collect();
+        assert_eq!(ids, [Literal::Uint(1), Literal::Uint(5)], "{query}");
+    }
+}
+
+#[test]
+fn test_like() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let names = ["foobar", "food", "barfoo", "afoob", "fob"];
+    db.execute(
+        parser()
+            .parse("CREATE TABLE test (id UINT PRIMARY KEY, name STRING(16))")
+            .unwrap(),
+    )
+    .unwrap();
+    for (id, name) in names.iter().enumerate() {
+        let insert = format!("INSERT INTO test (id, name) VALUES ({id}, \"{name}\")");
+        db.execute(parser().parse(&insert).unwrap()).unwrap();
+    }
+
+    for (pattern, expected) in [
+        ("foo%", vec![0usize, 1]),
+        ("%foo", vec![2]),
+        ("%oo_%", vec![0, 1, 3]),
+        ("f_b", vec![4]),
+    ] {
+        let query = format!("SELECT id FROM test WHERE name LIKE \"{pattern}\"");
+        let OperationResult::Entries(entries) =
+            db.execute(parser().parse(&query).unwrap()).unwrap()
+        else {
+            panic!("Should return entries");
+        };
+        let ids: Vec<_> = entries.iter().map(|row| row[0].clone()).collect();
+        let expected: Vec<_> = expected.into_iter().map(Literal::Uint).collect();
+        assert_eq!(ids, expected, "{pattern}");
+    }
+}
+
+#[test]
+fn test_unknown_column() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+
+    for query in [
+        "SELECT id, valeu FROM test",
+        "INSERT INTO test (id, valeu) VALUES (1, 10)",
+        "UPDATE test SET valeu = 1",
+        "SELECT id FROM test WHERE (id > 1 AND valeu = 10)",
+        "SELECT id FROM test ORDER BY valeu",
+    ] {
+        let result = db.execute(parser().parse(query).unwrap());
+        assert!(
+            matches!(&result, Err(DBError::UnknownColumn(column)) if column == "valeu"),
+            "{query}"
+        );
+    }
+    assert!(db.table("test").unwrap().find(1).is_err());
+
+    // The key of a row can't be changed by an update
+    db.table("test")
+        .unwrap()
+        .insert(1, &10i64.to_le_bytes())
+        .unwrap();
+    let update = parser()
+        .parse("UPDATE test SET id = 5 WHERE id = 1")
+        .unwrap();
+    let result = db.execute(update);
+    assert!(matches!(&result, Err(DBError::PrimaryField(column)) if column == "id"));
+    assert!(db.table("test").unwrap().find(1).is_ok());
+}
+
+#[test]
+fn test_execution_stats() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+    let table = db.table("test").unwrap();
+    for id in 0..1000usize {
+        table.insert(id, &(id as i64).to_le_bytes()).unwrap();
+    }
+
+    let statement = parser()
+        .parse("SELECT id FROM test WHERE (id >= 10 AND id < 20)")
+        .unwrap();
+    let (_, seek) = db.execute_with_stats(statement).unwrap();
+    assert_eq!(seek.rows_examined, 10);
+    assert_eq!(seek.rows_matched, 10);
+    assert!(seek.used_index);
+    assert!(seek.pages_read > 0);
+
+    let statement = parser()
+        .parse("SELECT id FROM test WHERE value < 5")
+        .unwrap();
+    let (_, scan) = db.execute_with_stats(statement).unwrap();
+    assert_eq!(scan.rows_examined, 1000);
+    assert_eq!(scan.rows_matched, 5);
+    assert!(!scan.used_index);
+    assert!(scan.pages_read > seek.pages_read);
+}
+
+#[test]
+fn test_overlapping_ranges_scan_rows_once() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+    let table = db.table("test").unwrap();
+    for id in 0..1000usize {
+        table.insert(id, &(id as i64).to_le_bytes()).unwrap();
+    }
+
+    let mut select = |query: &str| {
+        let statement = parser().parse(query).unwrap();
+        let (result, stats) = db.execute_with_stats(statement).unwrap();
+        let OperationResult::Entries(entries) = result else {
+            panic!("Should return entries");
+        };
+        let ids: Vec<_> = entries
+            .iter()
+            .map(|row| match row[0] {
+                Literal::Uint(id) => id,
+                _ => panic!("Ids are UINT"),
+            })
+            .collect();
+        (ids, stats)
+    };
+
+    let (ids, stats) = select("SELECT id FROM test WHERE (id > 3 OR id > 5)");
+    assert_eq!(ids, (4..1000).collect::<Vec<_>>());
+    assert_eq!(stats.rows_examined, ids.len());
+
+    let (ids, stats) = select("SELECT id FROM test WHERE ((id < 300 OR id > 700) AND id > 650)");
+    assert_eq!(ids, (701..1000).collect::<Vec<_>>());
+    assert_eq!(stats.rows_examined, ids.len());
+
+    // A single key is only kept if the rest of the comparisons include it
+    for query in [
+        "SELECT id FROM test WHERE (id = 5 AND id > 5)",
+        "SELECT id FROM test WHERE (id > 5 AND id = 5)",
+        "SELECT id FROM test WHERE (id = 5 AND id < 5)",
+        "SELECT id FROM test WHERE (id = 5 AND id != 5)",
+    ] {
+        let (ids, _) = select(query);
+        assert_eq!(ids, Vec::<usize>::new(), "{query}");
+    }
+    let (ids, _) = select("SELECT id FROM test WHERE (id = 5 AND id >= 5)");
+    assert_eq!(ids, [5]);
+
+    // The two sides of NotEquals are scanned separately, without the excluded key
+    let (ids, stats) = select("SELECT id FROM test WHERE id != 5");
+    let expected: Vec<_> = (0..1000).filter(|&id| id != 5).collect();
+    assert_eq!(ids, expected);
+    assert_eq!(stats.rows_examined, ids.len());
+}
+
+#[test]
+fn test_multi_row_autoincrement_insert() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY AUTOINCREMENT, value INT)";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+
+    // Every row gets a key, so the count is returned instead of only the last one
+    let insert = "INSERT INTO test (value) VALUES (1), (2), (3)";
+    let result = db.execute(parser().parse(insert).unwrap()).unwrap();
+    assert!(matches!(result, OperationResult::Count(3)));
+    let insert = "INSERT INTO test (id, value) VALUES (10, 4), (11, 5)";
+    let result = db.execute(parser().parse(insert).unwrap()).unwrap();
+    assert!(matches!(result, OperationResult::Count(2)));
+    let insert = "INSERT INTO test (value) VALUES (6)";
+    let result = db.execute(parser().parse(insert).unwrap()).unwrap();
+    assert!(matches!(result, OperationResult::Inserted(12)));
+
+    let rows: Vec<_> = db
+        .table("test")
+        .unwrap()
+        .rows()
+        .map(Result::unwrap)
+        .map(|(key, data)| (key, i64::from_le_bytes(data.read_all().try_into().unwrap())))
+        .collect();
+    assert_eq!(rows, [(0, 1), (1, 2), (2, 3), (10, 4), (11, 5), (12, 6)]);
+}
+
+#[test]
+fn test_autoincrement_insert() {
+    let dir = tempdir().unwrap();
+    {
+        let mut db = DB::new(dir.path());
+        let create = "CREATE TABLE test (id UINT PRIMARY KEY AUTOINCREMENT, value INT)";
+        db.execute(parser().parse(create).unwrap()).unwrap();
+        for expected in 0..3 {
+            let insert = "INSERT INTO test (value) VALUES (7)";
+            let result = db.execute(parser().parse(insert).unwrap()).unwrap();
+            assert!(matches!(result, OperationResult::Inserted(key) if key == expected));
+        }
+        let insert = "INSERT INTO test (value) VALUES (8), (9)";
+        let result = db.execute(parser().parse(insert).unwrap()).unwrap();
+        assert!(matches!(result, OperationResult::Count(2)));
+    }
+
+    let mut db = DB::new(dir.path());
+    let insert = "INSERT INTO test (value) VALUES (10)";
+    let result = db.execute(parser().parse(insert).unwrap()).unwrap();
+    assert!(matches!(result, OperationResult::Inserted(5)));
+    let keys: Vec<_> = db
+        .table("test")
+        .unwrap()
+        .rows()
+        .map(Result::unwrap)
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(keys, [0, 1, 2, 3, 4, 5]);
+
+    db.execute(
+        parser()
+            .parse("CREATE TABLE other (id UINT PRIMARY KEY, value INT)")
+            .unwrap(),
+    )
+    .unwrap();
+    let result = db.execute(
+        parser()
+            .parse("INSERT INTO other (value) VALUES (1)")
+            .unwrap(),
+    );
+    assert!(matches!(result, Err(DBError::MissingValue(f)) if f == "id"));
+}
+
+#[test]
+fn test_insert_on_conflict_replace() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+
+    let insert = "INSERT INTO test (id, value) VALUES (1, 10)";
+    db.execute(parser().parse(insert).unwrap()).unwrap();
+    let result = db.execute(parser().parse(insert).unwrap());
+    assert!(matches!(
+        result,
+        Err(DBError::TableError(TableError::DuplicateKey))
+    ));
+
+    let upsert = "INSERT INTO test (id, value) VALUES (1, 20), (2, 30) ON CONFLICT REPLACE";
+    let result = db.execute(parser().parse(upsert).unwrap()).unwrap();
+    assert!(matches!(result, OperationResult::Count(2)));
+
+    let table = db.table("test").unwrap();
+    let field = *table.metadata.metadata.field("value").unwrap();
+    let values: Vec<_> = table
+        .rows()
+        .map(Result::unwrap)
+        .map(|(key, data)| (key, field.read(data)))
+        .collect();
+    assert_eq!(values, [(1, Literal::Int(20)), (2, Literal::Int(30))]);
+}
+
+#[test]
+fn test_single_key_update() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    for name in ["fast", "general"] {
+        db.create_table(name, ("id", Type::Uint), &[("value", Type::Int)])
+            .unwrap();
+        let table = db.table(name).unwrap();
+        for id in 0..200usize {
+            table.insert(id, &(id as i64).to_le_bytes()).unwrap();
+        }
+    }
+
+    let queries = [
+        ("id = 5", "(id >= 5 AND id < 6)"),
+        (
+            "(id = 7 AND value = 7)",
+            "((id >= 7 AND id < 8) AND value = 7)",
+        ),
+        (
+            "(id = 9 AND value = 0)",
+            "((id >= 9 AND id < 10) AND value = 0)",
+        ),
+        ("id = 500", "(id >= 500 AND id < 501)"),
+    ];
+    for (fast, general) in queries {
+        let update = format!("UPDATE fast SET value = -1 WHERE {fast}");
+        let statement = parser().parse(&update).unwrap();
+        let (fast_result, stats) = db.execute_with_stats(statement).unwrap();
+        let OperationResult::Count(fast_count) = fast_result else {
+            panic!("Should return a count");
+        };
+        assert!(stats.rows_examined <= 1, "{fast}");
+
+        let update = format!("UPDATE general SET value = -1 WHERE {general}");
+        let statement = parser().parse(&update).unwrap();
+        let OperationResult::Count(general_count) = db.execute(statement).unwrap() else {
+            panic!("Should return a count");
+        };
+        assert_eq!(fast_count, general_count, "{fast}");
+    }
+
+    let rows = |db: &mut DB, name: &str| {
+        let table = db.table(name).unwrap();
+        table
+            .rows()
+            .map(Result::unwrap)
+            .map(|(key, data)| (key, data.read_all().to_vec()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(rows(&mut db, "fast"), rows(&mut db, "general"));
+}
+
+#[test]
+fn test_limit_offset() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+    let table = db.table("test").unwrap();
+    for id in 0..20usize {
+        table.insert(id, &(id as i64).to_le_bytes()).unwrap();
+    }
+
+    for (query, expected) in [
+        ("SELECT id FROM test LIMIT 3 OFFSET 5", vec![5, 6, 7]),
+        ("SELECT id FROM test OFFSET 5 LIMIT 3", vec![5, 6, 7]),
+        (
+            "SELECT id FROM test ORDER BY value DESC OFFSET 1 LIMIT 2",
+            vec![18, 17],
+        ),
+        ("SELECT id FROM test OFFSET 18", vec![18, 19]),
+        ("SELECT id FROM test LIMIT 0", vec![]),
+        ("SELECT id FROM test ORDER BY value LIMIT 0", vec![]),
+    ] {
+        let statement = parser().parse(query).unwrap();
+        let OperationResult::Entries(entries) = db.execute(statement).unwrap() else {
+            panic!("Should return entries");
+        };
+        let ids: Vec<_> = entries.iter().map(|row| row[0].clone()).collect();
+        let expected: Vec<_> = expected.into_iter().map(Literal::Uint).collect();
+        assert_eq!(ids, expected, "{query}");
+    }
+
+    let update = "UPDATE test SET value = 0 LIMIT 0";
+    let result = db.execute(parser().parse(update).unwrap()).unwrap();
+    assert!(matches!(result, OperationResult::Count(0)));
+    let result = db.execute(
+        parser()
+            .parse("UPDATE test SET value = 0 WHERE id = 3 LIMIT 0")
+            .unwrap(),
+    );
+    assert!(matches!(result, Ok(OperationResult::Count(0))));
+}
+
+#[test]
+fn test_select_distinct() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY, name STRING(8), level INT)";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+    let insert = r#"INSERT INTO test (id, name, level) VALUES
+        (0, "a", 1), (1, "b", 1), (2, "a", 1), (3, "a", 2), (4, "b", 1), (5, "c", 3)"#;
+    db.execute(parser().parse(insert).unwrap()).unwrap();
+
+    let a = Literal::from("a");
+    let b = Literal::from("b");
+    let c = Literal::from("c");
+    for (query, expected) in [
+        (
+            "SELECT DISTINCT name FROM test",
+            vec![vec![a.clone()], vec![b.clone()], vec![c.clone()]],
+        ),
+        (
+            "SELECT DISTINCT name, level FROM test",
+            vec![
+                vec![a.clone(), Literal::Int(1)],
+                vec![b.clone(), Literal::Int(1)],
+                vec![a.clone(), Literal::Int(2)],
+                vec![c.clone(), Literal::Int(3)],
+            ],
+        ),
+        (
+            "SELECT DISTINCT name FROM test WHERE level = 1 LIMIT 1 SKIP 1",
+            vec![vec![b.clone()]],
+        ),
+        (
+            "SELECT DISTINCT name FROM test ORDER BY name DESC",
+            vec![vec![c.clone()], vec![b.clone()], vec![a.clone()]],
+        ),
+        (
+            "SELECT name FROM test WHERE id < 3",
+            vec![vec![a.clone()], vec![b.clone()], vec![a.clone()]],
+        ),
+    ] {
+        let statement = parser().parse(query).unwrap();
+        let OperationResult::Entries(entries) = db.execute(statement).unwrap() else {
+            panic!("Should return entries");
+        };
+        let rows: Vec<_> = entries.iter().map(<[_]>::to_vec).collect();
+        assert_eq!(rows, expected, "{query}");
+    }
+}
+
+#[test]
+fn test_group_by_count() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY, name STRING(8), level INT)";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+    let insert = r#"INSERT INTO test (id, name, level) VALUES
+        (0, "b", 1), (1, "a", 1), (2, "b", 2), (3, "c", 2), (4, "b", 3), (5, "a", 3)"#;
+    db.execute(parser().parse(insert).unwrap()).unwrap();
+
+    let a = Literal::from("a");
+    let b = Literal::from("b");
+    let c = Literal::from("c");
+    let count = Literal::Uint;
+    for (query, expected) in [
+        (
+            "SELECT name, COUNT(*) FROM test GROUP BY name",
+            vec![
+                vec![a.clone(), count(2)],
+                vec![b.clone(), count(3)],
+                vec![c.clone(), count(1)],
+            ],
+        ),
+        (
+            "SELECT COUNT(*), name FROM test WHERE level > 1 GROUP BY name ORDER BY name DESC",
+            vec![
+                vec![count(1), c.clone()],
+                vec![count(2), b.clone()],
+                vec![count(1), a.clone()],
+            ],
+        ),
+        (
+            "SELECT name, COUNT(*) FROM test GROUP BY name LIMIT 1 SKIP 1",
+            vec![vec![b.clone(), count(3)]],
+        ),
+        (
+            "SELECT COUNT(*) FROM test WHERE level > 1",
+            vec![vec![count(4)]],
+        ),
+        (
+            "SELECT COUNT(*) FROM test WHERE level > 5",
+            vec![vec![count(0)]],
+        ),
+    ] {
+        let statement = parser().parse(query).unwrap();
+        let OperationResult::Entries(entries) = db.execute(statement).unwrap() else {
+            panic!("Should return entries");
+        };
+        let rows: Vec<_> = entries.iter().map(<[_]>::to_vec).collect();
+        assert_eq!(rows, expected, "{query}");
+    }
+
+    let query = "SELECT level, COUNT(*) FROM test GROUP BY name";
+    let result = db.execute(parser().parse(query).unwrap());
+    assert!(matches!(result, Err(DBError::NotGrouped(column)) if column == "level"));
+}
+
+#[test]
+fn test_column_aliases() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY, name STRING(8))";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+    let insert = r#"INSERT INTO test (id, name) VALUES (0, "a"), (1, "b"), (2, "a")"#;
+    db.execute(parser().parse(insert).unwrap()).unwrap();
+
+    for (query, names) in [
+        ("SELECT id AS key, name FROM test", vec!["key", "name"]),
+        (
+            "SELECT id, name AS label FROM test ORDER BY name",
+            vec!["id", "label"],
+        ),
+        (
+            "SELECT name, COUNT(*) FROM test GROUP BY name",
+            vec!["name", "COUNT(*)"],
+        ),
+        (
+            "SELECT name AS n, COUNT(*) AS total FROM test GROUP BY name",
+            vec!["n", "total"],
+        ),
+    ] {
+        let statement = parser().parse(query).unwrap();
+        let OperationResult::Entries(entries) = db.execute(statement).unwrap() else {
+            panic!("Should return entries");
+        };
+        assert_eq!(entries.column_names(), names, "{query}");
+    }
+
+    let statement = parser().parse("SELECT id AS key FROM test").unwrap();
+    let OperationResult::Entries(entries) = db.execute(statement).unwrap() else {
+        panic!("Should return entries");
+    };
+    let keys: Vec<_> = entries.iter().map(|row| row[0].clone()).collect();
+    assert_eq!(keys, [Literal::Uint(0), Literal::Uint(1), Literal::Uint(2)]);
+}
+
+#[test]
+fn test_where_literal_types() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id INT PRIMARY KEY, int INT, name STRING(8))";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+    let insert = r#"INSERT INTO test (id, int, name) VALUES
+        (-10, -10, "a"), (-5, -5, "b"), (0, 0, "c"), (5, 5, "d")"#;
+    db.execute(parser().parse(insert).unwrap()).unwrap();
+
+    for (query, expected) in [
+        ("SELECT id FROM test WHERE int >= -5", vec![-5, 0, 5]),
+        ("SELECT id FROM test WHERE id >= -5", vec![-5, 0, 5]),
+        (
+            "SELECT id FROM test WHERE (int > 0 OR int < -5)",
+            vec![-10, 5],
+        ),
+        ("SELECT id FROM test WHERE id < 1", vec![-10, -5, 0]),
+    ] {
+        let statement = parser().parse(query).unwrap();
+        let OperationResult::Entries(entries) = db.execute(statement).unwrap() else {
+            panic!("Should return entries");
+        };
+        let ids: Vec<_> = entries.iter().map(|row| row[0].clone()).collect();
+        let expected: Vec<_> = expected.into_iter().map(Literal::Int).collect();
+        assert_eq!(ids, expected, "{query}");
+    }
+
+    for (query, column) in [
+        ("SELECT id FROM test WHERE int >= 1.5", "int"),
+        ("SELECT id FROM test WHERE name = 5", "name"),
+        (r#"UPDATE test SET int = 1 WHERE id = "a""#, "id"),
+    ] {
+        let result = db.execute(parser().parse(query).unwrap());
+        assert!(
+            matches!(&result, Err(DBError::MismatchedType(c)) if c == column),
+            "{query}"
+        );
+    }
+}
+
+/// Returns the ids of the rows with that name
+fn ids_named(db: &mut DB, name: &str) -> Vec<usize> {
+    let query = format!(r#"SELECT id FROM test WHERE name = "{name}""#);
+    let (result, stats) = db
+        .execute_with_stats(parser().parse(&query).unwrap())
+        .unwrap();
+    assert!(stats.used_index, "{query}");
+    let OperationResult::Entries(entries) = result else {
+        panic!("Should return entries");
+    };
+    entries
+        .iter()
+        .map(|row| match row[0] {
+            Literal::Uint(id) => id,
+            _ => panic!("Ids are UINT"),
+        })
+        .collect()
+}
+
+#[test]
+fn test_secondary_index() {
+    let dir = tempdir().unwrap();
+    {
+        let mut db = DB::new(dir.path());
+        let create = "CREATE TABLE test (id UINT PRIMARY KEY, name STRING(8), level INT)";
+        db.execute(parser().parse(create).unwrap()).unwrap();
+        for id in 0..100usize {
+            let insert = format!(
+                r#"INSERT INTO test (id, name, level) VALUES ({id}, "n{}", 1)"#,
+                id % 10
+            );
+            db.execute(parser().parse(&insert).unwrap()).unwrap();
+            // Rows inserted before the index is created are added to it
+            if id == 49 {
+                let index = "CREATE INDEX ON test (name)";
+                db.execute(parser().parse(index).unwrap()).unwrap();
+            }
+        }
+        assert_eq!(
+            ids_named(&mut db, "n3"),
+            (3..100).step_by(10).collect::<Vec<_>>()
+        );
+
+        let update = r#"UPDATE test SET name = "x" WHERE id = 13"#;
+        db.execute(parser().parse(update).unwrap()).unwrap();
+        let upsert =
+            r#"INSERT INTO test (id, name, level) VALUES (23, "x", 2) ON CONFLICT REPLACE"#;
+        db.execute(parser().parse(upsert).unwrap()).unwrap();
+        assert_eq!(ids_named(&mut db, "n3"), [3, 33, 43, 53, 63, 73, 83, 93]);
+        assert_eq!(ids_named(&mut db, "x"), [13, 23]);
+        assert_eq!(ids_named(&mut db, "y"), [] as [usize; 0]);
+
+        for column in ["name", "id"] {
+            let index = format!("CREATE INDEX ON test ({column})");
+            let result = db.execute(parser().parse(&index).unwrap());
+            assert!(matches!(result, Err(DBError::IndexExists(c)) if c == column));
+        }
+    }
+
+    // The index is opened again with the table
+    let mut db = DB::new(dir.path());
+    assert_eq!(ids_named(&mut db, "x"), [13, 23]);
+    db.drop_table("test").unwrap();
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_delete_keeps_index() {
+    let dir = tempdir().unwrap();
+    {
+        let mut db = DB::new(dir.path());
+        let create = "CREATE TABLE test (id UINT PRIMARY KEY, name STRING(8))";
+        db.execute(parser().parse(create).unwrap()).unwrap();
+        db.execute(parser().parse("CREATE INDEX ON test (name)").unwrap())
+            .unwrap();
+        for id in 0..100usize {
+            let insert = format!(
+                r#"INSERT INTO test (id, name) VALUES ({id}, "n{}")"#,
+                id % 10
+            );
+            db.execute(parser().parse(&insert).unwrap()).unwrap();
+        }
+
+        let mut delete = |query: &str| match db.execute(parser().parse(query).unwrap()).unwrap() {
+            OperationResult::Count(count) => count,
+            _ => panic!("Deletes return the number of rows"),
+        };
+        assert_eq!(delete("DELETE FROM test WHERE id = 33"), 1);
+        assert_eq!(delete("DELETE FROM test WHERE id = 33"), 0);
+        // Found through the index
+        assert_eq!(delete(r#"DELETE FROM test WHERE name = "n5""#), 10);
+        assert_eq!(delete("DELETE FROM test WHERE id >= 90"), 9);
+
+        assert_eq!(ids_named(&mut db, "n3"), [3, 13, 23, 43, 53, 63, 73, 83]);
+        assert_eq!(ids_named(&mut db, "n5"), [] as [usize; 0]);
+        assert_eq!(db.table("test").unwrap().len(), 80);
+
+        // A deleted key can be inserted again, and is found through the index
+        let insert = r#"INSERT INTO test (id, name) VALUES (33, "x")"#;
+        db.execute(parser().parse(insert).unwrap()).unwrap();
+        assert_eq!(ids_named(&mut db, "x"), [33]);
+    }
+
+    let mut db = DB::new(dir.path());
+    assert_eq!(ids_named(&mut db, "n3"), [3, 13, 23, 43, 53, 63, 73, 83]);
+    assert_eq!(ids_named(&mut db, "x"), [33]);
+    let delete = parser().parse("DELETE FROM test").unwrap();
+    assert!(matches!(db.execute(delete), Ok(OperationResult::Count(81))));
+    assert!(db.table("test").unwrap().is_empty());
+    assert_eq!(ids_named(&mut db, "x"), [] as [usize; 0]);
+}
+
+#[test]
+fn test_secondary_index_reads_fewer_pages() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY, name STRING(8), level INT)";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+    for id in 0..1000usize {
+        let insert = format!(r#"INSERT INTO test (id, name, level) VALUES ({id}, "n{id}", 1)"#);
+        db.execute(parser().parse(&insert).unwrap()).unwrap();
+    }
+
+    let query = r#"SELECT id FROM test WHERE name = "n500""#;
+    let (_, scan) = db
+        .execute_with_stats(parser().parse(query).unwrap())
+        .unwrap();
+    assert!(!scan.used_index);
+    assert_eq!(scan.rows_examined, 1000);
+
+    db.execute(parser().parse("CREATE INDEX ON test (name)").unwrap())
+        .unwrap();
+    let (result, indexed) = db
+        .execute_with_stats(parser().parse(query).unwrap())
+        .unwrap();
+    assert!(indexed.used_index);
+    assert_eq!(indexed.rows_examined, 1);
+    assert_eq!(indexed.rows_matched, 1);
+    assert!(indexed.pages_read < scan.pages_read);
+    let OperationResult::Entries(entries) = result else {
+        panic!("Should return entries");
+    };
+    assert_eq!(entries.iter().next().unwrap(), [Literal::Uint(500)]);
+}
+
+#[test]
+fn test_covering_index_skips_table() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let create = "CREATE TABLE test (id UINT PRIMARY KEY, name STRING(8), level INT)";
+    db.execute(parser().parse(create).unwrap()).unwrap();
+    db.execute(parser().parse("CREATE INDEX ON test (name)").unwrap())
+        .unwrap();
+    for id in 0..1000usize {
+        let name = id % 100;
+        let insert = format!(r#"INSERT INTO test (id, name, level) VALUES ({id}, "n{name}", 1)"#);
+        db.execute(parser().parse(&insert).unwrap()).unwrap();
+    }
+
+    // Only the key and the indexed column are read, so the index answers the query
+    db.table("test").unwrap().pager.reset_stats();
+    let query = r#"SELECT id, name FROM test WHERE name = "n42" LIMIT 3"#;
+    let (result, stats) = db
+        .execute_with_stats(parser().parse(query).unwrap())
+        .unwrap();
+    assert!(stats.used_index);
+    assert!(stats.pages_read > 0);
+    let OperationResult::Entries(entries) = result else {
+        panic!("Should return entries");
+    };
+    let rows: Vec<_> = entries.iter().map(|row| row.to_vec()).collect();
+    let expected: Vec<_> = [42, 142, 242]
+        .map(|id| vec![Literal::Uint(id), Literal::from("n42")])
+        .into();
+    assert_eq!(rows, expected);
+    assert_eq!(db.table("test").unwrap().pager.stats().page_reads, 0);
+
+    // Other columns are read from the rows of the table
+    let query = r#"SELECT id, level FROM test WHERE name = "n42""#;
+    let (result, stats) = db
+        .execute_with_stats(parser().parse(query).unwrap())
+        .unwrap();
+    assert!(stats.used_index);
+    assert_eq!(stats.rows_examined, 10);
+    let OperationResult::Entries(entries) = result else {
+        panic!("Should return entries");
+    };
+    assert_eq!(entries.iter().count(), 10);
+    assert!(db.table("test").unwrap().pager.stats().page_reads > 0);
+}
+
+#[test]
+fn test_composite_primary_key() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table_with_key(
+        "test",
+        &[("a", Type::Uint), ("b", Type::Uint)],
+        &[("value", Type::Int)],
+    )
+    .unwrap();
+    for a in 0..10 {
+        let insert = format!(
+            "INSERT INTO test (b, a, value) VALUES (2, {a}, {a}2), (0, {a}, {a}0), (1, {a}, {a}1)"
+        );
+        db.execute(parser().parse(&insert).unwrap()).unwrap();
+    }
+    let selects = [
+        (
+            "SELECT a, b, value FROM test WHERE a = 4 AND b = 1",
+            vec![vec![Literal::Uint(4), Literal::Uint(1), Literal::Int(41)]],
+        ),
+        (
+            "SELECT value FROM test WHERE a = 7",
+            vec![
+                vec![Literal::Int(70)],
+                vec![Literal::Int(71)],
+                vec![Literal::Int(72)],
+            ],
+        ),
+        (
+            "SELECT b FROM test WHERE a > 8 AND b > 0",
+            vec![vec![Literal::Uint(1)], vec![Literal::Uint(2)]],
+        ),
+    ];
+    for (query, expected) in selects {
+        let result = db.execute(parser().parse(query).unwrap()).unwrap();
+        let OperationResult::Entries(result) = result else {
+            panic!("{query} didn't return entries");
+        };
+        let rows: Vec<_> = result.entries.iter().map(|row| row.to_vec()).collect();
+        assert_eq!(rows, expected, "{query}");
+    }
+
+    let insert = "INSERT INTO test (a, value) VALUES (20, 0)";
+    let result = db.execute(parser().parse(insert).unwrap());
+    assert!(matches!(result, Err(DBError::MissingValue(f)) if f == "b"));
+    let insert = "INSERT INTO test (a, b, value) VALUES (4, 1, 0)";
+    let result = db.execute(parser().parse(insert).unwrap());
+    assert!(matches!(
+        result,
+        Err(DBError::TableError(TableError::DuplicateKey))
+    ));
+}
+
+#[test]
+fn test_execute_iter() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    db.create_table("test", ("id", Type::Uint), &[("value", Type::Int)])
+        .unwrap();
+    let table = db.table("test").unwrap();
+    for id in 0..5000usize {
+        table.insert(id, &(id as i64).to_le_bytes()).unwrap();
+    }
+    let query = "SELECT id, value AS v FROM test WHERE value >= 10";
+    let (_, full) = db
+        .execute_with_stats(parser().parse(query).unwrap())
+        .unwrap();
+
+    db.table("test").unwrap().pager.reset_stats();
+    let mut rows = db.execute_iter(parser().parse(query).unwrap()).unwrap();
+    assert_eq!(rows.column_names(), ["id", "v"]);
+    assert_eq!(
+        rows.next().unwrap().unwrap(),
+        [Literal::Uint(10), Literal::Int(10)]
+    );
+    drop(rows);
+    let pages_read = db.table("test").unwrap().pager.stats().page_reads;
+    assert!(pages_read * 10 < full.pages_read, "{pages_read}");
+
+    let query = "SELECT value FROM test WHERE id < 100 LIMIT 3 SKIP 95";
+    let rows: Vec<_> = db
+        .execute_iter(parser().parse(query).unwrap())
+        .unwrap()
+        .collect::<DBResult<_>>()
+        .unwrap();
+    assert_eq!(
+        rows,
+        [[Literal::Int(95)], [Literal::Int(96)], [Literal::Int(97)]]
+    );
+
+    // Sorted selects are collected before returning the first row
+    let query = "SELECT id FROM test WHERE id < 3 ORDER BY id DESC";
+    let rows: Vec<_> = db
+        .execute_iter(parser().parse(query).unwrap())
+        .unwrap()
+        .collect::<DBResult<_>>()
+        .unwrap();
+    assert_eq!(
+        rows,
+        [[Literal::Uint(2)], [Literal::Uint(1)], [Literal::Uint(0)]]
+    );
+
+    let update = parser().parse("UPDATE test SET value = 0").unwrap();
+    assert!(matches!(db.execute_iter(update), Err(DBError::NotSelect)));
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "serde")]
+
+use std::borrow::Cow;
+
+use chumsky::Parser;
+use rustdb::{
+    db::{DB, OperationResult},
+    query::{Literal, parser},
+    table::metadata::Type,
+};
+use tempfile::tempdir;
+
+#[test]
+fn test_literal_json() {
+    for (literal, json) in [
+        (Literal::Null, "null"),
+        (Literal::from("name"), r#""name""#),
+        (Literal::from("with \"quotes\""), r#""with \"quotes\"""#),
+        (Literal::Int(-5), "-5"),
+        (Literal::Uint(7), "7"),
+        (Literal::Float(2.5), "2.5"),
+    ] {
+        assert_eq!(serde_json::to_string(&literal).unwrap(), json);
+        let parsed: Literal = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed, literal, "{json}");
+    }
+
+    // Strings with escapes can't be borrowed from the input, so they are owned
+    let parsed: Literal = serde_json::from_str(r#""with \"quotes\"""#).unwrap();
+    assert!(matches!(parsed, Literal::String(Cow::Owned(_))));
+    let parsed: Literal = serde_json::from_str(r#""name""#).unwrap();
+    assert!(matches!(parsed, Literal::String(Cow::Borrowed("name"))));
+
+    // Integers that fit in a Uint can't be told apart from one
+    let parsed: Literal = serde_json::from_str("5").unwrap();
+    assert_eq!(parsed, Literal::Uint(5));
+    let rows = vec![vec![Literal::Uint(1), Literal::from("a")]];
+    assert_eq!(serde_json::to_string(&rows).unwrap(), r#"[[1,"a"]]"#);
+}
+
+#[test]
+fn test_type_json() {
+    for (typ, json) in [
+        (Type::String(10), r#"{"type":"String","length":10}"#),
+        (Type::CString(4), r#"{"type":"CString","length":4}"#),
+        (Type::Int, r#"{"type":"Int"}"#),
+        (Type::Uint, r#"{"type":"Uint"}"#),
+        (Type::Float, r#"{"type":"Float"}"#),
+    ] {
+        assert_eq!(serde_json::to_string(&typ).unwrap(), json);
+        assert_eq!(serde_json::from_str::<Type>(json).unwrap(), typ);
+    }
+}
+
+#[test]
+fn test_result_json() {
+    let dir = tempdir().unwrap();
+    let mut db = DB::new(dir.path());
+    let mut execute = |sql: &str| {
+        let result = db.execute(parser().parse(sql).unwrap()).unwrap();
+        let columns = match &result {
+            OperationResult::Entries(result) => result.column_names().to_vec(),
+            _ => vec![],
+        };
+        result.to_json(&columns)
+    };
+
+    let create =
+        "CREATE TABLE test (id UINT PRIMARY KEY AUTOINCREMENT, name STRING(8) NULL, score INT)";
+    assert_eq!(execute(create), r#"{"status":"ok"}"#);
+    let insert = r#"INSERT INTO test (id, name, score) VALUES (1, "a", 5), (2, NULL, -2)"#;
+    assert_eq!(execute(insert), r#"{"count":2}"#);
+    let insert = r#"INSERT INTO test (name, score) VALUES ("say \"hi\"", 0)"#;
+    assert_eq!(execute(insert), r#"{"inserted":3}"#);
+    assert_eq!(
+        execute("UPDATE test SET score = 3 WHERE id > 1"),
+        r#"{"count":2}"#
+    );
+
+    let select = "SELECT id AS key, name, score FROM test";
+    assert_eq!(
+        execute(select),
+        r#"[{"key":1,"name":"a","score":5},{"key":2,"name":null,"score":3},{"key":3,"name":"say \"hi\"","score":3}]"#
+    );
+    assert_eq!(execute("SELECT id FROM test WHERE id > 5"), "[]");
+}
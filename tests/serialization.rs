@@ -11,7 +11,7 @@ macro_rules! test_serialize {
     ($literal:expr, $type:expr) => {
         let size = $type.size().size;
         let mut buf = vec![0u8; size];
-        $literal.write_to(&mut buf);
+        $literal.write_to(&mut buf).unwrap();
 
         let result = $type.read(&buf);
 
@@ -40,7 +40,50 @@ fn test_float() {
 #[test]
 fn test_string() {
     let value = "testing";
-    test_serialize!(Literal::String(value), Type::String(255));
+    test_serialize!(Literal::from(value), Type::String(255));
+}
+
+#[test]
+fn test_cstring() {
+    let value = Literal::from("testing");
+    let typ = Type::CString(10);
+    let mut buf = vec![0u8; typ.size().size];
+    typ.write(&value, &mut buf).unwrap();
+    assert_eq!(&buf[..8], b"testing\0");
+    assert_eq!(typ.read(&buf), value);
+}
+
+#[test]
+fn test_string_encodings() {
+    let value = Literal::from("abc");
+    for typ in [Type::String(3), Type::CString(3)] {
+        let mut buf = vec![0u8; typ.size().size];
+        typ.write(&value, &mut buf).unwrap();
+        assert_eq!(typ.read(&buf), value, "Round trip failed for {:?}", typ);
+    }
+}
+
+#[test]
+fn test_write_too_long() {
+    let value = Literal::from("123456789");
+    for typ in [Type::String(8), Type::CString(8)] {
+        let mut buf = vec![0u8; typ.size().size];
+        let error = typ.write(&value, &mut buf).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput, "{typ:?}");
+        assert_eq!(typ.coerce(value.clone()), None, "{typ:?}");
+    }
+
+    let mut buf = [0u8; 4];
+    Literal::Int(1).write_to(&mut buf).unwrap_err();
+}
+
+#[test]
+fn test_cstring_with_nul() {
+    let typ = Type::CString(10);
+    let mut buf = vec![0u8; typ.size().size];
+    let error = typ.write(&Literal::from("te\0st"), &mut buf).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(buf, vec![0u8; typ.size().size]);
 }
 
 #[test]
@@ -49,14 +92,13 @@ fn test_multiple_values() {
         ("uint", Type::Uint, Literal::Uint(25)),
         ("int", Type::Int, Literal::Int(-5)),
         ("float", Type::Float, Literal::Float(4.5)),
-        ("string", Type::String(255), Literal::String("testing")),
+        ("string", Type::String(255), Literal::from("testing")),
     ];
     let types: Vec<_> = test_data
         .iter()
-        .copied()
-        .map(|(name, typ, _)| (name, typ))
+        .map(|&(name, typ, _)| (name, typ))
         .collect();
-    let metadata = Metadata::new(PageNum(0), ("id", Type::Uint), &types);
+    let metadata = Metadata::new(PageNum(0), ("id", Type::Uint), &types).unwrap();
 
     let entry_size = metadata.entry_size();
     println!("Entry size: {:?}", entry_size);
@@ -64,14 +106,17 @@ fn test_multiple_values() {
     let mut buf = vec![0u8; entry_size.aligned];
     let data = Data::new_mut(&mut buf);
 
-    let iter = metadata.fields().skip(1).zip(test_data.map(|(_, _, l)| l));
+    let iter = metadata
+        .fields()
+        .skip(1)
+        .zip(test_data.iter().map(|(_, _, l)| l));
     println!("Writing fields");
     for (f, l) in iter.clone() {
         if !f.primary {
             println!("Field: {:?}", f);
             println!("Literal: {:?}", l);
             let field_buf = data.get_mut(f.layout);
-            l.write_to(field_buf);
+            l.write_to(field_buf).unwrap();
             println!("Buf: {:?}", field_buf);
         }
     }
@@ -84,7 +129,63 @@ fn test_multiple_values() {
             println!("Buf: {:?}", field_buf);
             let value = f.typ.read(field_buf);
             println!("Literal: {:?}", value);
-            assert_eq!(value, l);
+            assert_eq!(&value, l);
         }
     }
 }
+
+#[test]
+fn test_little_endian_format() {
+    let mut buf = [0u8; 8];
+    Literal::Int(-2).write_to(&mut buf).unwrap();
+    assert_eq!(buf, [0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+
+    let bytes = [0x01, 0x02, 0, 0, 0, 0, 0, 0];
+    Literal::Uint(0x0201).write_to(&mut buf).unwrap();
+    assert_eq!(buf, bytes);
+    assert_eq!(Type::Uint.read(&bytes), Literal::Uint(0x0201));
+
+    let bytes = 1.5f64.to_bits().to_le_bytes();
+    assert_eq!(Type::Float.read(&bytes), Literal::Float(1.5));
+
+    let typ = Type::String(4);
+    assert_eq!(typ.size().size, 8);
+    let bytes = [3, 0, 0, 0, b'a', b'b', b'c', 0];
+    assert_eq!(typ.read(&bytes), Literal::from("abc"));
+    let mut buf = [0u8; 8];
+    Literal::from("abc").write_to(&mut buf).unwrap();
+    assert_eq!(buf, bytes);
+}
+
+#[cfg(not(feature = "unchecked-utf8"))]
+#[test]
+fn test_invalid_utf8() {
+    let typ = Type::String(4);
+    let bytes = [2, 0, 0, 0, 0xff, 0xfe, 0, 0];
+    assert!(typ.try_read(&bytes).is_err());
+
+    let typ = Type::CString(4);
+    let bytes = [b'a', 0xc3, 0, 0, 0];
+    assert!(typ.try_read(&bytes).is_err());
+    assert_eq!(typ.try_read(b"ab\0\0\0").unwrap(), Literal::from("ab"));
+}
+
+#[test]
+fn test_corrupt_strings() {
+    use std::io::ErrorKind;
+
+    // The length prefix says 200 bytes, but the field only has 8
+    let typ = Type::String(8);
+    let mut bytes = vec![0u8; typ.size().size];
+    bytes[..4].copy_from_slice(&200u32.to_le_bytes());
+    let error = typ.try_read(&bytes).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        typ.try_read(&bytes[..2]).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+
+    let typ = Type::CString(4);
+    let error = typ.try_read(b"abcde").unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
@@ -1,7 +1,15 @@
+use std::borrow::Cow;
 use std::ops::Range;
 
+use rustdb::pager::PageNum;
+use rustdb::query::Literal;
 use rustdb::table::{
-    Table, debug::debug_table, internal::INTERNAL_NODE_CELL_COUNT, metadata::Type,
+    Table, TableError,
+    data::Data,
+    debug::{count_leaf_cells, debug_table},
+    internal::INTERNAL_NODE_CELL_COUNT,
+    metadata::{FieldDefinition, Metadata, MetadataHandler, Type},
+    options::TableOptions,
 };
 use tempfile::tempfile;
 
@@ -43,6 +51,144 @@ fn test_persistence() {
     assert_eq!(data.read_all(), entry);
 }
 
+#[test]
+fn test_primary_not_first_round_trip() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let fields = [
+        FieldDefinition::new("name", Type::String(8)),
+        FieldDefinition::new("score", Type::Int).nullable(),
+    ];
+    let metadata = Metadata::with_primary_at(PageNum(1), 1, ("id", Type::Uint), &fields).unwrap();
+    MetadataHandler::new(metadata_file.try_clone().unwrap(), metadata)
+        .flush()
+        .unwrap();
+
+    let mut table = Table::open(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+    )
+    .unwrap();
+    let metadata = &table.metadata.metadata;
+    let entry_size = metadata.entry_size().size;
+    let name = *metadata.field("name").unwrap();
+    let score = *metadata.field("score").unwrap();
+    for key in 0..50usize {
+        let mut row = vec![0; entry_size];
+        let data = Data::new_mut(&mut row);
+        name.write(&Literal::from(format!("row{key}")), data)
+            .unwrap();
+        let value = match key % 3 {
+            0 => Literal::Null,
+            _ => Literal::Int(-(key as isize)),
+        };
+        score.write(&value, data).unwrap();
+        table.insert(key, &row).unwrap();
+    }
+    drop(table);
+
+    let table = Table::open(data_file, metadata_file).unwrap();
+    let metadata = &table.metadata.metadata;
+    assert_eq!(metadata.primary().name.str(), "id");
+    let id = metadata.primary();
+    let name = metadata.field("name").unwrap();
+    let score = metadata.field("score").unwrap();
+    for (key, data) in table.rows().map(Result::unwrap) {
+        assert_eq!(id.read_row(key, data), Literal::Uint(key));
+        assert_eq!(name.read(data), Literal::from(format!("row{key}")));
+        let expected = match key % 3 {
+            0 => Literal::Null,
+            _ => Literal::Int(-(key as isize)),
+        };
+        assert_eq!(score.read(data), expected);
+    }
+    assert_eq!(table.rows().map(Result::unwrap).count(), 50);
+}
+
+#[test]
+fn test_overflow_entries() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let fields = [
+        FieldDefinition::new("body", Type::String(4096)),
+        FieldDefinition::new("n", Type::Uint),
+    ];
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &fields,
+    )
+    .unwrap();
+    assert!(table.is_overflow());
+    let entry_size = table.entry_size.size;
+    let body_field = *table.metadata.metadata.field("body").unwrap();
+    let n_field = *table.metadata.metadata.field("n").unwrap();
+    let body = |key: usize| -> String {
+        (0..4096)
+            .map(|i| (b'a' + ((key + i) % 26) as u8) as char)
+            .collect()
+    };
+    let row = |key: usize, n: usize| {
+        let mut row = vec![0; entry_size];
+        let data = Data::new_mut(&mut row);
+        body_field.write(&Literal::from(body(key)), data).unwrap();
+        n_field.write(&Literal::Uint(n), data).unwrap();
+        row
+    };
+    let check = |table: &Table, key: usize, n: usize| {
+        let data = table.find(key).unwrap();
+        assert_eq!(body_field.read(data), Literal::from(body(key)));
+        assert_eq!(n_field.read(data), Literal::Uint(n));
+    };
+
+    for key in 0..200 {
+        table.insert(key, &row(key, key)).unwrap();
+    }
+    table.validate().unwrap();
+    assert!(table.height().unwrap() > 1);
+    (0..200).for_each(|key| check(&table, key, key));
+    assert_eq!(
+        table
+            .rows()
+            .map(Result::unwrap)
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>(),
+        (0..200).collect::<Vec<_>>()
+    );
+
+    // Values are written back to their pages
+    let cursor = table.find_cursor(10).unwrap();
+    cursor
+        .set_value(&mut table, Data::new_ref(&row(10, 1000)))
+        .unwrap();
+    assert!(table.upsert(20, &row(20, 2000)).unwrap());
+    check(&table, 10, 1000);
+    check(&table, 20, 2000);
+
+    // Deleted entries free their pages, which are reused by the next inserts
+    for key in 100..200 {
+        table.delete(key).unwrap();
+    }
+    let pages = table.pager.pages.read().unwrap().len();
+    for key in 100..200 {
+        table.insert(key, &row(key, key)).unwrap();
+    }
+    assert!(table.pager.pages.read().unwrap().len() < pages + 10);
+    drop(table);
+
+    let table = Table::open(data_file, metadata_file).unwrap();
+    table.validate().unwrap();
+    for key in 0..200 {
+        let n = match key {
+            10 => 1000,
+            20 => 2000,
+            _ => key,
+        };
+        check(&table, key, n);
+    }
+}
+
 #[test]
 fn test_duplicate_key() {
     let entry = 20usize.to_ne_bytes();
@@ -57,9 +203,32 @@ fn test_duplicate_key() {
     )
     .unwrap();
     table.insert(0, &entry).unwrap();
-    table
+    let error = table
         .insert(0, &entry)
         .expect_err("Should return duplicate key");
+    assert!(matches!(error, TableError::DuplicateKey));
+
+    let error = std::io::Error::from(error);
+    assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+    assert!(matches!(
+        error.into_inner().unwrap().downcast_ref(),
+        Some(TableError::DuplicateKey)
+    ));
+}
+
+#[test]
+fn test_find_missing_key() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file,
+        metadata_file,
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..10);
+    assert!(matches!(table.find(10), Err(TableError::KeyNotFound)));
 }
 
 #[test]
@@ -80,6 +249,7 @@ fn test_fill_leaf() {
     insert_range(&mut table, entries.clone());
     debug_table(&table);
     check_range(&mut table, entries);
+    table.validate().unwrap();
 }
 
 #[test]
@@ -100,6 +270,7 @@ fn test_split_leaf_node() {
     insert_range(&mut table, entries.clone());
     debug_table(&table);
     check_range(&mut table, entries);
+    table.validate().unwrap();
 }
 
 #[test]
@@ -126,6 +297,7 @@ fn test_fill_internal_node() {
     insert_range(&mut table, entries.clone());
     debug_table(&table);
     check_range(&mut table, entries);
+    table.validate().unwrap();
 }
 
 #[test]
@@ -153,6 +325,7 @@ fn test_split_internal_node() {
     insert_range(&mut table, entries.clone());
     debug_table(&table);
     check_range(&mut table, entries);
+    table.validate().unwrap();
 }
 
 #[test]
@@ -197,17 +370,17 @@ fn test_advancing_cursor() {
     let mut entries = 0usize..max_entries;
     insert_range(&mut table, entries.clone());
     debug_table(&table);
-    let mut cursor = table.find_cursor(0);
+    let mut cursor = table.find_cursor(0).unwrap();
     let e = entries.next().unwrap();
-    let bytes = cursor.value(&table);
+    let bytes = cursor.value(&table).unwrap();
     let data = usize::from_ne_bytes(bytes.read_all().try_into().expect("Data didn't fit"));
     assert_eq!(data, e);
 
-    while cursor.advance(&table) {
+    while cursor.advance(&table).unwrap() {
         println!("Cursor -> {:?}: {:?}", cursor.page_num, cursor.cell_num);
         let e = entries.next().unwrap();
         println!("Entry: {}", e);
-        let bytes = cursor.value(&table);
+        let bytes = cursor.value(&table).unwrap();
         let data = usize::from_ne_bytes(bytes.read_all().try_into().expect("Data didn't fit"));
         assert_eq!(data, e);
     }
@@ -217,3 +390,1615 @@ fn test_advancing_cursor() {
         cursor.page_num, cursor.cell_num
     );
 }
+
+#[test]
+fn test_bloom_filter() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+
+    let entries = 0usize..50;
+    insert_range(&mut table, entries.clone());
+    table.enable_bloom_filter().unwrap();
+    insert_range(&mut table, 50..100);
+    drop(table);
+
+    let table = Table::open(data_file, metadata_file).unwrap();
+    for key in 1000..1100 {
+        table.find(key).expect_err("Key shouldn't exist");
+    }
+    assert_eq!(
+        table.pager.pages.read().unwrap().len(),
+        0,
+        "Missing keys shouldn't read any pages"
+    );
+    for key in 0..100 {
+        table.find(key).unwrap();
+    }
+}
+
+#[test]
+fn test_wal_recovers_committed_state() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let wal_file = tempfile().unwrap();
+    let files = || {
+        (
+            data_file.try_clone().unwrap(),
+            metadata_file.try_clone().unwrap(),
+            wal_file.try_clone().unwrap(),
+        )
+    };
+    let (data, metadata, wal) = files();
+    let mut table = Table::create_with_options(
+        data,
+        metadata,
+        &[("id", Type::Uint)],
+        &[("name", Type::Uint)],
+        TableOptions::new().wal(wal),
+    )
+    .unwrap();
+    insert_range(&mut table, 0..300);
+    table.commit().unwrap();
+    insert_range(&mut table, 300..600);
+    table.commit().unwrap();
+    // Crashes without flushing, the data file was never written
+    insert_range(&mut table, 600..1000);
+    table.delete(5).unwrap();
+    std::mem::forget(table);
+    assert_eq!(data_file.metadata().unwrap().len(), 0);
+
+    let (data, metadata, wal) = files();
+    let mut table = Table::open_with_options(data, metadata, TableOptions::new().wal(wal)).unwrap();
+    assert!(table.wal.as_ref().unwrap().is_empty());
+    table.validate().unwrap();
+    assert_eq!(table.len(), 600);
+    check_range(&mut table, 0..600);
+    assert!(!table.contains(600).unwrap());
+
+    // A transaction that wasn't completely written is discarded
+    insert_range(&mut table, 600..700);
+    table.commit().unwrap();
+    insert_range(&mut table, 700..800);
+    table.commit().unwrap();
+    let wal_len = wal_file.metadata().unwrap().len();
+    wal_file.set_len(wal_len - 10).unwrap();
+    std::mem::forget(table);
+
+    let (data, metadata, wal) = files();
+    let mut table = Table::open_with_options(data, metadata, TableOptions::new().wal(wal)).unwrap();
+    table.validate().unwrap();
+    assert_eq!(table.len(), 700);
+    check_range(&mut table, 0..700);
+
+    // Flushing is a checkpoint, the log is emptied once the files have the changes
+    insert_range(&mut table, 700..900);
+    table.flush().unwrap();
+    assert_eq!(wal_file.metadata().unwrap().len(), 0);
+    std::mem::forget(table);
+    let (data, metadata, _) = files();
+    let mut table = Table::open(data, metadata).unwrap();
+    assert_eq!(table.len(), 900);
+    check_range(&mut table, 0..900);
+}
+
+#[test]
+fn test_insertion_log() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let log_file = tempfile().unwrap();
+    let mut table = Table::create_with_options(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        &[("id", Type::Uint)],
+        &[("name", Type::Uint)],
+        TableOptions::new().insertion_log(log_file.try_clone().unwrap()),
+    )
+    .unwrap();
+
+    let keys = [40usize, 3, 17, 0, 25];
+    for key in keys {
+        table.insert(key, &key.to_ne_bytes()).unwrap();
+    }
+    drop(table);
+
+    Table::open(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+    )
+    .err()
+    .expect("Opening without the insertion log should fail");
+
+    let options = TableOptions::new().insertion_log(log_file);
+    let mut table = Table::open_with_options(data_file, metadata_file, options).unwrap();
+    table.insert(10, &10usize.to_ne_bytes()).unwrap();
+
+    let by_insertion: Vec<_> = table
+        .rows_by_insertion()
+        .unwrap()
+        .map(|row| row.unwrap().0)
+        .collect();
+    assert_eq!(by_insertion, [40, 3, 17, 0, 25, 10]);
+
+    let by_key: Vec<_> = table
+        .rows()
+        .map(Result::unwrap)
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(by_key, [0, 3, 10, 17, 25, 40]);
+}
+
+#[test]
+fn test_distinct_values() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("score", Type::Float)],
+    )
+    .unwrap();
+
+    let scores = [2.5, f64::NAN, -1.0, 2.5, 7.0, -1.0, f64::NAN, 2.5];
+    for (id, score) in scores.into_iter().enumerate() {
+        table.insert(id, &score.to_le_bytes()).unwrap();
+    }
+
+    let values = table.distinct_values("score").unwrap();
+    assert_eq!(
+        values,
+        vec![
+            Literal::Float(-1.0),
+            Literal::Float(2.5),
+            Literal::Float(7.0),
+            Literal::Float(f64::NAN)
+        ]
+    );
+
+    let ids = table.distinct_values("id").unwrap();
+    assert_eq!(
+        ids,
+        (0..scores.len()).map(Literal::Uint).collect::<Vec<_>>()
+    );
+    assert!(table.distinct_values("missing").is_err());
+}
+
+#[test]
+fn test_description() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let table = Table::create_with_options(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        &[("id", Type::Uint)],
+        &[("name", Type::Uint)],
+        TableOptions::new().description("Users and their names"),
+    )
+    .unwrap();
+    assert_eq!(table.description(), Some("Users and their names"));
+    drop(table);
+
+    let table = Table::open(data_file, metadata_file).unwrap();
+    assert_eq!(table.description(), Some("Users and their names"));
+
+    let table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    assert_eq!(table.description(), None);
+}
+
+#[test]
+fn test_scan() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..200);
+
+    let keys = |start, end| -> Vec<usize> {
+        table
+            .scan(start, end)
+            .map(Result::unwrap)
+            .map(|(k, _)| k)
+            .collect()
+    };
+    assert_eq!(keys(Some(10), Some(20)), (10..20).collect::<Vec<_>>());
+    assert_eq!(keys(None, Some(3)), vec![0, 1, 2]);
+    assert_eq!(keys(Some(195), None), (195..200).collect::<Vec<_>>());
+    assert_eq!(keys(Some(20), Some(20)), Vec::<usize>::new());
+    assert_eq!(keys(Some(300), None), Vec::<usize>::new());
+    assert_eq!(keys(None, None).len(), 200);
+}
+
+#[test]
+fn test_scan_between_keys() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    (0..400)
+        .step_by(2)
+        .for_each(|e: usize| table.insert(e, &e.to_ne_bytes()).unwrap());
+
+    // Starting keys that aren't in the table
+    for start in (1..399).step_by(2) {
+        let (key, data) = table
+            .scan(Some(start), None)
+            .map(Result::unwrap)
+            .next()
+            .unwrap();
+        assert_eq!(key, start + 1);
+        assert_eq!(data.read_all(), &key.to_ne_bytes());
+    }
+}
+
+#[test]
+fn test_leaf_page_ranges() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    assert!(table.leaf_page_ranges(4).unwrap().is_empty());
+    insert_range(&mut table, 0..1000);
+
+    for n in [1, 3, 4, 1000] {
+        let partitions = table.leaf_page_ranges(n).unwrap();
+        assert!(!partitions.is_empty() && partitions.len() <= n);
+        let keys: Vec<_> = partitions
+            .into_iter()
+            .flat_map(|p| {
+                table
+                    .scan_leaves(p)
+                    .unwrap()
+                    .map(Result::unwrap)
+                    .map(|(k, _)| k)
+            })
+            .collect();
+        assert_eq!(keys, (0..1000).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn test_min_cursor_iteration() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    assert_eq!(
+        table
+            .min_cursor()
+            .unwrap()
+            .into_iter(&table)
+            .map(Result::unwrap)
+            .count(),
+        0
+    );
+
+    let keys = [500usize, 3, 250, 77, 999, 0, 120];
+    for key in keys {
+        table.insert(key, &key.to_ne_bytes()).unwrap();
+    }
+    insert_range(&mut table, 1000..1300);
+
+    let cursor = table.min_cursor().unwrap();
+    assert_eq!(cursor.cell_num, 0);
+    let iterated: Vec<_> = cursor
+        .into_iter(&table)
+        .map(Result::unwrap)
+        .map(|(key, _)| key)
+        .collect();
+    let mut expected: Vec<_> = keys.into_iter().chain(1000..1300).collect();
+    expected.sort();
+    assert_eq!(iterated, expected);
+}
+
+#[test]
+fn test_insert_returning_key() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    let value = 0usize.to_ne_bytes();
+
+    assert_eq!(table.insert_returning_key(None, &value).unwrap(), 0);
+    assert_eq!(table.insert_returning_key(Some(41), &value).unwrap(), 41);
+    assert_eq!(table.insert_returning_key(None, &value).unwrap(), 42);
+    assert_eq!(table.insert_returning_key(Some(7), &value).unwrap(), 7);
+    assert_eq!(table.insert_returning_key(None, &value).unwrap(), 43);
+    assert!(table.insert_returning_key(Some(41), &value).is_err());
+
+    table.insert(usize::MAX, &value).unwrap();
+    assert!(matches!(
+        table.insert_returning_key(None, &value),
+        Err(TableError::KeysExhausted)
+    ));
+}
+
+#[test]
+fn test_autoincrement_persistence() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    let value = 0usize.to_ne_bytes();
+
+    let keys: Vec<_> = (0..3)
+        .map(|_| table.insert_returning_key(None, &value).unwrap())
+        .collect();
+    assert_eq!(keys, [0, 1, 2]);
+    // The biggest key is gone, but it was already handed out
+    table.delete(2).unwrap();
+    assert_eq!(table.next_autoincrement(), 3);
+    drop(table);
+
+    let mut table = Table::open(data_file, metadata_file).unwrap();
+    assert_eq!(table.next_autoincrement(), 3);
+    assert_eq!(table.insert_returning_key(None, &value).unwrap(), 3);
+
+    table.set_next_autoincrement(100);
+    assert_eq!(table.insert_returning_key(None, &value).unwrap(), 100);
+    table.insert(200, &value).unwrap();
+    assert_eq!(table.insert_returning_key(None, &value).unwrap(), 201);
+}
+
+#[test]
+fn test_cursor_set_value() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..100);
+
+    let cursor = table.find_cursor(42).unwrap();
+    let value = 4242usize.to_ne_bytes();
+    cursor.set_value(&mut table, Data::new_ref(&value)).unwrap();
+    assert_eq!(table.find(42).unwrap().read_all(), value);
+    assert_eq!(cursor.value(&table).unwrap().read_all(), value);
+
+    let too_short = [0u8; 4];
+    let error = cursor.set_value(&mut table, Data::new_ref(&too_short));
+    assert_eq!(error.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(table.find(42).unwrap().read_all(), value);
+
+    check_range(&mut table, 0..42);
+    check_range(&mut table, 43..100);
+}
+
+#[test]
+fn test_cursor_seek() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..500);
+
+    let mut cursor = table.min_cursor().unwrap();
+    for key in [10, 480, 3, 250, 499, 0] {
+        cursor.seek(&table, key).unwrap();
+        assert_eq!(cursor.cell(&table).key, key);
+        let data = cursor.value(&table).unwrap().read_all();
+        assert_eq!(data, key.to_ne_bytes());
+    }
+
+    cursor.seek(&table, 480).unwrap();
+    let leaf = cursor.page_num;
+    cursor.reset(&table).unwrap();
+    assert_ne!(cursor.page_num, leaf);
+    assert_eq!(cursor.cell(&table).key, 0);
+}
+
+#[test]
+fn test_height_and_node_count() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    assert_eq!(table.height().unwrap(), 1);
+    assert_eq!(table.node_count().unwrap(), (0, 1));
+
+    // Filling the root leaf keeps the height, one more row splits it
+    let max_cells = table.max_leaf_cells;
+    insert_range(&mut table, 0..max_cells);
+    assert_eq!(table.height().unwrap(), 1);
+    assert_eq!(table.node_count().unwrap(), (0, 1));
+    insert_range(&mut table, max_cells..max_cells + 1);
+    assert_eq!(table.height().unwrap(), 2);
+    assert_eq!(table.node_count().unwrap(), (1, 2));
+
+    insert_range(&mut table, max_cells + 1..5000);
+    let (internal, leaves) = table.node_count().unwrap();
+    assert!(internal >= 1);
+    assert!(leaves * max_cells >= 5000);
+    // One segment per leaf when asking for more segments than leaves
+    assert_eq!(table.leaf_page_ranges(usize::MAX).unwrap().len(), leaves);
+}
+
+#[test]
+fn test_max_height() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    assert_eq!(table.height().unwrap(), 1);
+    table.set_max_height(Some(2));
+
+    let mut inserted = 0;
+    let error = loop {
+        match table.insert(inserted, &inserted.to_ne_bytes()) {
+            Ok(()) => inserted += 1,
+            Err(e) => break e,
+        }
+        assert!(inserted < 1_000_000, "The limit should have been hit");
+    };
+    assert!(matches!(error, TableError::MaxHeightExceeded));
+    assert_eq!(table.height().unwrap(), 2);
+    assert!(inserted > table.max_leaf_cells * INTERNAL_NODE_CELL_COUNT / 2);
+    check_range(&mut table, 0..inserted);
+    drop(table);
+
+    let mut table = Table::open(data_file, metadata_file).unwrap();
+    assert_eq!(table.max_height(), Some(2));
+    assert!(table.insert(inserted, &inserted.to_ne_bytes()).is_err());
+    table.set_max_height(None);
+    table.insert(inserted, &inserted.to_ne_bytes()).unwrap();
+    assert_eq!(table.height().unwrap(), 3);
+}
+
+#[test]
+fn test_scattered_inserts() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    let n = 5000;
+    for key in (0..n).map(|i| (i * 7919) % n) {
+        table.insert(key, &key.to_ne_bytes()).unwrap();
+    }
+    check_range(&mut table, 0..n);
+    let keys: Vec<_> = table.rows().map(Result::unwrap).map(|(k, _)| k).collect();
+    assert_eq!(keys, (0..n).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_delete_merges_nodes() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    let n = 5000;
+    insert_range(&mut table, 0..n);
+    let height = table.height().unwrap();
+    assert!(height >= 3);
+
+    // Delete in a scattered order, keeping every 50th key
+    for key in (0..n).map(|i| (i * 7919) % n).filter(|k| k % 50 != 0) {
+        table.delete(key).unwrap();
+    }
+    assert!(matches!(table.delete(1), Err(TableError::KeyNotFound)));
+    assert!(table.height().unwrap() < height);
+    table.validate().unwrap();
+
+    let remaining: Vec<_> = (0..n).step_by(50).collect();
+    for &key in &remaining {
+        let data = table.find(key).unwrap().read_all();
+        assert_eq!(data, key.to_ne_bytes());
+    }
+    assert!(table.find(51).is_err());
+    let keys: Vec<_> = table.rows().map(Result::unwrap).map(|(k, _)| k).collect();
+    assert_eq!(keys, remaining);
+
+    for key in remaining {
+        table.delete(key).unwrap();
+    }
+    assert_eq!(table.height().unwrap(), 1);
+    assert_eq!(table.rows().map(Result::unwrap).count(), 0);
+    insert_range(&mut table, 0..10);
+    check_range(&mut table, 0..10);
+}
+
+#[test]
+fn test_flush_skips_clean_pages() {
+    use rustdb::pager::{PAGE_SIZE, PageNum};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let mut data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..200);
+    drop(table);
+
+    let mut table = Table::open(data_file.try_clone().unwrap(), metadata_file).unwrap();
+    check_range(&mut table, 0..200);
+    assert_eq!(table.rows().map(Result::unwrap).count(), 200);
+    let pages = data_file.metadata().unwrap().len() as usize / PAGE_SIZE;
+    assert!((0..pages).all(|i| !table.pager.is_dirty(PageNum(i))));
+
+    // Overwrite a page behind the pager's back, flushing clean pages must not restore it
+    let marker = [0xAB; PAGE_SIZE];
+    let offset = ((pages - 1) * PAGE_SIZE) as u64;
+    data_file.seek(SeekFrom::Start(offset)).unwrap();
+    data_file.write_all(&marker).unwrap();
+    table.pager.flush().unwrap();
+
+    let mut page = [0; PAGE_SIZE];
+    data_file.seek(SeekFrom::Start(offset)).unwrap();
+    data_file.read_exact(&mut page).unwrap();
+    assert_eq!(page, marker);
+
+    table.insert(200, &200usize.to_ne_bytes()).unwrap();
+    assert!((0..pages).any(|i| table.pager.is_dirty(PageNum(i))));
+    table.pager.flush().unwrap();
+    assert!((0..pages).all(|i| !table.pager.is_dirty(PageNum(i))));
+}
+
+#[test]
+fn test_validate_detects_corruption() {
+    use rustdb::table::node::NodeMut;
+
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..1000);
+    table.validate().unwrap();
+
+    let cursor = table.find_cursor(500).unwrap();
+    let page_num = cursor.page_num;
+    let NodeMut::LeafNode(leaf) = table.pager.get_node(page_num).unwrap() else {
+        panic!("Cursor should point to a leaf");
+    };
+    let entry_size = table.entry_size;
+    let cell = leaf.cell_mut_unchecked(1, entry_size);
+    let key = cell.key;
+    cell.key = 0;
+    let error = table.validate().unwrap_err();
+    assert!(error.contains(&format!("Page {}", page_num.0)), "{error}");
+
+    // Restoring the key makes the tree valid again, then break the parent pointer instead
+    let NodeMut::LeafNode(leaf) = table.pager.get_node(page_num).unwrap() else {
+        unreachable!()
+    };
+    leaf.cell_mut_unchecked(1, entry_size).key = key;
+    table.validate().unwrap();
+    let NodeMut::LeafNode(leaf) = table.pager.get_node(page_num).unwrap() else {
+        unreachable!()
+    };
+    let parent = leaf.parent_ptr;
+    leaf.parent_ptr = rustdb::pager::PageNum(parent.0 + 1000);
+    let error = table.validate().unwrap_err();
+    assert!(error.contains("parent"), "{error}");
+}
+
+#[test]
+fn test_pager_stats() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..20000);
+    table.pager.flush().unwrap();
+    let written = table.pager.stats().page_writes;
+    assert!(written > 0);
+    drop(table);
+
+    let table = Table::open(data_file, metadata_file).unwrap();
+
+    // A point query loads a page per level, the leaf is requested again to read the cell
+    table.pager.reset_stats();
+    table.find(12345).unwrap();
+    let stats = table.pager.stats();
+    let height = table.height().unwrap();
+    assert!(height > 2);
+    assert_eq!(stats.cache_misses, height);
+    assert_eq!(stats.cache_hits + stats.cache_misses, stats.page_reads);
+    assert!(stats.page_reads <= height * 2);
+
+    table.pager.reset_stats();
+    table.find(12345).unwrap();
+    let stats = table.pager.stats();
+    assert!(stats.page_reads <= height * 2);
+    assert_eq!(stats.cache_hits, stats.page_reads);
+    assert_eq!(stats.cache_misses, 0);
+    assert_eq!(stats.page_writes, 0);
+
+    // A full scan has to go through every leaf
+    table.pager.reset_stats();
+    assert_eq!(table.rows().map(Result::unwrap).count(), 20000);
+    assert!(table.pager.stats().page_reads > height * 10);
+}
+
+#[test]
+#[cfg(not(feature = "unchecked-checksums"))]
+fn test_checksum_mismatch() {
+    use rustdb::pager::PAGE_SIZE;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let mut data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..10);
+    drop(table);
+
+    // Flip a byte in the body of the root leaf
+    let offset = (PAGE_SIZE + PAGE_SIZE / 2) as u64;
+    let mut byte = [0];
+    data_file.seek(SeekFrom::Start(offset)).unwrap();
+    data_file.read_exact(&mut byte).unwrap();
+    data_file.seek(SeekFrom::Start(offset)).unwrap();
+    data_file.write_all(&[!byte[0]]).unwrap();
+
+    // The page is only verified once it's read
+    let table = Table::open(data_file, metadata_file).unwrap();
+    let Err(TableError::Io(error)) = table.find(0) else {
+        panic!("Reading a corrupted page should fail");
+    };
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("Checksum mismatch on page 1"));
+    assert!(table.rows().next().unwrap().is_err());
+
+    let error = table.verify().unwrap_err();
+    assert!(error.to_string().contains("Checksum mismatch on page 1"));
+}
+
+#[test]
+fn test_upsert_existing_key() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..100);
+
+    let num_cells = table.find_cursor(42).unwrap().leaf(&table).num_cells;
+    let value = 4242usize.to_ne_bytes();
+    assert!(table.upsert(42, &value).unwrap());
+    assert_eq!(table.find(42).unwrap().read_all(), value);
+    assert_eq!(
+        table.find_cursor(42).unwrap().leaf(&table).num_cells,
+        num_cells
+    );
+
+    check_range(&mut table, 0..42);
+    check_range(&mut table, 43..100);
+}
+
+#[test]
+fn test_upsert_new_key() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    insert_range(&mut table, 0..10);
+
+    assert!(!table.upsert(10, &10usize.to_ne_bytes()).unwrap());
+    check_range(&mut table, 0..11);
+}
+
+/// Splits a CSV line into its cells, unquoting them. The flag is set for quoted cells
+fn split_csv_line(line: &str) -> Vec<(bool, String)> {
+    let mut cells = vec![(false, String::new())];
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        let (was_quoted, cell) = cells.last_mut().unwrap();
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' => {
+                quoted = !quoted;
+                *was_quoted = true;
+            }
+            ',' if !quoted => cells.push((false, String::new())),
+            c => cell.push(c),
+        }
+    }
+    cells
+}
+
+/// Rows of the table created by csv_table, as (id, name, score)
+const CSV_ROWS: [(isize, Literal, f64); 3] = [
+    (
+        -3,
+        Literal::String(Cow::Borrowed("plain")),
+        0.30000000000000004,
+    ),
+    (
+        0,
+        Literal::String(Cow::Borrowed(r#"a "quoted", name"#)),
+        -2.0,
+    ),
+    (7, Literal::Null, 1e300),
+];
+
+/// Creates a table with the schema used by the CSV tests, filled with CSV_ROWS if fill is set
+fn csv_table(fill: bool) -> Table {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Int),
+        &[
+            FieldDefinition::new("name", Type::String(16)).nullable(),
+            FieldDefinition::new("score", Type::Float),
+        ],
+    )
+    .unwrap();
+    let fields: Vec<_> = table.metadata.metadata.data_fields().copied().collect();
+    for (id, name, score) in CSV_ROWS.into_iter().filter(|_| fill) {
+        let mut buf = vec![0u8; table.entry_size.size];
+        let data = Data::new_mut(&mut buf);
+        fields[0].write(&name, data).unwrap();
+        fields[1].write(&Literal::Float(score), data).unwrap();
+        let key = Type::Int.encode_key(&Literal::Int(id)).unwrap();
+        table.insert(key, &buf).unwrap();
+    }
+    table
+}
+
+#[test]
+fn test_export_csv() {
+    let table = csv_table(true);
+    let mut csv = vec![];
+    table.export_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("id,name,score"));
+    for (line, (id, name, score)) in lines.by_ref().zip(CSV_ROWS) {
+        let cells = split_csv_line(line);
+        assert_eq!(cells.len(), 3, "{line}");
+        assert_eq!(cells[0].1.parse::<isize>().unwrap(), id);
+        match name {
+            Literal::String(name) => assert_eq!(cells[1], (true, name.into_owned())),
+            _ => assert_eq!(cells[1], (false, String::new())),
+        }
+        assert_eq!(cells[2].1.parse::<f64>().unwrap(), score);
+    }
+    assert_eq!(lines.next(), None);
+}
+
+#[test]
+fn test_import_csv() {
+    let mut csv = vec![];
+    csv_table(true).export_csv(&mut csv).unwrap();
+    let mut table = csv_table(false);
+    assert_eq!(
+        table.import_csv(&mut csv.as_slice()).unwrap(),
+        CSV_ROWS.len()
+    );
+    let mut exported = vec![];
+    table.export_csv(&mut exported).unwrap();
+    assert_eq!(String::from_utf8(exported), String::from_utf8(csv));
+
+    // Columns can be in any order and the missing nullable ones are NULL
+    let mut table = csv_table(false);
+    let csv = "score,id\n1.5,-1\n";
+    assert_eq!(table.import_csv(&mut csv.as_bytes()).unwrap(), 1);
+    let key = Type::Int.encode_key(&Literal::Int(-1)).unwrap();
+    let data = table.find(key).unwrap();
+    let fields: Vec<_> = table.metadata.metadata.data_fields().copied().collect();
+    assert_eq!(fields[0].read(data), Literal::Null);
+    assert_eq!(fields[1].read(data), Literal::Float(1.5));
+
+    for (csv, error) in [
+        (
+            "id,name,score\n1,\"a\",1.0\n2,\"b\"\n",
+            "line 3: expected 3 cells, found 2",
+        ),
+        (
+            "id,name,score\n1,\"a\",x\n",
+            "line 2: invalid value for score",
+        ),
+        (
+            "id,name,score\n1,\"a\",\n",
+            "line 2: invalid value for score",
+        ),
+        (
+            "id,name,score\n1,\"this is much too long\",1\n",
+            "line 2: invalid value for name",
+        ),
+        (
+            "id,name,score\n1,\"a\",1\n1,\"b\",2\n",
+            "line 3: Duplicate key",
+        ),
+        ("id,name,score\n1,\"a,1\n", "line 2: unterminated quote"),
+        // Lines inside of quoted cells are counted
+        (
+            "id,name,score\n1,\"two\nlines\",1\n2,\"b\",x\n",
+            "line 4: invalid value for score",
+        ),
+        ("id,nme,score\n", "line 1: unknown column nme"),
+        ("name\n", "line 1: missing the primary key column"),
+    ] {
+        let error = error.to_owned();
+        let result = csv_table(false).import_csv(&mut csv.as_bytes());
+        assert_eq!(result.map_err(|e| e.to_string()), Err(error), "{csv}");
+    }
+}
+
+#[test]
+fn test_bulk_insert() {
+    let create = || {
+        Table::create(
+            tempfile().unwrap(),
+            tempfile().unwrap(),
+            ("id", Type::Uint),
+            &[("num", Type::Uint)],
+        )
+        .unwrap()
+    };
+    let values: Vec<_> = (0..5000usize).map(|e| (e * 3).to_ne_bytes()).collect();
+
+    let mut bulk = create();
+    let entries = values.iter().enumerate().map(|(e, v)| (e, &v[..]));
+    assert_eq!(bulk.bulk_insert(entries).unwrap(), values.len());
+    let mut single = create();
+    for (e, v) in values.iter().enumerate() {
+        single.insert(e, v).unwrap();
+    }
+    let rows = |table: &Table| -> Vec<_> {
+        table
+            .rows()
+            .map(Result::unwrap)
+            .map(|(key, data)| (key, data.read_all().to_vec()))
+            .collect()
+    };
+    assert_eq!(rows(&bulk), rows(&single));
+    assert_eq!(bulk.height().unwrap(), single.height().unwrap());
+
+    // Keys that aren't after the biggest one fall back to a normal insert
+    let mut table = create();
+    insert_range(&mut table, 100..200);
+    let unsorted = [250usize, 50, 300, 150, 400];
+    let entries = unsorted.iter().map(|e| (*e + 1000, &values[0][..]));
+    assert_eq!(table.bulk_insert(entries.clone()).unwrap(), unsorted.len());
+    assert!(matches!(
+        table.bulk_insert(entries),
+        Err(TableError::DuplicateKey)
+    ));
+    let mut keys: Vec<_> = (100..200).chain(unsorted.map(|e| e + 1000)).collect();
+    keys.sort();
+    assert_eq!(
+        table
+            .rows()
+            .map(Result::unwrap)
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>(),
+        keys
+    );
+}
+
+#[test]
+fn test_len() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    assert!(table.is_empty());
+
+    insert_range(&mut table, 0..2000);
+    assert!(table.insert(10, &10usize.to_ne_bytes()).is_err());
+    assert_eq!(table.len(), 2000);
+    for key in (0..2000).step_by(3) {
+        table.delete(key).unwrap();
+    }
+    assert!(table.delete(0).is_err());
+    table.upsert(1, &0usize.to_ne_bytes()).unwrap();
+    table.upsert(3000, &0usize.to_ne_bytes()).unwrap();
+    assert_eq!(table.len(), 2000 - 667 + 1);
+    assert_eq!(table.len(), count_leaf_cells(&table).unwrap());
+    assert!(!table.is_empty());
+
+    drop(table);
+    let table = Table::open(data_file, metadata_file).unwrap();
+    assert_eq!(table.len(), count_leaf_cells(&table).unwrap());
+}
+
+#[test]
+fn test_contains() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    assert!(!table.contains(0).unwrap());
+    for key in (0..1000).step_by(2) {
+        table.insert(key, &key.to_ne_bytes()).unwrap();
+    }
+    for key in 0..1000 {
+        assert_eq!(table.contains(key).unwrap(), key % 2 == 0, "{key}");
+    }
+    assert!(!table.contains(usize::MAX).unwrap());
+
+    // Keys between the last key of a leaf and the first key of the next one
+    let mut cursor = table.min_cursor().unwrap();
+    let mut previous = (cursor.page_num, cursor.cell(&table).key);
+    let mut boundaries = 0;
+    while cursor.advance(&table).unwrap() {
+        let key = cursor.cell(&table).key;
+        if cursor.page_num != previous.0 {
+            assert!(!table.contains(previous.1 + 1).unwrap());
+            assert!(table.contains(key).unwrap());
+            boundaries += 1;
+        }
+        previous = (cursor.page_num, key);
+    }
+    assert!(boundaries > 0);
+}
+
+#[test]
+fn test_min_max_key() {
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    assert_eq!(table.min_key().unwrap(), None);
+    assert_eq!(table.max_key().unwrap(), None);
+
+    insert_range(&mut table, 500..1500);
+    insert_range(&mut table, 10..20);
+    assert!(table.height().unwrap() > 1);
+    assert_eq!(table.min_key().unwrap(), Some(10));
+    assert_eq!(table.max_key().unwrap(), Some(1499));
+
+    table.delete(10).unwrap();
+    table.delete(1499).unwrap();
+    assert_eq!(table.min_key().unwrap(), Some(11));
+    assert_eq!(table.max_key().unwrap(), Some(1498));
+}
+
+#[test]
+fn test_invalid_metadata_file() {
+    use std::io::{ErrorKind, Seek, SeekFrom, Write};
+
+    let open_error = |contents: &[u8]| {
+        let mut metadata_file = tempfile().unwrap();
+        metadata_file.write_all(contents).unwrap();
+        let error = Table::open(tempfile().unwrap(), metadata_file)
+            .err()
+            .expect("Opening the table should fail");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        error.to_string()
+    };
+    assert!(open_error(b"").contains("truncated"));
+    assert!(open_error(b"short").contains("truncated"));
+    assert!(open_error(&[0xAB; 4096]).contains("magic number"));
+
+    let data_file = tempfile().unwrap();
+    let mut metadata_file = tempfile().unwrap();
+    let table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    drop(table);
+    let len = metadata_file.metadata().unwrap().len();
+    metadata_file.set_len(len - 10).unwrap();
+    let error = Table::open(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+    )
+    .err()
+    .unwrap();
+    assert!(error.to_string().contains("truncated"), "{error}");
+
+    // A file from another version is rejected even if its size looks right
+    metadata_file.set_len(len).unwrap();
+    metadata_file.seek(SeekFrom::Start(8)).unwrap();
+    metadata_file.write_all(&99u32.to_le_bytes()).unwrap();
+    let error = Table::open(data_file, metadata_file).err().unwrap();
+    assert!(error.to_string().contains("version 99"), "{error}");
+}
+
+#[test]
+fn test_insert_key() {
+    let no_fields: &[(&str, Type)] = &[];
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        no_fields,
+    )
+    .unwrap();
+    assert_eq!(table.entry_size.size, 0);
+    let keys: Vec<_> = (0..2000).map(|i| i * 3).collect();
+    for &key in &keys {
+        table.insert_key(key).unwrap();
+    }
+    assert!(matches!(table.insert_key(3), Err(TableError::DuplicateKey)));
+    assert!(table.height().unwrap() > 1);
+    table.validate().unwrap();
+    assert_eq!(table.len(), keys.len());
+    assert!(table.find(6).unwrap().read_all().is_empty());
+    assert!(table.find(7).is_err());
+    let found: Vec<_> = table.rows().map(Result::unwrap).map(|(k, _)| k).collect();
+    assert_eq!(found, keys);
+
+    let fields = [
+        FieldDefinition::new("count", Type::Uint),
+        FieldDefinition::new("note", Type::String(8)).nullable(),
+    ];
+    let mut table = Table::create(
+        tempfile().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &fields,
+    )
+    .unwrap();
+    table.insert_key(1).unwrap();
+    let data = table.find(1).unwrap();
+    let metadata = &table.metadata.metadata;
+    assert_eq!(
+        metadata.field("count").unwrap().read(data),
+        Literal::Uint(0)
+    );
+    assert_eq!(metadata.field("note").unwrap().read(data), Literal::Null);
+}
+
+#[test]
+fn test_mismatched_metadata_file() {
+    use std::io::ErrorKind;
+
+    let create = |fields: &[(&str, Type)]| {
+        let data_file = tempfile().unwrap();
+        let metadata_file = tempfile().unwrap();
+        let mut table = Table::create(
+            data_file.try_clone().unwrap(),
+            metadata_file.try_clone().unwrap(),
+            ("id", Type::Uint),
+            fields,
+        )
+        .unwrap();
+        table.insert_key(1).unwrap();
+        (data_file, metadata_file)
+    };
+    let (numbers_data, numbers_metadata) = create(&[("num", Type::Uint)]);
+    let (names_data, names_metadata) = create(&[("name", Type::String(20))]);
+    // Same entry size as the first table, but the field has another type
+    let (signed_data, signed_metadata) = create(&[("num", Type::Int)]);
+
+    for (data_file, metadata_file) in [
+        (&numbers_data, &names_metadata),
+        (&names_data, &numbers_metadata),
+        (&numbers_data, &signed_metadata),
+        (&signed_data, &numbers_metadata),
+    ] {
+        let error = Table::open(
+            data_file.try_clone().unwrap(),
+            metadata_file.try_clone().unwrap(),
+        )
+        .err()
+        .expect("Opening with another table's metadata should fail");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("schema"), "{error}");
+    }
+
+    let table = Table::open(numbers_data, numbers_metadata).unwrap();
+    assert!(table.contains(1).unwrap());
+    drop(table);
+    let table = Table::open(names_data, names_metadata).unwrap();
+    assert!(table.contains(1).unwrap());
+}
+
+#[test]
+fn test_concurrent_reads() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    let n = 20000;
+    insert_range(&mut table, 0..n);
+    drop(table);
+
+    // Reopened so that the threads load the pages from disk while the others read
+    let table = Table::open(data_file, metadata_file).unwrap();
+    std::thread::scope(|scope| {
+        for thread in 0..4 {
+            let table = &table;
+            scope.spawn(move || {
+                for i in 0..n {
+                    let key = (i * 7919 + thread * 101) % n;
+                    let data = table.find(key).unwrap().read_all();
+                    assert_eq!(data, key.to_ne_bytes());
+                }
+                assert!(table.find(n + thread).is_err());
+            });
+        }
+    });
+    let stats = table.pager.stats();
+    assert_eq!(stats.page_reads, stats.cache_hits + stats.cache_misses);
+    table.validate().unwrap();
+}
+
+#[test]
+fn test_flush() {
+    use std::io::{Read, Seek};
+
+    let mut data_file = tempfile().unwrap();
+    let mut metadata_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        ("id", Type::Uint),
+        &[("num", Type::Uint)],
+    )
+    .unwrap();
+    let marker = 0x5eed_f00d_cafe_beefusize;
+    table.insert(7, &marker.to_ne_bytes()).unwrap();
+    table.flush().unwrap();
+
+    // The handles share their position with the ones of the table
+    let mut contents = vec![];
+    data_file.rewind().unwrap();
+    data_file.read_to_end(&mut contents).unwrap();
+    assert!(
+        contents
+            .windows(size_of::<usize>())
+            .any(|w| w == marker.to_ne_bytes())
+    );
+    let mut metadata = vec![];
+    metadata_file.rewind().unwrap();
+    metadata_file.read_to_end(&mut metadata).unwrap();
+    assert!(!metadata.is_empty());
+    assert!(!table.pager.is_dirty(rustdb::pager::PageNum(1)));
+
+    // The table keeps working after the flush
+    insert_range(&mut table, 100..2000);
+    check_range(&mut table, 100..2000);
+    assert_eq!(table.find(7).unwrap().read_all(), marker.to_ne_bytes());
+    table.flush().unwrap();
+    drop(table);
+
+    let mut table = Table::open(data_file, metadata_file).unwrap();
+    assert_eq!(table.len(), 1901);
+    check_range(&mut table, 100..2000);
+}
+
+#[test]
+fn test_composite_primary_key() {
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create_with_options(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        &[("a", Type::Uint), ("b", Type::Uint)],
+        &[("value", Type::Uint)],
+        TableOptions::new(),
+    )
+    .unwrap();
+    let key = |table: &Table, a: usize, b: usize| {
+        table
+            .metadata
+            .metadata
+            .encode_key(&[Literal::Uint(a), Literal::Uint(b)])
+            .unwrap()
+    };
+    // Inserted out of order, the key sorts by a and then by b
+    for b in (0..50).rev() {
+        for a in 0..20 {
+            let k = key(&table, a, b);
+            table.insert(k, &(a * 100 + b).to_ne_bytes()).unwrap();
+        }
+    }
+    assert_eq!(table.len(), 1000);
+    assert_eq!(
+        table.find(key(&table, 7, 3)).unwrap().read_all(),
+        703usize.to_ne_bytes()
+    );
+
+    let prefix: Vec<_> = table
+        .scan_key_prefix(&[Literal::Uint(7)])
+        .unwrap()
+        .map(Result::unwrap)
+        .map(|(_, data)| usize::from_ne_bytes(data.read_all().try_into().unwrap()))
+        .collect();
+    assert_eq!(prefix, (700..750).collect::<Vec<_>>());
+    assert_eq!(
+        table.scan_key_prefix(&[Literal::Uint(20)]).unwrap().count(),
+        0
+    );
+    let full_key = [Literal::Uint(19), Literal::Uint(49)];
+    assert_eq!(table.scan_key_prefix(&full_key).unwrap().count(), 1);
+    drop(table);
+
+    let table = Table::open(data_file, metadata_file).unwrap();
+    table.validate().unwrap();
+    let a: Vec<_> = (0..20).map(Literal::Uint).collect();
+    assert_eq!(table.distinct_values("a").unwrap(), a);
+    let b = table.metadata.metadata.field("b").unwrap();
+    let first = table.rows().map(Result::unwrap).next().unwrap().0;
+    assert_eq!(
+        b.read_row(first, table.find(first).unwrap()),
+        Literal::Uint(0)
+    );
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_mmap_pager() {
+    use rustdb::pager::mmap::MmapPager;
+    use rustdb::table::node::Node;
+
+    fn collect_rows(pager: &MmapPager, page_num: PageNum, table: &Table, rows: &mut Vec<Vec<u8>>) {
+        match pager.get_node_ref(page_num) {
+            Node::InternalNode(internal) => {
+                for i in 0..=internal.num_keys {
+                    collect_rows(pager, internal.ptr(i), table, rows);
+                }
+            }
+            Node::LeafNode(leaf) => {
+                for i in 0..leaf.num_cells {
+                    let cell = leaf.cell_unchecked(i, table.entry_size);
+                    let mut row = cell.key.to_ne_bytes().to_vec();
+                    row.extend_from_slice(cell.data(table.entry_size).read_all());
+                    rows.push(row);
+                }
+            }
+        }
+    }
+
+    let data_file = tempfile().unwrap();
+    let mut table = Table::create(
+        data_file.try_clone().unwrap(),
+        tempfile().unwrap(),
+        ("id", Type::Uint),
+        &[("name", Type::Uint)],
+    )
+    .unwrap();
+    (0..20_000usize)
+        .map(|i| (i * 7919) % 20_000)
+        .for_each(|key| table.insert(key, &(key * 3).to_ne_bytes()).unwrap());
+    table.flush().unwrap();
+
+    let mut pager = MmapPager::new(data_file.try_clone().unwrap()).unwrap();
+    let mut mapped = vec![];
+    collect_rows(&pager, table.get_root(), &table, &mut mapped);
+    let read: Vec<_> = table
+        .rows()
+        .map(Result::unwrap)
+        .map(|(key, data)| [&key.to_ne_bytes()[..], data.read_all()].concat())
+        .collect();
+    assert_eq!(mapped.len(), 20_000);
+    assert_eq!(mapped, read);
+
+    // Pages allocated past the end of the file don't move the ones already borrowed
+    let num_pages = pager.num_pages();
+    let root = pager.get_page_ref(table.get_root());
+    let new_pages: Vec<_> = (0..100).map(|_| pager.get_free_page()).collect();
+    assert_eq!(new_pages[0], PageNum(num_pages));
+    assert_eq!(pager.num_pages(), num_pages + 100);
+    assert!(matches!(root.page_header().node(), Node::InternalNode(_)));
+    pager.flush().unwrap();
+    drop(pager);
+    assert_eq!(
+        data_file.metadata().unwrap().len() as usize,
+        (num_pages + 100) * rustdb::pager::PAGE_SIZE
+    );
+    let pager = MmapPager::new(data_file).unwrap();
+    pager.verify().unwrap();
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn test_compressed_pages() {
+    use rustdb::pager::{PAGE_SIZE, Pager};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create_with_options(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        &[("id", Type::Uint)],
+        &[("name", Type::Uint)],
+        TableOptions::new().compressed(),
+    )
+    .unwrap();
+    assert!(table.pager.is_compressed());
+    for key in 0..5000usize {
+        table.insert(key, &7usize.to_ne_bytes()).unwrap();
+    }
+    table.flush().unwrap();
+    let num_pages = table.pager.pages.read().unwrap().len();
+    let file_size = data_file.metadata().unwrap().len() as usize;
+    assert!(file_size < num_pages * PAGE_SIZE / 2);
+    assert!(Pager::is_compressed_file(&data_file).unwrap());
+    let mut file = data_file.try_clone().unwrap();
+    let mut metadata_page = vec![0; PAGE_SIZE];
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.read_exact(&mut metadata_page).unwrap();
+
+    // Pages that compress worse than before are moved
+    for key in (0..5000usize).step_by(3) {
+        table.upsert(key, &(key * 7919).to_ne_bytes()).unwrap();
+    }
+    table.delete(10).unwrap();
+    table.flush().unwrap();
+    drop(table);
+
+    // The moved pages don't overwrite the previous map, so the file can still be read if the
+    // flush stops before the metadata page is written
+    let mut new_metadata_page = vec![0; PAGE_SIZE];
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.read_exact(&mut new_metadata_page).unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.write_all(&metadata_page).unwrap();
+    Pager::new(data_file.try_clone().unwrap()).unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.write_all(&new_metadata_page).unwrap();
+
+    let table = Table::open(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+    )
+    .unwrap();
+    assert!(table.pager.is_compressed());
+    table.validate().unwrap();
+    assert_eq!(table.len(), 4999);
+    for (key, data) in table.rows().map(Result::unwrap) {
+        let expected = if key % 3 == 0 { key * 7919 } else { 7 };
+        assert_eq!(data.read_all(), expected.to_ne_bytes());
+    }
+}
+
+#[cfg(all(feature = "compression", feature = "encryption"))]
+#[test]
+fn test_combined_options() {
+    let key = [3u8; rustdb::pager::ENCRYPTION_KEY_SIZE];
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let wal_file = tempfile().unwrap();
+    let log_file = tempfile().unwrap();
+    let options = || {
+        TableOptions::new()
+            .compressed()
+            .encrypted(&key)
+            .wal(wal_file.try_clone().unwrap())
+            .insertion_log(log_file.try_clone().unwrap())
+    };
+    let mut table = Table::create_with_options(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        &[("id", Type::Uint)],
+        &[("name", Type::Uint)],
+        options().description("Everything at once"),
+    )
+    .unwrap();
+    assert!(table.pager.is_compressed());
+    assert!(table.pager.is_encrypted());
+    insert_range(&mut table, 0..300);
+    table.flush().unwrap();
+    insert_range(&mut table, 300..600);
+    table.commit().unwrap();
+    // Crashes after committing, the log has the pages that weren't flushed
+    insert_range(&mut table, 600..700);
+    std::mem::forget(table);
+
+    let open = || {
+        Table::open_with_options(
+            data_file.try_clone().unwrap(),
+            metadata_file.try_clone().unwrap(),
+            options(),
+        )
+    };
+    let mut table = open().unwrap();
+    assert!(table.wal.as_ref().unwrap().is_empty());
+    assert!(table.pager.is_compressed());
+    assert!(table.pager.is_encrypted());
+    assert_eq!(table.description(), Some("Everything at once"));
+    table.validate().unwrap();
+    assert_eq!(table.len(), 600);
+    check_range(&mut table, 0..600);
+    drop(table);
+
+    let mut table = open().unwrap();
+    table.validate().unwrap();
+    check_range(&mut table, 0..600);
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn test_encrypted_pages() {
+    use rustdb::pager::Pager;
+    use std::io::Read;
+
+    let key = [7u8; rustdb::pager::ENCRYPTION_KEY_SIZE];
+    let marker = 0x5eed_cafe_f00d_beefusize;
+    let data_file = tempfile().unwrap();
+    let metadata_file = tempfile().unwrap();
+    let mut table = Table::create_with_options(
+        data_file.try_clone().unwrap(),
+        metadata_file.try_clone().unwrap(),
+        &[("id", Type::Uint)],
+        &[("name", Type::Uint)],
+        TableOptions::new().encrypted(&key),
+    )
+    .unwrap();
+    assert!(table.pager.is_encrypted());
+    for key in 0..2000usize {
+        table.insert(key, &(marker ^ key).to_ne_bytes()).unwrap();
+    }
+    drop(table);
+
+    // The values aren't in the file in plain
+    let mut contents = vec![];
+    data_file
+        .try_clone()
+        .unwrap()
+        .read_to_end(&mut contents)
+        .unwrap();
+    let plain = (marker ^ 1000).to_ne_bytes();
+    assert!(!contents.windows(plain.len()).any(|bytes| bytes == plain));
+    assert!(Pager::is_encrypted_file(&data_file).unwrap());
+
+    let open = |key: &[u8; 32]| {
+        Table::open_with_options(
+            data_file.try_clone().unwrap(),
+            metadata_file.try_clone().unwrap(),
+            TableOptions::new().encrypted(key),
+        )
+    };
+    let table = open(&key).unwrap();
+    table.validate().unwrap();
+    assert_eq!(table.len(), 2000);
+    for (key, data) in table.rows().map(Result::unwrap) {
+        assert_eq!(data.read_all(), (marker ^ key).to_ne_bytes());
+    }
+    drop(table);
+
+    let mut wrong_key = key;
+    wrong_key[31] ^= 1;
+    let error = open(&wrong_key).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    let error = Table::open(data_file.try_clone().unwrap(), metadata_file)
+        .err()
+        .unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn test_encrypted_pages_are_authenticated() {
+    use rustdb::pager::{ENCRYPTED_PAGE_SIZE, PAGE_HEADER_SIZE, PageNum, Pager};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    let key = [7u8; rustdb::pager::ENCRYPTION_KEY_SIZE];
+    let mut data_file = tempfile().unwrap();
+    let mut table = Table::create_with_options(
+        data_file.try_clone().unwrap(),
+        tempfile().unwrap(),
+        &[("id", Type::Uint)],
+        &[("name", Type::Uint)],
+        TableOptions::new().encrypted(&key),
+    )
+    .unwrap();
+    table.insert(1, &1usize.to_ne_bytes()).unwrap();
+    table.pager.flush().unwrap();
+    let root_offset = ENCRYPTED_PAGE_SIZE as u64;
+    let read_root = |file: &mut std::fs::File| {
+        let mut page = vec![0; ENCRYPTED_PAGE_SIZE];
+        file.seek(SeekFrom::Start(root_offset)).unwrap();
+        file.read_exact(&mut page).unwrap();
+        page
+    };
+    let first = read_root(&mut data_file);
+
+    // Writing the same contents again uses another nonce
+    table.pager.get_page(PageNum(1)).unwrap();
+    table.pager.flush().unwrap();
+    let root = read_root(&mut data_file);
+    assert_ne!(first, root);
+    drop(table);
+    let pager_file = data_file.try_clone().unwrap();
+    let open = || Pager::new_encrypted(pager_file.try_clone().unwrap(), &key)?.verify();
+    open().unwrap();
+
+    // Changing a byte of the header, the body, the nonce or the tag fails to authenticate it
+    for offset in [
+        0,
+        PAGE_HEADER_SIZE + 4,
+        ENCRYPTED_PAGE_SIZE - 20,
+        ENCRYPTED_PAGE_SIZE - 1,
+    ] {
+        let mut tampered = root.clone();
+        tampered[offset] ^= 1;
+        data_file.seek(SeekFrom::Start(root_offset)).unwrap();
+        data_file.write_all(&tampered).unwrap();
+        let error = open().err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+    data_file.seek(SeekFrom::Start(root_offset)).unwrap();
+    data_file.write_all(&root).unwrap();
+    open().unwrap();
+}